@@ -1,17 +1,19 @@
-use std::convert::TryFrom;
 use std::fmt::Debug;
 use std::sync::Arc;
 
-use http::{Method, Request, Response, Uri};
+use http::{Method, Request, Response};
 
 use crate::body::Body;
 use crate::config::{AgentScope, Config, ConfigBuilder, HttpCrateScope, RequestLevelConfig};
+use crate::family::FamilyStats;
 use crate::middleware::MiddlewareNext;
-use crate::pool::ConnectionPool;
+use crate::pool::{ConnectionPool, DrainStats, PreConnected};
 use crate::resolver::{DefaultResolver, Resolver};
 use crate::send_body::AsSendBody;
-use crate::transport::{Connector, DefaultConnector};
-use crate::{Error, RequestBuilder, SendBody};
+use crate::transport::{Connector, DefaultConnector, Transport};
+use crate::RequestBuilder;
+use crate::{Batch, BodyWriter, Download, Error, IntoUri, Pages, PollEvent, PollNext};
+use crate::{SendBody, Upload, UploadProtocol};
 use crate::{WithBody, WithoutBody};
 
 /// Agents keep state between requests.
@@ -88,7 +90,7 @@ pub struct Agent {
     pub(crate) resolver: Arc<dyn Resolver>,
 
     #[cfg(feature = "cookies")]
-    pub(crate) jar: Arc<crate::cookies::SharedCookieJar>,
+    pub(crate) jar: crate::cookies::SharedCookieJar,
 }
 
 impl Agent {
@@ -120,7 +122,14 @@ impl Agent {
     /// Creates an agent with a bespoke transport and resolver.
     ///
     /// _This is low level API that isn't for regular use of ureq._
-    pub fn with_parts(config: Config, connector: impl Connector, resolver: impl Resolver) -> Self {
+    pub fn with_parts(
+        mut config: Config,
+        connector: impl Connector,
+        resolver: impl Resolver,
+    ) -> Self {
+        // Each agent counts for itself, also when created from the same config.
+        config.family_counters = Arc::default();
+
         let pool = Arc::new(ConnectionPool::new(connector, &config));
 
         Agent {
@@ -129,7 +138,7 @@ impl Agent {
             resolver: Arc::new(resolver),
 
             #[cfg(feature = "cookies")]
-            jar: Arc::new(crate::cookies::SharedCookieJar::new()),
+            jar: crate::cookies::SharedCookieJar::new(),
         }
     }
 
@@ -192,6 +201,280 @@ impl Agent {
         self.run_via_middleware(request, body)
     }
 
+    /// Run a request on an already connected transport.
+    ///
+    /// The HTTP layer drives the given [`Transport`], instead of connecting via the
+    /// connector chain. This is for connections ureq can't make itself, such as from
+    /// NAT traversal or a bespoke tunnel. The transport is used as is, which means
+    /// no TLS is added for `https` and the proxy setting is not used.
+    ///
+    /// The request is a single exchange on the transport. Redirects are returned
+    /// as is and retries are not made, since they would need another connection.
+    /// The transport is not returned to the connection pool.
+    ///
+    /// ```
+    /// # fn my_tunnel() -> Box<dyn ureq::transport::Transport> { unimplemented!() }
+    /// use ureq::Agent;
+    ///
+    /// let agent = Agent::new_with_defaults();
+    /// # if false {
+    /// let transport = my_tunnel();
+    /// let request = http::Request::get("http://peer.test/status").body(())?;
+    ///
+    /// let response = agent.request_on(transport, request)?;
+    /// # }
+    /// # Ok::<(), ureq::Error>(())
+    /// ```
+    pub fn request_on(
+        &self,
+        transport: Box<dyn Transport>,
+        request: Request<impl AsSendBody>,
+    ) -> Result<Response<Body>, Error> {
+        let (mut parts, mut body) = request.into_parts();
+
+        let mut config = parts
+            .extensions
+            .remove::<RequestLevelConfig>()
+            .unwrap_or_else(|| self.new_request_level_config());
+
+        config.0.pre_connected = Some(PreConnected::new(transport));
+        config.0.max_redirects = 0;
+        config.0.retry = None;
+
+        parts.extensions.insert(config);
+
+        let body = body.as_body();
+        let request = Request::from_parts(parts, ());
+
+        self.run_via_middleware(request, body)
+    }
+
+    /// Open a connection to `uri` ahead of time.
+    ///
+    /// Resolves the host, connects and makes the TLS handshake, and then parks the
+    /// connection in the pool. The first request to the same host then doesn't pay
+    /// for setting up the connection. Only the scheme, host and port of the uri
+    /// are used. Like a request, it fails for a uri that
+    /// [`https_only()`](crate::config::ConfigBuilder::https_only) or
+    /// [`offline_routes()`](crate::config::ConfigBuilder::offline_routes) don't allow.
+    ///
+    /// The connection is subject to the same idle limits as any other pooled
+    /// connection, see
+    /// [`ConfigBuilder::max_idle_age()`](crate::config::ConfigBuilder::max_idle_age).
+    ///
+    /// ```no_run
+    /// use ureq::Agent;
+    ///
+    /// let agent = Agent::new_with_defaults();
+    /// agent.preconnect("https://api.example.test")?;
+    ///
+    /// // Later, on the warm connection.
+    /// let body = agent.get("https://api.example.test/status").call()?;
+    /// # Ok::<(), ureq::Error>(())
+    /// ```
+    pub fn preconnect(&self, uri: impl IntoUri) -> Result<(), Error> {
+        crate::run::preconnect(self, &uri.into_uri()?)
+    }
+
+    /// Send the request head, and return a writer for the body.
+    ///
+    /// Low level API to make a request in phases. See [`BodyWriter`].
+    ///
+    /// ```
+    /// use std::io::Write;
+    /// use ureq::Agent;
+    ///
+    /// let agent = Agent::new_with_defaults();
+    /// let request = http::Request::put("http://httpbin.org/put").body(())?;
+    ///
+    /// let mut writer = agent.send_head(request)?;
+    /// writer.write_all(b"streamed body")?;
+    ///
+    /// let response = writer.read_head()?;
+    /// # Ok::<(), ureq::Error>(())
+    /// ```
+    pub fn send_head(&self, request: Request<()>) -> Result<BodyWriter, Error> {
+        let call = crate::run::PhasedCall::start(self, request)?;
+        Ok(BodyWriter::new(call))
+    }
+
+    /// Download a large file in parallel ranges, with resume support.
+    ///
+    /// See [`Download`].
+    ///
+    /// ```no_run
+    /// use ureq::Agent;
+    ///
+    /// let agent = Agent::new_with_defaults();
+    ///
+    /// agent.download("https://example.test/big.iso").to_file("big.iso")?;
+    /// # Ok::<(), ureq::Error>(())
+    /// ```
+    pub fn download(&self, uri: impl IntoUri) -> Download {
+        Download::new(self.clone(), uri)
+    }
+
+    /// Upload a large file in a resumable session (tus or Google style).
+    ///
+    /// See [`Upload`].
+    ///
+    /// ```no_run
+    /// use ureq::{Agent, UploadProtocol};
+    ///
+    /// let agent = Agent::new_with_defaults();
+    ///
+    /// let data = vec![0_u8; 10_000_000];
+    ///
+    /// agent
+    ///     .upload(UploadProtocol::Tus, "https://tus.example.test/files/")
+    ///     .create(data.len() as u64)?
+    ///     .upload(std::io::Cursor::new(data))?;
+    /// # Ok::<(), ureq::Error>(())
+    /// ```
+    pub fn upload(&self, protocol: UploadProtocol, uri: impl IntoUri) -> Upload {
+        Upload::new(self.clone(), protocol, uri)
+    }
+
+    /// Send several requests as one `multipart/mixed` batch request.
+    ///
+    /// See [`Batch`].
+    ///
+    /// ```no_run
+    /// use ureq::Agent;
+    ///
+    /// let agent = Agent::new_with_defaults();
+    ///
+    /// let responses = agent
+    ///     .batch("https://example.test/$batch")
+    ///     .add(http::Request::get("/People('a')").body(vec![])?)
+    ///     .add(http::Request::get("/People('b')").body(vec![])?)
+    ///     .send()?;
+    /// # Ok::<(), ureq::Error>(())
+    /// ```
+    pub fn batch(&self, uri: impl IntoUri) -> Batch {
+        Batch::new(self.clone(), uri)
+    }
+
+    /// A JSON-RPC 2.0 client for the endpoint at `uri`.
+    ///
+    /// Requires the **json** feature. See [`JsonRpc`](crate::JsonRpc).
+    ///
+    /// ```no_run
+    /// use ureq::Agent;
+    ///
+    /// let agent = Agent::new_with_defaults();
+    ///
+    /// let sum: i64 = agent.jsonrpc("http://localhost:8080/rpc").call("add", (1, 2))?;
+    /// # Ok::<(), ureq::Error>(())
+    /// ```
+    #[cfg(feature = "json")]
+    pub fn jsonrpc(&self, uri: impl IntoUri) -> crate::JsonRpc {
+        crate::JsonRpc::new(self.clone(), uri)
+    }
+
+    /// Make a unary gRPC-Web call.
+    ///
+    /// Requires the **grpc-web** feature. See [`grpc_web`](crate::grpc_web).
+    #[cfg(feature = "grpc-web")]
+    pub fn grpc_web(&self, uri: impl IntoUri) -> crate::grpc_web::GrpcWeb {
+        crate::grpc_web::GrpcWeb::new(self.clone(), uri)
+    }
+
+    /// An XML-RPC client for the endpoint at `uri`.
+    ///
+    /// Requires the **xmlrpc** feature. See [`xmlrpc`](crate::xmlrpc).
+    #[cfg(feature = "xmlrpc")]
+    pub fn xmlrpc(&self, uri: impl IntoUri) -> crate::xmlrpc::XmlRpc {
+        crate::xmlrpc::XmlRpc::new(self.clone(), uri)
+    }
+
+    /// Make a call to a SOAP service.
+    ///
+    /// Requires the **soap** feature. See [`soap`](crate::soap).
+    #[cfg(feature = "soap")]
+    pub fn soap(&self, uri: impl IntoUri) -> crate::soap::Soap {
+        crate::soap::Soap::new(self.clone(), uri)
+    }
+
+    /// Get tokens from an OpenID Connect provider.
+    ///
+    /// The `issuer` is the base of `.well-known/openid-configuration`. This agent is
+    /// used for the token requests.
+    ///
+    /// Requires the **oidc** feature. See [`oidc`](crate::oidc).
+    #[cfg(feature = "oidc")]
+    pub fn oidc(&self, issuer: &str, client_id: &str) -> crate::oidc::Oidc {
+        crate::oidc::Oidc::new(self.clone(), issuer, client_id)
+    }
+
+    /// Iterate over the pages of a paginated API.
+    ///
+    /// Runs `request` for the first page, and then follows the `Link: <...>; rel="next"`
+    /// header, or a closure given to [`Pages::next_page()`], for the following pages.
+    /// Pages are requested lazily as the iterator advances.
+    ///
+    /// ```
+    /// use ureq::Agent;
+    ///
+    /// let agent = Agent::new_with_defaults();
+    /// let request = http::Request::get("https://api.github.com/repos/algesten/ureq/issues")
+    ///     .body(())?;
+    ///
+    /// for page in agent.paginate(request) {
+    ///     let text = page?.body_mut().read_to_string()?;
+    /// }
+    /// # Ok::<(), ureq::Error>(())
+    /// ```
+    pub fn paginate(&self, request: Request<()>) -> Pages {
+        Pages::new(self.clone(), request)
+    }
+
+    /// Long poll by making the same request over and over.
+    ///
+    /// This is the pattern of watch APIs such as Consul blocking queries or Kubernetes
+    /// watches, where the server holds the request until something changes.
+    ///
+    /// The `handler` is called after each request with what happened, and the next
+    /// request to make. It can alter the next request to carry a cursor, such as an
+    /// index in the query. If the response has an `ETag`, the next request gets a
+    /// matching `If-None-Match` header.
+    ///
+    /// * Timeouts ([`Error::Timeout`]) are reported as [`PollEvent::Timeout`], and the
+    ///   next request is made right away. Use a timeout longer than the server's hold
+    ///   time.
+    /// * Other errors are reported as [`PollEvent::Error`], and the next request is
+    ///   made after a backoff, starting at 1 second and doubling up to 30 seconds.
+    ///   A successful response resets the backoff.
+    ///
+    /// The poll ends when the handler returns [`PollNext::Stop`], or with the error
+    /// if the handler returns one.
+    ///
+    /// ```no_run
+    /// use ureq::{Agent, PollEvent, PollNext, ResponseExt};
+    ///
+    /// let agent = Agent::new_with_defaults();
+    /// let request = http::Request::get("http://localhost:8500/v1/kv/config?index=0")
+    ///     .body(())?;
+    ///
+    /// agent.long_poll(request, |event, next| {
+    ///     if let PollEvent::Response(res) = event {
+    ///         if let Some(index) = res.header_as::<u64>("x-consul-index")? {
+    ///             let uri = format!("http://localhost:8500/v1/kv/config?index={}", index);
+    ///             *next.uri_mut() = uri.parse().unwrap();
+    ///         }
+    ///         println!("{}", res.body_mut().read_to_string()?);
+    ///     }
+    ///     Ok(PollNext::Continue)
+    /// })?;
+    /// # Ok::<(), ureq::Error>(())
+    /// ```
+    pub fn long_poll<F>(&self, request: Request<()>, handler: F) -> Result<(), Error>
+    where
+        F: FnMut(PollEvent<'_>, &mut Request<()>) -> Result<PollNext, Error>,
+    {
+        crate::long_poll::long_poll(self, request, handler)
+    }
+
     pub(crate) fn run_via_middleware(
         &self,
         request: Request<()>,
@@ -204,11 +487,63 @@ impl Agent {
         next.handle(request)
     }
 
+    /// Counters of connects per address family.
+    ///
+    /// Useful to detect a broken IPv6 path from the client side. See also
+    /// [`ConfigBuilder::on_family_event()`].
+    pub fn family_stats(&self) -> FamilyStats {
+        self.config.family_counters.stats()
+    }
+
+    /// Statistics over response bodies dropped before being read to the end.
+    ///
+    /// See [`ConfigBuilder::max_drain_on_drop()`].
+    pub fn drain_stats(&self) -> DrainStats {
+        self.pool.drain_stats()
+    }
+
     /// Get the config for this agent.
     pub fn config(&self) -> &Config {
         &self.config
     }
 
+    /// An agent with changes to the config of this one.
+    ///
+    /// The new agent shares the connection pool, resolver and cookie jar of this
+    /// agent, only the config differs. This is cheaper than making a new agent, and
+    /// keeps the pooled connections.
+    ///
+    /// Settings of the pool itself, such as [`ConfigBuilder::max_idle_connections()`],
+    /// remain those of this agent. A different TLS config is honored, the pool
+    /// doesn't reuse connections made with another TLS config.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// let agent = ureq::agent();
+    ///
+    /// let impatient = agent.with_overrides(|c| {
+    ///     c.timeout_global(Some(Duration::from_secs(2)))
+    ///         .max_redirects(0)
+    /// });
+    /// ```
+    pub fn with_overrides(
+        &self,
+        f: impl FnOnce(ConfigBuilder<AgentScope>) -> ConfigBuilder<AgentScope>,
+    ) -> Agent {
+        let builder = ConfigBuilder(AgentScope((*self.config).clone()));
+        let config = f(builder).build();
+
+        Agent {
+            config: Arc::new(config),
+            pool: self.pool.clone(),
+            resolver: self.resolver.clone(),
+
+            #[cfg(feature = "cookies")]
+            jar: self.jar.clone(),
+        }
+    }
+
     /// Alter the configuration for an http crate request.
     ///
     /// Notice: It's an error to configure a [`http::Request`] using
@@ -240,11 +575,7 @@ macro_rules! mk_method {
             $(
                 #[doc = concat!("Make a ", stringify!($m), " request using this agent.")]
                 #[must_use]
-                pub fn $f<T>(&self, uri: T) -> RequestBuilder<$b>
-                where
-                    Uri: TryFrom<T>,
-                    <Uri as TryFrom<T>>::Error: Into<http::Error>,
-                {
+                pub fn $f(&self, uri: impl IntoUri) -> RequestBuilder<$b> {
                     RequestBuilder::<$b>::new(self.clone(), Method::$m, uri)
                 }
             )*
@@ -287,4 +618,156 @@ mod test {
         let a = Agent::new_with_defaults();
         assert_no_alloc(|| a.clone());
     }
+
+    #[test]
+    fn overrides_share_pool() {
+        use crate::transport::MockConnector;
+
+        let mock =
+            MockConnector::new().route("/", "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok");
+        let agent = Agent::with_parts(Config::default(), mock.clone(), mock.clone());
+        let other =
+            agent.with_overrides(|c| c.user_agent(Some("other/1.0".into())).max_redirects(2));
+
+        assert!(Arc::ptr_eq(&agent.pool, &other.pool));
+        assert!(Arc::ptr_eq(&agent.resolver, &other.resolver));
+        assert_eq!(other.config.max_redirects, 2);
+        assert_eq!(agent.config.max_redirects, 10);
+
+        other.get("http://overrides.test/").call().unwrap();
+        let request = String::from_utf8(mock.requests().pop().unwrap()).unwrap();
+        assert!(request.contains("user-agent: other/1.0\r\n"));
+    }
+
+    #[test]
+    fn request_on_transport() {
+        use crate::transport::MockTransport;
+
+        let transport =
+            MockTransport::new(&b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello"[..]);
+
+        // The host doesn't resolve, there is no need to since we are connected.
+        let agent = Agent::new_with_defaults();
+        let request = Request::get("http://peer.invalid/").body(()).unwrap();
+
+        let mut res = agent.request_on(Box::new(transport), request).unwrap();
+        assert_eq!(res.body_mut().read_to_string().unwrap(), "hello");
+        assert_eq!(agent.pool_count(), 0);
+    }
+
+    #[test]
+    #[cfg(feature = "_test")]
+    fn preconnect_parks_connection() {
+        crate::test::init_test_log();
+
+        let agent = Agent::new_with_defaults();
+        agent.preconnect("https://www.google.com").unwrap();
+        assert_eq!(agent.pool_count(), 1);
+
+        // The request is served on the parked connection.
+        let mut res = agent.get("https://www.google.com/").call().unwrap();
+        assert_eq!(agent.pool_count(), 0);
+        let body = res.body_mut().read_to_string().unwrap();
+        assert_eq!(body, "ureq test server here");
+    }
+
+    #[test]
+    fn preconnect_checks_config() {
+        use crate::transport::MockConnector;
+
+        let mock = MockConnector::new().route("/", "HTTP/1.1 204 No Content\r\n\r\n");
+        let config = Config::builder()
+            .https_only(true)
+            .offline_routes(Some(&["https://api.test/*"]))
+            .build();
+        let agent = Agent::with_parts(config, mock.clone(), mock.clone());
+
+        let err = agent.preconnect("http://api.test").unwrap_err();
+        assert!(matches!(err, Error::RequireHttpsOnly(_)));
+
+        let err = agent.preconnect("https://other.test").unwrap_err();
+        assert!(matches!(err, Error::Offline(_)));
+
+        agent.preconnect("https://api.test").unwrap();
+        assert!(mock.requests().is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "_test")]
+    fn retire_connection_after_max_requests() {
+        crate::test::init_test_log();
+
+        let agent: Agent = Config::builder()
+            .max_requests_per_connection(Some(1))
+            .build()
+            .into();
+        let mut res = agent
+            .get("https://example.test/ranged/1000")
+            .call()
+            .unwrap();
+        res.body_mut().read_to_vec().unwrap();
+        assert_eq!(agent.pool_count(), 0);
+
+        let agent: Agent = Config::builder()
+            .max_requests_per_connection(Some(2))
+            .build()
+            .into();
+        let mut res = agent
+            .get("https://example.test/ranged/1000")
+            .call()
+            .unwrap();
+        res.body_mut().read_to_vec().unwrap();
+        assert_eq!(agent.pool_count(), 1);
+    }
+
+    #[test]
+    #[cfg(feature = "_test")]
+    fn drain_on_drop() {
+        crate::test::init_test_log();
+
+        // Default is to close the connection of an unread body.
+        let agent = Agent::new_with_defaults();
+        let res = agent
+            .get("https://example.test/ranged/1000")
+            .call()
+            .unwrap();
+        drop(res);
+        assert_eq!(agent.pool_count(), 0);
+        assert_eq!(agent.drain_stats().closed, 1);
+
+        // Small enough to drain.
+        let agent: Agent = Config::builder().max_drain_on_drop(1000).build().into();
+        let res = agent
+            .get("https://example.test/ranged/1000")
+            .call()
+            .unwrap();
+        drop(res);
+        assert_eq!(agent.pool_count(), 1);
+        let stats = agent.drain_stats();
+        assert_eq!(
+            (stats.drained, stats.drained_bytes, stats.closed),
+            (1, 1000, 0)
+        );
+
+        // Too big to drain.
+        let agent: Agent = Config::builder().max_drain_on_drop(999).build().into();
+        let res = agent
+            .get("https://example.test/ranged/1000")
+            .call()
+            .unwrap();
+        drop(res);
+        assert_eq!(agent.pool_count(), 0);
+        let stats = agent.drain_stats();
+        assert_eq!((stats.drained, stats.closed), (0, 1));
+
+        // Explicit close skips draining.
+        let agent: Agent = Config::builder().max_drain_on_drop(1000).build().into();
+        let res = agent
+            .get("https://example.test/ranged/1000")
+            .call()
+            .unwrap();
+        res.into_body().close();
+        assert_eq!(agent.pool_count(), 0);
+        assert_eq!(agent.drain_stats().closed, 1);
+    }
 }
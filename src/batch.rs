@@ -0,0 +1,320 @@
+use std::io::Read;
+
+use http::{header, HeaderMap, HeaderName, HeaderValue, Request, Response, StatusCode};
+use http::{Uri, Version};
+
+use crate::headers::multipart_content_type;
+use crate::run::host_header_value;
+use crate::{Agent, Body, Error, IntoUri};
+
+/// Max size of a single response in a batch.
+const MAX_PART_SIZE: u64 = 10 * 1024 * 1024;
+
+/// Several requests sent as one `multipart/mixed` request.
+///
+/// Created by [`Agent::batch()`]. Batch endpoints, such as those of OData services
+/// and Google APIs, take a number of HTTP requests serialized as the parts of a
+/// single request body, and answer with the responses as the parts of a
+/// `multipart/mixed` response.
+///
+/// The responses are returned in the order of the parts in the batch response,
+/// which is normally the order of the requests. A 4xx or 5xx status of a single
+/// response is not an error, only that of the batch response itself.
+///
+/// ```no_run
+/// use ureq::Agent;
+///
+/// let agent = Agent::new_with_defaults();
+///
+/// let responses = agent
+///     .batch("https://www.googleapis.com/batch/drive/v3")
+///     .add(http::Request::get("/drive/v3/files/a").body(vec![])?)
+///     .add(http::Request::delete("/drive/v3/files/b").body(vec![])?)
+///     .send()?;
+///
+/// for mut response in responses {
+///     println!("{}: {}", response.status(), response.body_mut().read_to_string()?);
+/// }
+/// # Ok::<_, ureq::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct Batch {
+    agent: Agent,
+    uri: Result<Uri, Error>,
+    boundary: String,
+    requests: Vec<Request<Vec<u8>>>,
+}
+
+impl Batch {
+    pub(crate) fn new(agent: Agent, uri: impl IntoUri) -> Self {
+        let n = agent.config().random_u64();
+
+        Batch {
+            agent,
+            uri: uri.into_uri(),
+            boundary: format!("batch_{:016x}", n),
+            requests: vec![],
+        }
+    }
+
+    /// Add a request to the batch.
+    ///
+    /// The uri is typically only a path and query, as in the examples of most
+    /// batch APIs. With a host, a `Host` header is added to the request.
+    pub fn add(mut self, request: Request<impl Into<Vec<u8>>>) -> Self {
+        self.requests.push(request.map(Into::into));
+        self
+    }
+
+    /// The boundary between the parts of the batch request.
+    ///
+    /// Defaults to a random boundary.
+    pub fn boundary(mut self, v: &str) -> Self {
+        self.boundary = v.to_string();
+        self
+    }
+
+    /// Serialize the requests into the batch request body.
+    fn to_body(&self) -> Vec<u8> {
+        let mut body = vec![];
+
+        for (i, request) in self.requests.iter().enumerate() {
+            body.extend_from_slice(format!("--{}\r\n", self.boundary).as_bytes());
+            body.extend_from_slice(b"Content-Type: application/http\r\n");
+            body.extend_from_slice(format!("Content-ID: <item{}>\r\n\r\n", i + 1).as_bytes());
+            write_request(&mut body, request);
+            body.extend_from_slice(b"\r\n");
+        }
+
+        body.extend_from_slice(format!("--{}--\r\n", self.boundary).as_bytes());
+        body
+    }
+
+    /// Send the batch, and parse the batched responses.
+    pub fn send(self) -> Result<Vec<Response<Body>>, Error> {
+        let body = self.to_body();
+        let content_type = multipart_content_type("mixed", &self.boundary);
+
+        let request = Request::post(self.uri?)
+            .header(header::CONTENT_TYPE, content_type)
+            .body(body)?;
+
+        debug!("Batch of {} requests", self.requests.len());
+
+        let response = self.agent.run(request)?;
+        let mut multipart = response.into_body().into_multipart()?;
+
+        let mut responses = vec![];
+
+        while let Some(part) = multipart.next_part()? {
+            let mut data = vec![];
+            part.take(MAX_PART_SIZE + 1).read_to_end(&mut data)?;
+
+            if data.len() as u64 > MAX_PART_SIZE {
+                return Err(Error::BodyExceedsLimit(MAX_PART_SIZE));
+            }
+
+            responses.push(parse_response(&data)?);
+        }
+
+        Ok(responses)
+    }
+}
+
+fn write_request(out: &mut Vec<u8>, request: &Request<Vec<u8>>) {
+    let uri = request.uri();
+    let target = uri.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+
+    let line = format!("{} {} HTTP/1.1\r\n", request.method(), target);
+    out.extend_from_slice(line.as_bytes());
+
+    if let Some(host) = host_header_value(uri) {
+        if !request.headers().contains_key(header::HOST) {
+            out.extend_from_slice(b"Host: ");
+            out.extend_from_slice(host.as_bytes());
+            out.extend_from_slice(b"\r\n");
+        }
+    }
+
+    for (name, value) in request.headers() {
+        out.extend_from_slice(name.as_str().as_bytes());
+        out.extend_from_slice(b": ");
+        out.extend_from_slice(value.as_bytes());
+        out.extend_from_slice(b"\r\n");
+    }
+
+    let body = request.body();
+    if !body.is_empty() && !request.headers().contains_key(header::CONTENT_LENGTH) {
+        out.extend_from_slice(format!("Content-Length: {}\r\n", body.len()).as_bytes());
+    }
+
+    out.extend_from_slice(b"\r\n");
+    out.extend_from_slice(body);
+}
+
+/// Parse a response serialized in a part of the batch response.
+fn parse_response(data: &[u8]) -> Result<Response<Body>, Error> {
+    let bad = |what: &str| Error::BadBody(format!("batch response: {}", what));
+
+    let head_end = data
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or_else(|| bad("no end of head"))?;
+
+    let head = std::str::from_utf8(&data[..head_end]).map_err(|_| bad("head is not utf-8"))?;
+    let mut lines = head.split("\r\n");
+
+    // HTTP/1.1 200 OK
+    let status_line = lines.next().unwrap_or_default();
+    let mut split = status_line.splitn(3, ' ');
+    let version = match split.next() {
+        Some("HTTP/1.0") => Version::HTTP_10,
+        Some("HTTP/1.1") => Version::HTTP_11,
+        _ => return Err(bad(status_line)),
+    };
+    let status = split
+        .next()
+        .and_then(|s| StatusCode::from_bytes(s.as_bytes()).ok())
+        .ok_or_else(|| bad(status_line))?;
+
+    let mut headers = HeaderMap::new();
+    for line in lines {
+        let (name, value) = line.split_once(':').ok_or_else(|| bad(line))?;
+        let name = HeaderName::from_bytes(name.as_bytes()).map_err(|_| bad(line))?;
+        let value = HeaderValue::from_str(value.trim()).map_err(|_| bad(line))?;
+        headers.append(name, value);
+    }
+
+    let mut body = &data[head_end + 4..];
+
+    // Some servers pad the part with line endings after the body.
+    let length = headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<usize>().ok());
+    if let Some(length) = length {
+        body = &body[..length.min(body.len())];
+    }
+
+    let body = Body::builder()
+        .content_type_of(&headers)
+        .data(body.to_vec());
+
+    let mut response = Response::new(body);
+    *response.status_mut() = status;
+    *response.version_mut() = version;
+    *response.headers_mut() = headers;
+
+    Ok(response)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::{Config, Random};
+    use crate::transport::MockConnector;
+
+    #[test]
+    fn batch_request_and_responses() {
+        let batch_response = "HTTP/1.1 200 OK\r\n\
+            Content-Type: multipart/mixed; boundary=resp\r\n\
+            \r\n\
+            --resp\r\n\
+            Content-Type: application/http\r\n\
+            \r\n\
+            HTTP/1.1 200 OK\r\n\
+            Content-Type: text/plain\r\n\
+            Content-Length: 5\r\n\
+            \r\n\
+            hello\r\n\
+            --resp\r\n\
+            Content-Type: application/http\r\n\
+            \r\n\
+            HTTP/1.1 404 Not Found\r\n\
+            \r\n\
+            \r\n\
+            --resp--\r\n";
+
+        let mock = MockConnector::new().route("/batch", batch_response);
+        let agent = Agent::with_parts(Config::default(), mock.clone(), mock.clone());
+
+        let responses = agent
+            .batch("http://example.test/batch")
+            .boundary("req")
+            .add(Request::get("/a").body(vec![]).unwrap())
+            .add(
+                Request::post("http://other.test/b")
+                    .header("content-type", "text/plain")
+                    .body("data")
+                    .unwrap(),
+            )
+            .send()
+            .unwrap();
+
+        let request = String::from_utf8(mock.requests().remove(0)).unwrap();
+        let (head, body) = request.split_once("\r\n\r\n").unwrap();
+        assert!(head.contains("content-type: multipart/mixed; boundary=req"));
+        assert_eq!(
+            body,
+            "--req\r\n\
+            Content-Type: application/http\r\n\
+            Content-ID: <item1>\r\n\
+            \r\n\
+            GET /a HTTP/1.1\r\n\
+            \r\n\
+            \r\n\
+            --req\r\n\
+            Content-Type: application/http\r\n\
+            Content-ID: <item2>\r\n\
+            \r\n\
+            POST /b HTTP/1.1\r\n\
+            Host: other.test\r\n\
+            content-type: text/plain\r\n\
+            Content-Length: 4\r\n\
+            \r\n\
+            data\r\n\
+            --req--\r\n"
+        );
+
+        assert_eq!(responses.len(), 2);
+
+        let mut first = responses.into_iter();
+        let mut hello = first.next().unwrap();
+        assert_eq!(hello.status(), 200);
+        assert_eq!(hello.body().mime_type(), Some("text/plain"));
+        assert_eq!(hello.body_mut().read_to_string().unwrap(), "hello");
+
+        let not_found = first.next().unwrap();
+        assert_eq!(not_found.status(), 404);
+    }
+
+    #[test]
+    fn host_with_port() {
+        let host = |uri: &str| {
+            let mut out = vec![];
+            write_request(&mut out, &Request::get(uri).body(vec![]).unwrap());
+            let out = String::from_utf8(out).unwrap();
+            out.lines().nth(1).unwrap().to_string()
+        };
+
+        assert_eq!(host("http://other.test:8080/b"), "Host: other.test:8080");
+        assert_eq!(host("http://other.test:80/b"), "Host: other.test");
+        assert_eq!(
+            host("https://user@other.test:8443/b"),
+            "Host: other.test:8443"
+        );
+    }
+
+    #[test]
+    fn seeded_boundary() {
+        let boundary = |seed| {
+            let config = Config::builder().random(Some(Random::seeded(seed))).build();
+            let agent = Agent::with_parts(config, MockConnector::new(), MockConnector::new());
+            agent.batch("http://example.test/batch").boundary
+        };
+
+        assert!(boundary(7).starts_with("batch_"));
+        assert_eq!(boundary(7), boundary(7));
+        assert_ne!(boundary(7), boundary(8));
+    }
+}
@@ -4,6 +4,8 @@ use std::sync::Arc;
 use hoot::BodyMode;
 
 use super::{Body, BodyDataSource, ContentEncoding, ResponseInfo};
+use crate::codec::Codecs;
+use crate::config::ContentSniffing;
 
 /// Builder for creating a response body.
 ///
@@ -45,7 +47,11 @@ impl BodyBuilder {
                 content_encoding: ContentEncoding::None,
                 mime_type: None,
                 charset: None,
+                boundary: None,
                 body_mode: BodyMode::NoBody,
+                sniffing: ContentSniffing::Off,
+                counters: Default::default(),
+                checksums: vec![],
             },
             limit: None,
         }
@@ -84,6 +90,20 @@ impl BodyBuilder {
         self
     }
 
+    /// Set mime type, charset and multipart boundary from the `content-type` header.
+    pub(crate) fn content_type_of(mut self, headers: &http::HeaderMap) -> Self {
+        let info = ResponseInfo::new(
+            headers,
+            BodyMode::NoBody,
+            ContentSniffing::Off,
+            &Codecs::default(),
+        );
+        self.info.mime_type = info.mime_type;
+        self.info.charset = info.charset;
+        self.info.boundary = info.boundary;
+        self
+    }
+
     /// Limit how much data is to be released from the body.
     ///
     /// **This does not set any HTTP headers. Affects Body decoding.**
@@ -0,0 +1,231 @@
+use std::io;
+
+use base64::prelude::BASE64_STANDARD;
+use base64::Engine;
+use http::HeaderMap;
+use md5::{Digest, Md5};
+use sha2::Sha256;
+
+use crate::Error;
+
+/// A checksum of the body, as sent in a response header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Checksum {
+    /// Which header, for the error.
+    name: &'static str,
+    algorithm: Algorithm,
+    expected: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Algorithm {
+    Md5,
+    Sha256,
+    Crc32,
+    Crc32c,
+}
+
+impl Checksum {
+    /// The checksums of the response headers.
+    ///
+    /// Supported are `Content-MD5`, the `md5` and `crc32c` of `x-goog-hash`, and
+    /// `x-amz-checksum-crc32`, `-crc32c` and `-sha256`. Values that don't decode,
+    /// such as the checksums of multipart uploads (`...-3`), are skipped.
+    pub fn from_headers(headers: &HeaderMap) -> Vec<Checksum> {
+        let mut checksums = vec![];
+
+        let mut add = |name: &'static str, algorithm: Algorithm, value: &str| {
+            let decoded = BASE64_STANDARD.decode(value.trim());
+
+            match decoded {
+                Ok(expected) if expected.len() == algorithm.len() => {
+                    let checksum = Checksum {
+                        name,
+                        algorithm,
+                        expected,
+                    };
+                    checksums.push(checksum);
+                }
+                _ => debug!("Skip checksum {}: {}", name, value),
+            }
+        };
+
+        let values = |name: &str| {
+            headers
+                .get_all(name)
+                .iter()
+                .filter_map(|v| v.to_str().ok())
+                .collect::<Vec<_>>()
+        };
+
+        for v in values("content-md5") {
+            add("content-md5", Algorithm::Md5, v);
+        }
+
+        // x-goog-hash: crc32c=n03x6A==,md5=Ojk9c3dhfxgoKVVHYwFbHQ==
+        for v in values("x-goog-hash") {
+            for pair in v.split(',') {
+                match pair.trim().split_once('=') {
+                    Some(("md5", h)) => add("x-goog-hash md5", Algorithm::Md5, h),
+                    Some(("crc32c", h)) => add("x-goog-hash crc32c", Algorithm::Crc32c, h),
+                    _ => {}
+                }
+            }
+        }
+
+        let amz = [
+            ("x-amz-checksum-crc32", Algorithm::Crc32),
+            ("x-amz-checksum-crc32c", Algorithm::Crc32c),
+            ("x-amz-checksum-sha256", Algorithm::Sha256),
+        ];
+        for (name, algorithm) in amz {
+            for v in values(name) {
+                add(name, algorithm, v);
+            }
+        }
+
+        checksums
+    }
+}
+
+impl Algorithm {
+    fn len(&self) -> usize {
+        match self {
+            Algorithm::Md5 => 16,
+            Algorithm::Sha256 => 32,
+            Algorithm::Crc32 | Algorithm::Crc32c => 4,
+        }
+    }
+
+    fn hasher(&self) -> Hasher {
+        match self {
+            Algorithm::Md5 => Hasher::Md5(Md5::new()),
+            Algorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            Algorithm::Crc32 => Hasher::Crc32(crc32fast::Hasher::new()),
+            Algorithm::Crc32c => Hasher::Crc32c(0),
+        }
+    }
+}
+
+enum Hasher {
+    Md5(Md5),
+    Sha256(Sha256),
+    Crc32(crc32fast::Hasher),
+    /// The CRC-32C so far.
+    Crc32c(u32),
+}
+
+impl Hasher {
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Md5(v) => v.update(data),
+            Hasher::Sha256(v) => v.update(data),
+            Hasher::Crc32(v) => v.update(data),
+            Hasher::Crc32c(v) => *v = crc32c::crc32c_append(*v, data),
+        }
+    }
+
+    fn finish(self) -> Vec<u8> {
+        match self {
+            Hasher::Md5(v) => v.finalize().to_vec(),
+            Hasher::Sha256(v) => v.finalize().to_vec(),
+            Hasher::Crc32(v) => v.finalize().to_be_bytes().to_vec(),
+            Hasher::Crc32c(v) => v.to_be_bytes().to_vec(),
+        }
+    }
+}
+
+/// Verifies the checksums once the body is read to the end.
+///
+/// A body that isn't read to the end is not verified.
+pub(crate) struct ChecksumReader<R> {
+    reader: R,
+    checks: Vec<(&'static str, Vec<u8>, Hasher)>,
+}
+
+impl<R> ChecksumReader<R> {
+    pub fn new(reader: R, checksums: &[Checksum]) -> Self {
+        let checks = checksums
+            .iter()
+            .map(|c| (c.name, c.expected.clone(), c.algorithm.hasher()))
+            .collect();
+
+        ChecksumReader { reader, checks }
+    }
+}
+
+impl<R: io::Read> io::Read for ChecksumReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.reader.read(buf)?;
+
+        if self.checks.is_empty() || buf.is_empty() {
+            return Ok(n);
+        }
+
+        if n > 0 {
+            for (_, _, hasher) in &mut self.checks {
+                hasher.update(&buf[..n]);
+            }
+            return Ok(n);
+        }
+
+        for (name, expected, hasher) in self.checks.drain(..) {
+            if hasher.finish() != expected {
+                return Err(Error::ChecksumMismatch(name.to_string()).into_io());
+            }
+            trace!("Checksum ok: {}", name);
+        }
+
+        Ok(0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Read;
+
+    use super::*;
+
+    fn read(headers: &[(&str, &str)], body: &[u8]) -> io::Result<Vec<u8>> {
+        let mut map = HeaderMap::new();
+        for (k, v) in headers {
+            map.append(*k, v.parse().unwrap());
+        }
+
+        let checksums = Checksum::from_headers(&map);
+        let mut reader = ChecksumReader::new(body, &checksums);
+
+        let mut out = vec![];
+        reader.read_to_end(&mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn verify_headers() {
+        // Checksums of "hello world" as sent by S3 and GCS.
+        let ok = [
+            ("content-md5", "XrY7u+Ae7tCTyyK7j1rNww=="),
+            (
+                "x-goog-hash",
+                "crc32c=yZRlqg==,md5=XrY7u+Ae7tCTyyK7j1rNww==",
+            ),
+            ("x-amz-checksum-crc32", "DUoRhQ=="),
+            ("x-amz-checksum-crc32c", "yZRlqg=="),
+            (
+                "x-amz-checksum-sha256",
+                "uU0nuZNNPgilLlLX2n2r+sSE7+N6U4DukIj3rOLvzek=",
+            ),
+        ];
+
+        for header in ok {
+            assert_eq!(read(&[header], b"hello world").unwrap(), b"hello world");
+
+            let err = read(&[header], b"hello w0rld").unwrap_err();
+            let err = Error::from(err);
+            assert!(matches!(err, Error::ChecksumMismatch(_)), "{:?}", header);
+        }
+
+        // Multipart upload checksums are skipped.
+        assert!(read(&[("x-amz-checksum-crc32", "DUoRhQ==-3")], b"x").is_ok());
+    }
+}
@@ -62,8 +62,12 @@ mod test {
         );
 
         let mut res = agent.get("https://example.test/gz_body").call().unwrap();
-        res.body_mut().read_to_string().unwrap();
+        let text = res.body_mut().read_to_string().unwrap();
 
         assert_eq!(agent.pool_count(), 1);
+
+        let stats = res.body().stats();
+        assert_eq!(stats.wire_bytes, 35);
+        assert_eq!(stats.decoded_bytes, text.len() as u64);
     }
 }
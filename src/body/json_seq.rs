@@ -0,0 +1,322 @@
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read};
+use std::marker::PhantomData;
+
+use serde::de::{DeserializeOwned, Error as _};
+
+use crate::Error;
+
+/// Max size of a single value of the sequence.
+const MAX_VALUE_SIZE: usize = 10 * 1024 * 1024;
+
+/// Record separator that starts each value of a JSON text sequence.
+const RS: u8 = 0x1e;
+
+/// Streaming parser of a sequence of JSON values.
+///
+/// Huge JSON bodies, such as exports, are parsed one value at a time, without holding
+/// the entire body in memory. The body can be:
+///
+/// * A JSON text sequence ([RFC 7464]), `application/json-seq`, where each value is
+///   preceded by an ASCII record separator (`0x1E`).
+/// * A top level array, where each element is a value.
+/// * Values separated by whitespace, such as newline delimited JSON.
+///
+/// The format is detected from the start of the body. A single value can be at most 10MB.
+/// Iteration stops after the first error.
+///
+/// A [`Body`](crate::Body) is parsed with [`Body::json_seq()`](crate::Body::json_seq)
+/// or [`Body::into_json_seq()`](crate::Body::into_json_seq), but any reader works.
+///
+/// ```
+/// use serde_json::Value;
+/// use ureq::JsonSeq;
+///
+/// let data = r#"[{"id": 1}, {"id": 2, "tags": ["a]"]}]"#;
+///
+/// let ids = JsonSeq::<_, Value>::new(data.as_bytes())
+///     .map(|v| v.map(|v| v["id"].as_u64()))
+///     .collect::<Result<Vec<_>, _>>()?;
+///
+/// assert_eq!(ids, [Some(1), Some(2)]);
+/// # Ok::<_, ureq::Error>(())
+/// ```
+///
+/// [RFC 7464]: https://www.rfc-editor.org/rfc/rfc7464
+pub struct JsonSeq<R, T> {
+    reader: BufReader<R>,
+    format: Option<Format>,
+    buf: Vec<u8>,
+    first: bool,
+    done: bool,
+    _ph: PhantomData<fn() -> T>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Seq,
+    Array,
+    Values,
+}
+
+impl<R: Read, T: DeserializeOwned> JsonSeq<R, T> {
+    /// Parse the values of `reader`.
+    pub fn new(reader: R) -> Self {
+        JsonSeq {
+            reader: BufReader::new(reader),
+            format: None,
+            buf: vec![],
+            first: true,
+            done: false,
+            _ph: PhantomData,
+        }
+    }
+
+    fn next_value(&mut self) -> Result<Option<T>, Error> {
+        let format = match self.format {
+            Some(v) => v,
+            None => {
+                let format = match self.peek_non_ws()? {
+                    None => return Ok(None),
+                    Some(RS) => Format::Seq,
+                    Some(b'[') => {
+                        self.reader.consume(1);
+                        Format::Array
+                    }
+                    Some(_) => Format::Values,
+                };
+                *self.format.insert(format)
+            }
+        };
+
+        match format {
+            Format::Seq => loop {
+                match self.peek_non_ws()? {
+                    None => return Ok(None),
+                    Some(RS) => self.reader.consume(1),
+                    Some(_) => break,
+                }
+            },
+            Format::Array => {
+                match self.peek_non_ws()? {
+                    None => return Err(invalid("unterminated array")),
+                    Some(b']') => {
+                        self.reader.consume(1);
+                        return Ok(None);
+                    }
+                    Some(b',') if !self.first => self.reader.consume(1),
+                    Some(_) if self.first => {}
+                    Some(_) => return Err(invalid("expected , or ] in array")),
+                }
+                if self.peek_non_ws()?.is_none() {
+                    return Err(invalid("unterminated array"));
+                }
+            }
+            Format::Values => {
+                if self.peek_non_ws()?.is_none() {
+                    return Ok(None);
+                }
+            }
+        }
+
+        self.first = false;
+        self.scan_value()?;
+
+        Ok(Some(serde_json::from_slice(&self.buf)?))
+    }
+
+    fn peek_non_ws(&mut self) -> io::Result<Option<u8>> {
+        loop {
+            let Some(&b) = self.reader.fill_buf()?.first() else {
+                return Ok(None);
+            };
+            if !b.is_ascii_whitespace() {
+                return Ok(Some(b));
+            }
+            self.reader.consume(1);
+        }
+    }
+
+    /// Read the bytes of the next value into the buffer.
+    fn scan_value(&mut self) -> Result<(), Error> {
+        self.buf.clear();
+        let mut scanner = Scanner::default();
+
+        loop {
+            let available = self.reader.fill_buf()?;
+            if available.is_empty() {
+                return Ok(());
+            }
+
+            let mut used = 0;
+            let mut end = false;
+
+            for b in available {
+                match scanner.step(*b) {
+                    Step::Take => used += 1,
+                    Step::TakeLast => {
+                        used += 1;
+                        end = true;
+                        break;
+                    }
+                    Step::End => {
+                        end = true;
+                        break;
+                    }
+                }
+            }
+
+            self.buf.extend_from_slice(&available[..used]);
+            self.reader.consume(used);
+
+            if self.buf.len() > MAX_VALUE_SIZE {
+                return Err(Error::BodyExceedsLimit(MAX_VALUE_SIZE as u64));
+            }
+
+            if end {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Finds the end of a JSON value, without parsing it.
+#[derive(Default)]
+struct Scanner {
+    depth: usize,
+    in_string: bool,
+    escape: bool,
+}
+
+enum Step {
+    /// The byte is part of the value.
+    Take,
+    /// The byte is the last of the value.
+    TakeLast,
+    /// The byte is after the value.
+    End,
+}
+
+impl Scanner {
+    fn step(&mut self, b: u8) -> Step {
+        if self.in_string {
+            if self.escape {
+                self.escape = false;
+            } else if b == b'\\' {
+                self.escape = true;
+            } else if b == b'"' {
+                self.in_string = false;
+                if self.depth == 0 {
+                    return Step::TakeLast;
+                }
+            }
+            return Step::Take;
+        }
+
+        match b {
+            b'"' => self.in_string = true,
+            b'{' | b'[' => self.depth += 1,
+            b'}' | b']' if self.depth == 0 => return Step::End,
+            b'}' | b']' => {
+                self.depth -= 1;
+                if self.depth == 0 {
+                    return Step::TakeLast;
+                }
+            }
+            b',' | RS if self.depth == 0 => return Step::End,
+            b if b.is_ascii_whitespace() && self.depth == 0 => return Step::End,
+            _ => {}
+        }
+
+        Step::Take
+    }
+}
+
+fn invalid(reason: &str) -> Error {
+    serde_json::Error::custom(reason).into()
+}
+
+impl<R: Read, T: DeserializeOwned> Iterator for JsonSeq<R, T> {
+    type Item = Result<T, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let result = self.next_value();
+
+        if !matches!(result, Ok(Some(_))) {
+            self.done = true;
+        }
+
+        result.transpose()
+    }
+}
+
+impl<R, T> fmt::Debug for JsonSeq<R, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JsonSeq")
+            .field("format", &self.format)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    fn parse(data: &str) -> Vec<Result<Value, Error>> {
+        JsonSeq::new(data.as_bytes()).collect()
+    }
+
+    fn values(data: &str) -> Vec<Value> {
+        parse(data).into_iter().map(Result::unwrap).collect()
+    }
+
+    #[test]
+    fn top_level_array() {
+        assert_eq!(
+            values(r#" [ {"a": "}]\"\\"}, [1, [2]], "x,y", 3.5 , null, true ] "#),
+            [
+                json!({"a": "}]\"\\"}),
+                json!([1, [2]]),
+                json!("x,y"),
+                json!(3.5),
+                json!(null),
+                json!(true)
+            ]
+        );
+        assert!(values("[]").is_empty());
+        assert!(values("").is_empty());
+    }
+
+    #[test]
+    fn json_text_sequence() {
+        assert_eq!(
+            values("\x1e{\"a\":1}\n\x1e[2]\n\x1e\"s\"\n\x1e4\n"),
+            [json!({"a": 1}), json!([2]), json!("s"), json!(4)]
+        );
+    }
+
+    #[test]
+    fn whitespace_separated() {
+        assert_eq!(
+            values("{\"a\":1}\n{\"a\":2}\r\n7 \"x\""),
+            [json!({"a": 1}), json!({"a": 2}), json!(7), json!("x")]
+        );
+    }
+
+    #[test]
+    fn stops_at_error() {
+        let result = parse("[1, 2 3]");
+        assert_eq!(result.len(), 3);
+        assert!(result[2].is_err());
+
+        let result = parse("[1, {\"a\":");
+        assert_eq!(result.len(), 2);
+        assert!(result[1].is_err());
+    }
+}
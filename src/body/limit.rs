@@ -16,6 +16,10 @@ impl<R> LimitReader<R> {
             left: limit,
         }
     }
+
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
 }
 
 impl<R: io::Read> io::Read for LimitReader<R> {
@@ -1,19 +1,38 @@
+use std::borrow::Cow;
 use std::fmt;
 use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 
 pub use build::BodyBuilder;
 use hoot::BodyMode;
+pub use multipart::{Multipart, MultipartPart};
 
-use crate::run::BodyHandler;
+#[cfg(feature = "json")]
+pub use json_seq::JsonSeq;
+
+use crate::codec::{Codec, Codecs};
+use crate::config::ContentSniffing;
+use crate::headers::split_params;
+use crate::run::{BodyHandler, ReadTimeout};
 use crate::Error;
 
+#[cfg(feature = "checksums")]
+use self::checksum::{Checksum, ChecksumReader};
 use self::limit::LimitReader;
 use self::lossy::LossyUtf8Reader;
+use self::sniff::SniffReader;
 
 mod build;
+#[cfg(feature = "checksums")]
+mod checksum;
 mod limit;
 mod lossy;
+mod multipart;
+mod sniff;
+
+#[cfg(feature = "json")]
+mod json_seq;
 
 #[cfg(feature = "charset")]
 mod charset;
@@ -63,7 +82,55 @@ pub(crate) struct ResponseInfo {
     content_encoding: ContentEncoding,
     mime_type: Option<String>,
     charset: Option<String>,
+    boundary: Option<String>,
     body_mode: BodyMode,
+    sniffing: ContentSniffing,
+    counters: Arc<ByteCounters>,
+    #[cfg(feature = "checksums")]
+    checksums: Vec<Checksum>,
+}
+
+/// Bytes of a response body read so far, before and after decompression.
+///
+/// Obtained via [`Body::stats()`]. Useful for accounting both the transferred and
+/// the logical size of responses. Without compression, the two are the same.
+///
+/// ```
+/// use std::io::Read;
+///
+/// let mut res = ureq::get("http://httpbin.org/gzip").call()?;
+/// res.body_mut().read_to_vec()?;
+///
+/// let stats = res.body().stats();
+/// println!(
+///     "{} bytes on the wire, {} decompressed",
+///     stats.wire_bytes, stats.decoded_bytes
+/// );
+/// # Ok::<_, ureq::Error>(())
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct BodyStats {
+    /// Bytes as received, after decoding chunks and before decompression.
+    pub wire_bytes: u64,
+    /// Bytes after decompression, before any charset conversion.
+    pub decoded_bytes: u64,
+}
+
+impl BodyStats {
+    /// How many times larger the decompressed body is, or `None` if nothing is read.
+    pub fn ratio(&self) -> Option<f64> {
+        if self.wire_bytes == 0 {
+            return None;
+        }
+        Some(self.decoded_bytes as f64 / self.wire_bytes as f64)
+    }
+}
+
+#[derive(Debug, Default)]
+struct ByteCounters {
+    wire: AtomicU64,
+    decoded: AtomicU64,
 }
 
 impl Body {
@@ -82,6 +149,27 @@ impl Body {
         }
     }
 
+    /// Close the connection without reading the rest of the body.
+    ///
+    /// Dropping an unread body might drain it to keep the connection reusable, see
+    /// [`ConfigBuilder::max_drain_on_drop()`][crate::config::ConfigBuilder::max_drain_on_drop].
+    /// This closes the connection straight away, which is the better choice for a
+    /// large body that is not wanted.
+    ///
+    /// ```
+    /// let res = ureq::get("http://httpbin.org/bytes/100000").call()?;
+    ///
+    /// if res.status() != 200 {
+    ///     res.into_body().close();
+    /// }
+    /// # Ok::<_, ureq::Error>(())
+    /// ```
+    pub fn close(self) {
+        if let BodyDataSource::Handler(mut handler) = self.source {
+            handler.close();
+        }
+    }
+
     /// The mime-type of the `content-type` header.
     ///
     /// For the below header, we would get `Some("text/plain")`:
@@ -124,6 +212,17 @@ impl Body {
         self.info.charset.as_deref()
     }
 
+    /// Bytes of the body read so far, before and after decompression.
+    ///
+    /// See [`BodyStats`].
+    pub fn stats(&self) -> BodyStats {
+        let counters = &self.info.counters;
+        BodyStats {
+            wire_bytes: counters.wire.load(Ordering::Relaxed),
+            decoded_bytes: counters.decoded.load(Ordering::Relaxed),
+        }
+    }
+
     /// Handle this body as a shared `impl Read` of the body.
     ///
     /// This is the regular API which goes via [`http::Response::body_mut()`] to get a
@@ -175,6 +274,26 @@ impl Body {
         self.into_with_config().reader()
     }
 
+    /// Unlimited reader of the bytes as sent by the server.
+    ///
+    /// No decompression or charset conversion, which matters when the bytes
+    /// are a range of a larger resource.
+    pub(crate) fn into_raw_reader(self) -> BodyReader<'static> {
+        let info = ResponseInfo {
+            content_encoding: ContentEncoding::None,
+            mime_type: None,
+            charset: None,
+            boundary: None,
+            body_mode: self.info.body_mode,
+            sniffing: ContentSniffing::Off,
+            counters: self.info.counters.clone(),
+            #[cfg(feature = "checksums")]
+            checksums: vec![],
+        };
+        let reader = LimitReader::new(self.source.into(), u64::MAX);
+        BodyReader::new(reader, &info, info.body_mode, false)
+    }
+
     /// Read the response as a string.
     ///
     /// * Response is limited to 10MB
@@ -254,6 +373,79 @@ impl Body {
         Ok(value)
     }
 
+    /// Read a JSON body one value at a time.
+    ///
+    /// For bodies too large for [`Body::read_json()`], such as exports. The body is a
+    /// JSON text sequence (`application/json-seq`), a top level array or values separated
+    /// by whitespace. See [`JsonSeq`] for details.
+    ///
+    /// ```no_run
+    /// use serde::Deserialize;
+    ///
+    /// #[derive(Deserialize)]
+    /// struct Record {
+    ///   id: u64,
+    /// }
+    ///
+    /// let mut res = ureq::get("http://example.com/export").call()?;
+    ///
+    /// for record in res.body_mut().json_seq::<Record>() {
+    ///     println!("{}", record?.id);
+    /// }
+    /// # Ok::<_, ureq::Error>(())
+    /// ```
+    #[cfg(feature = "json")]
+    pub fn json_seq<T: serde::de::DeserializeOwned>(&mut self) -> JsonSeq<BodyReader, T> {
+        JsonSeq::new(self.as_reader())
+    }
+
+    /// Consume self and read a JSON body one value at a time.
+    ///
+    /// Like [`Body::json_seq()`], but the values can be read with lifetime `'static`.
+    #[cfg(feature = "json")]
+    pub fn into_json_seq<T: serde::de::DeserializeOwned>(self) -> JsonSeq<BodyReader<'static>, T> {
+        JsonSeq::new(self.into_reader())
+    }
+
+    /// Read a multipart body one part at a time.
+    ///
+    /// The boundary is taken from the `content-type` header, such as
+    /// `multipart/mixed; boundary=batch`. Errors if there is none.
+    ///
+    /// ```no_run
+    /// use std::io::Read;
+    ///
+    /// let mut res = ureq::get("http://example.com/batch").call()?;
+    ///
+    /// let mut multipart = res.body_mut().as_multipart()?;
+    ///
+    /// while let Some(mut part) = multipart.next_part()? {
+    ///     let mut data = vec![];
+    ///     part.read_to_end(&mut data)?;
+    ///     println!("{:?}: {} bytes", part.mime_type(), data.len());
+    /// }
+    /// # Ok::<_, ureq::Error>(())
+    /// ```
+    pub fn as_multipart(&mut self) -> Result<Multipart<BodyReader>, Error> {
+        let boundary = self.boundary()?;
+        Ok(Multipart::new(self.as_reader(), &boundary))
+    }
+
+    /// Consume self and read a multipart body one part at a time.
+    ///
+    /// Like [`Body::as_multipart()`], but the parts can be read with lifetime `'static`.
+    pub fn into_multipart(self) -> Result<Multipart<BodyReader<'static>>, Error> {
+        let boundary = self.boundary()?;
+        Ok(Multipart::new(self.into_reader(), &boundary))
+    }
+
+    fn boundary(&self) -> Result<String, Error> {
+        self.info
+            .boundary
+            .clone()
+            .ok_or_else(|| Error::BadHeader("content-type without multipart boundary".into()))
+    }
+
     /// Read the body data with configuration.
     ///
     /// This borrows the body which gives easier use with [`http::Response::body_mut()`].
@@ -299,6 +491,69 @@ impl Body {
         let handler = self.source.into();
         BodyWithConfig::new(handler, self.info.clone())
     }
+
+    /// Transform the body data by wrapping the reader in another layer.
+    ///
+    /// The function receives a reader of the decoded body (decompressed, and charset
+    /// converted with the **charset** feature), and returns a reader of the transformed
+    /// data. This is typically used in [`Middleware`][crate::middleware::Middleware] to
+    /// decrypt, strip framing or hash the body before it reaches the user. Layers stack
+    /// by calling this repeatedly.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::io::Read;
+    ///
+    /// let res = ureq::get("http://httpbin.org/bytes/100")
+    ///     .call()?;
+    ///
+    /// let (parts, body) = res.into_parts();
+    ///
+    /// // Only let through the first 10 bytes.
+    /// let body = body.map_reader(|r| r.take(10));
+    ///
+    /// let mut res = ureq::http::Response::from_parts(parts, body);
+    ///
+    /// let bytes = res.body_mut().read_to_vec()?;
+    /// assert_eq!(bytes.len(), 10);
+    /// # Ok::<_, ureq::Error>(())
+    /// ```
+    pub fn map_reader<F, R>(self, f: F) -> Body
+    where
+        F: FnOnce(BodyReader<'static>) -> R,
+        R: io::Read + Send + Sync + 'static,
+    {
+        // The data from the reader is already decoded.
+        let info = ResponseInfo {
+            content_encoding: ContentEncoding::None,
+            mime_type: self.info.mime_type.clone(),
+            charset: if cfg!(feature = "charset") {
+                None
+            } else {
+                self.info.charset.clone()
+            },
+            boundary: self.info.boundary.clone(),
+            // The layer might change the length of the data.
+            body_mode: match self.info.body_mode {
+                BodyMode::NoBody => BodyMode::NoBody,
+                _ => BodyMode::Chunked,
+            },
+            // Already checked by the reader passed to the layer.
+            sniffing: ContentSniffing::Off,
+            counters: Arc::default(),
+            // Already verified by the reader passed to the layer.
+            #[cfg(feature = "checksums")]
+            checksums: vec![],
+        };
+
+        let reader = f(self.into_reader());
+
+        Body {
+            source: BodyDataSource::Reader(Box::new(reader)),
+            info: Arc::new(info),
+        }
+    }
 }
 
 /// Configuration of how to read the body.
@@ -417,36 +672,60 @@ impl<'a> BodyWithConfig<'a> {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Clone)]
 enum ContentEncoding {
     None,
     Gzip,
     Brotli,
+    Custom(Arc<dyn Codec>),
     Unknown,
 }
 
 impl ResponseInfo {
-    pub fn new(headers: &http::HeaderMap, body_mode: BodyMode) -> Self {
+    pub fn new(
+        headers: &http::HeaderMap,
+        body_mode: BodyMode,
+        sniffing: ContentSniffing,
+        codecs: &Codecs,
+    ) -> Self {
         let content_encoding = headers
             .get("content-encoding")
             .and_then(|v| v.to_str().ok())
-            .map(ContentEncoding::from)
+            .map(|v| ContentEncoding::new(v, codecs))
             .unwrap_or(ContentEncoding::None);
 
-        let (mime_type, charset) = headers
+        let (mime_type, charset, boundary) = headers
             .get("content-type")
             .and_then(|v| v.to_str().ok())
             .map(split_content_type)
-            .unwrap_or((None, None));
+            .map(|(mime_type, charset, boundary)| {
+                (
+                    Some(mime_type.to_string()),
+                    charset.map(Cow::into_owned),
+                    boundary.map(Cow::into_owned),
+                )
+            })
+            .unwrap_or((None, None, None));
 
         ResponseInfo {
             content_encoding,
             mime_type,
             charset,
+            boundary,
             body_mode,
+            sniffing,
+            counters: Arc::default(),
+            #[cfg(feature = "checksums")]
+            checksums: vec![],
         }
     }
 
+    /// Verify the body against the checksums in `headers`, if any.
+    #[cfg(feature = "checksums")]
+    pub fn verify_checksums(&mut self, headers: &http::HeaderMap) {
+        self.checksums = Checksum::from_headers(headers);
+    }
+
     /// Whether the mime type indicats text.
     fn is_text(&self) -> bool {
         self.mime_type
@@ -456,24 +735,26 @@ impl ResponseInfo {
     }
 }
 
-fn split_content_type(content_type: &str) -> (Option<String>, Option<String>) {
-    // Content-Type: text/plain; charset=iso-8859-1
-    let mut split = content_type.split(';');
+type ContentTypeParts<'a> = (&'a str, Option<Cow<'a, str>>, Option<Cow<'a, str>>);
 
-    let Some(mime_type) = split.next() else {
-        return (None, None);
-    };
+/// The mime type, charset and boundary of a `content-type` header value.
+pub(crate) fn split_content_type(content_type: &str) -> ContentTypeParts<'_> {
+    // Content-Type: text/plain; charset=iso-8859-1
+    // Content-Type: multipart/mixed; boundary="batch"
+    let (mime_type, params) = split_params(content_type);
 
     let mut charset = None;
+    let mut boundary = None;
 
-    for maybe_charset in split {
-        let maybe_charset = maybe_charset.trim();
-        if let Some(s) = maybe_charset.strip_prefix("charset=") {
-            charset = Some(s.to_string());
+    for (name, value) in params {
+        match name.as_str() {
+            "charset" => charset = Some(value),
+            "boundary" => boundary = Some(value),
+            _ => {}
         }
     }
 
-    (Some(mime_type.to_string()), charset)
+    (mime_type, charset, boundary)
 }
 
 /// A reader of the response data.
@@ -516,11 +797,12 @@ fn split_content_type(content_type: &str) -> (Option<String>, Option<String>) {
 /// # Ok::<_, ureq::Error>(())
 /// ```
 pub struct BodyReader<'a> {
-    reader: MaybeLossyDecoder<CharsetDecoder<ContentDecoder<LimitReader<BodySourceRef<'a>>>>>,
+    reader: MaybeLossyDecoder<CharsetDecoder<SniffReader<DecodedReader<'a>>>>,
     // If this reader is used as SendBody for another request, this
     // body mode can indiciate the content-length. Gzip, charset etc
     // would mean input is not same as output.
     outgoing_body_mode: BodyMode,
+    read_timeout: Option<ReadTimeout>,
 }
 
 impl<'a> BodyReader<'a> {
@@ -534,8 +816,21 @@ impl<'a> BodyReader<'a> {
         // in a proxy situation.
         let mut outgoing_body_mode = incoming_body_mode;
 
-        let reader = match info.content_encoding {
+        let read_timeout = reader.get_ref().read_timeout();
+
+        let reader = CountReader::wire(reader, &info.counters);
+
+        // Checksums are of the body as sent, before decompressing.
+        #[cfg(feature = "checksums")]
+        let reader = ChecksumReader::new(reader, &info.checksums);
+
+        let reader = match &info.content_encoding {
             ContentEncoding::None | ContentEncoding::Unknown => ContentDecoder::PassThrough(reader),
+            ContentEncoding::Custom(codec) => {
+                debug!("Decoding {}", codec.name());
+                outgoing_body_mode = BodyMode::Chunked;
+                ContentDecoder::Custom(codec.decoder(Box::new(reader)))
+            }
             #[cfg(feature = "gzip")]
             ContentEncoding::Gzip => {
                 debug!("Decoding gzip");
@@ -554,6 +849,10 @@ impl<'a> BodyReader<'a> {
             ContentEncoding::Brotli => ContentDecoder::PassThrough(reader),
         };
 
+        let reader = CountReader::decoded(reader, &info.counters);
+
+        let reader = SniffReader::new(reader, &info.sniffing, info.mime_type.as_deref());
+
         let reader = if info.is_text() {
             charset_decoder(
                 reader,
@@ -574,12 +873,45 @@ impl<'a> BodyReader<'a> {
         BodyReader {
             outgoing_body_mode,
             reader,
+            read_timeout,
         }
     }
 
     pub(crate) fn body_mode(&self) -> BodyMode {
         self.outgoing_body_mode
     }
+
+    /// Set the timeout for each read from here on.
+    ///
+    /// Replaces the [`timeout_recv_body`][crate::config::ConfigBuilder::timeout_recv_body]
+    /// of the request, which counts for the entire body. This suits bodies that alternate
+    /// between bursts and idle periods, such as event streams, which can be read with a
+    /// long timeout while idle, and a short one while receiving. The global and per call
+    /// timeouts still apply. `None` goes back to the timeout of the request.
+    ///
+    /// A timed out read fails with [`Error::Timeout`] as [`Timeout::RecvBody`][crate::Timeout::RecvBody].
+    /// Has no effect for bodies that are not read from a connection.
+    ///
+    /// ```
+    /// use std::io::Read;
+    /// use std::time::Duration;
+    ///
+    /// let mut reader = ureq::get("http://httpbin.org/stream/10")
+    ///     .call()?
+    ///     .into_body()
+    ///     .into_reader();
+    ///
+    /// reader.set_read_timeout(Some(Duration::from_secs(60)));
+    ///
+    /// let mut buf = [0; 1024];
+    /// let n = reader.read(&mut buf)?;
+    /// # Ok::<_, ureq::Error>(())
+    /// ```
+    pub fn set_read_timeout(&mut self, v: Option<std::time::Duration>) {
+        if let Some(t) = &self.read_timeout {
+            t.set(v.map(Into::into));
+        }
+    }
 }
 
 #[allow(unused)]
@@ -649,21 +981,72 @@ impl<R: io::Read> io::Read for CharsetDecoder<R> {
     }
 }
 
-enum ContentDecoder<R: io::Read> {
+/// The body after decompression, counting bytes before and after.
+type DecodedReader<'a> = CountReader<ContentDecoder<'a, WireReader<'a>>>;
+
+/// The body as sent, before decompression.
+#[cfg(feature = "checksums")]
+type WireReader<'a> = ChecksumReader<CountReader<LimitReader<BodySourceRef<'a>>>>;
+#[cfg(not(feature = "checksums"))]
+type WireReader<'a> = CountReader<LimitReader<BodySourceRef<'a>>>;
+
+/// Adds the bytes read to one of the [`ByteCounters`].
+struct CountReader<R> {
+    reader: R,
+    counters: Arc<ByteCounters>,
+    decoded: bool,
+}
+
+impl<R> CountReader<R> {
+    fn wire(reader: R, counters: &Arc<ByteCounters>) -> Self {
+        CountReader {
+            reader,
+            counters: counters.clone(),
+            decoded: false,
+        }
+    }
+
+    fn decoded(reader: R, counters: &Arc<ByteCounters>) -> Self {
+        CountReader {
+            reader,
+            counters: counters.clone(),
+            decoded: true,
+        }
+    }
+}
+
+impl<R: io::Read> io::Read for CountReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.reader.read(buf)?;
+
+        let counter = if self.decoded {
+            &self.counters.decoded
+        } else {
+            &self.counters.wire
+        };
+        counter.fetch_add(n as u64, Ordering::Relaxed);
+
+        Ok(n)
+    }
+}
+
+enum ContentDecoder<'a, R: io::Read> {
     #[cfg(feature = "gzip")]
     Gzip(Box<gzip::GzipDecoder<R>>),
     #[cfg(feature = "brotli")]
     Brotli(Box<brotli::BrotliDecoder<R>>),
+    Custom(Box<dyn io::Read + Send + Sync + 'a>),
     PassThrough(R),
 }
 
-impl<R: io::Read> io::Read for ContentDecoder<R> {
+impl<'a, R: io::Read> io::Read for ContentDecoder<'a, R> {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         match self {
             #[cfg(feature = "gzip")]
             ContentDecoder::Gzip(v) => v.read(buf),
             #[cfg(feature = "brotli")]
             ContentDecoder::Brotli(v) => v.read(buf),
+            ContentDecoder::Custom(v) => v.read(buf),
             ContentDecoder::PassThrough(v) => v.read(buf),
         }
     }
@@ -675,8 +1058,13 @@ impl fmt::Debug for Body {
     }
 }
 
-impl From<&str> for ContentEncoding {
-    fn from(s: &str) -> Self {
+impl ContentEncoding {
+    /// A registered codec takes precedence over the built in ones.
+    fn new(s: &str, codecs: &Codecs) -> Self {
+        if let Some(codec) = codecs.get(s) {
+            return ContentEncoding::Custom(codec.clone());
+        }
+
         match s {
             "gzip" => ContentEncoding::Gzip,
             "br" => ContentEncoding::Brotli,
@@ -712,6 +1100,16 @@ pub(crate) enum BodySourceRef<'a> {
     ReaderOwned(Box<dyn io::Read + Send + Sync>),
 }
 
+impl<'a> BodySourceRef<'a> {
+    fn read_timeout(&self) -> Option<ReadTimeout> {
+        match self {
+            BodySourceRef::HandlerShared(v) => Some(v.read_timeout()),
+            BodySourceRef::HandlerOwned(v) => Some(v.read_timeout()),
+            _ => None,
+        }
+    }
+}
+
 impl<'a> io::Read for BodySourceRef<'a> {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         match self {
@@ -728,9 +1126,103 @@ mod test {
     use std::iter;
 
     use crate::test::init_test_log;
-    use crate::transport::set_handler;
+    use crate::transport::{set_handler, Buffers, ConnectionDetails, Connector};
+    use crate::transport::{NextTimeout, Transport};
     use crate::Error;
 
+    #[test]
+    fn read_with_read_timeout() {
+        use std::io::Read;
+        use std::time::Duration;
+
+        init_test_log();
+        set_handler("/get", 200, &[("content-length", "5")], b"hello");
+
+        let res = crate::get("https://my.test/get").call().unwrap();
+        let mut reader = res.into_body().into_reader();
+        reader.set_read_timeout(Some(Duration::from_secs(10)));
+
+        let mut s = String::new();
+        reader.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "hello");
+    }
+
+    /// Passes the chained transport through, stalling once it has nothing more to give.
+    #[derive(Debug)]
+    struct Stall;
+
+    #[derive(Debug)]
+    struct StallTransport(Box<dyn Transport>);
+
+    impl Connector for Stall {
+        fn connect(
+            &self,
+            _details: &ConnectionDetails,
+            chained: Option<Box<dyn Transport>>,
+        ) -> Result<Option<Box<dyn Transport>>, Error> {
+            Ok(chained.map(|t| Box::new(StallTransport(t)) as Box<dyn Transport>))
+        }
+    }
+
+    impl Transport for StallTransport {
+        fn buffers(&mut self) -> &mut dyn Buffers {
+            self.0.buffers()
+        }
+
+        fn transmit_output(&mut self, amount: usize, timeout: NextTimeout) -> Result<(), Error> {
+            self.0.transmit_output(amount, timeout)
+        }
+
+        fn await_input(&mut self, timeout: NextTimeout) -> Result<bool, Error> {
+            if self.0.await_input(timeout)? {
+                return Ok(true);
+            }
+            // The server stops sending without closing the connection.
+            std::thread::sleep(*timeout.after);
+            Err(Error::Timeout(timeout.reason))
+        }
+
+        fn is_open(&mut self) -> bool {
+            self.0.is_open()
+        }
+    }
+
+    #[test]
+    fn read_timeout_fires() {
+        use std::io::Read;
+        use std::time::{Duration, Instant};
+
+        use crate::config::Config;
+        use crate::transport::{ChainedConnector, MockConnector};
+        use crate::{Agent, Timeout};
+
+        init_test_log();
+        let mock =
+            MockConnector::new().route("/", "HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nhello");
+        let connector = ChainedConnector::new([mock.clone().boxed(), Stall.boxed()]);
+        let agent = Agent::with_parts(Config::default(), connector, mock);
+
+        let res = agent.get("http://stall.test/").call().unwrap();
+        let mut reader = res.into_body().into_reader();
+        reader.set_read_timeout(Some(Duration::from_millis(100)));
+
+        let mut buf = [0; 10];
+        let n = reader.read(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"hello");
+
+        let start = Instant::now();
+        let err = Error::from(reader.read(&mut buf).unwrap_err());
+        assert!(
+            matches!(err, Error::Timeout(Timeout::RecvBody)),
+            "{:?}",
+            err
+        );
+
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(100), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_secs(5), "{:?}", elapsed);
+    }
+
     #[test]
     fn content_type_without_charset() {
         init_test_log();
@@ -779,6 +1271,28 @@ mod test {
         assert_eq!(b, "hello world!!!");
     }
 
+    #[test]
+    fn map_reader_layers() {
+        use std::io::Read;
+
+        init_test_log();
+        set_handler("/get", 200, &[("content-length", "5")], b"hello");
+
+        fn skip_one(mut r: impl Read) -> impl Read {
+            let mut b = [0];
+            r.read_exact(&mut b).unwrap();
+            r
+        }
+
+        let (_, body) = crate::get("https://my.test/get")
+            .call()
+            .unwrap()
+            .into_parts();
+        let mut body = body.map_reader(skip_one).map_reader(skip_one);
+
+        assert_eq!(body.read_to_string().unwrap(), "llo");
+    }
+
     #[test]
     fn large_response_header() {
         init_test_log();
@@ -795,4 +1309,57 @@ mod test {
         let err = crate::get("https://my.test/get").call().unwrap_err();
         assert!(matches!(err, Error::LargeResponseHeader(_, _)));
     }
+
+    #[test]
+    fn content_sniffing_error() {
+        use crate::config::ContentSniffing;
+        use crate::Agent;
+
+        init_test_log();
+        set_handler(
+            "/sniff_error",
+            200,
+            &[("content-type", "application/json")],
+            b"<!DOCTYPE html><html>Bad gateway</html>",
+        );
+
+        let agent: Agent = Agent::config_builder()
+            .content_sniffing(ContentSniffing::Error)
+            .build()
+            .into();
+
+        let mut res = agent.get("https://my.test/sniff_error").call().unwrap();
+        let err = res.body_mut().read_to_string().unwrap_err();
+        assert!(matches!(err, Error::ContentMismatch(_, "text/html")));
+    }
+
+    #[test]
+    fn content_sniffing_warn() {
+        use std::sync::{Arc, Mutex};
+
+        use crate::config::ContentSniffing;
+        use crate::Agent;
+
+        init_test_log();
+        set_handler(
+            "/sniff_warn",
+            200,
+            &[("content-type", "text/csv")],
+            b"\x89PNG\r\n\x1a\n",
+        );
+
+        let seen = Arc::new(Mutex::new(None));
+        let seen2 = seen.clone();
+        let agent: Agent = Agent::config_builder()
+            .content_sniffing(ContentSniffing::warn(move |declared, sniffed| {
+                *seen2.lock().unwrap() = Some(format!("{} {}", declared, sniffed));
+            }))
+            .build()
+            .into();
+
+        let mut res = agent.get("https://my.test/sniff_warn").call().unwrap();
+        let data = res.body_mut().read_to_vec().unwrap();
+        assert_eq!(data.len(), 8);
+        assert_eq!(seen.lock().unwrap().as_deref(), Some("text/csv image/png"));
+    }
 }
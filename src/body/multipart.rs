@@ -0,0 +1,345 @@
+use std::fmt;
+use std::io;
+
+use http::{HeaderMap, HeaderName, HeaderValue};
+
+use crate::util::ConsumeBuf;
+use crate::Error;
+
+/// Max size of the headers of a single part.
+const MAX_PART_HEADER_SIZE: usize = 64 * 1024;
+
+/// Size to grow the buffer by when reading.
+const READ_SIZE: usize = 8 * 1024;
+
+/// Streaming parser of a multipart body.
+///
+/// Responses such as those of batch APIs can be `multipart/mixed` or
+/// `multipart/form-data`. The parts are read one at a time from the underlying
+/// reader, without holding the entire body in memory.
+///
+/// A [`Body`](crate::Body) is parsed with [`Body::as_multipart()`](crate::Body::as_multipart)
+/// or [`Body::into_multipart()`](crate::Body::into_multipart), but any reader works
+/// given the boundary.
+///
+/// ```
+/// use std::io::Read;
+/// use ureq::Multipart;
+///
+/// let data = "--b\r\n\
+///     Content-Type: text/plain\r\n\
+///     \r\n\
+///     first\r\n\
+///     --b\r\n\
+///     \r\n\
+///     second\r\n\
+///     --b--\r\n";
+///
+/// let mut multipart = Multipart::new(data.as_bytes(), "b");
+///
+/// let mut part = multipart.next_part()?.unwrap();
+/// assert_eq!(part.mime_type(), Some("text/plain"));
+/// let mut s = String::new();
+/// part.read_to_string(&mut s)?;
+/// assert_eq!(s, "first");
+///
+/// // The rest of a part is skipped when moving on to the next.
+/// let part = multipart.next_part()?.unwrap();
+/// assert!(part.headers().is_empty());
+///
+/// assert!(multipart.next_part()?.is_none());
+/// # Ok::<_, ureq::Error>(())
+/// ```
+pub struct Multipart<R> {
+    reader: R,
+    /// `\r\n--` followed by the boundary.
+    delimiter: Vec<u8>,
+    buf: ConsumeBuf,
+    done: bool,
+}
+
+/// A part of a [`Multipart`] body.
+///
+/// The part body is read using [`std::io::Read`].
+pub struct MultipartPart<'a, R> {
+    headers: HeaderMap,
+    multipart: &'a mut Multipart<R>,
+}
+
+impl<R: io::Read> Multipart<R> {
+    /// Parse the parts of `reader`, separated by `boundary`.
+    ///
+    /// The boundary is the parameter of the `content-type` header, such as
+    /// `b` in `multipart/mixed; boundary=b`.
+    pub fn new(reader: R, boundary: &str) -> Self {
+        let mut delimiter = b"\r\n--".to_vec();
+        delimiter.extend_from_slice(boundary.as_bytes());
+
+        // The line ending before the first delimiter is optional. Starting with one
+        // means the first delimiter is found like all others.
+        let mut buf = ConsumeBuf::new(READ_SIZE);
+        buf.free_mut()[..2].copy_from_slice(b"\r\n");
+        buf.add_filled(2);
+
+        Multipart {
+            reader,
+            delimiter,
+            buf,
+            done: false,
+        }
+    }
+
+    /// The next part, or `None` after the last.
+    ///
+    /// Whatever is left unread of the previous part is skipped.
+    pub fn next_part(&mut self) -> Result<Option<MultipartPart<'_, R>>, Error> {
+        if self.done {
+            return Ok(None);
+        }
+
+        // Skip the preamble, or the rest of the previous part.
+        let mut skip = [0; 1024];
+        while self.read_body(&mut skip)? > 0 {}
+        self.buf.consume(self.delimiter.len());
+
+        // The last delimiter is followed by "--".
+        self.fill_until(|input| (input.len() >= 2).then_some(2))?;
+        if self.buf.unconsumed().starts_with(b"--") {
+            self.done = true;
+            return Ok(None);
+        }
+
+        let headers = self.read_headers()?;
+
+        Ok(Some(MultipartPart {
+            headers,
+            multipart: self,
+        }))
+    }
+
+    /// Read part body until the next delimiter, which gives 0.
+    fn read_body(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let input = self.buf.unconsumed();
+
+            let (amount, at_delimiter) = match find(input, &self.delimiter) {
+                Some(index) => (index, true),
+                // Hold back what could be the start of a delimiter.
+                None => (input.len().saturating_sub(self.delimiter.len() - 1), false),
+            };
+
+            if amount > 0 || at_delimiter {
+                let amount = amount.min(out.len());
+                out[..amount].copy_from_slice(&input[..amount]);
+                self.buf.consume(amount);
+                return Ok(amount);
+            }
+
+            if !self.fill()? {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "multipart body ended without closing delimiter",
+                ));
+            }
+        }
+    }
+
+    fn read_headers(&mut self) -> Result<HeaderMap, Error> {
+        // Anything after the delimiter up until the line ending is padding.
+        let line_end = self.fill_until(|input| find(input, b"\r\n"))?;
+        let padding = &self.buf.unconsumed()[..line_end];
+        if !padding.iter().all(|c| *c == b' ' || *c == b'\t') {
+            return Err(Error::BadBody("multipart delimiter not on own line".into()));
+        }
+        self.buf.consume(line_end);
+
+        // Header lines following the line ending, up until an empty line.
+        let end = self.fill_until(|input| find(input, b"\r\n\r\n"))?;
+        let lines = self.buf.unconsumed().get(2..end).unwrap_or_default();
+
+        let mut headers = HeaderMap::new();
+
+        for line in lines.split(|c| *c == b'\n') {
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            if line.is_empty() {
+                continue;
+            }
+
+            let (name, value) = line
+                .iter()
+                .position(|c| *c == b':')
+                .map(|i| (&line[..i], &line[i + 1..]))
+                .ok_or_else(|| bad_part_header(line))?;
+
+            let name = HeaderName::from_bytes(name).map_err(|_| bad_part_header(line))?;
+            let value = HeaderValue::from_bytes(trim(value)).map_err(|_| bad_part_header(line))?;
+
+            headers.append(name, value);
+        }
+
+        self.buf.consume(end + 4);
+
+        Ok(headers)
+    }
+
+    /// Read until `f` finds an index in the buffered input.
+    fn fill_until(&mut self, f: impl Fn(&[u8]) -> Option<usize>) -> Result<usize, Error> {
+        loop {
+            let input = self.buf.unconsumed();
+
+            if let Some(index) = f(input) {
+                return Ok(index);
+            }
+
+            if input.len() > MAX_PART_HEADER_SIZE {
+                return Err(Error::BadBody("multipart part header too large".into()));
+            }
+
+            if !self.fill()? {
+                return Err(Error::disconnected());
+            }
+        }
+    }
+
+    /// Read more input, returns false at the end of the reader.
+    fn fill(&mut self) -> io::Result<bool> {
+        if self.buf.free_mut().is_empty() {
+            self.buf.add_space(READ_SIZE);
+        }
+
+        let amount = self.reader.read(self.buf.free_mut())?;
+        self.buf.add_filled(amount);
+
+        Ok(amount > 0)
+    }
+}
+
+impl<'a, R> MultipartPart<'a, R> {
+    /// The headers of the part.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// The mime-type of the part `content-type` header, such as `application/json`.
+    pub fn mime_type(&self) -> Option<&str> {
+        let value = self
+            .headers
+            .get(http::header::CONTENT_TYPE)?
+            .to_str()
+            .ok()?;
+        value.split(';').next().map(|s| s.trim())
+    }
+}
+
+impl<'a, R: io::Read> io::Read for MultipartPart<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        self.multipart.read_body(buf)
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+fn trim(mut s: &[u8]) -> &[u8] {
+    while let [b' ' | b'\t', rest @ ..] = s {
+        s = rest;
+    }
+    while let [rest @ .., b' ' | b'\t'] = s {
+        s = rest;
+    }
+    s
+}
+
+fn bad_part_header(line: &[u8]) -> Error {
+    Error::BadBody(format!(
+        "multipart part header: {}",
+        String::from_utf8_lossy(line)
+    ))
+}
+
+impl<R> fmt::Debug for Multipart<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Multipart")
+            .field("done", &self.done)
+            .finish()
+    }
+}
+
+impl<'a, R> fmt::Debug for MultipartPart<'a, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MultipartPart")
+            .field("headers", &self.headers)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Read;
+
+    use super::*;
+
+    /// Reader giving one byte at a time, to hit every buffer boundary.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl io::Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let Some((first, rest)) = self.0.split_first() else {
+                return Ok(0);
+            };
+            buf[0] = *first;
+            self.0 = rest;
+            Ok(1)
+        }
+    }
+
+    const DATA: &[u8] = b"preamble\r\n\
+        --xyz\r\n\
+        Content-Disposition: form-data; name=\"a\"\r\n\
+        Content-Type: text/plain\r\n\
+        \r\n\
+        one\r\n\
+        --xy\r\n\
+        --xyz  \r\n\
+        \r\n\
+        \r\n\
+        --xyz--\r\n\
+        epilogue";
+
+    #[test]
+    fn parse_parts() {
+        let mut multipart = Multipart::new(Trickle(DATA), "xyz");
+
+        let mut part = multipart.next_part().unwrap().unwrap();
+        assert_eq!(part.mime_type(), Some("text/plain"));
+        assert_eq!(
+            part.headers()["content-disposition"],
+            "form-data; name=\"a\""
+        );
+        let mut body = String::new();
+        part.read_to_string(&mut body).unwrap();
+        assert_eq!(body, "one\r\n--xy");
+
+        let mut part = multipart.next_part().unwrap().unwrap();
+        assert!(part.headers().is_empty());
+        let mut body = String::new();
+        part.read_to_string(&mut body).unwrap();
+        assert_eq!(body, "");
+
+        assert!(multipart.next_part().unwrap().is_none());
+        assert!(multipart.next_part().unwrap().is_none());
+    }
+
+    #[test]
+    fn missing_close_delimiter() {
+        let mut multipart = Multipart::new(&b"--b\r\n\r\nunfinished"[..], "b");
+
+        let mut part = multipart.next_part().unwrap().unwrap();
+        let err = part.read_to_end(&mut vec![]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -0,0 +1,280 @@
+use std::io;
+
+use crate::config::ContentSniffing;
+use crate::Error;
+
+/// Max number of bytes at the start of the body to look at.
+const SNIFF_WINDOW: usize = 512;
+
+const MAGIC: &[(&[u8], &str)] = &[
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"GIF87a", "image/gif"),
+    (b"GIF89a", "image/gif"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"%PDF-", "application/pdf"),
+    (b"PK\x03\x04", "application/zip"),
+    (b"\x1f\x8b\x08", "application/gzip"),
+];
+
+/// Starts of html documents, after leading whitespace, matched case insensitively.
+const HTML: &[&[u8]] = &[b"<!doctype html", b"<html"];
+
+/// Checks the first bytes of the body against the declared content-type.
+pub(crate) struct SniffReader<R> {
+    reader: R,
+    pending: Option<(ContentSniffing, String)>,
+    /// The start of the body, buffered until it is enough to check.
+    head: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: io::Read> SniffReader<R> {
+    pub fn new(reader: R, sniffing: &ContentSniffing, mime_type: Option<&str>) -> Self {
+        let pending = match (sniffing, mime_type) {
+            (ContentSniffing::Off, _) | (_, None) => None,
+            (_, Some(m)) => Some((sniffing.clone(), m.trim().to_ascii_lowercase())),
+        };
+
+        SniffReader {
+            reader,
+            pending,
+            head: vec![],
+            pos: 0,
+        }
+    }
+
+    /// Read until the bytes so far are enough to check, which usually is the
+    /// first read. This doesn't wait for more of a streamed body, such as server
+    /// sent events, than needed. The bytes read are kept across errors such as
+    /// `Interrupted`.
+    fn fill_head(&mut self) -> io::Result<()> {
+        let mut chunk = [0; SNIFF_WINDOW];
+
+        while self.head.len() < SNIFF_WINDOW && needs_more(&self.head) {
+            let max = SNIFF_WINDOW - self.head.len();
+            let n = self.reader.read(&mut chunk[..max])?;
+            if n == 0 {
+                break;
+            }
+            self.head.extend_from_slice(&chunk[..n]);
+        }
+
+        Ok(())
+    }
+
+    fn check(&self, sniffing: ContentSniffing, declared: String) -> io::Result<()> {
+        let Some(sniffed) = sniff(&self.head).filter(|s| is_mismatch(&declared, s)) else {
+            return Ok(());
+        };
+
+        debug!(
+            "Content-Type {} doesn't match body that looks like {}",
+            declared, sniffed
+        );
+
+        match sniffing {
+            ContentSniffing::Off => {}
+            ContentSniffing::Warn(hook) => hook(&declared, sniffed),
+            ContentSniffing::Error => {
+                return Err(Error::ContentMismatch(declared, sniffed).into_io());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<R: io::Read> io::Read for SniffReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pending.is_some() {
+            self.fill_head()?;
+            let (sniffing, declared) = self.pending.take().unwrap();
+            self.check(sniffing, declared)?;
+        }
+
+        if self.pos < self.head.len() {
+            let n = (self.head.len() - self.pos).min(buf.len());
+            buf[..n].copy_from_slice(&self.head[self.pos..self.pos + n]);
+            self.pos += n;
+            return Ok(n);
+        }
+
+        self.reader.read(buf)
+    }
+}
+
+/// Guess the mime type from magic bytes at the start of the data.
+fn sniff(data: &[u8]) -> Option<&'static str> {
+    if let Some((_, mime)) = MAGIC.iter().find(|(m, _)| data.starts_with(m)) {
+        return Some(mime);
+    }
+
+    if data.len() >= 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+        return Some("image/webp");
+    }
+
+    let text = trim_start(data);
+
+    let is_html = HTML
+        .iter()
+        .any(|p| text.len() >= p.len() && text[..p.len()].eq_ignore_ascii_case(p));
+
+    if is_html {
+        return Some("text/html");
+    }
+
+    None
+}
+
+/// Whether more data could change what [`sniff()`] finds for `data`.
+fn needs_more(data: &[u8]) -> bool {
+    if sniff(data).is_some() {
+        return false;
+    }
+
+    let is_start_of = |p: &[u8]| data.len() < p.len() && p.starts_with(data);
+
+    if MAGIC.iter().any(|(m, _)| is_start_of(m)) {
+        return true;
+    }
+
+    // RIFF, 4 bytes of length, then WEBP.
+    if data.len() < 12 && (is_start_of(b"RIFF") || data.starts_with(b"RIFF")) {
+        return true;
+    }
+
+    let text = trim_start(data);
+
+    text.is_empty()
+        || HTML
+            .iter()
+            .any(|p| text.len() < p.len() && p[..text.len()].eq_ignore_ascii_case(text))
+}
+
+fn trim_start(data: &[u8]) -> &[u8] {
+    let start = data
+        .iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .unwrap_or(data.len());
+    &data[start..]
+}
+
+/// Whether the declared mime type is grossly wrong for the sniffed one.
+///
+/// Close relatives are not mismatches, such as `image/jpeg` data declared as
+/// `image/png`, or zip data declared as one of the many zip based formats.
+fn is_mismatch(declared: &str, sniffed: &str) -> bool {
+    if declared == "application/octet-stream" {
+        return false;
+    }
+
+    let ok = match sniffed {
+        "text/html" => {
+            declared.starts_with("text/") || declared.contains("html") || declared.contains("xml")
+        }
+        "application/pdf" => declared.ends_with("pdf"),
+        "application/zip" => declared.starts_with("application/"),
+        "application/gzip" => {
+            declared.contains("gzip") || declared.contains("tar") || declared.contains("compressed")
+        }
+        _ => {
+            let top = sniffed.split('/').next().unwrap_or(sniffed);
+            declared.split('/').next() == Some(top)
+        }
+    };
+
+    !ok
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sniff_magic_bytes() {
+        assert_eq!(sniff(b"\x89PNG\r\n\x1a\n...."), Some("image/png"));
+        assert_eq!(sniff(b"RIFF\0\0\0\0WEBPVP8 "), Some("image/webp"));
+        assert_eq!(sniff(b"\n  <!DOCTYPE HTML><html>"), Some("text/html"));
+        assert_eq!(sniff(b"{\"a\": 1}"), None);
+    }
+
+    /// Hands out the data one byte per read.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl io::Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.0.len().min(buf.len()).min(1);
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn sniff_across_short_reads() {
+        use std::io::Read;
+
+        let data = b"\x89PNG\r\n\x1a\n....";
+        let mut reader = SniffReader::new(Trickle(data), &ContentSniffing::Error, Some("text/csv"));
+        let err = reader.read_to_end(&mut vec![]).unwrap_err();
+        assert!(err.to_string().contains("image/png"), "{}", err);
+
+        let mut reader =
+            SniffReader::new(Trickle(data), &ContentSniffing::Error, Some("image/png"));
+        let mut out = vec![];
+        reader.read_to_end(&mut out).unwrap();
+        assert_eq!(out, data);
+    }
+
+    #[test]
+    fn sniff_needs_more() {
+        assert!(needs_more(b""));
+        assert!(needs_more(b"\x89PN"));
+        assert!(needs_more(b"RIFF\0\0"));
+        assert!(needs_more(b"\n <!DOC"));
+        assert!(!needs_more(b"\x89PNG\r\n\x1a\n"));
+        assert!(!needs_more(b"RIFF\0\0\0\0WAVE"));
+        assert!(!needs_more(b"data: {}"));
+    }
+
+    /// Hands out the data in one read, and panics if read again.
+    struct Once<'a>(Option<&'a [u8]>);
+
+    impl io::Read for Once<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let data = self.0.take().expect("read after the first read");
+            buf[..data.len()].copy_from_slice(data);
+            Ok(data.len())
+        }
+    }
+
+    #[test]
+    fn sniff_stream_without_waiting() {
+        use std::io::Read;
+
+        let data = b"data: {}\n\n";
+        let mut reader = SniffReader::new(
+            Once(Some(data)),
+            &ContentSniffing::Error,
+            Some("text/event-stream"),
+        );
+
+        let mut buf = [0; 64];
+        let n = reader.read(&mut buf).unwrap();
+        assert_eq!(&buf[..n], data);
+    }
+
+    #[test]
+    fn gross_mismatches() {
+        assert!(is_mismatch("application/json", "text/html"));
+        assert!(is_mismatch("text/csv", "image/png"));
+        assert!(is_mismatch("application/json", "application/gzip"));
+        assert!(!is_mismatch("image/jpeg", "image/png"));
+        assert!(!is_mismatch("application/xhtml+xml", "text/html"));
+        assert!(!is_mismatch(
+            "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+            "application/zip"
+        ));
+        assert!(!is_mismatch("application/octet-stream", "image/png"));
+    }
+}
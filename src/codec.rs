@@ -0,0 +1,200 @@
+//! Custom `Content-Encoding` codecs.
+//!
+//! Besides the built in `gzip` and `br` (with the **gzip** and **brotli** features),
+//! response bodies can be decoded with codecs added via
+//! [`ConfigBuilder::codec()`](crate::config::ConfigBuilder::codec). A registered
+//! codec takes precedence over a built in one of the same name. The names of the
+//! registered codecs are added to the `Accept-Encoding` header.
+//!
+//! Request bodies are encoded with a registered codec via
+//! [`ConfigBuilder::request_encoding()`](crate::config::ConfigBuilder::request_encoding).
+//!
+//! With the **zstd** feature, there is a [`Zstd`] codec, which can use a dictionary.
+//!
+//! ```
+//! use std::io::Read;
+//! use ureq::codec::Codec;
+//!
+//! /// A (not very useful) codec flipping all bits.
+//! struct Flip;
+//!
+//! struct FlipReader<R>(R);
+//!
+//! impl<R: Read> Read for FlipReader<R> {
+//!     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+//!         let n = self.0.read(buf)?;
+//!         buf[..n].iter_mut().for_each(|b| *b = !*b);
+//!         Ok(n)
+//!     }
+//! }
+//!
+//! impl Codec for Flip {
+//!     fn name(&self) -> &str {
+//!         "x-flip"
+//!     }
+//!
+//!     fn decoder<'a>(
+//!         &self,
+//!         reader: Box<dyn Read + Send + Sync + 'a>,
+//!     ) -> Box<dyn Read + Send + Sync + 'a> {
+//!         Box::new(FlipReader(reader))
+//!     }
+//!
+//!     fn encoder<'a>(&self, reader: Box<dyn Read + 'a>) -> Box<dyn Read + 'a> {
+//!         Box::new(FlipReader(reader))
+//!     }
+//! }
+//!
+//! let agent: ureq::Agent = ureq::Agent::config_builder()
+//!     .codec(Flip)
+//!     .request_encoding(Some("x-flip"))
+//!     .build()
+//!     .into();
+//! ```
+
+use std::fmt;
+use std::io::Read;
+use std::sync::Arc;
+
+#[cfg(feature = "zstd")]
+mod zstd;
+#[cfg(feature = "zstd")]
+pub use self::zstd::Zstd;
+
+/// A `Content-Encoding`, such as `lz4` or a proprietary scheme.
+///
+/// Both directions work on readers. The decoder reads the encoded response body
+/// and yields the decoded bytes. The encoder reads the plain request body and
+/// yields the encoded bytes.
+///
+/// Errors of the wrapped reader should be passed on as is. See the
+/// [module](self) documentation for an example.
+pub trait Codec: Send + Sync + 'static {
+    /// The name as used in `Content-Encoding`, such as `lz4`.
+    ///
+    /// Matched without regard to case.
+    fn name(&self) -> &str;
+
+    /// Wrap an encoded body to read it decoded.
+    fn decoder<'a>(
+        &self,
+        reader: Box<dyn Read + Send + Sync + 'a>,
+    ) -> Box<dyn Read + Send + Sync + 'a>;
+
+    /// Wrap a plain body to read it encoded.
+    fn encoder<'a>(&self, reader: Box<dyn Read + 'a>) -> Box<dyn Read + 'a>;
+}
+
+/// The codecs added to the config.
+#[derive(Clone, Default)]
+pub(crate) struct Codecs {
+    codecs: Arc<Vec<Arc<dyn Codec>>>,
+}
+
+impl Codecs {
+    pub fn add(&mut self, codec: impl Codec) {
+        Arc::make_mut(&mut self.codecs).push(Arc::new(codec));
+    }
+
+    /// The codec of `name`. The last added wins.
+    pub fn get(&self, name: &str) -> Option<&Arc<dyn Codec>> {
+        let name = name.trim();
+        self.codecs
+            .iter()
+            .rev()
+            .find(|c| c.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.codecs.iter().map(|c| c.name())
+    }
+}
+
+impl fmt::Debug for Codecs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.names()).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io;
+
+    use super::*;
+    use crate::config::Config;
+    use crate::transport::MockConnector;
+    use crate::Agent;
+
+    struct Flip;
+
+    struct FlipReader<R>(R);
+
+    impl<R: Read> Read for FlipReader<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.0.read(buf)?;
+            buf[..n].iter_mut().for_each(|b| *b = !*b);
+            Ok(n)
+        }
+    }
+
+    impl Codec for Flip {
+        fn name(&self) -> &str {
+            "x-flip"
+        }
+
+        fn decoder<'a>(
+            &self,
+            reader: Box<dyn Read + Send + Sync + 'a>,
+        ) -> Box<dyn Read + Send + Sync + 'a> {
+            Box::new(FlipReader(reader))
+        }
+
+        fn encoder<'a>(&self, reader: Box<dyn Read + 'a>) -> Box<dyn Read + 'a> {
+            Box::new(FlipReader(reader))
+        }
+    }
+
+    #[test]
+    fn encode_and_decode() {
+        let mut response = b"HTTP/1.1 200 OK\r\n\
+            Content-Encoding: X-Flip\r\n\
+            Content-Length: 5\r\n\
+            \r\n"
+            .to_vec();
+        response.extend(b"hello".iter().map(|b| !b));
+
+        let mock = MockConnector::new().route("/", response);
+        let config = Config::builder()
+            .codec(Flip)
+            .request_encoding(Some("x-flip"))
+            .build();
+        let agent = Agent::with_parts(config, mock.clone(), mock.clone());
+
+        let mut res = agent.post("http://codec.test/").send("abc").unwrap();
+        assert_eq!(res.body_mut().read_to_string().unwrap(), "hello");
+
+        let request = mock.requests().pop().unwrap();
+        let flipped: Vec<u8> = b"abc".iter().map(|b| !b).collect();
+        let head = String::from_utf8_lossy(&request);
+        assert!(head.contains("content-encoding: x-flip\r\n"));
+        assert!(head.contains("accept-encoding: "));
+        assert!(head.contains("x-flip\r\n"));
+        assert!(head.contains("transfer-encoding: chunked\r\n"));
+        assert!(request.windows(3).any(|w| w == flipped));
+
+        // Not sending a body means not encoding one.
+        agent.get("http://codec.test/").call().unwrap();
+        let request = String::from_utf8(mock.requests().pop().unwrap()).unwrap();
+        assert!(!request.contains("content-encoding"));
+    }
+
+    #[test]
+    fn unknown_codec() {
+        let mock = MockConnector::new().route("/", "HTTP/1.1 204 No Content\r\n\r\n");
+        let config = Config::builder().request_encoding(Some("lz4")).build();
+        let agent = Agent::with_parts(config, mock.clone(), mock);
+
+        let err = agent.post("http://codec.test/").send("abc").unwrap_err();
+        assert!(matches!(err, crate::Error::UnknownCodec(_)));
+    }
+}
@@ -0,0 +1,165 @@
+use std::io::{self, BufReader, Read};
+use std::sync::{Arc, Mutex};
+
+use ::zstd::stream::read::{Decoder, Encoder};
+
+use crate::Error;
+
+use super::Codec;
+
+/// The `zstd` content-encoding, optionally with a dictionary.
+///
+/// Requires the **zstd** feature.
+///
+/// A dictionary makes small payloads, such as short JSON documents, compress well.
+/// Both ends must use the same dictionary, which an API typically ships with its
+/// client.
+///
+/// ```
+/// use ureq::codec::Zstd;
+///
+/// // Trained with `zstd --train`.
+/// # let dictionary: Vec<u8> = vec![];
+/// # #[cfg(any())]
+/// let dictionary = std::fs::read("api.dict")?;
+///
+/// let agent: ureq::Agent = ureq::Agent::config_builder()
+///     .codec(Zstd::new().dictionary(dictionary))
+///     .request_encoding(Some("zstd"))
+///     .build()
+///     .into();
+/// # Ok::<_, std::io::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct Zstd {
+    level: i32,
+    dictionary: Arc<[u8]>,
+}
+
+impl Zstd {
+    /// Creates the codec without dictionary.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compress and decompress with a dictionary.
+    ///
+    /// An empty dictionary is the same as none.
+    pub fn dictionary(mut self, dictionary: impl Into<Vec<u8>>) -> Self {
+        self.dictionary = dictionary.into().into();
+        self
+    }
+
+    /// The level for compressing request bodies, from 1 to 22.
+    ///
+    /// Defaults to 3.
+    pub fn level(mut self, level: i32) -> Self {
+        self.level = level;
+        self
+    }
+}
+
+impl Default for Zstd {
+    fn default() -> Self {
+        Zstd {
+            level: 3,
+            dictionary: Arc::new([]),
+        }
+    }
+}
+
+impl Codec for Zstd {
+    fn name(&self) -> &str {
+        "zstd"
+    }
+
+    fn decoder<'a>(
+        &self,
+        reader: Box<dyn Read + Send + Sync + 'a>,
+    ) -> Box<dyn Read + Send + Sync + 'a> {
+        let reader = BufReader::new(reader);
+        match Decoder::with_dictionary(reader, &self.dictionary) {
+            Ok(decoder) => Box::new(ZstdReader::new(Ok(decoder))),
+            Err(e) => Box::new(ZstdReader::<io::Empty>::new(Err(e))),
+        }
+    }
+
+    fn encoder<'a>(&self, reader: Box<dyn Read + 'a>) -> Box<dyn Read + 'a> {
+        let reader = BufReader::new(reader);
+        match Encoder::with_dictionary(reader, self.level, &self.dictionary) {
+            Ok(encoder) => Box::new(encoder),
+            Err(e) => Box::new(ZstdReader::<io::Empty>::new(Err(e))),
+        }
+    }
+}
+
+/// Maps the errors of decompression, or fails on the first read if the
+/// decoder couldn't be created.
+///
+/// The zstd contexts are `Send` but not `Sync`, which the mutex makes up for.
+struct ZstdReader<R>(Mutex<Result<R, Option<io::Error>>>);
+
+impl<R> ZstdReader<R> {
+    fn new(reader: io::Result<R>) -> Self {
+        ZstdReader(Mutex::new(reader.map_err(Some)))
+    }
+}
+
+impl<R: Read> Read for ZstdReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Never locked, so never poisoned.
+        let result = match self.0.get_mut().unwrap() {
+            Ok(reader) => reader.read(buf),
+            Err(e) => match e.take() {
+                Some(e) => Err(e),
+                None => Err(io::ErrorKind::InvalidData.into()),
+            },
+        };
+
+        result.map_err(|e| Error::Decompress("zstd", e).into_io())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn round_trip(codec: &Zstd, data: &[u8]) -> (Vec<u8>, Vec<u8>) {
+        let mut encoded = vec![];
+        codec
+            .encoder(Box::new(data))
+            .read_to_end(&mut encoded)
+            .unwrap();
+
+        let mut decoded = vec![];
+        codec
+            .decoder(Box::new(io::Cursor::new(encoded.clone())))
+            .read_to_end(&mut decoded)
+            .unwrap();
+
+        (encoded, decoded)
+    }
+
+    #[test]
+    fn with_dictionary() {
+        let json = br#"{"id":1,"kind":"event","status":"ok"}"#;
+        let dictionary = br#"{"id":,"kind":"event","status":"ok"}{"id":,"kind":"event","#;
+
+        let plain = Zstd::new();
+        let with_dict = Zstd::new().dictionary(&dictionary[..]);
+
+        let (encoded, decoded) = round_trip(&plain, json);
+        assert_eq!(decoded, json);
+        let (encoded_dict, decoded) = round_trip(&with_dict, json);
+        assert_eq!(decoded, json);
+        assert!(encoded_dict.len() < encoded.len());
+
+        // Without the dictionary, decoding fails.
+        let mut out = vec![];
+        let err = plain
+            .decoder(Box::new(io::Cursor::new(encoded_dict)))
+            .read_to_end(&mut out)
+            .unwrap_err();
+        assert!(matches!(Error::from(err), Error::Decompress("zstd", _)));
+    }
+}
@@ -1,23 +1,67 @@
 use std::borrow::Cow;
+use std::collections::HashMap;
 use std::fmt;
 use std::io;
-use std::sync::{Mutex, MutexGuard};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
 
 use cookie_store::CookieStore;
 use http::Uri;
 
 use crate::util::UriExt;
-use crate::Error;
+use crate::{Agent, Error};
 
-#[derive(Debug)]
-pub(crate) struct SharedCookieJar {
-    inner: Mutex<CookieStore>,
+/// A cookie jar that can be shared between requests.
+///
+/// Each agent has one, reached via [`Agent::cookie_jar_lock`][crate::Agent::cookie_jar_lock].
+/// Separate jars are used with [`RequestBuilder::cookie_jar()`][crate::RequestBuilder::cookie_jar]
+/// to keep the cookies of some requests apart from the agent's, while still
+/// sharing the agent's connection pool.
+///
+/// Cloning the jar is cheap, and the clones share the same cookies.
+///
+/// ```
+/// use ureq::SharedCookieJar;
+///
+/// let tenant_jar = SharedCookieJar::new();
+///
+/// let res = ureq::get("http://httpbin.org/cookies/set?session=1")
+///     .cookie_jar(tenant_jar.clone())
+///     .call()?;
+///
+/// let names: Vec<_> = tenant_jar.lock().iter().map(|c| c.name().to_string()).collect();
+/// # Ok::<_, ureq::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct SharedCookieJar {
+    inner: Arc<Mutex<CookieStore>>,
+}
+
+/// Which cookie jar a request uses.
+#[derive(Debug, Clone)]
+pub(crate) enum CookieJarChoice {
+    /// The jar of the agent.
+    Agent,
+    /// No cookies are sent or stored.
+    None,
+    /// A separate jar.
+    Jar(SharedCookieJar),
+}
+
+impl CookieJarChoice {
+    pub(crate) fn jar<'a>(&'a self, agent: &'a Agent) -> Option<&'a SharedCookieJar> {
+        match self {
+            CookieJarChoice::Agent => Some(&agent.jar),
+            CookieJarChoice::None => None,
+            CookieJarChoice::Jar(v) => Some(v),
+        }
+    }
 }
 
 /// Collection of cookies.
 ///
-/// The jar is accessed using [`Agent::cookie_jar_lock`][crate::Agent::cookie_jar_lock].
-/// It can be saved and loaded.
+/// The jar is accessed using [`Agent::cookie_jar_lock`][crate::Agent::cookie_jar_lock]
+/// or [`SharedCookieJar::lock()`]. It can be saved and loaded.
 pub struct CookieJar<'a>(MutexGuard<'a, CookieStore>);
 
 /// Representation of an HTTP cookie.
@@ -80,7 +124,51 @@ impl<'a> Cookie<'a> {
         }
     }
 
-    #[cfg(test)]
+    /// The `Domain` attribute, if set.
+    ///
+    /// Cookies without it are only sent to the host that set them.
+    pub fn domain(&self) -> Option<&str> {
+        self.as_cookie_store().domain()
+    }
+
+    /// The `Path` attribute, if set.
+    pub fn path(&self) -> Option<&str> {
+        self.as_cookie_store().path()
+    }
+
+    /// Whether the `Secure` attribute is set.
+    pub fn secure(&self) -> bool {
+        self.as_cookie_store().secure().unwrap_or(false)
+    }
+
+    /// Whether the `HttpOnly` attribute is set.
+    pub fn http_only(&self) -> bool {
+        self.as_cookie_store().http_only().unwrap_or(false)
+    }
+
+    /// The `SameSite` attribute, if set.
+    pub fn same_site(&self) -> Option<SameSite> {
+        self.as_cookie_store().same_site().map(|s| match s {
+            cookie::SameSite::Strict => SameSite::Strict,
+            cookie::SameSite::Lax => SameSite::Lax,
+            cookie::SameSite::None => SameSite::None,
+        })
+    }
+
+    /// The `Max-Age` attribute, if set.
+    ///
+    /// A negative max age is returned as zero.
+    pub fn max_age(&self) -> Option<Duration> {
+        self.as_cookie_store()
+            .max_age()
+            .map(|d| Duration::from_secs(d.whole_seconds().max(0) as u64))
+    }
+
+    /// Whether the `Partitioned` attribute is set (CHIPS).
+    pub fn partitioned(&self) -> bool {
+        self.as_cookie_store().partitioned().unwrap_or(false)
+    }
+
     fn as_cookie_store(&self) -> &cookie_store::Cookie<'a> {
         match &self.0 {
             CookieInner::Borrowed(v) => v,
@@ -89,6 +177,17 @@ impl<'a> Cookie<'a> {
     }
 }
 
+/// The `SameSite` attribute of a [`Cookie`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SameSite {
+    /// Only sent with requests from the same site.
+    Strict,
+    /// Also sent when navigating to the site from elsewhere.
+    Lax,
+    /// Sent with all requests. Requires `Secure`.
+    None,
+}
+
 impl Cookie<'static> {
     fn into_owned(self) -> cookie_store::Cookie<'static> {
         match self.0 {
@@ -153,24 +252,36 @@ impl<'a> CookieJar<'a> {
         &mut self,
         iter: impl Iterator<Item = Cookie<'b>>,
         uri: &Uri,
+        limits: CookieLimits,
     ) {
         let url = uri.try_into_url().expect("uri to be a url");
         let raw_cookies = iter.map(|c| c.0.into_static().into());
         self.0.store_response_cookies(raw_cookies, &url);
+        evict(&mut self.0, limits);
     }
 
     /// Release the cookie jar.
     pub fn release(self) {}
 }
 
+impl Default for SharedCookieJar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl SharedCookieJar {
-    pub(crate) fn new() -> Self {
+    /// Creates an empty cookie jar.
+    pub fn new() -> Self {
         SharedCookieJar {
-            inner: Mutex::new(CookieStore::new()),
+            inner: Arc::new(Mutex::new(CookieStore::new())),
         }
     }
 
-    pub(crate) fn lock(&self) -> CookieJar<'_> {
+    /// Lock the jar to persist and manipulate the cookies.
+    ///
+    /// Requests using this jar block until the lock is released.
+    pub fn lock(&self) -> CookieJar<'_> {
         let lock = self.inner.lock().unwrap();
         CookieJar(lock)
     }
@@ -205,6 +316,98 @@ impl SharedCookieJar {
     }
 }
 
+/// Storage limits of a cookie jar, see [RFC 6265 6.1][1].
+///
+/// [1]: https://datatracker.ietf.org/doc/html/rfc6265#section-6.1
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct CookieLimits {
+    pub per_domain: usize,
+    pub total: usize,
+}
+
+/// Remove cookies over the limits.
+///
+/// Expired cookies go first, then the cookies of domains over their limit, then
+/// cookies of any domain until under the total limit. Within each step, the
+/// cookies expiring soonest are removed first, with session cookies last and
+/// ties broken by the oldest stored.
+fn evict(store: &mut CookieStore, limits: CookieLimits) {
+    let expired: Vec<_> = store
+        .iter_any()
+        .filter(|c| c.is_expired())
+        .map(CookieKey::new)
+        .collect();
+
+    for key in expired {
+        key.remove(store);
+    }
+
+    // Stable sort keeps insertion order (the preserve_order feature) for ties.
+    let mut keys: Vec<_> = store.iter_any().map(CookieKey::new).collect();
+    keys.sort_by_key(|k| k.expires);
+
+    let mut per_domain: HashMap<&str, usize> = HashMap::new();
+    for key in &keys {
+        *per_domain.entry(&key.domain).or_default() += 1;
+    }
+
+    let mut to_remove = vec![];
+    let mut kept = vec![];
+
+    for key in &keys {
+        let count = per_domain.get_mut(key.domain.as_str()).unwrap();
+        if *count > limits.per_domain {
+            *count -= 1;
+            to_remove.push(key);
+        } else {
+            kept.push(key);
+        }
+    }
+
+    let over_total = kept.len().saturating_sub(limits.total);
+    to_remove.extend(kept.into_iter().take(over_total));
+
+    if !to_remove.is_empty() {
+        debug!("Evict {} cookies over the jar limits", to_remove.len());
+    }
+
+    for key in to_remove {
+        key.remove(store);
+    }
+}
+
+struct CookieKey {
+    domain: String,
+    path: String,
+    name: String,
+    /// Session cookies sort last.
+    expires: (bool, i64),
+}
+
+impl CookieKey {
+    fn new(c: &cookie_store::Cookie<'_>) -> Self {
+        let expires = match &c.expires {
+            cookie_store::CookieExpiration::AtUtc(t) => (false, t.unix_timestamp()),
+            cookie_store::CookieExpiration::SessionEnd => (true, 0),
+        };
+
+        CookieKey {
+            domain: c
+                .domain
+                .as_cow()
+                .map(|d| d.into_owned())
+                .unwrap_or_default(),
+            path: c.path.as_ref().to_string(),
+            name: c.name().to_string(),
+            expires,
+        }
+    }
+
+    fn remove(&self, store: &mut CookieStore) {
+        store.remove(&self.domain, &self.path, &self.name);
+    }
+}
+
 fn is_cookie_rfc_compliant(cookie: &cookie_store::Cookie) -> bool {
     // https://tools.ietf.org/html/rfc6265#page-9
     // set-cookie-header = "Set-Cookie:" SP set-cookie-string
@@ -314,4 +517,65 @@ mod test {
         let cookie = Cookie::parse("name=value", &uri()).unwrap();
         assert!(is_cookie_rfc_compliant(cookie.as_cookie_store()));
     }
+
+    #[test]
+    fn evict_over_limits() {
+        let jar = SharedCookieJar::new();
+        let mut jar = jar.lock();
+        let a = Uri::from_static("https://a.test");
+        let b = Uri::from_static("https://b.test");
+
+        let cookies = [
+            "s1=1",
+            "p1=1; Max-Age=100",
+            "p2=1; Max-Age=50",
+            "p3=1; Max-Age=200",
+        ];
+        let iter = cookies.iter().map(|c| Cookie::parse(*c, &a).unwrap());
+        let limits = CookieLimits {
+            per_domain: 2,
+            total: 3,
+        };
+        jar.store_response_cookies(iter, &a, limits);
+
+        // The soonest expiring go first, session cookies last.
+        let mut names: Vec<_> = jar.iter().map(|c| c.name().to_string()).collect();
+        names.sort();
+        assert_eq!(names, ["p3", "s1"]);
+
+        let iter = ["x=1; Max-Age=10", "y=1; Max-Age=500"]
+            .iter()
+            .map(|c| Cookie::parse(*c, &b).unwrap());
+        jar.store_response_cookies(iter, &b, limits);
+
+        let mut names: Vec<_> = jar.iter().map(|c| c.name().to_string()).collect();
+        names.sort();
+        assert_eq!(names, ["p3", "s1", "y"]);
+    }
+
+    #[test]
+    fn cookie_attributes() {
+        let cookie = Cookie::parse(
+            "id=a3fWa; Max-Age=2592000; Domain=example.test; Path=/docs; \
+            Secure; HttpOnly; SameSite=None; Partitioned",
+            &uri(),
+        )
+        .unwrap();
+
+        assert_eq!(cookie.domain(), Some("example.test"));
+        assert_eq!(cookie.path(), Some("/docs"));
+        assert!(cookie.secure());
+        assert!(cookie.http_only());
+        assert_eq!(cookie.same_site(), Some(SameSite::None));
+        assert_eq!(cookie.max_age(), Some(Duration::from_secs(2592000)));
+        assert!(cookie.partitioned());
+
+        // The attributes survive storing in the jar.
+        let jar = SharedCookieJar::new();
+        let mut jar = jar.lock();
+        jar.insert(cookie, &uri()).unwrap();
+        let stored = jar.get("example.test", "/docs", "id").unwrap();
+        assert_eq!(stored.same_site(), Some(SameSite::None));
+        assert!(stored.partitioned());
+    }
 }
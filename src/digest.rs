@@ -0,0 +1,239 @@
+//! HTTP Digest authentication ([RFC 7616](https://www.rfc-editor.org/rfc/rfc7616)).
+
+use md5::{Digest, Md5};
+use sha2::Sha256;
+
+use crate::util::hex;
+
+/// A `Digest` challenge, as found in `WWW-Authenticate` or `Proxy-Authenticate`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct DigestChallenge {
+    realm: String,
+    nonce: String,
+    opaque: Option<String>,
+    algorithm: Algorithm,
+    /// Whether the challenge has `qop=auth`. We don't do `auth-int`.
+    qop_auth: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Algorithm {
+    Md5,
+    Md5Sess,
+    Sha256,
+    Sha256Sess,
+}
+
+impl DigestChallenge {
+    /// Parse a `Digest` challenge.
+    ///
+    /// `None` if the header isn't a `Digest` challenge, or if it asks for an
+    /// algorithm or quality of protection we can't do.
+    pub fn parse(header: &str) -> Option<Self> {
+        let (scheme, rest) = header.trim().split_once(' ')?;
+
+        if !scheme.eq_ignore_ascii_case("digest") {
+            return None;
+        }
+
+        let mut realm = None;
+        let mut nonce = None;
+        let mut opaque = None;
+        let mut algorithm = Algorithm::Md5;
+        let mut qop = None;
+
+        for (name, value) in params(rest) {
+            match name.to_ascii_lowercase().as_str() {
+                "realm" => realm = Some(value),
+                "nonce" => nonce = Some(value),
+                "opaque" => opaque = Some(value),
+                "qop" => qop = Some(value),
+                "algorithm" => {
+                    algorithm = match value.to_ascii_uppercase().as_str() {
+                        "MD5" => Algorithm::Md5,
+                        "MD5-SESS" => Algorithm::Md5Sess,
+                        "SHA-256" => Algorithm::Sha256,
+                        "SHA-256-SESS" => Algorithm::Sha256Sess,
+                        _ => {
+                            debug!("Unsupported digest algorithm: {}", value);
+                            return None;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let qop_auth = match qop {
+            None => false,
+            Some(v) if v.split(',').any(|q| q.trim().eq_ignore_ascii_case("auth")) => true,
+            Some(v) => {
+                debug!("Unsupported digest qop: {}", v);
+                return None;
+            }
+        };
+
+        Some(DigestChallenge {
+            realm: realm?,
+            nonce: nonce?,
+            opaque,
+            algorithm,
+            qop_auth,
+        })
+    }
+
+    /// The value of an `Authorization` or `Proxy-Authorization` answering the challenge.
+    ///
+    /// The client nonce is made from `random`.
+    pub fn authorization(
+        &self,
+        method: &str,
+        uri: &str,
+        username: &str,
+        password: &str,
+        random: u64,
+    ) -> String {
+        let cnonce = format!("{:016x}", random);
+        self.authorization_with(method, uri, username, password, &cnonce)
+    }
+
+    fn authorization_with(
+        &self,
+        method: &str,
+        uri: &str,
+        username: &str,
+        password: &str,
+        cnonce: &str,
+    ) -> String {
+        // Each challenge is answered once, hence the nonce count is always 1.
+        const NC: &str = "00000001";
+
+        let h = |s: String| match self.algorithm {
+            Algorithm::Md5 | Algorithm::Md5Sess => hex(&Md5::digest(s.as_bytes())),
+            Algorithm::Sha256 | Algorithm::Sha256Sess => hex(&Sha256::digest(s.as_bytes())),
+        };
+
+        let mut ha1 = h(format!("{}:{}:{}", username, self.realm, password));
+        if matches!(self.algorithm, Algorithm::Md5Sess | Algorithm::Sha256Sess) {
+            ha1 = h(format!("{}:{}:{}", ha1, self.nonce, cnonce));
+        }
+
+        let ha2 = h(format!("{}:{}", method, uri));
+
+        let response = if self.qop_auth {
+            h(format!(
+                "{}:{}:{}:{}:auth:{}",
+                ha1, self.nonce, NC, cnonce, ha2
+            ))
+        } else {
+            h(format!("{}:{}:{}", ha1, self.nonce, ha2))
+        };
+
+        let algorithm = match self.algorithm {
+            Algorithm::Md5 => "MD5",
+            Algorithm::Md5Sess => "MD5-sess",
+            Algorithm::Sha256 => "SHA-256",
+            Algorithm::Sha256Sess => "SHA-256-sess",
+        };
+
+        let mut value = format!(
+            "Digest username=\"{}\", realm=\"{}\", nonce=\"{}\", uri=\"{}\", \
+            algorithm={}, response=\"{}\"",
+            quote(username),
+            quote(&self.realm),
+            quote(&self.nonce),
+            quote(uri),
+            algorithm,
+            response
+        );
+
+        if self.qop_auth {
+            value.push_str(&format!(", qop=auth, nc={}, cnonce=\"{}\"", NC, cnonce));
+        }
+
+        if let Some(opaque) = &self.opaque {
+            value.push_str(&format!(", opaque=\"{}\"", quote(opaque)));
+        }
+
+        value
+    }
+}
+
+/// Parse the `name=value` params of a challenge, where the value is a token or a
+/// quoted string.
+fn params(s: &str) -> Vec<(String, String)> {
+    let mut params = vec![];
+    let mut chars = s.chars().peekable();
+
+    loop {
+        while matches!(chars.peek(), Some(c) if *c == ',' || c.is_whitespace()) {
+            chars.next();
+        }
+
+        let name: String = chars.by_ref().take_while(|c| *c != '=').collect();
+        if name.is_empty() {
+            break;
+        }
+
+        let mut value = String::new();
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => value.extend(chars.next()),
+                    '"' => break,
+                    c => value.push(c),
+                }
+            }
+        } else {
+            while let Some(c) = chars.next_if(|c| *c != ',') {
+                value.push(c);
+            }
+        }
+
+        params.push((name.trim().to_string(), value.trim().to_string()));
+    }
+
+    params
+}
+
+fn quote(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rfc2617_example() {
+        let challenge = DigestChallenge::parse(
+            "Digest realm=\"testrealm@host.com\", qop=\"auth,auth-int\", \
+            nonce=\"dcd98b7102dd2f0e8b11d0f600bfb0c093\", \
+            opaque=\"5ccc069c403ebaf9f0171e9517f40e41\"",
+        )
+        .unwrap();
+
+        let value = challenge.authorization_with(
+            "GET",
+            "/dir/index.html",
+            "Mufasa",
+            "Circle Of Life",
+            "0a4f113b",
+        );
+
+        assert!(value.starts_with("Digest username=\"Mufasa\""));
+        assert!(value.contains("response=\"6629fae49393a05397450978507c4ef1\""));
+        assert!(value.contains("qop=auth, nc=00000001, cnonce=\"0a4f113b\""));
+        assert!(value.contains("opaque=\"5ccc069c403ebaf9f0171e9517f40e41\""));
+    }
+
+    #[test]
+    fn not_digest() {
+        assert!(DigestChallenge::parse("Basic realm=\"proxy\"").is_none());
+        assert!(DigestChallenge::parse("Digest realm=\"a\", nonce=\"b\", qop=auth-int").is_none());
+        assert!(
+            DigestChallenge::parse("Digest realm=\"a\", nonce=\"b\", algorithm=SHA-512").is_none()
+        );
+    }
+}
@@ -0,0 +1,408 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+
+use http::{header, HeaderValue, Request, Response, StatusCode, Uri};
+
+use crate::{Agent, Body, Error, IntoUri, ResponseExt};
+
+/// Download of a large file in parallel ranges.
+///
+/// Created by [`Agent::download()`]. If the server supports range requests
+/// (`Accept-Ranges: bytes`) and tells the length of the file, the file is split
+/// into segments that are fetched on parallel connections and written straight
+/// into place in the target file.
+///
+/// Every range request carries the `ETag` (or `Last-Modified`) of the file in an
+/// `If-Range` header. If the file changes on the server during the download, the
+/// server sends the entire new file instead of the range, and the download fails
+/// with [`Error::ResourceChanged`].
+///
+/// The progress is kept in a file next to the target, named like the target with
+/// an added `.ureq-state`. If a download fails, running it again resumes where it
+/// stopped, provided the file on the server is unchanged. The state file is removed
+/// when the download completes.
+///
+/// Servers without range support get a plain download.
+///
+/// ```no_run
+/// use ureq::Agent;
+///
+/// let agent = Agent::new_with_defaults();
+///
+/// let len = agent
+///     .download("https://example.test/big.iso")
+///     .segments(8)
+///     .to_file("big.iso")?;
+/// # Ok::<_, ureq::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct Download {
+    agent: Agent,
+    uri: Result<Uri, Error>,
+    segments: u64,
+    min_segment_size: u64,
+}
+
+impl Download {
+    pub(crate) fn new(agent: Agent, uri: impl IntoUri) -> Self {
+        Download {
+            agent,
+            uri: uri.into_uri(),
+            segments: 4,
+            min_segment_size: 1024 * 1024,
+        }
+    }
+
+    /// Number of parallel ranges (and connections) to use.
+    ///
+    /// Defaults to `4`.
+    pub fn segments(mut self, v: usize) -> Self {
+        self.segments = v.max(1) as u64;
+        self
+    }
+
+    /// Smallest size of a range.
+    ///
+    /// Smaller files are split in fewer segments, down to a single one.
+    ///
+    /// Defaults to 1MB.
+    pub fn min_segment_size(mut self, v: u64) -> Self {
+        self.min_segment_size = v.max(1);
+        self
+    }
+
+    /// Download to a file.
+    ///
+    /// Returns the length of the file.
+    pub fn to_file(self, path: impl AsRef<Path>) -> Result<u64, Error> {
+        let uri = self.uri?;
+        let path = path.as_ref();
+        let state_path = state_path(path);
+
+        let head = self.agent.run(identity(Request::head(uri.clone()))?)?;
+
+        let accepts_ranges = head
+            .headers()
+            .get(header::ACCEPT_RANGES)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.split(',').any(|r| r.trim().eq_ignore_ascii_case("bytes")))
+            .unwrap_or(false);
+
+        let Some(length) = head.content_length().filter(|_| accepts_ranges) else {
+            debug!("No range support, plain download");
+            let _ = fs::remove_file(&state_path);
+            return plain_download(&self.agent, uri, path);
+        };
+
+        let validator = validator(&head);
+
+        let resumed = validator
+            .as_ref()
+            .and_then(|v| State::load(&state_path, v, length));
+
+        let state = match resumed {
+            Some(state) if path.exists() => {
+                debug!("Resume download from {:?}", state_path);
+                state
+            }
+            _ => {
+                let count = (length / self.min_segment_size).clamp(1, self.segments);
+                let file = File::create(path)?;
+                file.set_len(length)?;
+                State::new(validator.unwrap_or_default(), length, count)
+            }
+        };
+
+        let if_range = Some(&state.validator)
+            .filter(|v| !v.is_empty())
+            .and_then(|v| HeaderValue::from_str(v).ok());
+
+        let results: Vec<Result<(), Error>> = thread::scope(|s| {
+            let handles: Vec<_> = state
+                .segments
+                .iter()
+                .filter(|seg| !seg.is_done())
+                .map(|seg| {
+                    let agent = &self.agent;
+                    let uri = &uri;
+                    let if_range = if_range.as_ref();
+                    s.spawn(move || fetch_segment(agent, uri, if_range, seg, path, length))
+                })
+                .collect();
+
+            handles
+                .into_iter()
+                .map(|h| h.join().expect("download thread not to panic"))
+                .collect()
+        });
+
+        if let Some(err) = results.into_iter().find_map(Result::err) {
+            if !state.validator.is_empty() {
+                if let Err(e) = state.save(&state_path) {
+                    debug!("Failed to save download state: {}", e);
+                }
+            }
+            return Err(err);
+        }
+
+        let _ = fs::remove_file(&state_path);
+
+        Ok(length)
+    }
+}
+
+/// Request a range and write it in place in the file.
+fn fetch_segment(
+    agent: &Agent,
+    uri: &Uri,
+    if_range: Option<&HeaderValue>,
+    segment: &Segment,
+    path: &Path,
+    length: u64,
+) -> Result<(), Error> {
+    let start = segment.start + segment.done.load(Ordering::SeqCst);
+
+    let mut builder = Request::get(uri.clone()).header(
+        header::RANGE,
+        format!("bytes={}-{}", start, segment.end - 1),
+    );
+    if let Some(v) = if_range {
+        builder = builder.header(header::IF_RANGE, v.clone());
+    }
+
+    let response = agent.run(identity(builder)?)?;
+
+    if response.status() != StatusCode::PARTIAL_CONTENT {
+        return Err(Error::ResourceChanged);
+    }
+
+    let expected = format!("bytes {}-{}/{}", start, segment.end - 1, length);
+    let content_range = response
+        .headers()
+        .get(header::CONTENT_RANGE)
+        .and_then(|v| v.to_str().ok());
+
+    if content_range != Some(expected.as_str()) {
+        return Err(Error::ResourceChanged);
+    }
+
+    let mut file = OpenOptions::new().write(true).open(path)?;
+    file.seek(SeekFrom::Start(start))?;
+
+    let mut writer = ProgressWriter {
+        file,
+        done: &segment.done,
+    };
+
+    let mut reader = response.into_body().into_raw_reader();
+    let amount = io::copy(&mut reader, &mut writer)?;
+
+    if start + amount != segment.end {
+        return Err(Error::disconnected());
+    }
+
+    Ok(())
+}
+
+fn plain_download(agent: &Agent, uri: Uri, path: &Path) -> Result<u64, Error> {
+    let response = agent.run(identity(Request::get(uri))?)?;
+    let mut file = File::create(path)?;
+    let mut reader = response.into_body().into_raw_reader();
+    Ok(io::copy(&mut reader, &mut file)?)
+}
+
+/// Ranges refer to the bytes as sent, so ask the server not to compress.
+fn identity(builder: http::request::Builder) -> Result<Request<()>, Error> {
+    Ok(builder
+        .header(header::ACCEPT_ENCODING, "identity")
+        .body(())?)
+}
+
+/// Strong ETag or Last-Modified, usable with If-Range.
+fn validator(response: &Response<Body>) -> Option<String> {
+    let headers = response.headers();
+    let etag = headers
+        .get(header::ETAG)
+        .and_then(|v| v.to_str().ok())
+        // Weak ETags are not allowed in If-Range.
+        .filter(|v| !v.starts_with("W/"));
+    let last_modified = headers
+        .get(header::LAST_MODIFIED)
+        .and_then(|v| v.to_str().ok());
+    etag.or(last_modified).map(|v| v.to_string())
+}
+
+fn state_path(path: &Path) -> PathBuf {
+    let mut s = path.as_os_str().to_owned();
+    s.push(".ureq-state");
+    PathBuf::from(s)
+}
+
+struct ProgressWriter<'a> {
+    file: File,
+    done: &'a AtomicU64,
+}
+
+impl<'a> Write for ProgressWriter<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.file.write(buf)?;
+        self.done.fetch_add(n as u64, Ordering::SeqCst);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Progress of a download, saved to resume it later.
+struct State {
+    validator: String,
+    length: u64,
+    segments: Vec<Segment>,
+}
+
+/// Range `start..end` of the file, of which `done` bytes are written.
+struct Segment {
+    start: u64,
+    end: u64,
+    done: AtomicU64,
+}
+
+impl Segment {
+    fn is_done(&self) -> bool {
+        self.start + self.done.load(Ordering::SeqCst) >= self.end
+    }
+}
+
+const STATE_HEADER: &str = "ureq-download 1";
+
+impl State {
+    fn new(validator: String, length: u64, count: u64) -> Self {
+        let size = length / count;
+        let segments = (0..count)
+            .map(|i| Segment {
+                start: i * size,
+                end: if i == count - 1 {
+                    length
+                } else {
+                    (i + 1) * size
+                },
+                done: AtomicU64::new(0),
+            })
+            .collect();
+
+        State {
+            validator,
+            length,
+            segments,
+        }
+    }
+
+    /// Load a saved state, if it is for the same version of the file.
+    fn load(path: &Path, validator: &str, length: u64) -> Option<State> {
+        let s = fs::read_to_string(path).ok()?;
+        let mut lines = s.lines();
+
+        if lines.next()? != STATE_HEADER
+            || lines.next()? != validator
+            || lines.next()?.parse::<u64>().ok()? != length
+        {
+            return None;
+        }
+
+        let segments = lines
+            .map(|l| {
+                let mut n = l.split(' ').map(|n| n.parse::<u64>().ok());
+                let (start, end, done) = (n.next()??, n.next()??, n.next()??);
+                Some(Segment {
+                    start,
+                    end,
+                    done: AtomicU64::new(done.min(end - start)),
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        Some(State {
+            validator: validator.to_string(),
+            length,
+            segments,
+        })
+    }
+
+    fn save(&self, path: &Path) -> io::Result<()> {
+        let mut s = format!("{}\n{}\n{}\n", STATE_HEADER, self.validator, self.length);
+        for seg in &self.segments {
+            let done = seg.done.load(Ordering::SeqCst);
+            s.push_str(&format!("{} {} {}\n", seg.start, seg.end, done));
+        }
+        fs::write(path, s)
+    }
+}
+
+#[cfg(all(test, feature = "_test"))]
+mod test {
+    use super::*;
+    use crate::test::init_test_log;
+
+    #[test]
+    fn segments_cover_file() {
+        let state = State::new("x".into(), 1003, 4);
+        let ranges: Vec<_> = state.segments.iter().map(|s| (s.start, s.end)).collect();
+        assert_eq!(ranges, [(0, 250), (250, 500), (500, 750), (750, 1003)]);
+    }
+
+    #[test]
+    fn download_in_ranges() {
+        init_test_log();
+        let dir = std::env::temp_dir().join(format!("ureq-download-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("ranged");
+
+        let len = Agent::new_with_defaults()
+            .download("http://example.org/ranged/1000")
+            .segments(3)
+            .min_segment_size(100)
+            .to_file(&path)
+            .unwrap();
+
+        let data = fs::read(&path).unwrap();
+        let expected: Vec<u8> = (0..1000).map(|i| (i % 251) as u8).collect();
+
+        assert_eq!(len, 1000);
+        assert_eq!(data, expected);
+        assert!(!state_path(&path).exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn resume_from_state() {
+        init_test_log();
+        let dir = std::env::temp_dir().join(format!("ureq-resume-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("ranged");
+
+        // Pretend the first half was downloaded earlier.
+        let expected: Vec<u8> = (0..1000).map(|i| (i % 251) as u8).collect();
+        let mut partial = expected[..500].to_vec();
+        partial.resize(1000, 0);
+        fs::write(&path, partial).unwrap();
+
+        let state = State::new("\"r1000\"".into(), 1000, 2);
+        state.segments[0].done.store(500, Ordering::SeqCst);
+        state.save(&state_path(&path)).unwrap();
+
+        Agent::new_with_defaults()
+            .download("http://example.org/ranged/1000")
+            .to_file(&path)
+            .unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), expected);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
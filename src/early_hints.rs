@@ -0,0 +1,288 @@
+use std::fmt;
+
+use hoot::parser::try_parse_response;
+use http::{header, Uri};
+
+use crate::config::Redaction;
+use crate::headers::MAX_RESPONSE_HEADERS;
+use crate::util::DebugUri;
+use crate::Error;
+
+/// Links received in `103 Early Hints` responses.
+///
+/// A server can send one or more informational `103` responses before the final
+/// response. These carry `Link` headers pointing out resources (stylesheets, scripts etc)
+/// the client is likely to need, which means they can be fetched while the server is
+/// still preparing the final response.
+///
+/// ureq collects the links and makes them available as an extension on the final response.
+///
+/// ```
+/// use ureq::EarlyHints;
+///
+/// let response = ureq::get("http://httpbin.org/get")
+///     .call()?;
+///
+/// if let Some(hints) = response.extensions().get::<EarlyHints>() {
+///     for link in hints.links() {
+///         println!("Preload {} ({:?})", link.uri(), link.rel());
+///     }
+/// }
+/// # Ok::<_, ureq::Error>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct EarlyHints {
+    links: Vec<LinkHint>,
+}
+
+/// A single link from a `Link` header in a `103 Early Hints` response.
+///
+/// For the header `Link: </style.css>; rel=preload; as=style`, the uri would be
+/// `/style.css` resolved against the request uri, and the parameters `rel=preload`
+/// and `as=style`.
+#[derive(Clone)]
+pub struct LinkHint {
+    uri: Uri,
+    params: Vec<(String, String)>,
+}
+
+impl EarlyHints {
+    /// All links received, in the order they were sent.
+    pub fn links(&self) -> &[LinkHint] {
+        &self.links
+    }
+
+    /// Links with a `rel` parameter, such as `preload` or `preconnect`.
+    pub fn links_with_rel<'a>(&'a self, rel: &'a str) -> impl Iterator<Item = &'a LinkHint> {
+        self.links.iter().filter(move |l| {
+            l.rel()
+                .map(|r| r.eq_ignore_ascii_case(rel))
+                .unwrap_or(false)
+        })
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.links.is_empty()
+    }
+
+    /// Attempt to read a `103` response from the start of `input`.
+    ///
+    /// Returns the amount of input used, or `0` if the input does not start with a
+    /// (complete) `103` response.
+    pub(crate) fn try_consume(&mut self, input: &[u8], base: &Uri) -> Result<usize, Error> {
+        const MIN_LEN: usize = "HTTP/1.1 103".len();
+
+        if input.len() < MIN_LEN || &input[8..MIN_LEN] != b" 103" {
+            return Ok(0);
+        }
+
+        let Some((amount, response)) = try_parse_response::<MAX_RESPONSE_HEADERS>(input)? else {
+            return Ok(0);
+        };
+
+        for value in response.headers().get_all(header::LINK) {
+            let Ok(value) = value.to_str() else {
+                continue;
+            };
+
+            for link in split_links(value) {
+                if let Some(hint) = LinkHint::parse(link, base) {
+                    trace!("Early hint: {:?}", hint);
+                    self.links.push(hint);
+                }
+            }
+        }
+
+        Ok(amount)
+    }
+}
+
+impl LinkHint {
+    pub(crate) fn parse(s: &str, base: &Uri) -> Option<LinkHint> {
+        let mut parts = s.split(';');
+
+        let target = parts.next()?.trim();
+        let target = target.strip_prefix('<')?.strip_suffix('>')?;
+
+        let uri = resolve(target, base)?;
+
+        let params = parts
+            .filter_map(|p| {
+                let (k, v) = p.split_once('=')?;
+                let v = v.trim().trim_matches('"');
+                Some((k.trim().to_ascii_lowercase(), v.to_string()))
+            })
+            .collect();
+
+        Some(LinkHint { uri, params })
+    }
+
+    /// The target of the link resolved against the request uri.
+    pub fn uri(&self) -> &Uri {
+        &self.uri
+    }
+
+    /// The `rel` parameter, such as `preload`.
+    pub fn rel(&self) -> Option<&str> {
+        self.param("rel")
+    }
+
+    /// Get a parameter by (case insensitive) name, such as `as` or `crossorigin`.
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// Split a header value with multiple comma separated links.
+///
+/// Commas are allowed inside the `<>` and quoted parameters.
+pub(crate) fn split_links(value: &str) -> impl Iterator<Item = &str> {
+    let mut start = 0;
+    let mut in_uri = false;
+    let mut in_quote = false;
+    let mut splits = vec![];
+
+    for (i, c) in value.char_indices() {
+        match c {
+            '<' if !in_quote => in_uri = true,
+            '>' if !in_quote => in_uri = false,
+            '"' if !in_uri => in_quote = !in_quote,
+            ',' if !in_uri && !in_quote => {
+                splits.push(&value[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    splits.push(&value[start..]);
+
+    splits.into_iter().map(str::trim).filter(|s| !s.is_empty())
+}
+
+pub(crate) fn resolve(target: &str, base: &Uri) -> Option<Uri> {
+    if let Ok(uri) = target.parse::<Uri>() {
+        if uri.scheme().is_some() {
+            return Some(uri);
+        }
+    }
+
+    let path = if target.starts_with('/') {
+        target.to_string()
+    } else {
+        let base_path = base.path();
+        let dir = &base_path[..base_path.rfind('/').map(|i| i + 1).unwrap_or(0)];
+        format!("{}{}", dir, target)
+    };
+
+    Uri::builder()
+        .scheme(base.scheme()?.clone())
+        .authority(base.authority()?.clone())
+        .path_and_query(path)
+        .build()
+        .ok()
+}
+
+impl fmt::Debug for LinkHint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LinkHint")
+            .field("uri", &DebugUri(&self.uri, Redaction::builtin()))
+            .field("params", &self.params)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_early_hints() {
+        let base = Uri::from_static("https://example.test/a/page.html");
+        let input = b"HTTP/1.1 103 Early Hints\r\n\
+            Link: </style.css>; rel=preload; as=style\r\n\
+            Link: <script.js>; rel=preload; as=\"script\", <https://cdn.test/x,y>; rel=preconnect\r\n\
+            \r\n\
+            HTTP/1.1 200 OK\r\n";
+
+        let mut hints = EarlyHints::default();
+        let amount = hints.try_consume(input, &base).unwrap();
+
+        assert_eq!(&input[amount..], b"HTTP/1.1 200 OK\r\n");
+
+        let uris: Vec<_> = hints.links().iter().map(|l| l.uri().to_string()).collect();
+        assert_eq!(
+            uris,
+            [
+                "https://example.test/style.css",
+                "https://example.test/a/script.js",
+                "https://cdn.test/x,y"
+            ]
+        );
+        assert_eq!(hints.links()[1].param("as"), Some("script"));
+        assert_eq!(hints.links_with_rel("preload").count(), 2);
+    }
+
+    #[test]
+    fn many_links() {
+        let base = Uri::from_static("https://example.test/");
+        let mut input = "HTTP/1.1 103 Early Hints\r\n".to_string();
+        for i in 0..100 {
+            input.push_str(&format!("Link: </{}.css>; rel=preload\r\n", i));
+        }
+        input.push_str("\r\n");
+
+        let mut hints = EarlyHints::default();
+        let amount = hints.try_consume(input.as_bytes(), &base).unwrap();
+        assert_eq!(amount, input.len());
+        assert_eq!(hints.links().len(), 100);
+    }
+
+    #[test]
+    fn interim_responses_count_towards_header_size() {
+        use crate::config::Config;
+        use crate::transport::MockConnector;
+        use crate::Agent;
+
+        let hint = "HTTP/1.1 103 Early Hints\r\nLink: </style.css>; rel=preload\r\n\r\n";
+        let response = format!(
+            "{}HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok",
+            hint.repeat(100)
+        );
+
+        let call = |max_header: usize| {
+            let mock = MockConnector::new().route("/", &response);
+            let config = Config::builder()
+                .max_response_header_size(max_header)
+                .build();
+            let agent = Agent::with_parts(config, mock.clone(), mock);
+            agent.get("http://hints.test/").call()
+        };
+
+        let res = call(64 * 1024).unwrap();
+        assert_eq!(
+            res.extensions().get::<EarlyHints>().unwrap().links().len(),
+            100
+        );
+
+        let err = call(1024).unwrap_err();
+        assert!(
+            matches!(err, Error::LargeResponseHeader(_, 1024)),
+            "{:?}",
+            err
+        );
+    }
+
+    #[test]
+    fn not_early_hints() {
+        let base = Uri::from_static("https://example.test/");
+        let mut hints = EarlyHints::default();
+        let amount = hints
+            .try_consume(b"HTTP/1.1 200 OK\r\n\r\n", &base)
+            .unwrap();
+        assert_eq!(amount, 0);
+        assert!(hints.is_empty());
+    }
+}
@@ -1,6 +1,9 @@
+use std::io::Read;
 use std::{fmt, io};
 
-use crate::Timeout;
+use crate::timings::{CallTimings, Timings};
+use crate::transport::ConnectionInfo;
+use crate::{Body, Timeout};
 
 /// Errors from ureq.
 #[derive(Debug)]
@@ -12,6 +15,13 @@ pub enum Error {
     /// This is the default behavior.
     StatusCode(u16),
 
+    /// Like [`Error::StatusCode`], but with the start of the response body.
+    ///
+    /// This is used instead of [`Error::StatusCode`] when
+    /// [`max_error_body_size()`](crate::config::ConfigBuilder::max_error_body_size)
+    /// is set, which lets us report error messages provided by the server.
+    StatusCodeBody(u16, Box<ErrorBody>),
+
     /// Errors arising from the http-crate.
     ///
     /// These errors happen for things like invalid characters in header names.
@@ -20,6 +30,18 @@ pub enum Error {
     /// Error if the URI is missing scheme or host.
     BadUri(String),
 
+    /// A response header value could not be parsed.
+    ///
+    /// The value is the (lowercase) header name. See
+    /// [`ResponseExt::header_as()`](crate::ResponseExt::header_as).
+    BadHeader(String),
+
+    /// A response body that doesn't follow its format.
+    ///
+    /// This is a multipart body, a batch response, gRPC-Web frames or an RPC
+    /// response that can't be parsed. The value describes what is wrong.
+    BadBody(String),
+
     /// An HTTP/1.1 protocol error.
     ///
     /// This can happen if the remote server ends incorrect HTTP data like
@@ -44,12 +66,44 @@ pub enum Error {
     /// redirect the POST body and instead raises this error.
     RedirectFailed,
 
+    /// A redirect loop was detected.
+    ///
+    /// ureq tracks the method, URI and cookies of every request made while
+    /// following redirects. If a redirect leads back to an already visited method
+    /// and URI, with the same cookies to send, the redirects would go on forever.
+    /// The value lists the cycle, starting and ending with the repeated request,
+    /// such as `["GET http://a.test/", "GET http://b.test/", "GET http://a.test/"]`.
+    RedirectLoop(Vec<String>),
+
+    /// A redirect to a scheme other than `http` or `https` was not followed.
+    ///
+    /// See [`ConfigBuilder::redirect_schemes()`](crate::config::ConfigBuilder::redirect_schemes).
+    /// The value is the uri of the redirect.
+    RedirectDenied(String),
+
     /// Error when creating proxy settings.
     InvalidProxyUrl,
 
     /// A connection failed.
     ConnectionFailed,
 
+    /// The resource changed on the server during a ranged download.
+    ///
+    /// The server didn't respond with the requested range of the same version of
+    /// the file. See [`Download`](crate::Download).
+    ResourceChanged,
+
+    /// A resumable upload failed because the server didn't follow the protocol.
+    ///
+    /// See [`Upload`](crate::Upload).
+    Upload(String),
+
+    /// The response content-type doesn't match the body data.
+    ///
+    /// The first value is the declared mime type, the second what the body looks
+    /// like. See [`ContentSniffing`](crate::config::ContentSniffing).
+    ContentMismatch(String, &'static str),
+
     /// A send body (Such as `&str`) is larger than the `content-length` header.
     BodyExceedsLimit(u64),
 
@@ -117,11 +171,48 @@ pub enum Error {
     /// the URI is not https.
     RequireHttpsOnly(String),
 
+    /// The response body doesn't match a checksum in the response headers.
+    ///
+    /// The value names the header, such as `content-md5`. Only checked with
+    /// [`verify_checksums`](crate::config::ConfigBuilder::verify_checksums).
+    #[cfg(feature = "checksums")]
+    ChecksumMismatch(String),
+
+    /// A presigned URL can't be valid for longer than 7 days, the value is the
+    /// asked for number of seconds.
+    ///
+    /// See [`Presigner::presign()`](crate::presign::Presigner::presign).
+    #[cfg(feature = "presign")]
+    PresignExpires(u64),
+
+    /// The setting [`offline_routes`](crate::config::ConfigBuilder::offline_routes) is
+    /// set and the URI matches none of the routes.
+    Offline(String),
+
+    /// The setting [`request_encoding`](crate::config::ConfigBuilder::request_encoding)
+    /// names a codec that isn't added to the config.
+    UnknownCodec(String),
+
+    /// The response doesn't tell its body length unambiguously.
+    ///
+    /// This is both `Content-Length` and `Transfer-Encoding`, `Content-Length`
+    /// with differing values, or a chunk size that can't be read, such as one too
+    /// big for a `u64`. Such responses are a means of request smuggling
+    /// when passing proxies, see
+    /// [`lenient_framing`](crate::config::ConfigBuilder::lenient_framing).
+    AmbiguousFraming(&'static str),
+
     /// The response header, from status up until body, is too big.
     LargeResponseHeader(usize, usize),
 
-    /// Body decompression failed (gzip or brotli).
-    #[cfg(any(feature = "gzip", feature = "brotli"))]
+    /// Middleware subrequests nested too deep.
+    ///
+    /// This is a middleware making subrequests for its own subrequests, see
+    /// [`MiddlewareNext::subrequest()`](crate::middleware::MiddlewareNext::subrequest).
+    SubrequestDepth(u32),
+
+    /// Body decompression failed (gzip, brotli or zstd).
+    #[cfg(any(feature = "gzip", feature = "brotli", feature = "zstd"))]
     Decompress(&'static str, io::Error),
 
     /// Serde JSON error.
@@ -131,6 +222,39 @@ pub enum Error {
     /// Attempt to connect to a CONNECT proxy failed.
     ConnectProxyFailed(String),
 
+    /// A JSON-RPC call got an error object.
+    ///
+    /// A response that isn't valid JSON-RPC is an [`Error::BadBody`].
+    #[cfg(feature = "json")]
+    JsonRpc(Box<crate::JsonRpcError>),
+
+    /// A gRPC-Web call failed with a `grpc-status` code and message.
+    #[cfg(feature = "grpc-web")]
+    Grpc(u32, String),
+
+    /// An XML-RPC call got a fault response, with the fault code and string.
+    ///
+    /// A response that isn't valid XML-RPC is an [`Error::BadBody`].
+    #[cfg(feature = "xmlrpc")]
+    XmlRpc(i32, String),
+
+    /// A SOAP call got a fault response.
+    #[cfg(feature = "soap")]
+    SoapFault(Box<crate::soap::SoapFault>),
+
+    /// An OpenID Connect provider refused a grant, or gave an invalid response.
+    ///
+    /// For an OAuth error response this is the `error` code and the description.
+    #[cfg(feature = "oidc")]
+    Oidc(String),
+
+    /// An error from connecting on, with how far the transfer got.
+    ///
+    /// This is only used when
+    /// [`transfer_stats_on_error()`](crate::config::ConfigBuilder::transfer_stats_on_error)
+    /// is enabled.
+    Transfer(Box<Error>, Box<TransferStats>),
+
     /// hoot made no progress and there is no more input to read.
     ///
     /// We should never see this value.
@@ -140,7 +264,192 @@ pub enum Error {
 
 impl std::error::Error for Error {}
 
+/// The (start of the) body of a response that was turned into an error.
+///
+/// See [`Error::StatusCodeBody`].
+///
+/// ```
+/// use ureq::Agent;
+///
+/// let agent: Agent = Agent::config_builder()
+///     .max_error_body_size(4096)
+///     .build()
+///     .into();
+///
+/// match agent.get("http://httpbin.org/status/500").call() {
+///     Err(ureq::Error::StatusCodeBody(status, body)) => {
+///         println!("{}: {}", status, body.as_str().unwrap_or_default());
+///     }
+///     _ => {}
+/// }
+/// ```
+#[derive(Debug)]
+pub struct ErrorBody {
+    mime_type: Option<String>,
+    data: Vec<u8>,
+    truncated: bool,
+    #[cfg(feature = "json")]
+    json: Option<serde_json::Value>,
+}
+
+impl ErrorBody {
+    pub(crate) fn read(body: &mut Body, max_size: usize) -> ErrorBody {
+        let mime_type = body.mime_type().map(|s| s.to_string());
+
+        // Read one more byte than the max to know whether the body was truncated.
+        let mut data = Vec::new();
+        let result = body
+            .as_reader()
+            .take(max_size as u64 + 1)
+            .read_to_end(&mut data);
+
+        if let Err(e) = result {
+            // Keep whatever we got until the error.
+            debug!("Failed to read error body: {:?}", e);
+        }
+
+        let truncated = data.len() > max_size;
+        data.truncate(max_size);
+
+        #[cfg(feature = "json")]
+        let json = {
+            let is_json = mime_type
+                .as_deref()
+                .map(|m| m == "application/json" || m.ends_with("+json"))
+                .unwrap_or(false);
+
+            if is_json && !truncated {
+                serde_json::from_slice(&data).ok()
+            } else {
+                None
+            }
+        };
+
+        ErrorBody {
+            mime_type,
+            data,
+            truncated,
+            #[cfg(feature = "json")]
+            json,
+        }
+    }
+
+    /// The mime-type of the `content-type` header, such as `application/json`.
+    pub fn mime_type(&self) -> Option<&str> {
+        self.mime_type.as_deref()
+    }
+
+    /// The body data.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// The body data as a string, if it is valid utf-8.
+    pub fn as_str(&self) -> Option<&str> {
+        std::str::from_utf8(&self.data).ok()
+    }
+
+    /// Whether the body was larger than the configured max size.
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// The body parsed as JSON.
+    ///
+    /// This is only attempted for a JSON `content-type` and when the
+    /// body was not truncated.
+    #[cfg(feature = "json")]
+    pub fn json(&self) -> Option<&serde_json::Value> {
+        self.json.as_ref()
+    }
+}
+
+/// How far a request got before failing.
+///
+/// Tells whether the server might have acted on the request. A request with a
+/// complete body could have been processed, even if no response head was received.
+/// Also has the connection and timings, the same as
+/// [`ResponseExt::connection_info()`](crate::ResponseExt::connection_info) and
+/// [`ResponseExt::timings()`](crate::ResponseExt::timings) of a response.
+///
+/// See [`Error::Transfer`].
+///
+/// ```
+/// use ureq::{Agent, Error};
+///
+/// let agent: Agent = Agent::config_builder()
+///     .transfer_stats_on_error(true)
+///     .build()
+///     .into();
+///
+/// match agent.post("http://httpbin.org/post").send("payload") {
+///     Err(Error::Transfer(e, stats)) if !stats.head_received => {
+///         println!("{} after {} body bytes, server may have the request", e, stats.body_sent);
+///     }
+///     _ => {}
+/// }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct TransferStats {
+    /// Whether the request head was sent in full.
+    pub head_sent: bool,
+    /// Bytes of the request body sent, not counting chunk overhead.
+    pub body_sent: u64,
+    /// Whether the response head was received in full.
+    pub head_received: bool,
+    /// Bytes of the response body received, after decoding chunks.
+    pub body_received: u64,
+    /// The connection the request was sent on, such as the server address and TLS version.
+    ///
+    /// `None` when connecting failed.
+    pub connection: Option<ConnectionInfo>,
+    /// How long each phase took, up to the error.
+    pub timings: Timings,
+}
+
+impl TransferStats {
+    /// Wrap the error with the stats, when configured to.
+    pub(crate) fn wrap(&self, enabled: bool, e: Error, timings: &CallTimings) -> Error {
+        if !enabled || matches!(e, Error::Transfer(..)) {
+            return e;
+        }
+        let stats = TransferStats {
+            timings: timings.breakdown(),
+            ..self.clone()
+        };
+        Error::Transfer(Box::new(e), Box::new(stats))
+    }
+}
+
+impl fmt::Display for TransferStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "sent {} body bytes", self.body_sent)?;
+        if self.head_received {
+            write!(f, ", received {} body bytes", self.body_received)
+        } else {
+            write!(f, ", no response")
+        }
+    }
+}
+
 impl Error {
+    /// How far the request got, for [`Error::Transfer`].
+    pub fn transfer_stats(&self) -> Option<&TransferStats> {
+        match self {
+            Error::Transfer(_, stats) => Some(&**stats),
+            _ => None,
+        }
+    }
+
+    /// The error itself, without the stats of an [`Error::Transfer`].
+    pub(crate) fn without_stats(&self) -> &Error {
+        match self {
+            Error::Transfer(e, _) => e,
+            e => e,
+        }
+    }
+
     /// Convert the error into a [`std::io::Error`].
     ///
     /// If the error is [`Error::Io`], we unpack the error. In othe cases we make
@@ -177,15 +486,29 @@ impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Error::StatusCode(v) => write!(f, "http status: {}", v),
+            Error::StatusCodeBody(v, _) => write!(f, "http status: {}", v),
             Error::Http(v) => write!(f, "http: {}", v),
             Error::BadUri(v) => write!(f, "bad uri: {}", v),
+            Error::BadHeader(v) => write!(f, "bad header: {}", v),
+            Error::BadBody(v) => write!(f, "bad body: {}", v),
             Error::Protocol(v) => write!(f, "protocol: {}", v),
             Error::Io(v) => write!(f, "io: {}", v),
             Error::Timeout(v) => write!(f, "timeout: {}", v),
             Error::HostNotFound => write!(f, "host not found"),
             Error::RedirectFailed => write!(f, "redirect failed"),
+            Error::RedirectLoop(v) => write!(f, "redirect loop: {}", v.join(" -> ")),
+            Error::RedirectDenied(v) => write!(f, "redirect denied: {}", v),
             Error::InvalidProxyUrl => write!(f, "invalid proxy url"),
             Error::ConnectionFailed => write!(f, "connection failed"),
+            Error::ResourceChanged => write!(f, "resource changed during ranged download"),
+            Error::Upload(v) => write!(f, "upload: {}", v),
+            Error::ContentMismatch(x, y) => {
+                write!(
+                    f,
+                    "content-type {} doesn't match body that looks like {}",
+                    x, y
+                )
+            }
             Error::BodyExceedsLimit(v) => {
                 write!(f, "the response body is larger than request limit: {}", v)
             }
@@ -208,14 +531,35 @@ impl fmt::Display for Error {
             #[cfg(feature = "charset")]
             Error::UnknownCharset(v) => write!(f, "unknown character set: {}", v),
             Error::RequireHttpsOnly(v) => write!(f, "configured for https only: {}", v),
+            #[cfg(feature = "checksums")]
+            Error::ChecksumMismatch(v) => write!(f, "body checksum mismatch: {}", v),
+            #[cfg(feature = "presign")]
+            Error::PresignExpires(v) => {
+                write!(f, "presign expires in {}s, more than 7 days", v)
+            }
+            Error::Offline(v) => write!(f, "offline, no route for: {}", v),
+            Error::UnknownCodec(v) => write!(f, "unknown content-encoding codec: {}", v),
+            Error::AmbiguousFraming(v) => write!(f, "ambiguous response framing: {}", v),
             Error::LargeResponseHeader(x, y) => {
                 write!(f, "response header is too big: {} > {}", x, y)
             }
-            #[cfg(any(feature = "gzip", feature = "brotli"))]
+            Error::SubrequestDepth(v) => write!(f, "middleware subrequests nested {} deep", v),
+            #[cfg(any(feature = "gzip", feature = "brotli", feature = "zstd"))]
             Error::Decompress(x, y) => write!(f, "{} decompression failed: {}", x, y),
             #[cfg(feature = "json")]
             Error::Json(v) => write!(f, "json: {}", v),
             Error::ConnectProxyFailed(v) => write!(f, "CONNECT proxy failed: {}", v),
+            #[cfg(feature = "json")]
+            Error::JsonRpc(v) => write!(f, "json-rpc: {}", v),
+            #[cfg(feature = "grpc-web")]
+            Error::Grpc(x, y) => write!(f, "grpc status {}: {}", x, y),
+            #[cfg(feature = "xmlrpc")]
+            Error::XmlRpc(x, y) => write!(f, "xml-rpc fault {}: {}", x, y),
+            #[cfg(feature = "soap")]
+            Error::SoapFault(v) => write!(f, "soap fault: {}", v),
+            #[cfg(feature = "oidc")]
+            Error::Oidc(v) => write!(f, "oidc: {}", v),
+            Error::Transfer(x, y) => write!(f, "{} ({})", x, y),
             Error::BodyStalled => write!(f, "body data reading stalled"),
         }
     }
@@ -305,6 +649,142 @@ mod test {
         assert!(matches!(err, Error::StatusCode(500)));
     }
 
+    #[test]
+    #[cfg(feature = "_test")]
+    fn redirect_loop() {
+        use crate::test::init_test_log;
+        use crate::transport::set_handler;
+        init_test_log();
+        set_handler(
+            "/loop_a",
+            302,
+            &[("Location", "http://example.org/loop_b")],
+            &[],
+        );
+        set_handler(
+            "/loop_b",
+            302,
+            &[("Location", "http://example.org/loop_a")],
+            &[],
+        );
+        let err = crate::get("http://example.org/loop_a").call().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "redirect loop: GET http://example.org/loop_a -> \
+            GET http://example.org/loop_b -> GET http://example.org/loop_a"
+        );
+    }
+
+    #[test]
+    #[cfg(all(feature = "_test", feature = "cookies"))]
+    fn redirect_to_self_setting_cookie() {
+        use std::io::Write;
+
+        use crate::test::init_test_log;
+        use crate::transport::set_handler_fn;
+        init_test_log();
+        set_handler_fn("/login", |_uri, req, w| {
+            if req.headers().contains_key("cookie") {
+                write!(w, "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok")
+            } else {
+                write!(
+                    w,
+                    "HTTP/1.1 302 Found\r\n\
+                    Set-Cookie: session=1\r\n\
+                    Location: http://example.org/login\r\n\
+                    Content-Length: 0\r\n\
+                    \r\n"
+                )
+            }
+        });
+        let mut res = crate::get("http://example.org/login").call().unwrap();
+        assert_eq!(res.body_mut().read_to_string().unwrap(), "ok");
+    }
+
+    #[test]
+    #[cfg(all(feature = "_test", feature = "json"))]
+    fn status_code_body() {
+        use crate::test::init_test_log;
+        use crate::transport::set_handler;
+        use crate::Agent;
+        init_test_log();
+        set_handler(
+            "/status_code_body",
+            404,
+            &[
+                ("Content-Type", "application/json"),
+                ("Content-Length", "19"),
+            ],
+            br#"{"message":"nope"} "#,
+        );
+        let agent: Agent = Agent::config_builder()
+            .max_error_body_size(1024)
+            .build()
+            .into();
+        let err = agent
+            .get("http://example.org/status_code_body")
+            .call()
+            .unwrap_err();
+        let Error::StatusCodeBody(404, body) = err else {
+            panic!("expected StatusCodeBody");
+        };
+        assert!(!body.is_truncated());
+        assert_eq!(body.json().unwrap()["message"], "nope");
+    }
+
+    #[test]
+    fn transfer_stats() {
+        use crate::config::Config;
+        use crate::transport::MockConnector;
+        use crate::{Agent, ResponseExt};
+
+        let mock = MockConnector::new().route(
+            "/short",
+            "HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nhello",
+        );
+        let config = Config::builder().transfer_stats_on_error(true).build();
+        let agent = Agent::with_parts(config, mock.clone(), mock);
+
+        let mut res = agent.post("http://example.test/short").send("abc").unwrap();
+        let timings = *res.timings().unwrap();
+        assert!(timings.connect.is_some() && timings.recv_response.is_some());
+
+        let err = res.body_mut().read_to_vec().unwrap_err();
+
+        let Error::Transfer(e, stats) = err else {
+            panic!("expected Transfer");
+        };
+        assert!(matches!(*e, Error::Io(_)));
+        assert!(stats.head_sent && stats.head_received);
+        assert_eq!(stats.body_sent, 3);
+        assert_eq!(stats.body_received, 5);
+        assert!(stats.connection.is_some());
+        assert_eq!(stats.timings.connect, timings.connect);
+        assert!(stats.timings.total >= timings.total);
+    }
+
+    #[test]
+    fn transfer_stats_on_connect() {
+        use crate::config::Config;
+        use crate::transport::MockConnector;
+        use crate::Agent;
+
+        let mock = MockConnector::new();
+        let config = Config::builder().transfer_stats_on_error(true).build();
+        let agent = Agent::with_parts(config, mock.clone(), mock);
+
+        let err = agent.get("http://example.test/").call().unwrap_err();
+
+        let Error::Transfer(e, stats) = &err else {
+            panic!("expected Transfer");
+        };
+        assert!(matches!(**e, Error::ConnectionFailed));
+        assert!(!stats.head_sent && stats.connection.is_none());
+        assert!(stats.timings.resolve.is_some());
+        assert!(stats.timings.connect.is_none());
+        assert!(matches!(err.without_stats(), Error::ConnectionFailed));
+    }
+
     #[test]
     fn ensure_error_size() {
         // This is platform dependent, so we can't be too strict or precise.
@@ -0,0 +1,170 @@
+//! IPv4/IPv6 connection metrics.
+
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+use crate::config::Config;
+
+/// Consecutive failures before a family is reported as failing.
+const FAILING_THRESHOLD: u32 = 3;
+
+/// IP address family.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Family {
+    /// IPv4
+    Ipv4,
+    /// IPv6
+    Ipv6,
+}
+
+impl Family {
+    pub(crate) fn of(addr: &SocketAddr) -> Self {
+        if addr.is_ipv4() {
+            Family::Ipv4
+        } else {
+            Family::Ipv6
+        }
+    }
+}
+
+impl fmt::Display for Family {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Family::Ipv4 => write!(f, "IPv4"),
+            Family::Ipv6 => write!(f, "IPv6"),
+        }
+    }
+}
+
+/// Event about the health of an address family.
+///
+/// See [`ConfigBuilder::on_family_event()`][crate::config::ConfigBuilder::on_family_event].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FamilyEvent {
+    /// Connecting failed for all addresses of one family, and succeeded
+    /// with the other as a last resort.
+    Fallback {
+        /// The family that failed.
+        failed: Family,
+        /// The family that was connected.
+        used: Family,
+    },
+
+    /// A family has failed to connect a number of times in a row.
+    ///
+    /// Reported once per run of failures. A typical cause is a broken IPv6
+    /// path, where the host has an IPv6 address but no working route.
+    Failing {
+        /// The failing family.
+        family: Family,
+        /// Number of failures in a row.
+        consecutive: u32,
+    },
+}
+
+/// Counters of connection attempts per address family.
+///
+/// See [`Agent::family_stats()`][crate::Agent::family_stats].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct FamilyStats {
+    /// Successful connects over IPv4.
+    pub ipv4_connects: u64,
+    /// Failed connects over IPv4.
+    pub ipv4_failures: u64,
+    /// Successful connects over IPv6.
+    pub ipv6_connects: u64,
+    /// Failed connects over IPv6.
+    pub ipv6_failures: u64,
+}
+
+/// Shared counters, one per agent.
+#[derive(Default)]
+pub(crate) struct FamilyCounters {
+    ipv4: Counts,
+    ipv6: Counts,
+}
+
+#[derive(Default)]
+struct Counts {
+    connects: AtomicU64,
+    failures: AtomicU64,
+    consecutive: AtomicU32,
+}
+
+impl FamilyCounters {
+    fn counts(&self, family: Family) -> &Counts {
+        match family {
+            Family::Ipv4 => &self.ipv4,
+            Family::Ipv6 => &self.ipv6,
+        }
+    }
+
+    pub fn connected(&self, family: Family) {
+        let counts = self.counts(family);
+        counts.connects.fetch_add(1, Ordering::Relaxed);
+        counts.consecutive.store(0, Ordering::Relaxed);
+    }
+
+    /// Count a failure, returns an event if the family now counts as failing.
+    pub fn failed(&self, family: Family) -> Option<FamilyEvent> {
+        let counts = self.counts(family);
+        counts.failures.fetch_add(1, Ordering::Relaxed);
+        let consecutive = counts.consecutive.fetch_add(1, Ordering::Relaxed) + 1;
+
+        (consecutive == FAILING_THRESHOLD).then_some(FamilyEvent::Failing {
+            family,
+            consecutive,
+        })
+    }
+
+    pub fn stats(&self) -> FamilyStats {
+        FamilyStats {
+            ipv4_connects: self.ipv4.connects.load(Ordering::Relaxed),
+            ipv4_failures: self.ipv4.failures.load(Ordering::Relaxed),
+            ipv6_connects: self.ipv6.connects.load(Ordering::Relaxed),
+            ipv6_failures: self.ipv6.failures.load(Ordering::Relaxed),
+        }
+    }
+}
+
+pub(crate) fn emit(config: &Config, event: FamilyEvent) {
+    debug!("{:?}", event);
+
+    if let Some(hook) = &config.on_family_event {
+        hook(&event);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn failing_after_threshold() {
+        let counters = FamilyCounters::default();
+
+        assert_eq!(counters.failed(Family::Ipv6), None);
+        assert_eq!(counters.failed(Family::Ipv6), None);
+        assert_eq!(
+            counters.failed(Family::Ipv6),
+            Some(FamilyEvent::Failing {
+                family: Family::Ipv6,
+                consecutive: 3
+            })
+        );
+        // Reported once per run.
+        assert_eq!(counters.failed(Family::Ipv6), None);
+
+        counters.connected(Family::Ipv4);
+        counters.connected(Family::Ipv6);
+        assert_eq!(counters.failed(Family::Ipv6), None);
+
+        let stats = counters.stats();
+        assert_eq!(stats.ipv4_connects, 1);
+        assert_eq!(stats.ipv6_connects, 1);
+        assert_eq!(stats.ipv6_failures, 5);
+    }
+}
@@ -0,0 +1,300 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Cursor, Read};
+use std::path::Path;
+
+use crate::headers::multipart_content_type;
+use crate::mime::guess_from_path;
+
+/// A `multipart/form-data` request body.
+///
+/// Sent with [`RequestBuilder::send_multipart()`](crate::RequestBuilder::send_multipart).
+/// The parts are streamed, files are read while sending, not up front.
+///
+/// ```no_run
+/// use ureq::{FormPart, MultipartForm};
+///
+/// let form = MultipartForm::new()
+///     .text("title", "Holiday")
+///     // Content-Type image/jpeg and filename photo.jpg from the path.
+///     .file("photo", "photo.jpg")?
+///     // Override both per part.
+///     .part(
+///         "raw",
+///         FormPart::file("photo.cr2")?
+///             .file_name("original.cr2")
+///             .content_type("image/x-canon-cr2"),
+///     );
+///
+/// let res = ureq::post("http://httpbin.org/post")
+///     .send_multipart(form)?;
+/// # Ok::<_, ureq::Error>(())
+/// ```
+#[derive(Default)]
+pub struct MultipartForm {
+    boundary: Option<String>,
+    parts: Vec<(String, FormPart)>,
+}
+
+/// A single part of a [`MultipartForm`].
+pub struct FormPart {
+    reader: Box<dyn Read>,
+    length: Option<u64>,
+    file_name: Option<String>,
+    content_type: Option<String>,
+}
+
+impl MultipartForm {
+    /// Creates an empty form.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The boundary between the parts.
+    ///
+    /// Defaults to a random boundary.
+    pub fn boundary(mut self, v: &str) -> Self {
+        self.boundary = Some(v.to_string());
+        self
+    }
+
+    /// Add a text field.
+    pub fn text(self, name: &str, value: &str) -> Self {
+        self.part(name, FormPart::bytes(value))
+    }
+
+    /// Add a file, with the filename and Content-Type taken from the path.
+    ///
+    /// Use [`FormPart::file()`] with [`MultipartForm::part()`] to override them.
+    pub fn file(self, name: &str, path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(self.part(name, FormPart::file(path)?))
+    }
+
+    /// Add a part.
+    pub fn part(mut self, name: &str, part: FormPart) -> Self {
+        self.parts.push((name.to_string(), part));
+        self
+    }
+
+    /// Turn the form into the `multipart/form-data` content type and a reader of
+    /// the body, with the length of the body if the length of all parts is known.
+    pub(crate) fn into_reader(
+        self,
+        default_boundary: impl FnOnce() -> String,
+    ) -> (String, Box<dyn Read>, Option<u64>) {
+        let boundary = self.boundary.unwrap_or_else(default_boundary);
+        let content_type = multipart_content_type("form-data", &boundary);
+
+        let mut reader: Box<dyn Read> = Box::new(io::empty());
+        let mut length = Some(0);
+
+        for (name, part) in self.parts {
+            let mut head = format!("--{}\r\n", boundary);
+            head.push_str("Content-Disposition: form-data; name=\"");
+            push_quoted(&mut head, &name);
+            head.push('"');
+            if let Some(file_name) = &part.file_name {
+                head.push_str("; filename=\"");
+                push_quoted(&mut head, file_name);
+                head.push('"');
+            }
+            head.push_str("\r\n");
+            if let Some(content_type) = &part.content_type {
+                head.push_str("Content-Type: ");
+                head.push_str(content_type);
+                head.push_str("\r\n");
+            }
+            head.push_str("\r\n");
+
+            length = length
+                .zip(part.length)
+                .map(|(n, m)| n + head.len() as u64 + m + 2);
+
+            reader = Box::new(
+                reader
+                    .chain(Cursor::new(head.into_bytes()))
+                    .chain(part.reader)
+                    .chain(&b"\r\n"[..]),
+            );
+        }
+
+        let tail = format!("--{}--\r\n", boundary);
+        length = length.map(|n| n + tail.len() as u64);
+        reader = Box::new(reader.chain(Cursor::new(tail.into_bytes())));
+
+        (content_type, reader, length)
+    }
+}
+
+impl FormPart {
+    /// A part from data in memory.
+    pub fn bytes(data: impl Into<Vec<u8>>) -> Self {
+        let data = data.into();
+        FormPart {
+            length: Some(data.len() as u64),
+            reader: Box::new(Cursor::new(data)),
+            file_name: None,
+            content_type: None,
+        }
+    }
+
+    /// A part read from `reader` while sending, of unknown length.
+    ///
+    /// A form with such a part is sent with chunked transfer encoding.
+    pub fn reader(reader: impl Read + 'static) -> Self {
+        FormPart {
+            reader: Box::new(reader),
+            length: None,
+            file_name: None,
+            content_type: None,
+        }
+    }
+
+    /// A part from a file.
+    ///
+    /// The filename is the last component of the path, and the Content-Type is
+    /// guessed from the extension (see the **mime-guess** feature), falling back
+    /// on `application/octet-stream`.
+    pub fn file(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path)?;
+        let length = file.metadata()?.len();
+
+        Ok(FormPart {
+            reader: Box::new(file),
+            length: Some(length),
+            file_name: path.file_name().map(|n| n.to_string_lossy().into_owned()),
+            content_type: Some(
+                guess_from_path(path)
+                    .unwrap_or("application/octet-stream")
+                    .to_string(),
+            ),
+        })
+    }
+
+    /// The filename of the part.
+    ///
+    /// Defaults to the file name for [`FormPart::file()`], otherwise none.
+    pub fn file_name(mut self, v: &str) -> Self {
+        self.file_name = Some(v.to_string());
+        self
+    }
+
+    /// The Content-Type of the part.
+    ///
+    /// Defaults to the type guessed for [`FormPart::file()`], otherwise none,
+    /// which means `text/plain`.
+    pub fn content_type(mut self, v: &str) -> Self {
+        self.content_type = Some(v.to_string());
+        self
+    }
+}
+
+/// Percent encode the characters that would end a quoted string or the header,
+/// like browsers do for names and filenames.
+fn push_quoted(out: &mut String, s: &str) {
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("%22"),
+            '\r' => out.push_str("%0D"),
+            '\n' => out.push_str("%0A"),
+            _ => out.push(c),
+        }
+    }
+}
+
+impl fmt::Debug for MultipartForm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MultipartForm")
+            .field("boundary", &self.boundary)
+            .field("parts", &self.parts)
+            .finish()
+    }
+}
+
+impl fmt::Debug for FormPart {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FormPart")
+            .field("length", &self.length)
+            .field("file_name", &self.file_name)
+            .field("content_type", &self.content_type)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn to_string(form: MultipartForm) -> (String, String, Option<u64>) {
+        let (content_type, mut reader, length) = form.into_reader(|| unreachable!());
+        let mut body = String::new();
+        reader.read_to_string(&mut body).unwrap();
+        (content_type, body, length)
+    }
+
+    #[test]
+    fn form_parts() {
+        let form = MultipartForm::new().boundary("b").text("title", "Hi").part(
+            "data",
+            FormPart::bytes("abc")
+                .file_name("x\".bin")
+                .content_type("application/x-thing"),
+        );
+
+        let (content_type, body, length) = to_string(form);
+        assert_eq!(content_type, "multipart/form-data; boundary=b");
+        assert_eq!(
+            body,
+            "--b\r\n\
+            Content-Disposition: form-data; name=\"title\"\r\n\
+            \r\n\
+            Hi\r\n\
+            --b\r\n\
+            Content-Disposition: form-data; name=\"data\"; filename=\"x%22.bin\"\r\n\
+            Content-Type: application/x-thing\r\n\
+            \r\n\
+            abc\r\n\
+            --b--\r\n"
+        );
+        assert_eq!(length, Some(body.len() as u64));
+    }
+
+    #[test]
+    fn file_part_override() {
+        let path = std::env::temp_dir().join(format!("ureq-form-{}.json", std::process::id()));
+        std::fs::write(&path, "{}").unwrap();
+
+        let guessed = FormPart::file(&path).unwrap();
+        let overridden = FormPart::file(&path)
+            .unwrap()
+            .file_name("data.txt")
+            .content_type("text/plain");
+
+        let form = MultipartForm::new()
+            .boundary("b")
+            .part("a", guessed)
+            .part("b", overridden);
+        let (_, body, length) = to_string(form);
+
+        std::fs::remove_file(&path).unwrap();
+
+        let file_name = path.file_name().unwrap().to_str().unwrap();
+        assert!(body.contains(&format!(
+            "name=\"a\"; filename=\"{}\"\r\nContent-Type: application/json\r\n",
+            file_name
+        )));
+        assert!(body.contains("name=\"b\"; filename=\"data.txt\"\r\nContent-Type: text/plain\r\n"));
+        assert_eq!(length, Some(body.len() as u64));
+    }
+
+    #[test]
+    fn reader_part_has_no_length() {
+        let form = MultipartForm::new()
+            .boundary("b")
+            .part("r", FormPart::reader(&b"abc"[..]));
+        let (_, body, length) = to_string(form);
+        assert!(body.contains("\r\n\r\nabc\r\n--b--\r\n"));
+        assert_eq!(length, None);
+    }
+}
@@ -0,0 +1,354 @@
+//! Unary gRPC-Web calls.
+//!
+//! Requires the **grpc-web** feature.
+//!
+//! [gRPC-Web] carries gRPC over plain HTTP/1.1, with the trailers at the end of the
+//! response body rather than as HTTP trailers. That makes simple unary RPCs possible
+//! with ureq, without a full gRPC client. Encoding and decoding the protobuf messages
+//! is left to the caller, for instance using the `prost` crate.
+//!
+//! ```no_run
+//! use ureq::Agent;
+//!
+//! let agent = Agent::new_with_defaults();
+//!
+//! // An encoded protobuf request message.
+//! let message = vec![0x0a, 0x05, b'w', b'o', b'r', b'l', b'd'];
+//!
+//! let response = agent
+//!     .grpc_web("https://grpc.example.test/helloworld.Greeter/SayHello")
+//!     .metadata("authorization", "Bearer token")
+//!     .call(&message)?;
+//!
+//! println!("{} bytes", response.message().len());
+//! # Ok::<_, ureq::Error>(())
+//! ```
+//!
+//! [gRPC-Web]: https://github.com/grpc/grpc/blob/master/doc/PROTOCOL-WEB.md
+
+use std::io::Read;
+
+use http::{header, HeaderMap, HeaderName, HeaderValue, Request, Uri};
+
+use crate::{Agent, Error, IntoUri};
+
+const CONTENT_TYPE: &str = "application/grpc-web+proto";
+
+/// Frame flag for trailers. The other bit in use is compression.
+const FLAG_TRAILERS: u8 = 0x80;
+const FLAG_COMPRESSED: u8 = 0x01;
+
+/// Default max size of the response message, as in most gRPC implementations.
+const DEFAULT_MAX_MESSAGE_SIZE: usize = 4 * 1024 * 1024;
+
+/// A unary gRPC-Web call.
+///
+/// Created by [`Agent::grpc_web()`]. See the [module](self) documentation.
+#[derive(Debug)]
+pub struct GrpcWeb {
+    agent: Agent,
+    uri: Result<Uri, Error>,
+    metadata: Vec<(String, String)>,
+    max_message_size: usize,
+}
+
+/// The response of a successful unary gRPC-Web call.
+#[derive(Debug)]
+pub struct GrpcWebResponse {
+    headers: HeaderMap,
+    message: Vec<u8>,
+    trailers: HeaderMap,
+}
+
+impl GrpcWeb {
+    pub(crate) fn new(agent: Agent, uri: impl IntoUri) -> Self {
+        GrpcWeb {
+            agent,
+            uri: uri.into_uri(),
+            metadata: vec![],
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+        }
+    }
+
+    /// Add custom metadata, sent as a request header.
+    pub fn metadata(mut self, key: &str, value: &str) -> Self {
+        self.metadata.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Max size of the response message.
+    ///
+    /// Defaults to 4MB.
+    pub fn max_message_size(mut self, v: usize) -> Self {
+        self.max_message_size = v;
+        self
+    }
+
+    /// Make the call with an encoded protobuf message.
+    ///
+    /// A `grpc-status` other than `0` (OK) is an [`Error::Grpc`].
+    pub fn call(self, message: &[u8]) -> Result<GrpcWebResponse, Error> {
+        let mut builder = Request::post(self.uri?)
+            .header(header::CONTENT_TYPE, CONTENT_TYPE)
+            .header(header::ACCEPT, CONTENT_TYPE)
+            .header("x-grpc-web", "1");
+
+        for (key, value) in &self.metadata {
+            builder = builder.header(key, value);
+        }
+
+        let request = builder.body(frame(0, message))?;
+
+        let response = self.agent.run(request)?;
+        let (parts, mut body) = response.into_parts();
+
+        // A response without message has the status in the headers ("trailers-only").
+        if let Some(status) = status(&parts.headers)? {
+            return Err(status);
+        }
+
+        // Each frame is limited, the message by the max message size, and the trailers
+        // like response headers.
+        let max_trailers_size = self.agent.config().max_response_header_size;
+        let mut reader = body.as_reader();
+
+        let mut message = None;
+        let mut trailers = None;
+
+        while let Some((flags, data)) =
+            read_frame(&mut reader, self.max_message_size, max_trailers_size)?
+        {
+            if flags & FLAG_COMPRESSED > 0 {
+                return Err(Error::Grpc(12, "compressed message".into()));
+            }
+
+            if flags & FLAG_TRAILERS > 0 {
+                trailers = Some(parse_trailers(&data)?);
+                break;
+            }
+
+            if message.is_some() {
+                return Err(Error::Grpc(
+                    12,
+                    "more than one message in unary call".into(),
+                ));
+            }
+            message = Some(data);
+        }
+
+        let trailers = trailers.unwrap_or_else(|| parts.headers.clone());
+
+        match status(&trailers)? {
+            Some(e) => Err(e),
+            None if message.is_none() => Err(Error::Grpc(13, "no message in response".into())),
+            None => Ok(GrpcWebResponse {
+                headers: parts.headers,
+                message: message.unwrap_or_default(),
+                trailers,
+            }),
+        }
+    }
+}
+
+impl GrpcWebResponse {
+    /// The encoded protobuf response message.
+    pub fn message(&self) -> &[u8] {
+        &self.message
+    }
+
+    /// Take the encoded protobuf response message.
+    pub fn into_message(self) -> Vec<u8> {
+        self.message
+    }
+
+    /// The response headers, with the initial metadata of the call.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// The trailers, such as `grpc-status`, sent at the end of the body.
+    pub fn trailers(&self) -> &HeaderMap {
+        &self.trailers
+    }
+}
+
+/// Length-prefixed message: flags, 4 bytes big endian length and the data.
+fn frame(flags: u8, data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() + 5);
+    out.push(flags);
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    out.extend_from_slice(data);
+    out
+}
+
+fn read_frame(
+    reader: &mut impl Read,
+    max_message_size: usize,
+    max_trailers_size: usize,
+) -> Result<Option<(u8, Vec<u8>)>, Error> {
+    let mut prefix = [0; 5];
+
+    // A clean end of the body is only allowed between frames.
+    let n = reader.read(&mut prefix[..1])?;
+    if n == 0 {
+        return Ok(None);
+    }
+    reader.read_exact(&mut prefix[1..])?;
+
+    let flags = prefix[0];
+    let len = u32::from_be_bytes([prefix[1], prefix[2], prefix[3], prefix[4]]) as usize;
+
+    if flags & FLAG_TRAILERS > 0 {
+        if len > max_trailers_size {
+            return Err(Error::LargeResponseHeader(len, max_trailers_size));
+        }
+    } else if len > max_message_size {
+        return Err(Error::BodyExceedsLimit(max_message_size as u64));
+    }
+
+    let mut data = vec![0; len];
+    reader.read_exact(&mut data)?;
+
+    Ok(Some((flags, data)))
+}
+
+/// Trailers are encoded as HTTP/1 headers: `grpc-status:0\r\ngrpc-message:\r\n`.
+fn parse_trailers(data: &[u8]) -> Result<HeaderMap, Error> {
+    let bad = || Error::BadBody("gRPC-Web trailers".into());
+
+    let text = std::str::from_utf8(data).map_err(|_| bad())?;
+    let mut trailers = HeaderMap::new();
+
+    for line in text.split("\r\n").filter(|l| !l.is_empty()) {
+        let (name, value) = line.split_once(':').ok_or_else(bad)?;
+        let name = HeaderName::from_bytes(name.trim().as_bytes()).map_err(|_| bad())?;
+        let value = HeaderValue::from_str(value.trim()).map_err(|_| bad())?;
+        trailers.append(name, value);
+    }
+
+    Ok(trailers)
+}
+
+/// The error for a `grpc-status` other than OK, if there is one.
+fn status(headers: &HeaderMap) -> Result<Option<Error>, Error> {
+    let Some(value) = headers.get("grpc-status") else {
+        return Ok(None);
+    };
+
+    let code: u32 = value
+        .to_str()
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .ok_or_else(|| Error::BadHeader("grpc-status".into()))?;
+
+    if code == 0 {
+        return Ok(None);
+    }
+
+    let message = headers
+        .get("grpc-message")
+        .and_then(|v| v.to_str().ok())
+        .map(|v| {
+            percent_encoding::percent_decode_str(v)
+                .decode_utf8_lossy()
+                .to_string()
+        })
+        .unwrap_or_default();
+
+    Ok(Some(Error::Grpc(code, message)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::Config;
+    use crate::transport::MockConnector;
+
+    fn response(body: &[u8]) -> Vec<u8> {
+        let mut response = format!(
+            "HTTP/1.1 200 OK\r\n\
+            Content-Type: application/grpc-web+proto\r\n\
+            Content-Length: {}\r\n\
+            \r\n",
+            body.len()
+        )
+        .into_bytes();
+        response.extend_from_slice(body);
+        response
+    }
+
+    #[test]
+    fn unary_call() {
+        let mut body = frame(0, b"reply");
+        body.extend(frame(FLAG_TRAILERS, b"grpc-status:0\r\ngrpc-message:\r\n"));
+
+        let mock = MockConnector::new().route("/Svc/Ok", response(&body));
+        let agent = Agent::with_parts(Config::default(), mock.clone(), mock.clone());
+
+        let res = agent
+            .grpc_web("http://example.test/Svc/Ok")
+            .call(b"hello")
+            .unwrap();
+
+        assert_eq!(res.message(), b"reply");
+        assert_eq!(res.trailers()["grpc-status"], "0");
+
+        let request = mock.requests().remove(0);
+        assert!(request.ends_with(b"\r\n\r\n\x00\x00\x00\x00\x05hello"));
+    }
+
+    #[test]
+    fn error_status() {
+        let body = frame(
+            FLAG_TRAILERS,
+            b"grpc-status:5\r\ngrpc-message:not%20found\r\n",
+        );
+
+        let mock = MockConnector::new().route("/Svc/Missing", response(&body));
+        let agent = Agent::with_parts(Config::default(), mock.clone(), mock);
+
+        let err = agent
+            .grpc_web("http://example.test/Svc/Missing")
+            .call(b"")
+            .unwrap_err();
+
+        assert!(matches!(err, Error::Grpc(5, m) if m == "not found"));
+    }
+
+    #[test]
+    fn limits_per_frame() {
+        let call = |body: &[u8], max_message_size: usize| {
+            let mock = MockConnector::new().route("/Svc/Big", response(body));
+            let agent = Agent::with_parts(Config::default(), mock.clone(), mock);
+            agent
+                .grpc_web("http://example.test/Svc/Big")
+                .max_message_size(max_message_size)
+                .call(b"")
+        };
+
+        // Trailers larger than a small message are fine.
+        let mut trailers = b"grpc-status:0\r\nx-debug:".to_vec();
+        trailers.extend(std::iter::repeat(b'a').take(4096));
+        trailers.extend(b"\r\n");
+
+        let mut body = frame(0, b"reply");
+        body.extend(frame(FLAG_TRAILERS, &trailers));
+
+        let res = call(&body, 16).unwrap();
+        assert_eq!(res.message(), b"reply");
+        assert_eq!(res.trailers()["x-debug"].len(), 4096);
+
+        let err = call(&body, 4).unwrap_err();
+        assert!(matches!(err, Error::BodyExceedsLimit(4)), "{:?}", err);
+
+        // Trailers are limited like response headers.
+        let mut body = frame(0, b"reply");
+        body.extend(frame(FLAG_TRAILERS, &vec![b'a'; 100 * 1024]));
+        let err = call(&body, 16).unwrap_err();
+        assert!(matches!(err, Error::LargeResponseHeader(..)), "{:?}", err);
+
+        let body = frame(FLAG_TRAILERS, b"grpc-status 0\r\n");
+        let err = call(&body, 16).unwrap_err();
+        assert!(matches!(err, Error::BadBody(_)), "{:?}", err);
+    }
+}
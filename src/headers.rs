@@ -0,0 +1,365 @@
+use std::borrow::Cow;
+
+use http::header::{self, HeaderName};
+use http::{HeaderMap, HeaderValue};
+
+use crate::config::HeaderFolding;
+use crate::Error;
+
+/// The most headers read in a response head, also for interim responses.
+///
+/// This matches the capacity used when parsing the final response.
+pub(crate) const MAX_RESPONSE_HEADERS: usize = 128;
+
+/// Response headers holding a single value, which should not repeat.
+const SINGLETONS: &[HeaderName] = &[
+    header::AGE,
+    header::CONTENT_LOCATION,
+    header::CONTENT_RANGE,
+    header::CONTENT_TYPE,
+    header::DATE,
+    header::ETAG,
+    header::EXPIRES,
+    header::LAST_MODIFIED,
+    header::LOCATION,
+    header::RETRY_AFTER,
+];
+
+/// Check that the response tells its body length unambiguously (RFC 9112, section 6.3).
+///
+/// hoot has framed the body from the headers before this check, so this only decides
+/// whether to fail, and tidies the headers the response shows. Repeats of the same
+/// `Content-Length` are reduced to one.
+pub(crate) fn check_framing(headers: &mut HeaderMap, lenient: bool) -> Result<(), Error> {
+    let lengths: Vec<String> = headers
+        .get_all(header::CONTENT_LENGTH)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|v| v.trim().to_string())
+        .collect();
+
+    if lengths.iter().any(|v| *v != lengths[0]) {
+        if !lenient {
+            return Err(Error::AmbiguousFraming("differing content-length"));
+        }
+        warn!("Differing content-length: {:?}", lengths);
+    } else if lengths.len() > 1 {
+        if let Ok(value) = HeaderValue::from_str(&lengths[0]) {
+            headers.insert(header::CONTENT_LENGTH, value);
+        }
+    }
+
+    if headers.contains_key(header::CONTENT_LENGTH)
+        && headers.contains_key(header::TRANSFER_ENCODING)
+    {
+        if !lenient {
+            return Err(Error::AmbiguousFraming(
+                "both content-length and transfer-encoding",
+            ));
+        }
+        warn!("Both content-length and transfer-encoding, ignore content-length");
+        headers.remove(header::CONTENT_LENGTH);
+    }
+
+    Ok(())
+}
+
+/// Warn about repeated headers that hold a single value, and join repeated headers
+/// for [`HeaderFolding::Join`].
+pub(crate) fn fold_headers(headers: &mut HeaderMap, folding: HeaderFolding) {
+    for name in SINGLETONS {
+        if is_repeated(headers, name) {
+            warn!("Repeated response header: {}", name);
+        }
+    }
+
+    if folding == HeaderFolding::Join {
+        let names: Vec<HeaderName> = headers
+            .keys()
+            .filter(|n| is_joinable(n) && is_repeated(headers, n))
+            .cloned()
+            .collect();
+
+        for name in names {
+            let mut joined = vec![];
+            for value in headers.get_all(&name) {
+                if !joined.is_empty() {
+                    joined.extend_from_slice(b", ");
+                }
+                joined.extend_from_slice(value.as_bytes());
+            }
+
+            // Valid values joined by ", " are still valid.
+            let value = HeaderValue::from_bytes(&joined).expect("joined header value");
+            headers.insert(name, value);
+        }
+    }
+}
+
+fn is_repeated(headers: &HeaderMap, name: &HeaderName) -> bool {
+    headers.get_all(name).iter().nth(1).is_some()
+}
+
+fn is_joinable(name: &HeaderName) -> bool {
+    *name != header::SET_COOKIE && *name != header::CONTENT_LENGTH && !SINGLETONS.contains(name)
+}
+
+/// Split a header value such as `multipart/mixed; boundary="a;b"` into the value
+/// and its parameters (RFC 9110, section 5.6.6).
+///
+/// The parameter names are lowercased, and quoted values are unquoted. Values
+/// are borrowed from `value` unless unquoting had to unescape them.
+pub(crate) fn split_params(value: &str) -> (&str, Vec<(String, Cow<'_, str>)>) {
+    let (head, mut rest) = value.split_once(';').unwrap_or((value, ""));
+    let mut params = vec![];
+
+    loop {
+        rest = rest.trim_start_matches(|c: char| c == ';' || c == ' ' || c == '\t');
+        if rest.is_empty() {
+            break;
+        }
+
+        let end = rest
+            .find(|c: char| c == '=' || c == ';')
+            .unwrap_or(rest.len());
+        let name = rest[..end].trim().to_ascii_lowercase();
+        rest = &rest[end..];
+
+        // A parameter without value.
+        let Some(after) = rest.strip_prefix('=') else {
+            continue;
+        };
+        let after = after.trim_start();
+
+        let value = if let Some(quoted) = after.strip_prefix('"') {
+            let mut value = String::new();
+            let mut escaped = false;
+            let mut len = quoted.len();
+            let mut end = quoted.len();
+            let mut chars = quoted.char_indices();
+            while let Some((i, c)) = chars.next() {
+                match c {
+                    '\\' => {
+                        escaped = true;
+                        value.extend(chars.next().map(|(_, c)| c));
+                    }
+                    '"' => {
+                        len = i;
+                        end = i + 1;
+                        break;
+                    }
+                    _ => value.push(c),
+                }
+            }
+            // Skip anything between the closing quote and the next parameter.
+            let tail = &quoted[end..];
+            rest = tail.find(';').map(|i| &tail[i..]).unwrap_or("");
+            if escaped {
+                Cow::Owned(value)
+            } else {
+                Cow::Borrowed(&quoted[..len])
+            }
+        } else {
+            let end = after.find(';').unwrap_or(after.len());
+            rest = &after[end..];
+            Cow::Borrowed(after[..end].trim())
+        };
+
+        params.push((name, value));
+    }
+
+    (head.trim(), params)
+}
+
+/// The content-type of a multipart body, with the boundary quoted if needed to
+/// be read back by [`split_params()`].
+pub(crate) fn multipart_content_type(subtype: &str, boundary: &str) -> String {
+    let is_token = !boundary.is_empty()
+        && boundary
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b));
+
+    if is_token {
+        format!("multipart/{}; boundary={}", subtype, boundary)
+    } else {
+        let escaped = boundary.replace('\\', "\\\\").replace('"', "\\\"");
+        format!("multipart/{}; boundary=\"{}\"", subtype, escaped)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::Config;
+    use crate::transport::MockConnector;
+    use crate::Agent;
+
+    const RESPONSE: &str = "HTTP/1.1 200 OK\r\n\
+        Content-Length: 2\r\n\
+        Cache-Control: no-cache\r\n\
+        Set-Cookie: a=1; Expires=Wed, 21 Oct 2026 07:28:00 GMT\r\n\
+        Content-Type: text/plain\r\n\
+        Cache-Control: no-store\r\n\
+        Set-Cookie: b=2\r\n\
+        Content-Type: text/html\r\n\
+        \r\n\
+        ok";
+
+    fn headers(folding: HeaderFolding) -> HeaderMap {
+        let mock = MockConnector::new().route("/", RESPONSE);
+        let config = Config::builder().header_folding(folding).build();
+        let agent = Agent::with_parts(config, mock.clone(), mock);
+
+        let res = agent.get("http://headers.test/").call().unwrap();
+        res.headers().clone()
+    }
+
+    #[test]
+    fn params() {
+        let (value, params) =
+            split_params("multipart/mixed; Boundary=\"a;b \\\"c\\\"\" ; CHARSET=utf-8;x");
+        assert_eq!(value, "multipart/mixed");
+        assert_eq!(
+            params,
+            [
+                ("boundary".to_string(), Cow::Owned("a;b \"c\"".to_string())),
+                ("charset".to_string(), Cow::Borrowed("utf-8")),
+            ]
+        );
+        assert!(matches!(params[1].1, Cow::Borrowed(_)));
+
+        for boundary in ["simple_1", "with space", "a;b", "q\"uote"] {
+            let content_type = multipart_content_type("form-data", boundary);
+            let (value, params) = split_params(&content_type);
+            assert_eq!(value, "multipart/form-data");
+            assert_eq!(params[0].0, "boundary");
+            assert_eq!(params[0].1, boundary);
+        }
+    }
+
+    #[test]
+    fn list_and_join() {
+        let list = headers(HeaderFolding::List);
+        assert_eq!(list.get_all("cache-control").iter().count(), 2);
+        assert_eq!(list.get_all("set-cookie").iter().count(), 2);
+
+        let join = headers(HeaderFolding::Join);
+        let values = |name: &str| join.get_all(name).iter().collect::<Vec<_>>();
+        assert_eq!(values("cache-control"), ["no-cache, no-store"]);
+        assert_eq!(values("set-cookie").len(), 2);
+        assert_eq!(values("content-type"), ["text/plain", "text/html"]);
+    }
+
+    #[test]
+    fn head_larger_than_input_buffer() {
+        let mut response = "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n".to_string();
+        for i in 0..60 {
+            response.push_str(&format!(
+                "Set-Cookie: tracking_{}={}; Path=/; Secure; HttpOnly\r\n",
+                i,
+                "x".repeat(120)
+            ));
+        }
+        response.push_str("\r\nok");
+
+        let call = |max_header: usize| {
+            let mock = MockConnector::new().route("/", &response);
+            let config = Config::builder()
+                .input_buffer_size(1024)
+                .max_response_header_size(max_header)
+                .build();
+            let agent = Agent::with_parts(config, mock.clone(), mock);
+            agent.get("http://headers.test/").call()
+        };
+
+        let mut res = call(64 * 1024).unwrap();
+        assert_eq!(res.headers().get_all("set-cookie").iter().count(), 60);
+        assert_eq!(res.body_mut().read_to_string().unwrap(), "ok");
+
+        let err = call(4096).unwrap_err();
+        assert!(
+            matches!(err, Error::LargeResponseHeader(_, 4096)),
+            "{:?}",
+            err
+        );
+    }
+
+    #[test]
+    fn repeated_content_length() {
+        let mut headers = HeaderMap::new();
+        headers.append("content-length", HeaderValue::from_static("5"));
+        headers.append("content-length", HeaderValue::from_static("5"));
+        check_framing(&mut headers, false).unwrap();
+        assert_eq!(headers.get_all("content-length").iter().count(), 1);
+
+        headers.append("content-length", HeaderValue::from_static("6"));
+        let err = check_framing(&mut headers, false).unwrap_err();
+        assert!(matches!(err, Error::AmbiguousFraming(_)));
+    }
+
+    fn check(lenient: bool, headers: &[(&str, &str)]) -> Result<HeaderMap, Error> {
+        let mut map = HeaderMap::new();
+        for (k, v) in headers {
+            map.append(*k, v.parse().unwrap());
+        }
+        check_framing(&mut map, lenient)?;
+        Ok(map)
+    }
+
+    #[test]
+    fn ambiguous_framing() {
+        let same = [("content-length", "5"), ("content-length", "5, 5")];
+        let map = check(false, &same).unwrap();
+        let lengths = map.get_all("content-length").iter().collect::<Vec<_>>();
+        assert_eq!(lengths, ["5"]);
+
+        let differing = [("content-length", "5"), ("content-length", "6")];
+        let both = [("content-length", "5"), ("transfer-encoding", "chunked")];
+
+        for headers in [&differing, &both] {
+            let err = check(false, headers).unwrap_err();
+            assert!(matches!(err, Error::AmbiguousFraming(_)), "{:?}", headers);
+        }
+
+        assert_eq!(check(true, &differing).unwrap().len(), 2);
+        let map = check(true, &both).unwrap();
+        assert!(!map.contains_key("content-length"));
+    }
+
+    #[test]
+    fn reject_smuggling_responses() {
+        let call = |response: &'static str| {
+            let mock = MockConnector::new().route("/", response);
+            let agent = Agent::with_parts(Config::default(), mock.clone(), mock);
+            agent
+                .get("http://headers.test/")
+                .call()
+                .and_then(|mut res| res.body_mut().read_to_string())
+        };
+
+        let err = call(
+            "HTTP/1.1 200 OK\r\n\
+            Content-Length: 3\r\n\
+            Transfer-Encoding: chunked\r\n\
+            \r\n\
+            0\r\n\r\n",
+        )
+        .unwrap_err();
+        assert!(matches!(err, Error::AmbiguousFraming(_)), "{:?}", err);
+
+        // The chunk size overflows a u64.
+        let res = call(
+            "HTTP/1.1 200 OK\r\n\
+            Transfer-Encoding: chunked\r\n\
+            \r\n\
+            10000000000000001\r\nok\r\n0\r\n\r\n",
+        );
+        let err = res.unwrap_err();
+        assert!(
+            matches!(err, Error::AmbiguousFraming("unreadable chunk size")),
+            "{:?}",
+            err
+        );
+    }
+}
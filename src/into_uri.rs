@@ -0,0 +1,189 @@
+use std::convert::TryFrom;
+
+use http::uri::{Authority, Parts, Scheme};
+use http::Uri;
+
+use crate::Error;
+
+/// Conversion into a [`Uri`] for making requests.
+///
+/// This is used everywhere ureq takes a URL, such as [`ureq::get()`](crate::get) or
+/// [`RequestBuilder::uri()`](crate::RequestBuilder::uri). It is implemented for:
+///
+/// * `&str`, `String` and `&String`
+/// * `&[u8]` and `Vec<u8>`
+/// * [`http::Uri`] and its [`Parts`] (without any string round trip)
+/// * `url::Url` when the **url** feature is enabled
+///
+/// Parse failures are reported as [`Error::BadUri`] with a description of which
+/// part of the URL is wrong.
+///
+/// ```
+/// use ureq::http::Uri;
+///
+/// let uri: Uri = "http://httpbin.org/get".parse()?;
+///
+/// let res = ureq::get(uri).call()?;
+/// # Ok::<_, ureq::Error>(())
+/// ```
+pub trait IntoUri {
+    /// Convert self into a [`Uri`].
+    fn into_uri(self) -> Result<Uri, Error>;
+}
+
+impl IntoUri for Uri {
+    fn into_uri(self) -> Result<Uri, Error> {
+        Ok(self)
+    }
+}
+
+impl IntoUri for &Uri {
+    fn into_uri(self) -> Result<Uri, Error> {
+        Ok(self.clone())
+    }
+}
+
+impl IntoUri for &str {
+    fn into_uri(self) -> Result<Uri, Error> {
+        parse_uri(self)
+    }
+}
+
+impl IntoUri for String {
+    fn into_uri(self) -> Result<Uri, Error> {
+        parse_uri(&self)
+    }
+}
+
+impl IntoUri for &String {
+    fn into_uri(self) -> Result<Uri, Error> {
+        parse_uri(self)
+    }
+}
+
+impl IntoUri for &[u8] {
+    fn into_uri(self) -> Result<Uri, Error> {
+        match std::str::from_utf8(self) {
+            Ok(s) => parse_uri(s),
+            Err(_) => Err(Error::BadUri("uri is not valid utf-8".to_string())),
+        }
+    }
+}
+
+impl IntoUri for Vec<u8> {
+    fn into_uri(self) -> Result<Uri, Error> {
+        self.as_slice().into_uri()
+    }
+}
+
+impl IntoUri for Parts {
+    fn into_uri(self) -> Result<Uri, Error> {
+        Uri::try_from(self).map_err(|e| Error::BadUri(e.to_string()))
+    }
+}
+
+#[cfg(feature = "_url")]
+impl IntoUri for url::Url {
+    fn into_uri(self) -> Result<Uri, Error> {
+        parse_uri(self.as_str())
+    }
+}
+
+#[cfg(feature = "_url")]
+impl IntoUri for &url::Url {
+    fn into_uri(self) -> Result<Uri, Error> {
+        parse_uri(self.as_str())
+    }
+}
+
+fn parse_uri(s: &str) -> Result<Uri, Error> {
+    s.parse::<Uri>().map_err(|e| {
+        let reason = describe_invalid(s).unwrap_or_else(|| e.to_string());
+        Error::BadUri(reason)
+    })
+}
+
+/// Try to find which part of a uri that failed to parse.
+///
+/// The uri itself is not part of the description, since it might contain credentials.
+fn describe_invalid(s: &str) -> Option<String> {
+    if s.is_empty() {
+        return Some("empty uri".to_string());
+    }
+
+    let bad_char = s
+        .char_indices()
+        .find(|(_, c)| !c.is_ascii() || c.is_ascii_whitespace() || c.is_ascii_control());
+
+    if let Some((i, c)) = bad_char {
+        return Some(format!("invalid character {:?} at position {}", c, i));
+    }
+
+    let (scheme, rest) = s.split_once("://")?;
+
+    if scheme.parse::<Scheme>().is_err() {
+        return Some(format!("invalid scheme {:?}", scheme));
+    }
+
+    let authority = rest
+        .split(|c| matches!(c, '/' | '?' | '#'))
+        .next()
+        .unwrap_or_default();
+
+    if authority.is_empty() {
+        return Some("missing host".to_string());
+    }
+
+    if authority.parse::<Authority>().is_err() {
+        return Some("invalid host or port".to_string());
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn err(s: &str) -> String {
+        s.into_uri().unwrap_err().to_string()
+    }
+
+    #[test]
+    fn uri_is_not_reparsed() {
+        let uri = Uri::from_static("https://example.test/path?q=1");
+        assert_eq!(uri.clone().into_uri().unwrap(), uri);
+    }
+
+    #[test]
+    fn precise_errors() {
+        assert_eq!(
+            err("https://example.test/a b"),
+            "bad uri: invalid character ' ' at position 22"
+        );
+        assert_eq!(err("file:///some/path"), "bad uri: missing host");
+        assert_eq!(
+            err("https://example.test:99999/"),
+            "bad uri: invalid host or port"
+        );
+    }
+
+    #[cfg(feature = "_url")]
+    #[test]
+    fn url_for_request() {
+        use crate::config::Config;
+        use crate::transport::MockConnector;
+        use crate::Agent;
+
+        let mock = MockConnector::new().route("/", "HTTP/1.1 204 No Content\r\n\r\n");
+        let agent = Agent::with_parts(Config::default(), mock.clone(), mock.clone());
+
+        let url = url::Url::parse("http://example.test/a?b=c").unwrap();
+        agent.get(&url).call().unwrap();
+        agent.get("http://other.test/").uri(url).call().unwrap();
+
+        for request in mock.requests() {
+            assert!(request.starts_with(b"GET /a?b=c HTTP/1.1\r\n"));
+        }
+    }
+}
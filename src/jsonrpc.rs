@@ -0,0 +1,379 @@
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use http::Uri;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+use crate::{Agent, Error, IntoUri};
+
+/// A JSON-RPC 2.0 client.
+///
+/// Created by [`Agent::jsonrpc()`]. Requires the **json** feature.
+///
+/// Each call gets a new id, and the id of the response is checked against it.
+/// Clones of the client share the id counter. An error object in the response
+/// is an [`Error::JsonRpc`], and a response that isn't valid JSON-RPC is an
+/// [`Error::BadBody`].
+///
+/// ```no_run
+/// use ureq::Agent;
+///
+/// let agent = Agent::new_with_defaults();
+/// let rpc = agent.jsonrpc("http://localhost:8545");
+///
+/// let block: String = rpc.call("eth_blockNumber", ())?;
+///
+/// let mut batch = rpc.batch();
+/// batch.call("eth_chainId", ())?;
+/// batch.call("eth_getBalance", ("0x407d73d8a49eeb85d32cf465507dd71d507100c1", "latest"))?;
+///
+/// for result in batch.send()? {
+///     match result {
+///         Ok(value) => println!("{}", value),
+///         Err(e) => println!("{}", e),
+///     }
+/// }
+/// # Ok::<_, ureq::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct JsonRpc {
+    agent: Agent,
+    uri: Result<Uri, String>,
+    next_id: Arc<AtomicU64>,
+}
+
+/// A batch of JSON-RPC calls sent in one request.
+///
+/// Created by [`JsonRpc::batch()`].
+#[derive(Debug)]
+pub struct JsonRpcBatch<'a> {
+    rpc: &'a JsonRpc,
+    requests: Vec<Value>,
+    ids: Vec<u64>,
+}
+
+/// The error object of a JSON-RPC response.
+///
+/// See [`Error::JsonRpc`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonRpcError {
+    /// The error code, such as `-32601` for a method that doesn't exist.
+    pub code: i64,
+    /// Short description of the error.
+    pub message: String,
+    /// Additional information about the error, defined by the server.
+    pub data: Option<Value>,
+}
+
+impl JsonRpc {
+    pub(crate) fn new(agent: Agent, uri: impl IntoUri) -> Self {
+        JsonRpc {
+            agent,
+            uri: uri.into_uri().map_err(|e| match e {
+                Error::BadUri(v) => v,
+                e => e.to_string(),
+            }),
+            next_id: Arc::new(AtomicU64::new(1)),
+        }
+    }
+
+    /// Call `method`, and deserialize the result.
+    ///
+    /// The `params` must serialize to an array or an object, where `()` means no params.
+    pub fn call<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: impl Serialize,
+    ) -> Result<T, Error> {
+        let id = self.next_id();
+        let request = request(method, params, Some(id))?;
+
+        let response = self.send(&request)?;
+        let result = parse_response(response, id)?.map_err(|e| Error::JsonRpc(Box::new(e)))?;
+
+        Ok(serde_json::from_value(result)?)
+    }
+
+    /// Send a notification, which is a call without a response.
+    pub fn notify(&self, method: &str, params: impl Serialize) -> Result<(), Error> {
+        let request = request(method, params, None)?;
+        self.post(&request)?;
+        Ok(())
+    }
+
+    /// Start a batch of calls.
+    pub fn batch(&self) -> JsonRpcBatch<'_> {
+        JsonRpcBatch {
+            rpc: self,
+            requests: vec![],
+            ids: vec![],
+        }
+    }
+
+    fn next_id(&self) -> u64 {
+        self.next_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    fn post(&self, request: &Value) -> Result<http::Response<crate::Body>, Error> {
+        let uri = self.uri.clone().map_err(Error::BadUri)?;
+
+        self.agent.post(uri).send_json(request)
+    }
+
+    fn send(&self, request: &Value) -> Result<Value, Error> {
+        self.post(request)?.body_mut().read_json()
+    }
+}
+
+impl<'a> JsonRpcBatch<'a> {
+    /// Add a call of `method` to the batch.
+    ///
+    /// Fails if the params can't be serialized.
+    pub fn call(&mut self, method: &str, params: impl Serialize) -> Result<(), Error> {
+        let id = self.rpc.next_id();
+        self.requests.push(request(method, params, Some(id))?);
+        self.ids.push(id);
+        Ok(())
+    }
+
+    /// Add a notification to the batch, which has no result.
+    pub fn notify(&mut self, method: &str, params: impl Serialize) -> Result<(), Error> {
+        self.requests.push(request(method, params, None)?);
+        Ok(())
+    }
+
+    /// Send the batch.
+    ///
+    /// The results are in the order of the calls, matched up by id since the
+    /// server may respond in any order. An error with a `null` id, sent when the
+    /// server couldn't read the id of a call, is the result of a call left
+    /// without a response.
+    pub fn send(self) -> Result<Vec<Result<Value, JsonRpcError>>, Error> {
+        if self.requests.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let batch = Value::Array(self.requests);
+
+        // A batch of only notifications has no response.
+        if self.ids.is_empty() {
+            self.rpc.post(&batch)?;
+            return Ok(vec![]);
+        }
+
+        let Value::Array(responses) = self.rpc.send(&batch)? else {
+            return Err(invalid("batch response is not an array"));
+        };
+
+        let mut results: Vec<Option<Result<Value, JsonRpcError>>> = vec![None; self.ids.len()];
+        let mut unmatched = vec![];
+
+        for response in responses {
+            let id = response.get("id").and_then(Value::as_u64);
+            let Some(index) = self.ids.iter().position(|i| Some(*i) == id) else {
+                // The error of a call the server couldn't read the id of.
+                if response.get("id").map_or(true, Value::is_null) {
+                    if let Err(e) = parse_response(response, 0)? {
+                        unmatched.push(e);
+                        continue;
+                    }
+                }
+                return Err(invalid("batch response with unknown id"));
+            };
+            results[index] = Some(parse_response(response, self.ids[index])?);
+        }
+
+        let mut unmatched = unmatched.into_iter();
+
+        results
+            .into_iter()
+            .map(|r| {
+                r.or_else(|| unmatched.next().map(Err))
+                    .ok_or_else(|| invalid("batch response is missing a call"))
+            })
+            .collect()
+    }
+}
+
+fn request(method: &str, params: impl Serialize, id: Option<u64>) -> Result<Value, Error> {
+    let mut request = Map::new();
+    request.insert("jsonrpc".into(), "2.0".into());
+    request.insert("method".into(), method.into());
+
+    match serde_json::to_value(params)? {
+        Value::Null => {}
+        v @ (Value::Array(_) | Value::Object(_)) => {
+            request.insert("params".into(), v);
+        }
+        // A single param is sent by position.
+        v => {
+            request.insert("params".into(), json!([v]));
+        }
+    }
+
+    if let Some(id) = id {
+        request.insert("id".into(), id.into());
+    }
+
+    Ok(Value::Object(request))
+}
+
+fn parse_response(mut response: Value, id: u64) -> Result<Result<Value, JsonRpcError>, Error> {
+    if response.get("jsonrpc").and_then(Value::as_str) != Some("2.0") {
+        return Err(invalid("not a JSON-RPC 2.0 response"));
+    }
+
+    // The id is null when the server failed to read the request id.
+    let response_id = response.get("id").and_then(Value::as_u64);
+
+    if let Some(error) = response.get_mut("error") {
+        let error = JsonRpcError {
+            code: error
+                .get("code")
+                .and_then(Value::as_i64)
+                .unwrap_or_default(),
+            message: error
+                .get("message")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string(),
+            data: error.get_mut("data").map(Value::take),
+        };
+        return Ok(Err(error));
+    }
+
+    if response_id != Some(id) {
+        return Err(invalid("response id doesn't match request"));
+    }
+
+    match response.get_mut("result") {
+        Some(result) => Ok(Ok(result.take())),
+        None => Err(invalid("response without result or error")),
+    }
+}
+
+fn invalid(reason: &str) -> Error {
+    Error::BadBody(format!("invalid JSON-RPC response: {}", reason))
+}
+
+impl fmt::Display for JsonRpcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.message, self.code)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::Config;
+    use crate::transport::MockConnector;
+
+    fn response(body: &str) -> String {
+        format!(
+            "HTTP/1.1 200 OK\r\n\
+            Content-Type: application/json\r\n\
+            Content-Length: {}\r\n\
+            \r\n\
+            {}",
+            body.len(),
+            body
+        )
+    }
+
+    #[test]
+    fn call_and_error() {
+        let mock = MockConnector::new()
+            .route(
+                "/ok",
+                response(r#"{"jsonrpc":"2.0","id":1,"result":[1,2,3]}"#),
+            )
+            .route(
+                "/err",
+                response(r#"{"jsonrpc":"2.0","id":1,"error":{"code":-32601,"message":"nope"}}"#),
+            );
+        let agent = Agent::with_parts(Config::default(), mock.clone(), mock.clone());
+
+        let result: Vec<u32> = agent
+            .jsonrpc("http://example.test/ok")
+            .call("sum", (1, 2))
+            .unwrap();
+        assert_eq!(result, [1, 2, 3]);
+
+        let request = String::from_utf8(mock.requests().remove(0)).unwrap();
+        let body: Value = serde_json::from_str(request.split("\r\n\r\n").nth(1).unwrap()).unwrap();
+        assert_eq!(
+            body,
+            json!({"jsonrpc": "2.0", "method": "sum", "params": [1, 2], "id": 1})
+        );
+
+        let err = agent
+            .jsonrpc("http://example.test/err")
+            .call::<Value>("missing", ())
+            .unwrap_err();
+        let Error::JsonRpc(e) = err else {
+            panic!("expected JsonRpc error");
+        };
+        assert_eq!(e.code, -32601);
+        assert_eq!(e.message, "nope");
+    }
+
+    #[test]
+    fn batch_in_any_order() {
+        let mock = MockConnector::new().route(
+            "/batch",
+            response(
+                r#"[{"jsonrpc":"2.0","id":2,"error":{"code":1,"message":"bad"}},
+                    {"jsonrpc":"2.0","id":1,"result":"first"}]"#,
+            ),
+        );
+        let agent = Agent::with_parts(Config::default(), mock.clone(), mock);
+
+        let rpc = agent.jsonrpc("http://example.test/batch");
+        let mut batch = rpc.batch();
+        batch.call("a", ()).unwrap();
+        batch.call("b", ["x"]).unwrap();
+        batch.notify("c", ()).unwrap();
+
+        let results = batch.send().unwrap();
+        assert_eq!(results[0], Ok(json!("first")));
+        assert_eq!(results[1].as_ref().unwrap_err().message, "bad");
+    }
+
+    #[test]
+    fn batch_null_id_error() {
+        let mock = MockConnector::new().route(
+            "/batch",
+            response(
+                r#"[{"jsonrpc":"2.0","id":1,"result":"first"},
+                    {"jsonrpc":"2.0","id":null,"error":{"code":-32600,"message":"no id"}}]"#,
+            ),
+        );
+        let agent = Agent::with_parts(Config::default(), mock.clone(), mock);
+
+        let rpc = agent.jsonrpc("http://example.test/batch");
+        let mut batch = rpc.batch();
+        batch.call("a", ()).unwrap();
+        batch.call("b", ()).unwrap();
+
+        let results = batch.send().unwrap();
+        assert_eq!(results[0], Ok(json!("first")));
+        assert_eq!(results[1].as_ref().unwrap_err().code, -32600);
+    }
+
+    #[test]
+    fn invalid_response() {
+        let mock = MockConnector::new()
+            .route("/version", response(r#"{"id":1,"result":1}"#))
+            .route("/id", response(r#"{"jsonrpc":"2.0","id":7,"result":1}"#));
+        let agent = Agent::with_parts(Config::default(), mock.clone(), mock);
+
+        for path in ["version", "id"] {
+            let uri = format!("http://example.test/{}", path);
+            let err = agent.jsonrpc(uri).call::<Value>("a", ()).unwrap_err();
+            assert!(matches!(err, Error::BadBody(_)), "{:?}", err);
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use std::io;
+use std::time::Duration;
+
+use http::{header, Request, Response};
+
+use crate::run::copy_request;
+use crate::{Agent, Body, Error};
+
+/// The outcome of one request in [`Agent::long_poll()`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum PollEvent<'a> {
+    /// The server responded.
+    ///
+    /// This includes `304 Not Modified` when nothing changed since the last `ETag`.
+    Response(&'a mut Response<Body>),
+
+    /// The request hit a timeout before the server responded.
+    ///
+    /// For long polling this is usually expected and the request is made again
+    /// right away.
+    Timeout,
+
+    /// The request failed. The next request is made after a backoff.
+    Error(&'a Error),
+}
+
+/// What to do after handling a [`PollEvent`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PollNext {
+    /// Make the next request.
+    Continue,
+
+    /// End the long poll.
+    Stop,
+}
+
+/// Backoff after failed polls. Doubles from the first value up to the second.
+const BACKOFF: (Duration, Duration) = (Duration::from_secs(1), Duration::from_secs(30));
+
+pub(crate) fn long_poll<F>(agent: &Agent, request: Request<()>, mut handler: F) -> Result<(), Error>
+where
+    F: FnMut(PollEvent<'_>, &mut Request<()>) -> Result<PollNext, Error>,
+{
+    let mut request = request;
+    let mut backoff = BACKOFF.0;
+
+    loop {
+        let mut next = copy_request(&request);
+
+        let result = agent.run(request);
+
+        let flow = match result {
+            Ok(mut response) => {
+                backoff = BACKOFF.0;
+
+                // Carry the ETag so the server can hold the request until something changes.
+                if let Some(etag) = response.headers().get(header::ETAG) {
+                    next.headers_mut()
+                        .insert(header::IF_NONE_MATCH, etag.clone());
+                }
+
+                handler(PollEvent::Response(&mut response), &mut next)?
+            }
+            Err(e) if is_timeout(e.without_stats()) => {
+                debug!("Long poll timeout: {}", e);
+                handler(PollEvent::Timeout, &mut next)?
+            }
+            Err(e) => {
+                debug!("Long poll error, backoff {:?}: {}", backoff, e);
+                let flow = handler(PollEvent::Error(&e), &mut next)?;
+
+                if flow == PollNext::Continue {
+                    std::thread::sleep(backoff);
+                    backoff = (backoff * 2).min(BACKOFF.1);
+                }
+
+                flow
+            }
+        };
+
+        if flow == PollNext::Stop {
+            return Ok(());
+        }
+
+        request = next;
+    }
+}
+
+fn is_timeout(e: &Error) -> bool {
+    match e {
+        Error::Timeout(_) => true,
+        // A socket timeout from the OS, or from a transport that doesn't map it.
+        Error::Io(e) => e.kind() == io::ErrorKind::TimedOut,
+        _ => false,
+    }
+}
+
+#[cfg(all(test, feature = "_test"))]
+mod test {
+    use super::*;
+    use crate::test::init_test_log;
+    use crate::transport::set_handler;
+
+    #[test]
+    fn long_poll_carries_etag() {
+        init_test_log();
+        set_handler(
+            "/long_poll",
+            200,
+            &[("ETag", "\"v42\""), ("Content-Length", "0")],
+            &[],
+        );
+
+        let agent = Agent::new_with_defaults();
+        let request = Request::get("http://example.org/long_poll?index=1")
+            .body(())
+            .unwrap();
+
+        let mut count = 0;
+        agent
+            .long_poll(request, |event, next| {
+                assert!(matches!(event, PollEvent::Response(_)));
+                assert_eq!(next.headers()["if-none-match"], "\"v42\"");
+
+                // Move a cursor along in the query.
+                count += 1;
+                let uri = format!("http://example.org/long_poll?index={}", count + 1);
+                *next.uri_mut() = uri.parse().unwrap();
+
+                Ok(if count < 3 {
+                    PollNext::Continue
+                } else {
+                    PollNext::Stop
+                })
+            })
+            .unwrap();
+
+        assert_eq!(count, 3);
+    }
+
+    /// Fails connections with the scripted io errors, then lets them through.
+    #[derive(Debug)]
+    struct Script(std::sync::Mutex<Vec<Option<io::ErrorKind>>>);
+
+    impl crate::transport::Connector for Script {
+        fn connect(
+            &self,
+            _details: &crate::transport::ConnectionDetails,
+            chained: Option<Box<dyn crate::transport::Transport>>,
+        ) -> Result<Option<Box<dyn crate::transport::Transport>>, Error> {
+            match self.0.lock().unwrap().remove(0) {
+                Some(kind) => Err(Error::Io(kind.into())),
+                None => Ok(chained),
+            }
+        }
+    }
+
+    #[test]
+    fn long_poll_timeouts_and_backoff() {
+        use crate::config::Config;
+        use crate::transport::{ChainedConnector, Connector, MockConnector};
+        use std::time::Instant;
+
+        init_test_log();
+        let mock = MockConnector::new().route("/", "HTTP/1.1 204 No Content\r\n\r\n");
+        let script = Script(std::sync::Mutex::new(vec![
+            Some(io::ErrorKind::TimedOut),
+            Some(io::ErrorKind::ConnectionReset),
+            None,
+            Some(io::ErrorKind::ConnectionReset),
+            None,
+        ]));
+        let connector = ChainedConnector::new([mock.clone().boxed(), script.boxed()]);
+        let agent = Agent::with_parts(Config::default(), connector, mock);
+        let request = Request::get("http://example.org/poll").body(()).unwrap();
+
+        let mut events = vec![];
+        agent
+            .long_poll(request, |event, _| {
+                let event = match event {
+                    PollEvent::Response(_) => "response",
+                    PollEvent::Timeout => "timeout",
+                    PollEvent::Error(_) => "error",
+                };
+                events.push((event, Instant::now()));
+                Ok(if events.len() < 5 {
+                    PollNext::Continue
+                } else {
+                    PollNext::Stop
+                })
+            })
+            .unwrap();
+
+        let names: Vec<_> = events.iter().map(|(e, _)| *e).collect();
+        assert_eq!(names, ["timeout", "error", "response", "error", "response"]);
+
+        let gap = |i: usize| events[i + 1].1 - events[i].1;
+        // No backoff after a timeout or a response.
+        assert!(gap(0) < BACKOFF.0);
+        assert!(gap(2) < BACKOFF.0);
+        // Backoff after an error, starting over after a response.
+        assert!(gap(1) >= BACKOFF.0);
+        assert!(gap(3) >= BACKOFF.0 && gap(3) < BACKOFF.0 * 2);
+    }
+}
@@ -0,0 +1,239 @@
+//! One-shot multicast DNS (RFC 6762) lookups of `.local` hosts.
+
+use std::convert::TryInto;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+use crate::resolver::IpFamily;
+
+/// The mDNS group and port.
+const MDNS_ADDR: (Ipv4Addr, u16) = (Ipv4Addr::new(224, 0, 0, 251), 5353);
+
+/// Longest to wait for an answer. Responders answer within about 120ms.
+pub(crate) const MDNS_TIMEOUT: Duration = Duration::from_secs(1);
+
+const TYPE_A: u16 = 1;
+const TYPE_AAAA: u16 = 28;
+const CLASS_IN: u16 = 1;
+
+/// Tells if `host` is in the `.local` domain.
+pub(crate) fn is_local(host: &str) -> bool {
+    let host = host.trim_end_matches('.');
+    let len = host.len();
+    len > 6 && host.is_char_boundary(len - 6) && host[len - 6..].eq_ignore_ascii_case(".local")
+}
+
+/// Ask the local network for the addresses of `host`.
+///
+/// This is a legacy unicast query (RFC 6762, section 6.7), sent from an ephemeral
+/// port, which responders answer directly. Waits at most `timeout` for the first
+/// answer with addresses. The query is identified by `id`, which should be random.
+pub(crate) fn resolve(
+    host: &str,
+    family: IpFamily,
+    timeout: Duration,
+    id: u16,
+) -> io::Result<Vec<IpAddr>> {
+    let host = host.trim_end_matches('.');
+    let query = query(id, host, family)?;
+
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+    socket.set_multicast_ttl_v4(255)?;
+    socket.send_to(&query, MDNS_ADDR)?;
+
+    let deadline = Instant::now() + timeout;
+    let mut buf = [0; 1500];
+
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Ok(vec![]);
+        }
+        socket.set_read_timeout(Some(left))?;
+
+        let n = match socket.recv_from(&mut buf) {
+            Ok((n, _)) => n,
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                return Ok(vec![]);
+            }
+            Err(e) => return Err(e),
+        };
+
+        let addrs = answers(&buf[..n], id, host);
+        if !addrs.is_empty() {
+            return Ok(addrs);
+        }
+    }
+}
+
+fn query(id: u16, host: &str, family: IpFamily) -> io::Result<Vec<u8>> {
+    let types: &[u16] = match family {
+        IpFamily::Any => &[TYPE_A, TYPE_AAAA],
+        IpFamily::Ipv4Only => &[TYPE_A],
+        IpFamily::Ipv6Only => &[TYPE_AAAA],
+    };
+
+    let mut q = vec![];
+    q.extend(id.to_be_bytes());
+    // Flags, question count, and no answer, authority or additional records.
+    q.extend([0, 0, 0, types.len() as u8, 0, 0, 0, 0, 0, 0]);
+
+    for t in types {
+        for label in host.split('.') {
+            if label.is_empty() || label.len() > 63 {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "bad mdns name"));
+            }
+            q.push(label.len() as u8);
+            q.extend(label.as_bytes());
+        }
+        q.push(0);
+        q.extend(t.to_be_bytes());
+        q.extend(CLASS_IN.to_be_bytes());
+    }
+
+    Ok(q)
+}
+
+/// The addresses of `host` in a response, skipping anything malformed.
+fn answers(msg: &[u8], id: u16, host: &str) -> Vec<IpAddr> {
+    let mut addrs = vec![];
+
+    let u16_at = |i: usize| msg.get(i..i + 2).map(|b| u16::from_be_bytes([b[0], b[1]]));
+
+    let (Some(msg_id), Some(flags), Some(qd), Some(an)) =
+        (u16_at(0), u16_at(2), u16_at(4), u16_at(6))
+    else {
+        return addrs;
+    };
+
+    // Must be a response, to our query.
+    if flags & 0x8000 == 0 || msg_id != id {
+        return addrs;
+    }
+
+    let mut pos = 12;
+
+    for _ in 0..qd {
+        let Some((_, next)) = read_name(msg, pos) else {
+            return addrs;
+        };
+        pos = next + 4;
+    }
+
+    for _ in 0..an {
+        let Some((name, next)) = read_name(msg, pos) else {
+            return addrs;
+        };
+        let (Some(rtype), Some(len)) = (u16_at(next), u16_at(next + 8)) else {
+            return addrs;
+        };
+        let start = next + 10;
+        let Some(data) = msg.get(start..start + len as usize) else {
+            return addrs;
+        };
+        pos = start + len as usize;
+
+        if !name.eq_ignore_ascii_case(host) {
+            continue;
+        }
+
+        match (rtype, data.len()) {
+            (TYPE_A, 4) => {
+                let ip: [u8; 4] = data.try_into().unwrap();
+                addrs.push(IpAddr::from(Ipv4Addr::from(ip)));
+            }
+            (TYPE_AAAA, 16) => {
+                let ip: [u8; 16] = data.try_into().unwrap();
+                addrs.push(IpAddr::from(Ipv6Addr::from(ip)));
+            }
+            _ => {}
+        }
+    }
+
+    addrs
+}
+
+/// Read a possibly compressed name at `pos`. Returns the name and the position after it.
+fn read_name(msg: &[u8], mut pos: usize) -> Option<(String, usize)> {
+    let mut labels: Vec<&[u8]> = vec![];
+    let mut end = None;
+    let mut jumps = 0;
+
+    loop {
+        let len = *msg.get(pos)? as usize;
+
+        if len & 0xc0 == 0xc0 {
+            let ptr = ((len & 0x3f) << 8) | *msg.get(pos + 1)? as usize;
+            end.get_or_insert(pos + 2);
+            jumps += 1;
+            if jumps > 16 {
+                return None;
+            }
+            pos = ptr;
+        } else if len == 0 {
+            let name = labels
+                .iter()
+                .map(|l| String::from_utf8_lossy(l))
+                .collect::<Vec<_>>()
+                .join(".");
+            return Some((name, end.unwrap_or(pos + 1)));
+        } else {
+            labels.push(msg.get(pos + 1..pos + 1 + len)?);
+            pos += 1 + len;
+        }
+    }
+}
+
+/// The socket addresses of `ips` with `port`.
+pub(crate) fn with_port(ips: Vec<IpAddr>, port: u16) -> impl Iterator<Item = SocketAddr> {
+    ips.into_iter().map(move |ip| SocketAddr::new(ip, port))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn local_hosts() {
+        assert!(is_local("printer.local"));
+        assert!(is_local("Printer.LOCAL."));
+        assert!(!is_local("local"));
+        assert!(!is_local(".local"));
+        assert!(!is_local("printer.localdomain"));
+    }
+
+    #[test]
+    fn parse_answers() {
+        let q = query(0x1234, "printer.local", IpFamily::Any).unwrap();
+        assert_eq!(q[..6], [0x12, 0x34, 0, 0, 0, 2]);
+        assert_eq!(&q[12..27], b"\x07printer\x05local\x00");
+
+        // Response echoing the first question, then an A record with a compressed
+        // name, an AAAA record and a record of another host.
+        let mut msg = vec![0x12, 0x34, 0x84, 0, 0, 1, 0, 3, 0, 0, 0, 0];
+        msg.extend(&q[12..31]);
+        msg.extend([0xc0, 12, 0, 1, 0x80, 1, 0, 0, 0, 120, 0, 4, 192, 168, 1, 20]);
+        msg.extend([0xc0, 12, 0, 28, 0x80, 1, 0, 0, 0, 120, 0, 16]);
+        msg.extend([0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+        msg.extend(b"\x03nas\xc0\x14");
+        msg.extend([0, 1, 0x80, 1, 0, 0, 0, 120, 0, 4, 192, 168, 1, 30]);
+
+        assert_eq!(
+            answers(&msg, 0x1234, "Printer.local"),
+            [
+                IpAddr::from([192, 168, 1, 20]),
+                "fe80::1".parse::<IpAddr>().unwrap()
+            ]
+        );
+
+        // Not our query, or truncated.
+        assert!(answers(&msg, 0x4321, "printer.local").is_empty());
+        assert_eq!(answers(&msg[..50], 0x1234, "printer.local").len(), 1);
+    }
+}
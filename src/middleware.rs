@@ -4,7 +4,7 @@ use std::fmt;
 use std::sync::Arc;
 
 use crate::run::run;
-use crate::{Agent, Body, Error, SendBody};
+use crate::{Agent, AsSendBody, Body, Error, SendBody};
 
 /// Chained processing of request (and response).
 ///
@@ -68,6 +68,32 @@ use crate::{Agent, Body, Error, SendBody};
 /// # } Ok::<_, ureq::Error>(())
 /// ```
 ///
+/// # Passing data
+///
+/// Middleware can hand typed data to later middleware, and to the caller, through the
+/// request [extensions](http::Extensions). These are set with
+/// [`RequestBuilder::extension()`](crate::RequestBuilder::extension) or by a middleware,
+/// and end up on the response.
+///
+/// ```
+/// use ureq::{Body, SendBody};
+/// use ureq::middleware::MiddlewareNext;
+/// use ureq::http::{Request, Response};
+///
+/// #[derive(Clone)]
+/// struct TraceId(String);
+///
+/// fn trace_middleware(mut req: Request<SendBody>, next: MiddlewareNext)
+///     -> Result<Response<Body>, ureq::Error> {
+///
+///     if req.extensions().get::<TraceId>().is_none() {
+///         req.extensions_mut().insert(TraceId("generated".into()));
+///     }
+///
+///     next.handle(req)
+/// }
+/// ```
+///
 /// # State
 ///
 /// To maintain state between middleware invocations, we need to do something more elaborate than
@@ -179,15 +205,86 @@ impl MiddlewareChain {
     }
 }
 
+/// How deep subrequests can nest, see [`MiddlewareNext::subrequest()`].
+const MAX_SUBREQUEST_DEPTH: u32 = 4;
+
 /// Continuation of a [`Middleware`] chain.
 pub struct MiddlewareNext<'a> {
     agent: &'a Agent,
     index: usize,
+    depth: u32,
+}
+
+/// Marks a request made by [`MiddlewareNext::subrequest()`].
+///
+/// Found in the request [extensions](http::Extensions), which lets a middleware
+/// leave its own subrequests alone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Subrequest {
+    depth: u32,
+}
+
+impl Subrequest {
+    /// How deep the subrequest is, 1 for a subrequest of a regular request.
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
 }
 
 impl<'a> MiddlewareNext<'a> {
     pub(crate) fn new(agent: &'a Agent) -> Self {
-        MiddlewareNext { agent, index: 0 }
+        MiddlewareNext {
+            agent,
+            index: 0,
+            depth: 0,
+        }
+    }
+
+    /// Run an auxiliary request through the same agent, such as to get a token.
+    ///
+    /// The subrequest goes through the entire middleware chain, including the
+    /// middleware making it. It is marked with a [`Subrequest`] extension, which
+    /// the middleware should check to not make subrequests for its own subrequests.
+    /// As a safeguard, subrequests nesting deeper than 4 fail with
+    /// [`Error::SubrequestDepth`].
+    ///
+    /// ```
+    /// use ureq::{Body, SendBody};
+    /// use ureq::middleware::{MiddlewareNext, Subrequest};
+    /// use ureq::http::{self, Request, Response};
+    ///
+    /// fn token_middleware(mut req: Request<SendBody>, next: MiddlewareNext)
+    ///     -> Result<Response<Body>, ureq::Error> {
+    ///
+    ///     if req.extensions().get::<Subrequest>().is_none() {
+    ///         let login = Request::post("https://auth.test/token").body("grant_type=x")?;
+    ///         let token = next.subrequest(login)?.body_mut().read_to_string()?;
+    ///         let value = format!("Bearer {}", token.trim()).parse().map_err(http::Error::from)?;
+    ///         req.headers_mut().insert("authorization", value);
+    ///     }
+    ///
+    ///     next.handle(req)
+    /// }
+    /// ```
+    pub fn subrequest(
+        &self,
+        request: http::Request<impl AsSendBody>,
+    ) -> Result<http::Response<Body>, Error> {
+        let depth = self.depth + 1;
+        if depth > MAX_SUBREQUEST_DEPTH {
+            return Err(Error::SubrequestDepth(depth));
+        }
+
+        let (mut parts, mut body) = request.into_parts();
+        parts.extensions.insert(Subrequest { depth });
+        let request = http::Request::from_parts(parts, body.as_body());
+
+        let next = MiddlewareNext {
+            agent: self.agent,
+            index: 0,
+            depth,
+        };
+        next.handle(request)
     }
 
     /// Continue the middleware chain.
@@ -234,3 +331,66 @@ impl fmt::Debug for MiddlewareChain {
             .finish()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use http::Request;
+
+    use super::*;
+    use crate::config::Config;
+    use crate::transport::MockConnector;
+
+    #[test]
+    fn subrequest_for_token() {
+        let mock = MockConnector::new()
+            .route("/token", "HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\nabc")
+            .route("/api", "HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n");
+
+        let token = |mut req: Request<SendBody>, next: MiddlewareNext| -> Result<_, Error> {
+            if req.extensions().get::<Subrequest>().is_none() {
+                let login = Request::post("http://auth.test/token").body("secret")?;
+                let token = next.subrequest(login)?.body_mut().read_to_string()?;
+                let value = format!("Bearer {}", token).parse().unwrap();
+                req.headers_mut().insert("authorization", value);
+            }
+            next.handle(req)
+        };
+
+        let config = Config::builder().middleware(token).build();
+        let agent = Agent::with_parts(config, mock.clone(), mock.clone());
+        agent.get("http://api.test/api").call().unwrap();
+
+        let requests: Vec<String> = mock
+            .requests()
+            .into_iter()
+            .map(|r| String::from_utf8(r).unwrap())
+            .collect();
+        assert!(requests[0].starts_with("POST /token "));
+        assert!(!requests[0].contains("authorization"));
+        assert!(requests[1].starts_with("GET /api "));
+        assert!(requests[1].contains("authorization: Bearer abc\r\n"));
+    }
+
+    #[test]
+    fn subrequest_depth() {
+        let mock = MockConnector::new().route("/", "HTTP/1.1 204 No Content\r\n\r\n");
+        let calls = Arc::new(AtomicU32::new(0));
+
+        // Forgets to check for its own subrequests.
+        let calls2 = calls.clone();
+        let looping = move |req: Request<SendBody>, next: MiddlewareNext| -> Result<_, Error> {
+            calls2.fetch_add(1, Ordering::Relaxed);
+            next.subrequest(Request::get("http://loop.test/").body(())?)?;
+            next.handle(req)
+        };
+
+        let config = Config::builder().middleware(looping).build();
+        let agent = Agent::with_parts(config, mock.clone(), mock);
+
+        let err = agent.get("http://loop.test/").call().unwrap_err();
+        assert!(matches!(err, Error::SubrequestDepth(5)), "{:?}", err);
+        assert_eq!(calls.load(Ordering::Relaxed), 5);
+    }
+}
@@ -0,0 +1,80 @@
+//! Guessing the Content-Type of files from their extension.
+
+use std::path::Path;
+
+/// Guess the mime type from the extension of a path.
+///
+/// With the **mime-guess** feature this uses the extensive table of the
+/// `mime_guess` crate. Without it, a small built-in table covers the most
+/// common file types.
+pub(crate) fn guess_from_path(path: &Path) -> Option<&'static str> {
+    #[cfg(feature = "mime-guess")]
+    {
+        mime_guess::from_path(path).first_raw()
+    }
+
+    #[cfg(not(feature = "mime-guess"))]
+    {
+        let ext = path.extension()?.to_str()?;
+        guess_from_extension(ext)
+    }
+}
+
+#[cfg(not(feature = "mime-guess"))]
+fn guess_from_extension(ext: &str) -> Option<&'static str> {
+    const TABLE: &[(&str, &str)] = &[
+        ("7z", "application/x-7z-compressed"),
+        ("avif", "image/avif"),
+        ("bin", "application/octet-stream"),
+        ("bmp", "image/bmp"),
+        ("css", "text/css"),
+        ("csv", "text/csv"),
+        ("gif", "image/gif"),
+        ("gz", "application/gzip"),
+        ("htm", "text/html"),
+        ("html", "text/html"),
+        ("ico", "image/x-icon"),
+        ("jpeg", "image/jpeg"),
+        ("jpg", "image/jpeg"),
+        ("js", "text/javascript"),
+        ("json", "application/json"),
+        ("md", "text/markdown"),
+        ("mjs", "text/javascript"),
+        ("mp3", "audio/mpeg"),
+        ("mp4", "video/mp4"),
+        ("ogg", "audio/ogg"),
+        ("pdf", "application/pdf"),
+        ("png", "image/png"),
+        ("svg", "image/svg+xml"),
+        ("tar", "application/x-tar"),
+        ("toml", "application/toml"),
+        ("txt", "text/plain"),
+        ("wasm", "application/wasm"),
+        ("wav", "audio/wav"),
+        ("webm", "video/webm"),
+        ("webp", "image/webp"),
+        ("woff", "font/woff"),
+        ("woff2", "font/woff2"),
+        ("xml", "application/xml"),
+        ("yaml", "application/yaml"),
+        ("yml", "application/yaml"),
+        ("zip", "application/zip"),
+    ];
+
+    TABLE
+        .iter()
+        .find(|(e, _)| e.eq_ignore_ascii_case(ext))
+        .map(|(_, m)| *m)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn guess_common_types() {
+        assert_eq!(guess_from_path(Path::new("a/b.png")), Some("image/png"));
+        assert_eq!(guess_from_path(Path::new("README.TXT")), Some("text/plain"));
+        assert_eq!(guess_from_path(Path::new("noext")), None);
+    }
+}
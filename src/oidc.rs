@@ -0,0 +1,560 @@
+//! OpenID Connect discovery and token grants.
+//!
+//! Requires the **oidc** feature.
+//!
+//! For tools calling APIs protected by an OpenID Connect provider. The provider
+//! is found via `.well-known/openid-configuration` of the issuer, and tokens are
+//! obtained with the client credentials grant (for services) or the device
+//! authorization grant (for CLI tools with a user). Tokens are cached and refreshed
+//! before they expire.
+//!
+//! [`Oidc`] is a [`Middleware`] setting `Authorization: Bearer` on each request.
+//! The token requests are made by the agent that created the [`Oidc`], which should
+//! not be the agent with the middleware.
+//!
+//! ```no_run
+//! use ureq::Agent;
+//! use ureq::config::Config;
+//!
+//! let oidc = Agent::new_with_defaults()
+//!     .oidc("https://login.example.test/realms/main", "my-cli")
+//!     .scope("api");
+//!
+//! // Ask the user to log in.
+//! oidc.device_code(|auth| {
+//!     println!("Open {} and enter {}", auth.verification_uri, auth.user_code);
+//! })?;
+//!
+//! let agent: Agent = Config::builder().middleware(oidc).build().into();
+//!
+//! let me = agent.get("https://api.example.test/me").call()?;
+//! # Ok::<_, ureq::Error>(())
+//! ```
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use http::header::AUTHORIZATION;
+use http::{HeaderValue, Request, Response};
+use once_cell::sync::OnceCell;
+use serde_json::Value;
+
+use crate::middleware::{Middleware, MiddlewareNext};
+use crate::{Agent, Body, Error, SendBody};
+
+/// Refresh tokens this long before they expire.
+const EXPIRY_MARGIN: Duration = Duration::from_secs(30);
+
+const GRANT_DEVICE_CODE: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+/// A client of an OpenID Connect provider.
+///
+/// Created by [`Agent::oidc()`]. See the [module](self) documentation.
+///
+/// Clones share the discovered configuration and the cached token.
+#[derive(Clone)]
+pub struct Oidc {
+    agent: Agent,
+    issuer: String,
+    client_id: String,
+    client_secret: Option<String>,
+    scopes: Vec<String>,
+    state: Arc<State>,
+}
+
+#[derive(Default)]
+struct State {
+    discovery: OnceCell<Discovery>,
+    token: Mutex<Option<Token>>,
+}
+
+/// The provider configuration found via discovery.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct Discovery {
+    /// The issuer, as stated by the provider.
+    pub issuer: String,
+    /// Endpoint for token grants.
+    pub token_endpoint: String,
+    /// Endpoint to start a device authorization, if supported.
+    pub device_authorization_endpoint: Option<String>,
+    /// Endpoint of the user info.
+    pub userinfo_endpoint: Option<String>,
+    /// The keys to verify the signature of ID tokens.
+    pub jwks_uri: Option<String>,
+}
+
+/// A token from the provider.
+#[derive(Clone)]
+pub struct Token {
+    access_token: String,
+    refresh_token: Option<String>,
+    id_token: Option<String>,
+    expires_at: Option<Instant>,
+}
+
+/// What to show the user during a device authorization.
+///
+/// See [`Oidc::device_code()`].
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct DeviceAuthorization {
+    /// Code for the user to enter.
+    pub user_code: String,
+    /// Page where the user enters the code.
+    pub verification_uri: String,
+    /// Page with the code already filled in, if supported.
+    pub verification_uri_complete: Option<String>,
+    /// How long the user has to complete the authorization.
+    pub expires_in: Duration,
+    device_code: String,
+    interval: Duration,
+}
+
+impl Oidc {
+    pub(crate) fn new(agent: Agent, issuer: &str, client_id: &str) -> Self {
+        Oidc {
+            agent,
+            issuer: issuer.trim_end_matches('/').to_string(),
+            client_id: client_id.to_string(),
+            client_secret: None,
+            scopes: vec![],
+            state: Arc::default(),
+        }
+    }
+
+    /// The secret of a confidential client.
+    ///
+    /// Required for the client credentials grant.
+    pub fn client_secret(mut self, v: &str) -> Self {
+        self.client_secret = Some(v.to_string());
+        self
+    }
+
+    /// Add a scope to request.
+    pub fn scope(mut self, v: &str) -> Self {
+        self.scopes.push(v.to_string());
+        self
+    }
+
+    /// The provider configuration, fetched once on first use.
+    pub fn discovery(&self) -> Result<&Discovery, Error> {
+        self.state.discovery.get_or_try_init(|| {
+            let uri = format!("{}/.well-known/openid-configuration", self.issuer);
+            let json: Value = self.agent.get(&uri).call()?.body_mut().read_json()?;
+
+            let string = |name: &str| json.get(name).and_then(Value::as_str).map(String::from);
+
+            Ok(Discovery {
+                issuer: string("issuer").unwrap_or_else(|| self.issuer.clone()),
+                token_endpoint: string("token_endpoint")
+                    .ok_or_else(|| Error::Oidc("discovery without token_endpoint".into()))?,
+                device_authorization_endpoint: string("device_authorization_endpoint"),
+                userinfo_endpoint: string("userinfo_endpoint"),
+                jwks_uri: string("jwks_uri"),
+            })
+        })
+    }
+
+    /// Get a token with the client credentials grant.
+    ///
+    /// The token is cached for [`Oidc::access_token()`].
+    pub fn client_credentials(&self) -> Result<Token, Error> {
+        let token = self.client_credentials_grant()?;
+        *self.state.token.lock().unwrap() = Some(token.clone());
+        Ok(token)
+    }
+
+    /// Get a token with the device authorization grant.
+    ///
+    /// `prompt` is called with the code for the user to enter, after which the
+    /// provider is polled until the user has logged in. The token is cached for
+    /// [`Oidc::access_token()`].
+    pub fn device_code(&self, prompt: impl FnOnce(&DeviceAuthorization)) -> Result<Token, Error> {
+        let endpoint = self
+            .discovery()?
+            .device_authorization_endpoint
+            .as_deref()
+            .ok_or_else(|| Error::Oidc("provider doesn't support device authorization".into()))?;
+
+        let scope = self.scopes.join(" ");
+        let mut form = vec![("client_id", self.client_id.as_str())];
+        if !scope.is_empty() {
+            form.push(("scope", scope.as_str()));
+        }
+
+        let json = self.post_form(endpoint, &form)?.map_err(oauth_error)?;
+
+        let string = |name: &str| json.get(name).and_then(Value::as_str).map(String::from);
+        let seconds = |name: &str, default: u64| {
+            Duration::from_secs(json.get(name).and_then(Value::as_u64).unwrap_or(default))
+        };
+
+        let mut auth = DeviceAuthorization {
+            user_code: string("user_code").unwrap_or_default(),
+            verification_uri: string("verification_uri")
+                .or_else(|| string("verification_url"))
+                .unwrap_or_default(),
+            verification_uri_complete: string("verification_uri_complete"),
+            expires_in: seconds("expires_in", 600),
+            device_code: string("device_code")
+                .ok_or_else(|| Error::Oidc("device authorization without device_code".into()))?,
+            interval: seconds("interval", 5),
+        };
+
+        prompt(&auth);
+
+        let deadline = Instant::now() + auth.expires_in;
+
+        let token = loop {
+            thread::sleep(auth.interval);
+
+            let form = [
+                ("grant_type", GRANT_DEVICE_CODE),
+                ("device_code", auth.device_code.as_str()),
+                ("client_id", self.client_id.as_str()),
+            ];
+
+            match self.token_grant(&form)? {
+                Ok(token) => break token,
+                Err((code, _)) if code == "authorization_pending" => {}
+                Err((code, _)) if code == "slow_down" => auth.interval += Duration::from_secs(5),
+                Err(e) => return Err(oauth_error(e)),
+            }
+
+            if Instant::now() >= deadline {
+                return Err(Error::Oidc("device authorization expired".into()));
+            }
+        };
+
+        *self.state.token.lock().unwrap() = Some(token.clone());
+        Ok(token)
+    }
+
+    /// A valid access token.
+    ///
+    /// This is the cached token, unless it's about to expire. Then it's refreshed
+    /// using the refresh token if there is one, or a new one is obtained with the
+    /// client credentials grant if there is a client secret.
+    pub fn access_token(&self) -> Result<String, Error> {
+        // Held during the grant, so concurrent requests wait for the same token.
+        let mut cached = self.state.token.lock().unwrap();
+
+        if let Some(token) = &*cached {
+            if token.is_fresh() {
+                return Ok(token.access_token.clone());
+            }
+        }
+
+        let refresh_token = cached.as_ref().and_then(|t| t.refresh_token.clone());
+
+        let token = match refresh_token {
+            Some(refresh_token) => {
+                let form = [
+                    ("grant_type", "refresh_token"),
+                    ("refresh_token", refresh_token.as_str()),
+                    ("client_id", self.client_id.as_str()),
+                ];
+                let mut token = self.with_secret(&form, |f| self.token_grant(f))?;
+
+                // The provider may keep the refresh token as is.
+                if let Ok(t) = &mut token {
+                    t.refresh_token.get_or_insert(refresh_token);
+                }
+
+                match token {
+                    Ok(t) => t,
+                    // An expired refresh token can still be replaced without a user.
+                    Err(_) if self.client_secret.is_some() => self.client_credentials_grant()?,
+                    Err(e) => return Err(oauth_error(e)),
+                }
+            }
+            None if self.client_secret.is_some() => self.client_credentials_grant()?,
+            None => return Err(Error::Oidc("no token, log in with device_code()".into())),
+        };
+
+        let access_token = token.access_token.clone();
+        *cached = Some(token);
+
+        Ok(access_token)
+    }
+
+    /// Make the cached token expire, for instance after the API answered 401.
+    pub fn expire(&self) {
+        if let Some(token) = &mut *self.state.token.lock().unwrap() {
+            token.expires_at = Some(Instant::now());
+        }
+    }
+
+    fn client_credentials_grant(&self) -> Result<Token, Error> {
+        if self.client_secret.is_none() {
+            return Err(Error::Oidc(
+                "client credentials without client_secret".into(),
+            ));
+        }
+
+        let scope = self.scopes.join(" ");
+        let mut form = vec![
+            ("grant_type", "client_credentials"),
+            ("client_id", self.client_id.as_str()),
+        ];
+        if !scope.is_empty() {
+            form.push(("scope", scope.as_str()));
+        }
+
+        self.with_secret(&form, |f| self.token_grant(f))?
+            .map_err(oauth_error)
+    }
+
+    /// Add the client secret to `form`, if there is one.
+    fn with_secret<T>(&self, form: &[(&str, &str)], f: impl FnOnce(&[(&str, &str)]) -> T) -> T {
+        let mut form = form.to_vec();
+        if let Some(secret) = &self.client_secret {
+            form.push(("client_secret", secret.as_str()));
+        }
+        f(&form)
+    }
+
+    /// Make a grant at the token endpoint, where the inner error is the OAuth error.
+    fn token_grant(&self, form: &[(&str, &str)]) -> Result<Result<Token, OAuthError>, Error> {
+        let endpoint = &self.discovery()?.token_endpoint;
+
+        let json = match self.post_form(endpoint, form)? {
+            Ok(v) => v,
+            Err(e) => return Ok(Err(e)),
+        };
+
+        let string = |name: &str| json.get(name).and_then(Value::as_str).map(String::from);
+
+        let access_token = string("access_token")
+            .ok_or_else(|| Error::Oidc("token response without access_token".into()))?;
+
+        let expires_at = json
+            .get("expires_in")
+            .and_then(Value::as_u64)
+            .map(|s| Instant::now() + Duration::from_secs(s));
+
+        Ok(Ok(Token {
+            access_token,
+            refresh_token: string("refresh_token"),
+            id_token: string("id_token"),
+            expires_at,
+        }))
+    }
+
+    fn post_form(
+        &self,
+        endpoint: &str,
+        form: &[(&str, &str)],
+    ) -> Result<Result<Value, OAuthError>, Error> {
+        let mut response = self
+            .agent
+            .post(endpoint)
+            .header("accept", "application/json")
+            .config()
+            .http_status_as_error(false)
+            .build()
+            .send_form(form.iter().copied())?;
+
+        let status = response.status();
+        let json: Value = response.body_mut().read_json()?;
+
+        match json.get("error").and_then(Value::as_str) {
+            Some(code) => {
+                let description = json
+                    .get("error_description")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default();
+                Ok(Err((code.to_string(), description.to_string())))
+            }
+            None if !status.is_success() => Err(Error::StatusCode(status.as_u16())),
+            None => Ok(Ok(json)),
+        }
+    }
+}
+
+/// The `error` and `error_description` of an OAuth error response.
+type OAuthError = (String, String);
+
+fn oauth_error((code, description): OAuthError) -> Error {
+    if description.is_empty() {
+        Error::Oidc(code)
+    } else {
+        Error::Oidc(format!("{}: {}", code, description))
+    }
+}
+
+impl Token {
+    /// The access token, to send as `Authorization: Bearer`.
+    pub fn access_token(&self) -> &str {
+        &self.access_token
+    }
+
+    /// The refresh token, if the provider gave one.
+    pub fn refresh_token(&self) -> Option<&str> {
+        self.refresh_token.as_deref()
+    }
+
+    /// The ID token, if the provider gave one.
+    ///
+    /// This is a JWT, which isn't verified.
+    pub fn id_token(&self) -> Option<&str> {
+        self.id_token.as_deref()
+    }
+
+    /// Time until the token expires, if the provider said.
+    pub fn expires_in(&self) -> Option<Duration> {
+        self.expires_at
+            .map(|at| at.saturating_duration_since(Instant::now()))
+    }
+
+    fn is_fresh(&self) -> bool {
+        self.expires_in().map(|d| d > EXPIRY_MARGIN).unwrap_or(true)
+    }
+}
+
+impl Middleware for Oidc {
+    fn handle(
+        &self,
+        mut request: Request<SendBody>,
+        next: MiddlewareNext,
+    ) -> Result<Response<Body>, Error> {
+        // A request with its own authorization is left alone.
+        if !request.headers().contains_key(AUTHORIZATION) {
+            let token = self.access_token()?;
+            let mut value = HeaderValue::from_str(&format!("Bearer {}", token))
+                .map_err(|_| Error::Oidc("access token is not a valid header".into()))?;
+            value.set_sensitive(true);
+            request.headers_mut().insert(AUTHORIZATION, value);
+        }
+
+        let response = next.handle(request);
+
+        let unauthorized = match &response {
+            Ok(r) => r.status() == 401,
+            Err(Error::StatusCode(401)) => true,
+            Err(_) => false,
+        };
+
+        // The token was revoked or expired early. Get a new one next time.
+        if unauthorized {
+            self.expire();
+        }
+
+        response
+    }
+}
+
+impl fmt::Debug for Oidc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Oidc")
+            .field("issuer", &self.issuer)
+            .field("client_id", &self.client_id)
+            .field("scopes", &self.scopes)
+            .finish()
+    }
+}
+
+impl fmt::Debug for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Token")
+            .field("refresh_token", &self.refresh_token.is_some())
+            .field("id_token", &self.id_token.is_some())
+            .field("expires_in", &self.expires_in())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::Config;
+    use crate::transport::MockConnector;
+
+    fn response(body: &str) -> String {
+        format!(
+            "HTTP/1.1 200 OK\r\n\
+            Content-Type: application/json\r\n\
+            Content-Length: {}\r\n\
+            \r\n\
+            {}",
+            body.len(),
+            body
+        )
+    }
+
+    fn mock() -> MockConnector {
+        MockConnector::new()
+            .route(
+                "/.well-known/openid-configuration",
+                response(
+                    r#"{"issuer":"http://issuer.test",
+                        "token_endpoint":"http://issuer.test/token",
+                        "device_authorization_endpoint":"http://issuer.test/device"}"#,
+                ),
+            )
+            .route(
+                "/token",
+                response(r#"{"access_token":"abc","token_type":"Bearer","expires_in":3600}"#),
+            )
+            .route(
+                "/device",
+                response(
+                    r#"{"device_code":"dev","user_code":"WDJB-MJHT",
+                        "verification_uri":"http://issuer.test/activate",
+                        "expires_in":60,"interval":0}"#,
+                ),
+            )
+            .route("/api", "HTTP/1.1 204 No Content\r\n\r\n")
+    }
+
+    #[test]
+    fn client_credentials_as_bearer() {
+        let mock = mock();
+        let oidc = Agent::with_parts(Config::default(), mock.clone(), mock.clone())
+            .oidc("http://issuer.test/", "cli")
+            .client_secret("s3cret")
+            .scope("api");
+
+        let config = Config::builder().middleware(oidc.clone()).build();
+        let agent = Agent::with_parts(config, mock.clone(), mock.clone());
+
+        agent.get("http://api.test/api/1").call().unwrap();
+        agent.get("http://api.test/api/2").call().unwrap();
+
+        let requests: Vec<String> = mock
+            .requests()
+            .into_iter()
+            .map(|r| String::from_utf8(r).unwrap())
+            .collect();
+
+        // Discovery and a single token grant, the token is cached.
+        assert_eq!(requests.len(), 4);
+        assert!(requests[1].ends_with(
+            "grant_type=client_credentials&client_id=cli&scope=api&client_secret=s3cret"
+        ));
+        assert!(requests[2].contains("authorization: Bearer abc\r\n"));
+        assert!(requests[3].contains("authorization: Bearer abc\r\n"));
+    }
+
+    #[test]
+    fn device_code_grant() {
+        let mock = mock();
+        let oidc = Agent::with_parts(Config::default(), mock.clone(), mock.clone())
+            .oidc("http://issuer.test", "cli");
+
+        let mut shown = None;
+        let token = oidc
+            .device_code(|auth| shown = Some(auth.user_code.clone()))
+            .unwrap();
+
+        assert_eq!(shown.as_deref(), Some("WDJB-MJHT"));
+        assert_eq!(token.access_token(), "abc");
+        assert_eq!(oidc.access_token().unwrap(), "abc");
+
+        let request = String::from_utf8(mock.requests().pop().unwrap()).unwrap();
+        assert!(request.contains("device_code=dev"));
+    }
+}
@@ -0,0 +1,346 @@
+//! Requests composed from OpenAPI operations.
+//!
+//! Requires the **openapi** feature.
+//!
+//! An [OpenAPI] operation is a method and a path template, with parameters that go
+//! in the path, the query, headers or cookies. This module places the parameters
+//! consistently, so that code generated from an OpenAPI document only needs to say
+//! where each parameter goes, instead of building the request itself.
+//!
+//! ```
+//! use ureq::http::Method;
+//! use ureq::openapi::{Operation, Params, Placement};
+//!
+//! /// Parameters of `GET /pets/{petId}`.
+//! struct GetPet {
+//!     pet_id: u64,
+//!     fields: Option<String>,
+//!     request_id: String,
+//! }
+//!
+//! impl Params for GetPet {
+//!     fn place(&self, p: &mut Placement) {
+//!         p.path("petId", self.pet_id);
+//!         if let Some(fields) = &self.fields {
+//!             p.query("fields", fields);
+//!         }
+//!         p.header("x-request-id", &self.request_id);
+//!     }
+//! }
+//!
+//! const GET_PET: Operation = Operation::new(Method::GET, "/pets/{petId}");
+//!
+//! let params = GetPet {
+//!     pet_id: 42,
+//!     fields: Some("name,tag".into()),
+//!     request_id: "abc".into(),
+//! };
+//!
+//! let request = GET_PET.build("https://petstore.test/v1", &params)?;
+//! assert_eq!(request.uri(), "https://petstore.test/v1/pets/42?fields=name%2Ctag");
+//!
+//! # fn no_run(request: ureq::http::Request<()>) -> Result<(), ureq::Error> {
+//! let response = ureq::run(request)?;
+//! # Ok(())}
+//! # Ok::<_, ureq::Error>(())
+//! ```
+//!
+//! A request body is added by mapping the request, such as
+//! `request.map(|_| json_bytes)`, and the request is sent with [`Agent::run()`].
+//!
+//! [OpenAPI]: https://spec.openapis.org/oas/latest.html
+
+use std::fmt;
+
+use http::{Method, Request};
+
+use crate::config::UrlEncoding;
+use crate::query::url_enc;
+use crate::{Agent, AsSendBody, Body, Error};
+
+/// An operation of an API: a method and a path template.
+///
+/// The template has parameters in braces, such as `/pets/{petId}`.
+#[derive(Debug, Clone)]
+pub struct Operation {
+    method: Method,
+    path: &'static str,
+}
+
+/// The parameters of an operation, typically generated from the OpenAPI document.
+///
+/// See the [module](self) documentation for an example.
+pub trait Params {
+    /// Tell where each parameter goes.
+    fn place(&self, placement: &mut Placement);
+}
+
+/// No parameters.
+impl Params for () {
+    fn place(&self, _: &mut Placement) {}
+}
+
+/// Where the parameters of an operation go, as told by [`Params::place()`].
+///
+/// Values are formatted with [`Display`](fmt::Display). Path and query values are
+/// percent encoded, keeping only the unreserved characters of RFC 3986. Query
+/// parameters given repeatedly are repeated in the query, which is the default
+/// `form` style of OpenAPI for arrays. Cookie values are sent as is, and must be
+/// valid cookie values (RFC 6265), without whitespace, `"`, `,`, `;` or `\`.
+#[derive(Debug, Default)]
+pub struct Placement {
+    path: Vec<(&'static str, String)>,
+    query: Vec<(&'static str, String)>,
+    headers: Vec<(&'static str, String)>,
+    cookies: Vec<(&'static str, String)>,
+}
+
+impl Operation {
+    /// Creates an operation.
+    pub const fn new(method: Method, path: &'static str) -> Self {
+        Operation { method, path }
+    }
+
+    /// The method of the operation.
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    /// The path template of the operation.
+    pub fn path(&self) -> &str {
+        self.path
+    }
+
+    /// Build the request of the operation against the server at `base`.
+    ///
+    /// The path is appended to `base`, which may have a path of its own, such as
+    /// `https://api.test/v1`. Fails with [`Error::BadUri`] if the template has
+    /// parameters that aren't placed, or the other way around, and with
+    /// [`Error::BadHeader`] for a cookie value that isn't valid.
+    pub fn build(&self, base: &str, params: &(impl Params + ?Sized)) -> Result<Request<()>, Error> {
+        let mut placement = Placement::default();
+        params.place(&mut placement);
+
+        let path = self.expand(&placement.path)?;
+
+        let mut uri = format!("{}{}", base.trim_end_matches('/'), path);
+        for (i, (name, value)) in placement.query.iter().enumerate() {
+            uri.push(if i == 0 { '?' } else { '&' });
+            uri.push_str(&url_enc(name, UrlEncoding::Rfc3986));
+            uri.push('=');
+            uri.push_str(&url_enc(value, UrlEncoding::Rfc3986));
+        }
+
+        let mut builder = Request::builder().method(self.method.clone()).uri(uri);
+
+        for (name, value) in &placement.headers {
+            builder = builder.header(*name, value.as_str());
+        }
+
+        if !placement.cookies.is_empty() {
+            if let Some((name, _)) = placement.cookies.iter().find(|(_, v)| !is_cookie_value(v)) {
+                return Err(Error::BadHeader(format!("cookie value of {}", name)));
+            }
+
+            let cookies: Vec<String> = placement
+                .cookies
+                .iter()
+                .map(|(name, value)| format!("{}={}", name, value))
+                .collect();
+            builder = builder.header("cookie", cookies.join("; "));
+        }
+
+        Ok(builder.body(())?)
+    }
+
+    /// Build the request and run it on the `agent`, without body.
+    pub fn call(
+        &self,
+        agent: &Agent,
+        base: &str,
+        params: &(impl Params + ?Sized),
+    ) -> Result<http::Response<Body>, Error> {
+        agent.run(self.build(base, params)?)
+    }
+
+    /// Build the request and run it on the `agent`, with a body.
+    ///
+    /// The content type, if any, is best placed as a header parameter.
+    pub fn send(
+        &self,
+        agent: &Agent,
+        base: &str,
+        params: &(impl Params + ?Sized),
+        body: impl AsSendBody,
+    ) -> Result<http::Response<Body>, Error> {
+        agent.run(self.build(base, params)?.map(|_| body))
+    }
+
+    /// The path with the parameters in the template replaced.
+    fn expand(&self, values: &[(&'static str, String)]) -> Result<String, Error> {
+        let mut path = String::with_capacity(self.path.len());
+        let mut rest = self.path;
+
+        while let Some(start) = rest.find('{') {
+            let Some(len) = rest[start..].find('}') else {
+                return Err(Error::BadUri(format!("unclosed parameter: {}", self.path)));
+            };
+            let name = &rest[start + 1..start + len];
+
+            let Some((_, value)) = values.iter().find(|(n, _)| *n == name) else {
+                return Err(Error::BadUri(format!("missing path parameter: {}", name)));
+            };
+
+            path.push_str(&rest[..start]);
+            path.push_str(&url_enc(value, UrlEncoding::Rfc3986));
+            rest = &rest[start + len + 1..];
+        }
+        path.push_str(rest);
+
+        let unknown = values
+            .iter()
+            .find(|(name, _)| !self.path.contains(&format!("{{{}}}", name)));
+        if let Some((name, _)) = unknown {
+            return Err(Error::BadUri(format!("unknown path parameter: {}", name)));
+        }
+
+        Ok(path)
+    }
+}
+
+/// Whether `v` is made of cookie-octets (RFC 6265, section 4.1.1), which keeps it
+/// from ending the value, or adding cookies of its own.
+fn is_cookie_value(v: &str) -> bool {
+    v.bytes()
+        .all(|b| matches!(b, 0x21 | 0x23..=0x2b | 0x2d..=0x3a | 0x3c..=0x5b | 0x5d..=0x7e))
+}
+
+impl Placement {
+    /// Place a parameter in the path template.
+    pub fn path(&mut self, name: &'static str, value: impl fmt::Display) {
+        self.path.push((name, value.to_string()));
+    }
+
+    /// Place a parameter in the query.
+    pub fn query(&mut self, name: &'static str, value: impl fmt::Display) {
+        self.query.push((name, value.to_string()));
+    }
+
+    /// Place a parameter in a header.
+    pub fn header(&mut self, name: &'static str, value: impl fmt::Display) {
+        self.headers.push((name, value.to_string()));
+    }
+
+    /// Place a parameter in the `Cookie` header.
+    pub fn cookie(&mut self, name: &'static str, value: impl fmt::Display) {
+        self.cookies.push((name, value.to_string()));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::Config;
+    use crate::transport::MockConnector;
+
+    struct UpdatePet {
+        store: &'static str,
+        pet_id: &'static str,
+        tags: Vec<&'static str>,
+        session: &'static str,
+    }
+
+    impl Params for UpdatePet {
+        fn place(&self, p: &mut Placement) {
+            p.path("store", self.store);
+            p.path("petId", self.pet_id);
+            for tag in &self.tags {
+                p.query("tag", tag);
+            }
+            p.header("content-type", "application/json");
+            p.cookie("session", self.session);
+            p.cookie("theme", "dark");
+        }
+    }
+
+    const UPDATE_PET: Operation = Operation::new(Method::PUT, "/stores/{store}/pets/{petId}");
+
+    fn params() -> UpdatePet {
+        UpdatePet {
+            store: "north",
+            pet_id: "a b/c",
+            tags: vec!["cat", "black & white"],
+            session: "s1",
+        }
+    }
+
+    #[test]
+    fn place_params() {
+        let req = UPDATE_PET.build("http://api.test/v1/", &params()).unwrap();
+
+        assert_eq!(req.method(), Method::PUT);
+        assert_eq!(
+            req.uri(),
+            "http://api.test/v1/stores/north/pets/a%20b%2Fc?tag=cat&tag=black%20%26%20white"
+        );
+        assert_eq!(req.headers()["content-type"], "application/json");
+        assert_eq!(req.headers()["cookie"], "session=s1; theme=dark");
+    }
+
+    #[test]
+    fn path_mismatch() {
+        struct Extra;
+        impl Params for Extra {
+            fn place(&self, p: &mut Placement) {
+                p.path("petId", 1);
+                p.path("owner", 2);
+            }
+        }
+
+        let get = Operation::new(Method::GET, "/pets/{petId}");
+        let err = get.build("http://api.test", &()).unwrap_err();
+        assert_eq!(err.to_string(), "bad uri: missing path parameter: petId");
+        let err = get.build("http://api.test", &Extra).unwrap_err();
+        assert!(matches!(err, Error::BadUri(_)));
+
+        let list = Operation::new(Method::GET, "/pets");
+        assert_eq!(
+            list.build("http://api.test", &()).unwrap().uri(),
+            "http://api.test/pets"
+        );
+    }
+
+    #[test]
+    fn cookie_values() {
+        struct Cookie(&'static str);
+        impl Params for Cookie {
+            fn place(&self, p: &mut Placement) {
+                p.cookie("session", self.0);
+            }
+        }
+
+        let list = Operation::new(Method::GET, "/pets");
+        for value in ["a; admin=1", "a,b", "a b", "\"a\""] {
+            let err = list.build("http://api.test", &Cookie(value)).unwrap_err();
+            assert_eq!(err.to_string(), "bad header: cookie value of session");
+        }
+
+        let req = list.build("http://api.test", &Cookie("dGVzdA==")).unwrap();
+        assert_eq!(req.headers()["cookie"], "session=dGVzdA==");
+    }
+
+    #[test]
+    fn send_body() {
+        let mock = MockConnector::new().route("/", "HTTP/1.1 204 No Content\r\n\r\n");
+        let agent = Agent::with_parts(Config::default(), mock.clone(), mock.clone());
+
+        let res = UPDATE_PET
+            .send(&agent, "http://api.test", &params(), r#"{"name":"Tom"}"#)
+            .unwrap();
+        assert_eq!(res.status(), 204);
+
+        let request = String::from_utf8(mock.requests().pop().unwrap()).unwrap();
+        assert!(request.starts_with("PUT /stores/north/pets/a%20b%2Fc?tag=cat&"));
+        assert!(request.ends_with(r#"{"name":"Tom"}"#));
+    }
+}
@@ -0,0 +1,231 @@
+use std::fmt;
+
+use http::{header, Request, Response};
+
+use crate::early_hints::{split_links, LinkHint};
+use crate::run::copy_request;
+use crate::util::DebugUri;
+use crate::{Agent, Body, Error};
+
+type NextPage = dyn FnMut(&mut Response<Body>) -> Result<Option<Request<()>>, Error>;
+
+/// Iterator over the pages of a paginated API.
+///
+/// Created by [`Agent::paginate()`]. Each item is the response for one page. The
+/// next request is only made when the iterator is advanced, and the iteration ends
+/// after the last page, or after the first error.
+///
+/// By default the next page is found via a `Link` header with `rel="next"`, as used
+/// by GitHub and many other APIs.
+///
+/// ```
+/// use ureq::Agent;
+///
+/// let agent = Agent::new_with_defaults();
+/// let request = http::Request::get("https://api.github.com/repos/algesten/ureq/issues")
+///     .body(())?;
+///
+/// for page in agent.paginate(request).take(3) {
+///     let text = page?.body_mut().read_to_string()?;
+/// }
+/// # Ok::<_, ureq::Error>(())
+/// ```
+///
+/// APIs that put a cursor in the body are handled with [`Pages::next_page()`].
+pub struct Pages {
+    agent: Agent,
+    next: Option<Request<()>>,
+    next_page: Option<Box<NextPage>>,
+}
+
+impl Pages {
+    pub(crate) fn new(agent: Agent, request: Request<()>) -> Self {
+        Pages {
+            agent,
+            next: Some(request),
+            next_page: None,
+        }
+    }
+
+    /// Find the next page using a closure instead of the `Link` header.
+    ///
+    /// The closure gets each response and returns the request for the following page,
+    /// or `None` when there are no more pages. This covers APIs that paginate via a
+    /// cursor in the response body.
+    ///
+    /// The closure can read the body. To make this possible, each body is read into
+    /// memory first (limited to 10MB), and the response is yielded with the full body
+    /// regardless of what the closure read.
+    ///
+    /// ```
+    /// # #[cfg(feature = "json")]
+    /// # {
+    /// use ureq::Agent;
+    ///
+    /// let agent = Agent::new_with_defaults();
+    /// let request = http::Request::get("http://httpbin.org/get").body(())?;
+    ///
+    /// let pages = agent.paginate(request).next_page(|res| {
+    ///     let json: serde_json::Value = res.body_mut().read_json()?;
+    ///
+    ///     let Some(cursor) = json["next_cursor"].as_str() else {
+    ///         return Ok(None);
+    ///     };
+    ///
+    ///     let uri = format!("http://httpbin.org/get?cursor={}", cursor);
+    ///     Ok(Some(http::Request::get(uri).body(())?))
+    /// });
+    ///
+    /// for page in pages {
+    ///     let json: serde_json::Value = page?.body_mut().read_json()?;
+    /// }
+    /// # }
+    /// # Ok::<_, ureq::Error>(())
+    /// ```
+    pub fn next_page<F>(mut self, f: F) -> Self
+    where
+        F: FnMut(&mut Response<Body>) -> Result<Option<Request<()>>, Error> + 'static,
+    {
+        self.next_page = Some(Box::new(f));
+        self
+    }
+
+    fn fetch(&mut self, request: Request<()>) -> Result<Response<Body>, Error> {
+        let template = copy_request(&request);
+
+        let mut response = self.agent.run(request)?;
+
+        let Some(next_page) = &mut self.next_page else {
+            self.next = next_from_link(&template, &response);
+            return Ok(response);
+        };
+
+        let data = response.body_mut().read_to_vec()?;
+        let mime_type = response.body().mime_type().map(|m| m.to_string());
+
+        let rebuild = |data: Vec<u8>| match &mime_type {
+            Some(m) => Body::builder().mime_type(m.clone()).data(data),
+            None => Body::builder().data(data),
+        };
+
+        *response.body_mut() = rebuild(data.clone());
+        self.next = next_page(&mut response)?;
+        *response.body_mut() = rebuild(data);
+
+        Ok(response)
+    }
+}
+
+impl Iterator for Pages {
+    type Item = Result<Response<Body>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let request = self.next.take()?;
+        Some(self.fetch(request))
+    }
+}
+
+/// The request for the `rel="next"` link, if any.
+///
+/// Method and headers are kept when the next page is on the same host.
+fn next_from_link(template: &Request<()>, response: &Response<Body>) -> Option<Request<()>> {
+    let base = template.uri();
+
+    let uri = response
+        .headers()
+        .get_all(header::LINK)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(split_links)
+        .filter_map(|l| LinkHint::parse(l, base))
+        .find(|l| {
+            l.rel()
+                .map(|r| r.split_whitespace().any(|r| r.eq_ignore_ascii_case("next")))
+                .unwrap_or(false)
+        })?
+        .uri()
+        .clone();
+
+    let mut next = copy_request(template);
+
+    if uri.authority() != base.authority() {
+        next.headers_mut().clear();
+    }
+
+    *next.uri_mut() = uri;
+
+    Some(next)
+}
+
+impl fmt::Debug for Pages {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let redaction = self.agent.config().redaction();
+        f.debug_struct("Pages")
+            .field(
+                "next",
+                &self.next.as_ref().map(|r| DebugUri(r.uri(), redaction)),
+            )
+            .field("next_page", &self.next_page.is_some())
+            .finish()
+    }
+}
+
+#[cfg(all(test, feature = "_test"))]
+mod test {
+    use super::*;
+    use crate::test::init_test_log;
+    use crate::transport::set_handler;
+
+    #[test]
+    fn paginate_link_header() {
+        init_test_log();
+        set_handler(
+            "/pages_1",
+            200,
+            &[
+                ("Link", "</pages_2>; rel=\"next\", </pages_1>; rel=first"),
+                ("Content-Length", "3"),
+            ],
+            b"one",
+        );
+        set_handler("/pages_2", 200, &[("Content-Length", "3")], b"two");
+
+        let agent = Agent::new_with_defaults();
+        let request = Request::get("http://example.org/pages_1").body(()).unwrap();
+
+        let bodies: Vec<_> = agent
+            .paginate(request)
+            .map(|r| r.unwrap().body_mut().read_to_string().unwrap())
+            .collect();
+
+        assert_eq!(bodies, ["one", "two"]);
+    }
+
+    #[test]
+    fn paginate_body_cursor() {
+        init_test_log();
+        set_handler("/cursor_a", 200, &[("Content-Length", "8")], b"cursor_b");
+        set_handler("/cursor_b", 200, &[("Content-Length", "0")], b"");
+
+        let agent = Agent::new_with_defaults();
+        let request = Request::get("http://example.org/cursor_a")
+            .body(())
+            .unwrap();
+
+        let pages = agent.paginate(request).next_page(|res| {
+            let cursor = res.body_mut().read_to_string()?;
+            if cursor.is_empty() {
+                return Ok(None);
+            }
+            let uri = format!("http://example.org/{}", cursor);
+            Ok(Some(Request::get(uri).body(())?))
+        });
+
+        let bodies: Vec<_> = pages
+            .map(|r| r.unwrap().body_mut().read_to_string().unwrap())
+            .collect();
+
+        // The closure read the body, but the pages still have it.
+        assert_eq!(bodies, ["cursor_b", ""]);
+    }
+}
@@ -0,0 +1,130 @@
+use std::fmt;
+use std::io;
+use std::time::Duration;
+
+use http::Response;
+
+use crate::run::PhasedCall;
+use crate::{Body, Error};
+
+/// A request made in phases, with the request head already sent.
+///
+/// Created by [`Agent::send_head()`][crate::Agent::send_head]. The call goes
+/// through four phases, each under the control of the caller:
+///
+/// 1. Send the request head, which happens in `send_head()`.
+/// 2. Stream the request body by writing to this `BodyWriter`.
+/// 3. Read the response head with [`BodyWriter::read_head()`].
+/// 4. Stream the response body from the returned [`Body`].
+///
+/// Unless the request has a `Content-Length` header, the body is sent with
+/// `Transfer-Encoding: chunked`. A chunked body can be ended at any point by
+/// reading the response head. Together with [`BodyWriter::response_started()`],
+/// this makes it possible to stop sending when the server responds early, such
+/// as with a `413 Payload Too Large`.
+///
+/// This is low level API. Middleware, retries and redirects are not applied.
+/// Redirect responses are returned as is.
+///
+/// ```
+/// use std::io::Write;
+/// use ureq::Agent;
+///
+/// let agent = Agent::new_with_defaults();
+/// let request = http::Request::post("http://httpbin.org/post").body(())?;
+///
+/// let mut writer = agent.send_head(request)?;
+///
+/// for i in 0..3 {
+///     writeln!(writer, "line {}", i)?;
+/// }
+///
+/// let mut response = writer.read_head()?;
+/// let text = response.body_mut().read_to_string()?;
+/// # Ok::<_, ureq::Error>(())
+/// ```
+pub struct BodyWriter {
+    call: PhasedCall,
+}
+
+impl BodyWriter {
+    pub(crate) fn new(call: PhasedCall) -> Self {
+        BodyWriter { call }
+    }
+
+    /// Check whether the server has started responding.
+    ///
+    /// Waits at most `wait` for the first bytes of a response. This is useful
+    /// between writes, to find out whether the server responds before the body
+    /// is sent.
+    pub fn response_started(&mut self, wait: Duration) -> Result<bool, Error> {
+        self.call.response_started(wait)
+    }
+
+    /// End the request body and read the response head.
+    ///
+    /// The returned response body is read as usual. A body shorter than the
+    /// `Content-Length` header is an error.
+    pub fn read_head(self) -> Result<Response<Body>, Error> {
+        self.call.read_head()
+    }
+}
+
+impl io::Write for BodyWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.call.write(buf).map_err(|e| e.into_io())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        // Every write is transmitted right away.
+        Ok(())
+    }
+}
+
+impl fmt::Debug for BodyWriter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BodyWriter").finish()
+    }
+}
+
+#[cfg(all(test, feature = "_test"))]
+mod test {
+    use std::io::Write;
+
+    use http::Request;
+
+    use crate::test::init_test_log;
+    use crate::Agent;
+
+    #[test]
+    fn send_in_phases() {
+        init_test_log();
+
+        let agent = Agent::new_with_defaults();
+        let request = Request::post("http://httpbin.org/post").body(()).unwrap();
+
+        let mut writer = agent.send_head(request).unwrap();
+        writer.write_all(b"hello ").unwrap();
+        writer.write_all(b"world").unwrap();
+
+        let mut response = writer.read_head().unwrap();
+        assert_eq!(response.status(), 200);
+        response.body_mut().read_to_string().unwrap();
+    }
+
+    #[test]
+    fn short_body_is_error() {
+        init_test_log();
+
+        let agent = Agent::new_with_defaults();
+        let request = Request::post("http://httpbin.org/post")
+            .header("content-length", "10")
+            .body(())
+            .unwrap();
+
+        let mut writer = agent.send_head(request).unwrap();
+        writer.write_all(b"hello").unwrap();
+
+        assert!(writer.read_head().is_err());
+    }
+}
@@ -1,20 +1,30 @@
 use std::collections::VecDeque;
 use std::fmt;
-use std::sync::{Arc, Mutex, Weak};
+use std::fs::File;
+use std::io::IoSlice;
+use std::net::SocketAddr;
+use std::sync::{Arc, Condvar, Mutex, Weak};
+use std::time;
 
 use http::uri::{Authority, Scheme};
 use http::Uri;
+use once_cell::sync::OnceCell;
 
 use crate::config::Config;
 use crate::proxy::Proxy;
+use crate::resolver::ResolvedSocketAddrs;
+#[cfg(feature = "_tls")]
+use crate::tls::TlsConfigId;
 use crate::transport::time::{Duration, Instant};
-use crate::transport::{Buffers, ConnectionDetails, Connector, NextTimeout, Transport};
+use crate::transport::Transport;
+use crate::transport::{Buffers, ConnectionDetails, ConnectionInfo, Connector, NextTimeout};
 use crate::util::DebugAuthority;
-use crate::Error;
+use crate::{Error, Timeout};
 
 pub(crate) struct ConnectionPool {
     connector: Box<dyn Connector>,
     pool: Arc<Mutex<Pool>>,
+    slots: Arc<HostSlots>,
 }
 
 impl ConnectionPool {
@@ -22,6 +32,7 @@ impl ConnectionPool {
         ConnectionPool {
             connector: Box::new(connector),
             pool: Arc::new(Mutex::new(Pool::new(config))),
+            slots: Arc::new(HostSlots::new(config.max_connections_per_host)),
         }
     }
 
@@ -30,14 +41,59 @@ impl ConnectionPool {
         details: &ConnectionDetails,
         max_idle_age: Duration,
     ) -> Result<Connection, Error> {
-        let key = PoolKey::new(details.uri, &details.config.proxy);
+        let key = PoolKey::new(
+            details.uri,
+            details.config,
+            details.server_name,
+            details.config.connection_tag.as_deref(),
+        );
+
+        if let Some(pre) = &details.config.pre_connected {
+            // The connection is not pooled, since it's not ours to reuse.
+            let transport = pre.take().ok_or(Error::ConnectionFailed)?;
+            debug!("Use pre-connected: {:?}", key);
+
+            return Ok(Connection {
+                transport,
+                key,
+                created: details.now,
+                resolved: None,
+                info: OnceCell::new(),
+                requests: 0,
+                last_use: details.now,
+                pool: Weak::new(),
+                position_per_host: None,
+                slot: None,
+            });
+        }
+
+        // Waiting for a slot is part of the connect timeout.
+        let remaining = details.remaining(Timeout::Connect)?;
+        let wait = match details.config.connection_wait {
+            Some(v) if Duration::from(v) < remaining.after => NextTimeout {
+                after: v.into(),
+                reason: Timeout::ConnectionWait,
+            },
+            _ => remaining,
+        };
+
+        let slot = HostSlots::acquire(&self.slots, &key, wait)?;
+
+        // Nothing is resolved for proxies that resolve the host themselves.
+        let addrs = &details.addrs;
+        let resolved = addrs.into_iter().any(|a| !a.ip().is_unspecified());
 
         {
             let mut pool = self.pool.lock().unwrap();
             pool.purge(details.now);
 
-            if let Some(conn) = pool.get(&key, max_idle_age, details.now) {
+            if details.config.retire_on_dns_change && resolved {
+                pool.retire_moved(&key, addrs, details.now);
+            }
+
+            if let Some(mut conn) = pool.get(&key, max_idle_age, details.now) {
                 debug!("Use pooled: {:?}", key);
+                conn.slot = slot;
                 return Ok(conn);
             }
         }
@@ -47,17 +103,29 @@ impl ConnectionPool {
             .connect(details, None)?
             .ok_or(Error::ConnectionFailed)?;
 
+        // A lookup can only confirm a connection to one of the addresses it gives.
+        let checkable = resolved && transport.peer_addr().is_some();
+
         let conn = Connection {
             transport,
             key,
+            created: details.now,
+            resolved: checkable.then_some(details.now),
+            info: OnceCell::new(),
+            requests: 0,
             last_use: details.now,
             pool: Arc::downgrade(&self.pool),
             position_per_host: None,
+            slot,
         };
 
         Ok(conn)
     }
 
+    pub fn drain_stats(&self) -> DrainStats {
+        self.pool.lock().unwrap().drain_stats
+    }
+
     #[cfg(test)]
     pub fn pool_count(&self) -> usize {
         let lock = self.pool.lock().unwrap();
@@ -68,6 +136,16 @@ impl ConnectionPool {
 pub(crate) struct Connection {
     transport: Box<dyn Transport>,
     key: PoolKey,
+    created: Instant,
+    /// When a lookup of the host last included the peer address.
+    ///
+    /// `None` for connections a lookup can't confirm, such as through a proxy that
+    /// resolves the host itself, or without a peer address. They don't expire.
+    resolved: Option<Instant>,
+    /// Asked of the transport on the first response, shared by all its responses.
+    info: OnceCell<Arc<ConnectionInfo>>,
+    /// Number of requests done on this connection.
+    requests: u32,
     last_use: Instant,
     pool: Weak<Mutex<Pool>>,
 
@@ -90,6 +168,9 @@ pub(crate) struct Connection {
     /// Once we have that enumeration, we can drop elements from the front where there
     /// position_per_host >= idle_per_host.
     position_per_host: Option<usize>,
+
+    /// Counts towards max_connections_per_host while the connection is in use.
+    slot: Option<HostSlot>,
 }
 
 impl Connection {
@@ -101,6 +182,25 @@ impl Connection {
         self.transport.transmit_output(amount, timeout)
     }
 
+    pub fn transmit_output_vectored(
+        &mut self,
+        amount: usize,
+        bufs: &[IoSlice<'_>],
+        timeout: NextTimeout,
+    ) -> Result<(), Error> {
+        self.transport
+            .transmit_output_vectored(amount, bufs, timeout)
+    }
+
+    pub fn send_file(
+        &mut self,
+        file: &File,
+        len: u64,
+        timeout: NextTimeout,
+    ) -> Result<bool, Error> {
+        self.transport.send_file(file, len, timeout)
+    }
+
     pub fn await_input(&mut self, timeout: NextTimeout) -> Result<bool, Error> {
         self.transport.await_input(timeout)
     }
@@ -109,12 +209,28 @@ impl Connection {
         self.transport.buffers().input_consume(amount)
     }
 
+    pub fn info(&self) -> Arc<ConnectionInfo> {
+        self.info
+            .get_or_init(|| Arc::new(self.transport.info()))
+            .clone()
+    }
+
     pub fn close(self) {
         debug!("Close: {:?}", self.key);
         // Just consume self.
     }
 
     pub fn reuse(mut self, now: Instant) {
+        self.requests += 1;
+        self.park(now);
+    }
+
+    /// Return the connection to the pool, without counting a request.
+    pub fn park(mut self, now: Instant) {
+        // Idle connections don't count as in use. Released before locking the
+        // pool to not hold both locks.
+        self.slot = None;
+
         if !self.transport.is_open() {
             // The purpose of probing is that is_open() for tcp connector attempts
             // to read some more bytes. If that succeeds, the connection is considered
@@ -134,20 +250,89 @@ impl Connection {
 
         let mut pool = arc.lock().unwrap();
 
+        if pool.is_retired(&self, now) {
+            debug!("Retire: {:?}", self.key);
+            return;
+        }
+
         pool.add(self);
         pool.purge(now);
     }
 
+    pub fn drain_stats(&self) -> DrainStatsHandle {
+        DrainStatsHandle(self.pool.clone())
+    }
+
     fn age(&self, now: Instant) -> Duration {
         now.duration_since(now)
     }
 
+    fn lifetime(&self, now: Instant) -> Duration {
+        now.duration_since(self.created)
+    }
+
     fn is_open(&mut self) -> bool {
         self.transport.is_open()
     }
+
+    fn peer_addr(&self) -> Option<SocketAddr> {
+        self.transport.peer_addr()
+    }
 }
 
-/// The pool key is the Scheme, Authority from the uri and the Proxy setting
+/// Transport given to [`Agent::request_on()`][crate::Agent::request_on], used
+/// instead of connecting.
+#[derive(Clone)]
+pub(crate) struct PreConnected(Arc<Mutex<Option<Box<dyn Transport>>>>);
+
+impl PreConnected {
+    pub fn new(transport: Box<dyn Transport>) -> Self {
+        PreConnected(Arc::new(Mutex::new(Some(transport))))
+    }
+
+    fn take(&self) -> Option<Box<dyn Transport>> {
+        self.0.lock().unwrap().take()
+    }
+}
+
+/// Statistics over response bodies that were dropped before being read to the end.
+///
+/// See [`ConfigBuilder::max_drain_on_drop()`][crate::config::ConfigBuilder::max_drain_on_drop].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct DrainStats {
+    /// Number of bodies drained to the end, keeping the connection reusable.
+    pub drained: u64,
+    /// Total number of bytes read and discarded while draining.
+    pub drained_bytes: u64,
+    /// Number of connections closed because of an unread body.
+    pub closed: u64,
+}
+
+/// Records drain statistics in the pool, if it's still around.
+pub(crate) struct DrainStatsHandle(Weak<Mutex<Pool>>);
+
+impl DrainStatsHandle {
+    pub fn drained(&self, amount: u64) {
+        self.update(|s| {
+            s.drained += 1;
+            s.drained_bytes += amount;
+        });
+    }
+
+    pub fn closed(&self) {
+        self.update(|s| s.closed += 1);
+    }
+
+    fn update(&self, f: impl FnOnce(&mut DrainStats)) {
+        if let Some(arc) = self.0.upgrade() {
+            f(&mut arc.lock().unwrap().drain_stats);
+        }
+    }
+}
+
+/// The pool key is the Scheme, Authority from the uri, the Proxy setting, the TLS
+/// server name, the connection tag and the TLS config.
 ///
 ///
 /// ```notrust
@@ -164,11 +349,15 @@ impl Connection {
 struct PoolKey(Arc<PoolKeyInner>);
 
 impl PoolKey {
-    fn new(uri: &Uri, proxy: &Option<Proxy>) -> Self {
+    fn new(uri: &Uri, config: &Config, server_name: Option<&str>, tag: Option<&str>) -> Self {
         let inner = PoolKeyInner(
             uri.scheme().expect("uri with scheme").clone(),
             uri.authority().expect("uri with authority").clone(),
-            proxy.clone(),
+            config.proxy.clone(),
+            server_name.map(|s| s.to_string()),
+            tag.map(|s| s.to_string()),
+            #[cfg(feature = "_tls")]
+            config.tls_config.id.clone(),
         );
 
         PoolKey(Arc::new(inner))
@@ -176,7 +365,14 @@ impl PoolKey {
 }
 
 #[derive(PartialEq, Eq)]
-struct PoolKeyInner(Scheme, Authority, Option<Proxy>);
+struct PoolKeyInner(
+    Scheme,
+    Authority,
+    Option<Proxy>,
+    Option<String>,
+    Option<String>,
+    #[cfg(feature = "_tls")] TlsConfigId,
+);
 
 #[derive(Debug)]
 struct Pool {
@@ -184,6 +380,11 @@ struct Pool {
     max_idle_connections: usize,
     max_idle_connections_per_host: usize,
     max_idle_age: Duration,
+    max_requests_per_connection: Option<u32>,
+    max_connection_lifetime: Option<Duration>,
+    /// Only set with retire_on_dns_change.
+    dns_ttl: Option<Duration>,
+    drain_stats: DrainStats,
 }
 
 impl Pool {
@@ -193,6 +394,13 @@ impl Pool {
             max_idle_connections: config.max_idle_connections,
             max_idle_connections_per_host: config.max_idle_connections_per_host,
             max_idle_age: config.max_idle_age.into(),
+            max_requests_per_connection: config.max_requests_per_connection,
+            max_connection_lifetime: config.max_connection_lifetime.map(Into::into),
+            dns_ttl: config
+                .dns_ttl
+                .filter(|_| config.retire_on_dns_change)
+                .map(Into::into),
+            drain_stats: DrainStats::default(),
         }
     }
 
@@ -207,6 +415,19 @@ impl Pool {
 
         // unwrap is ok because update_position_per_host() should have set all
         self.lru.retain(|c| c.position_per_host.unwrap() < max);
+
+        if let Some(ttl) = self.dns_ttl {
+            self.lru.retain(|c| {
+                let Some(resolved) = c.resolved else {
+                    return true;
+                };
+                let keep = now.duration_since(resolved) < ttl;
+                if !keep {
+                    debug!("Retire, address not confirmed by DNS: {:?}", c.key);
+                }
+                keep
+            });
+        }
     }
 
     fn front_is_too_old(&self, now: Instant) -> bool {
@@ -244,6 +465,43 @@ impl Pool {
         }
     }
 
+    /// Whether the connection has reached max requests or lifetime.
+    fn is_retired(&self, conn: &Connection, now: Instant) -> bool {
+        let too_many = self
+            .max_requests_per_connection
+            .map(|max| conn.requests >= max)
+            .unwrap_or(false);
+
+        let too_old = self
+            .max_connection_lifetime
+            .map(|max| conn.lifetime(now) >= max)
+            .unwrap_or(false);
+
+        too_many || too_old
+    }
+
+    /// Drop idle connections for `key` to addresses no longer among `addrs`, and
+    /// note the others as confirmed at `now`.
+    fn retire_moved(&mut self, key: &PoolKey, addrs: &ResolvedSocketAddrs, now: Instant) {
+        self.lru.retain_mut(|c| {
+            let Some(peer) = c.peer_addr() else {
+                return true;
+            };
+
+            if c.key != *key {
+                return true;
+            }
+
+            let keep = addrs.into_iter().any(|a| *a == peer);
+            if keep && c.resolved.is_some() {
+                c.resolved = Some(now);
+            } else {
+                debug!("Retire, {} no longer resolved: {:?}", peer, c.key);
+            }
+            keep
+        });
+    }
+
     fn add(&mut self, conn: Connection) {
         self.lru.push_back(conn)
     }
@@ -258,6 +516,11 @@ impl Pool {
                 continue;
             }
 
+            if self.is_retired(&conn, now) {
+                debug!("Retire: {:?}", conn.key);
+                continue;
+            }
+
             if conn.age(now) >= max_idle_age {
                 // A max_duration that is shorter in the request than the pool.
                 // This connection survives in the pool, but is not used for this
@@ -271,6 +534,135 @@ impl Pool {
     }
 }
 
+/// Limits the connections in use per host.
+///
+/// Waiting requests get a ticket in a queue per host, and are let through in
+/// ticket order as connections are done.
+struct HostSlots {
+    max: Option<usize>,
+    state: Mutex<SlotsState>,
+    freed: Condvar,
+}
+
+#[derive(Default)]
+struct SlotsState {
+    next_ticket: u64,
+    hosts: Vec<(PoolKey, HostQueue)>,
+}
+
+#[derive(Default)]
+struct HostQueue {
+    in_use: usize,
+    waiting: VecDeque<u64>,
+}
+
+impl SlotsState {
+    fn queue(&mut self, key: &PoolKey) -> &mut HostQueue {
+        let idx = match self.hosts.iter().position(|(k, _)| k == key) {
+            Some(idx) => idx,
+            None => {
+                self.hosts.push((key.clone(), HostQueue::default()));
+                self.hosts.len() - 1
+            }
+        };
+        &mut self.hosts[idx].1
+    }
+
+    fn remove_if_unused(&mut self, key: &PoolKey) {
+        self.hosts
+            .retain(|(k, q)| k != key || q.in_use > 0 || !q.waiting.is_empty());
+    }
+}
+
+impl HostSlots {
+    fn new(max: Option<usize>) -> Self {
+        HostSlots {
+            max,
+            state: Mutex::new(SlotsState::default()),
+            freed: Condvar::new(),
+        }
+    }
+
+    /// Wait for a free slot for the host. `None` if there is no limit.
+    fn acquire(
+        this: &Arc<HostSlots>,
+        key: &PoolKey,
+        timeout: NextTimeout,
+    ) -> Result<Option<HostSlot>, Error> {
+        let Some(max) = this.max else {
+            return Ok(None);
+        };
+
+        let deadline = if timeout.after.is_not_happening() {
+            None
+        } else {
+            Some(time::Instant::now() + *timeout.after)
+        };
+
+        let mut state = this.state.lock().unwrap();
+
+        let ticket = state.next_ticket;
+        state.next_ticket += 1;
+        state.queue(key).waiting.push_back(ticket);
+
+        loop {
+            let queue = state.queue(key);
+
+            if queue.waiting.front() == Some(&ticket) && queue.in_use < max.max(1) {
+                queue.waiting.pop_front();
+                queue.in_use += 1;
+
+                // The next in line might also fit.
+                this.freed.notify_all();
+
+                return Ok(Some(HostSlot {
+                    key: key.clone(),
+                    slots: this.clone(),
+                }));
+            }
+
+            let now = time::Instant::now();
+
+            let remaining = match deadline {
+                Some(d) if now >= d => {
+                    debug!("Timeout waiting for connection: {:?}", key);
+                    queue.waiting.retain(|t| *t != ticket);
+                    state.remove_if_unused(key);
+                    this.freed.notify_all();
+                    return Err(Error::Timeout(timeout.reason));
+                }
+                Some(d) => Some(d - now),
+                None => None,
+            };
+
+            state = match remaining {
+                Some(v) => this.freed.wait_timeout(state, v).unwrap().0,
+                None => this.freed.wait(state).unwrap(),
+            };
+        }
+    }
+
+    fn release(&self, key: &PoolKey) {
+        let mut state = self.state.lock().unwrap();
+        let queue = state.queue(key);
+        queue.in_use = queue.in_use.saturating_sub(1);
+        state.remove_if_unused(key);
+        self.freed.notify_all();
+    }
+}
+
+/// A connection in use, counted towards max_connections_per_host until dropped.
+struct HostSlot {
+    key: PoolKey,
+    slots: Arc<HostSlots>,
+}
+
+impl Drop for HostSlot {
+    fn drop(&mut self) {
+        self.slots.release(&self.key);
+    }
+}
+
 impl fmt::Debug for ConnectionPool {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("ConnectionPool")
@@ -294,6 +686,8 @@ impl fmt::Debug for PoolKey {
             .field("scheme", &self.0 .0)
             .field("authority", &DebugAuthority(&self.0 .1))
             .field("proxy", &self.0 .2)
+            .field("server_name", &self.0 .3)
+            .field("tag", &self.0 .4)
             .finish()
     }
 }
@@ -305,6 +699,183 @@ mod test {
     #[test]
     fn poolkey_new() {
         // Test that PoolKey::new() does not panic on unrecognized schemes.
-        PoolKey::new(&Uri::from_static("zzz://example.com"), &None);
+        PoolKey::new(
+            &Uri::from_static("zzz://example.com"),
+            &Config::default(),
+            None,
+            None,
+        );
+    }
+
+    #[test]
+    fn poolkey_tag() {
+        let uri = Uri::from_static("https://a.test");
+        let config = Config::default();
+        let a = PoolKey::new(&uri, &config, None, Some("tenant-a"));
+
+        assert!(a == PoolKey::new(&uri, &config, None, Some("tenant-a")));
+        assert!(a != PoolKey::new(&uri, &config, None, Some("tenant-b")));
+        assert!(a != PoolKey::new(&uri, &config, None, None));
+    }
+
+    #[cfg(feature = "_tls")]
+    #[test]
+    fn poolkey_tls_config() {
+        use crate::tls::TlsConfig;
+
+        let uri = Uri::from_static("https://a.test");
+        let config = Config::default();
+        let other = Config::builder()
+            .tls_config(TlsConfig::builder().build())
+            .build();
+        let a = PoolKey::new(&uri, &config, None, None);
+
+        assert!(a == PoolKey::new(&uri, &config.clone(), None, None));
+        assert!(a != PoolKey::new(&uri, &other, None, None));
+    }
+
+    #[test]
+    fn retire_moved_addresses() {
+        use crate::transport::LazyBuffers;
+        use crate::util::ArrayVec;
+
+        struct Peer(SocketAddr, LazyBuffers);
+
+        impl fmt::Debug for Peer {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.debug_tuple("Peer").field(&self.0).finish()
+            }
+        }
+
+        impl Transport for Peer {
+            fn buffers(&mut self) -> &mut dyn Buffers {
+                &mut self.1
+            }
+            fn transmit_output(&mut self, _: usize, _: NextTimeout) -> Result<(), Error> {
+                Ok(())
+            }
+            fn await_input(&mut self, _: NextTimeout) -> Result<bool, Error> {
+                Ok(false)
+            }
+            fn is_open(&mut self) -> bool {
+                true
+            }
+            fn peer_addr(&self) -> Option<SocketAddr> {
+                Some(self.0)
+            }
+        }
+
+        let config = Config::default();
+        let key = PoolKey::new(&Uri::from_static("https://a.test"), &config, None, None);
+        let other = PoolKey::new(&Uri::from_static("https://b.test"), &config, None, None);
+        let now = Instant::now();
+
+        let conn = |key: &PoolKey, addr: &str| Connection {
+            transport: Box::new(Peer(addr.parse().unwrap(), LazyBuffers::new(0, 0))),
+            key: key.clone(),
+            created: now,
+            resolved: Some(now),
+            info: OnceCell::new(),
+            requests: 0,
+            last_use: now,
+            pool: Weak::new(),
+            position_per_host: None,
+            slot: None,
+        };
+
+        let mut pool = Pool::new(&config);
+        pool.add(conn(&key, "10.0.0.1:443"));
+        pool.add(conn(&key, "10.0.0.2:443"));
+        pool.add(conn(&other, "10.0.0.1:443"));
+
+        let mut addrs: ResolvedSocketAddrs = ArrayVec::from_fn(|_| "0.0.0.0:0".parse().unwrap());
+        addrs.push("10.0.0.2:443".parse().unwrap());
+        pool.retire_moved(&key, &addrs, now);
+
+        let left: Vec<_> = pool.lru.iter().map(|c| c.peer_addr().unwrap()).collect();
+        let expected: [SocketAddr; 2] = [
+            "10.0.0.2:443".parse().unwrap(),
+            "10.0.0.1:443".parse().unwrap(),
+        ];
+        assert_eq!(left, expected);
+        assert!(pool.lru[1].key == other);
+
+        // The connection to b.test might have moved too, but b.test isn't
+        // requested again. It expires instead.
+        let config = Config::builder()
+            .retire_on_dns_change(true)
+            .dns_ttl(Some(time::Duration::from_secs(10)))
+            .build();
+        let mut pool = Pool::new(&config);
+        pool.add(conn(&key, "10.0.0.2:443"));
+        pool.add(conn(&other, "10.0.0.1:443"));
+
+        // A request to a.test confirms its address.
+        pool.retire_moved(&key, &addrs, now + Duration::from_secs(8));
+        pool.purge(now + Duration::from_secs(12));
+
+        assert_eq!(pool.lru.len(), 1);
+        assert!(pool.lru[0].key == key);
+
+        // A connection through a proxy resolving the host can't be confirmed,
+        // and doesn't expire.
+        let mut pool = Pool::new(&config);
+        pool.add(Connection {
+            resolved: None,
+            ..conn(&other, "10.0.0.1:443")
+        });
+        pool.purge(now + Duration::from_secs(60));
+        assert_eq!(pool.lru.len(), 1);
+    }
+
+    #[test]
+    fn host_slots_fifo_and_timeout() {
+        use std::sync::mpsc;
+        use std::thread;
+
+        let slots = Arc::new(HostSlots::new(Some(1)));
+        let config = Config::default();
+        let key = PoolKey::new(&Uri::from_static("https://a.test"), &config, None, None);
+        let other = PoolKey::new(&Uri::from_static("https://b.test"), &config, None, None);
+
+        let short = NextTimeout {
+            after: time::Duration::from_millis(10).into(),
+            reason: Timeout::ConnectionWait,
+        };
+        let forever = NextTimeout {
+            after: Duration::NotHappening,
+            reason: Timeout::Global,
+        };
+
+        let first = HostSlots::acquire(&slots, &key, forever).unwrap();
+
+        // Another host is not held up.
+        let _b = HostSlots::acquire(&slots, &other, short).unwrap();
+
+        let err = HostSlots::acquire(&slots, &key, short).unwrap_err();
+        assert!(matches!(err, Error::Timeout(Timeout::ConnectionWait)));
+
+        let (tx, rx) = mpsc::channel();
+        let mut handles = vec![];
+        for i in 0..3 {
+            let slots = slots.clone();
+            let key = key.clone();
+            let tx = tx.clone();
+            handles.push(thread::spawn(move || {
+                let _slot = HostSlots::acquire(&slots, &key, forever).unwrap();
+                tx.send(i).unwrap();
+            }));
+            // Let the thread get in line.
+            thread::sleep(time::Duration::from_millis(50));
+        }
+
+        drop(first);
+
+        for h in handles {
+            h.join().unwrap();
+        }
+
+        let order: Vec<_> = rx.try_iter().collect();
+        assert_eq!(order, [0, 1, 2]);
     }
 }
@@ -4,7 +4,9 @@ use std::iter::Enumerate;
 use std::ops::Deref;
 use std::str::Chars;
 
-use percent_encoding::utf8_percent_encode;
+use percent_encoding::{percent_decode_str, utf8_percent_encode};
+
+use crate::config::UrlEncoding;
 
 #[derive(Clone)]
 pub(crate) struct QueryParam<'a> {
@@ -17,13 +19,13 @@ enum Source<'a> {
     Owned(String),
 }
 
-pub fn url_enc(i: &str) -> Cow<str> {
-    utf8_percent_encode(i, percent_encoding::NON_ALPHANUMERIC).into()
+pub fn url_enc(i: &str, encoding: UrlEncoding) -> Cow<str> {
+    utf8_percent_encode(i, encoding.ascii_set()).into()
 }
 
 impl<'a> QueryParam<'a> {
-    pub fn new_key_value(param: &str, value: &str) -> QueryParam<'static> {
-        let s = format!("{}={}", url_enc(param), url_enc(value));
+    pub fn new_key_value(param: &str, value: &str, encoding: UrlEncoding) -> QueryParam<'static> {
+        let s = format!("{}={}", url_enc(param, encoding), url_enc(value, encoding));
         QueryParam {
             source: Source::Owned(s),
         }
@@ -35,6 +37,54 @@ impl<'a> QueryParam<'a> {
             Source::Owned(v) => v.as_str(),
         }
     }
+
+    /// A parameter that is sent exactly as given, without percent encoding.
+    pub fn new_raw(raw: &str) -> QueryParam<'static> {
+        QueryParam {
+            source: Source::Owned(raw.to_string()),
+        }
+    }
+
+    /// The percent decoded key of the parameter.
+    pub fn key(&self) -> Cow<str> {
+        let s = self.as_str();
+        let key = s.split_once('=').map(|(k, _)| k).unwrap_or(s);
+        percent_decode_str(key).decode_utf8_lossy()
+    }
+}
+
+/// Query parameters already in the uri that are to be removed.
+#[derive(Debug, Default)]
+pub(crate) enum QueryDrop {
+    #[default]
+    None,
+    Keys(Vec<String>),
+    All,
+}
+
+impl QueryDrop {
+    pub fn add_key(&mut self, key: &str) {
+        match self {
+            QueryDrop::None => *self = QueryDrop::Keys(vec![key.to_string()]),
+            QueryDrop::Keys(v) => v.push(key.to_string()),
+            QueryDrop::All => {}
+        }
+    }
+
+    pub fn is_none(&self) -> bool {
+        matches!(self, QueryDrop::None)
+    }
+
+    pub fn drops(&self, param: &QueryParam) -> bool {
+        match self {
+            QueryDrop::None => false,
+            QueryDrop::Keys(v) => {
+                let key = param.key();
+                v.iter().any(|k| *k == key)
+            }
+            QueryDrop::All => true,
+        }
+    }
 }
 
 pub(crate) fn parse_query_params(query_string: &str) -> impl Iterator<Item = QueryParam<'_>> {
@@ -144,4 +194,28 @@ mod test {
         assert_eq!(p("foo=bar&"), vec!["foo=bar"]);
         assert_eq!(p("foo=bar&foo2=bar2"), vec!["foo=bar", "foo2=bar2"]);
     }
+
+    #[test]
+    fn url_encodings() {
+        let s = "a-b_c+d,e|f g&h";
+        let enc = |e| url_enc(s, e).to_string();
+        assert_eq!(
+            enc(UrlEncoding::NonAlphanumeric),
+            "a%2Db%5Fc%2Bd%2Ce%7Cf%20g%26h"
+        );
+        assert_eq!(enc(UrlEncoding::Rfc3986), "a-b_c%2Bd%2Ce%7Cf%20g%26h");
+        assert_eq!(enc(UrlEncoding::Minimal), "a-b_c+d,e|f%20g%26h");
+    }
+
+    #[test]
+    fn query_param_key() {
+        let keys: Vec<_> = parse_query_params("a%5Fb=1&c&=2")
+            .map(|q| q.key().to_string())
+            .collect();
+        assert_eq!(keys, vec!["a_b", "c", ""]);
+        assert_eq!(
+            QueryParam::new_key_value("a_b", "x", UrlEncoding::NonAlphanumeric).key(),
+            "a_b"
+        );
+    }
 }
@@ -11,13 +11,17 @@
 use std::fmt::{self, Debug};
 use std::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4, ToSocketAddrs};
 use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
 use std::thread::{self};
+use std::time;
 use std::vec::IntoIter;
 
 use http::uri::{Authority, Scheme};
 use http::Uri;
 
 use crate::config::Config;
+#[cfg(feature = "mdns")]
+use crate::mdns;
 use crate::transport::NextTimeout;
 use crate::util::{ArrayVec, SchemeExt, UriExt};
 use crate::Error;
@@ -45,9 +49,13 @@ pub type ResolvedSocketAddrs = ArrayVec<SocketAddr, MAX_ADDRS>;
 ///
 /// Uses std::net [`ToSocketAddrs`](https://doc.rust-lang.org/std/net/trait.ToSocketAddrs.html) to
 /// do the lookup. Can optionally spawn a thread to abort lookup if the relevant timeout is set.
-#[derive(Default)]
+///
+/// With a [`ResolvConf`], hosts with fewer dots than `ndots`, such as the single label
+/// service names inside a Kubernetes cluster, are also looked up with the `search`
+/// domains when the system resolver doesn't find them as is. By default, all hosts
+/// are left to the system resolver.
 pub struct DefaultResolver {
-    _private: (),
+    resolv_conf: Arc<ResolvConf>,
 }
 
 /// Configuration of IP family to use.
@@ -65,6 +73,16 @@ pub enum IpFamily {
 }
 
 impl DefaultResolver {
+    /// Creates a resolver looking up unqualified hosts with `resolv_conf`.
+    ///
+    /// Use [`ResolvConf::from_system()`] for the `resolv.conf` of the system. An empty
+    /// [`ResolvConf::default()`] leaves all hosts to the system resolver.
+    pub fn with_resolv_conf(resolv_conf: ResolvConf) -> Self {
+        DefaultResolver {
+            resolv_conf: Arc::new(resolv_conf),
+        }
+    }
+
     /// Helper to combine scheme host and port to a single string.
     ///
     /// This knows about the default ports for http, https and socks proxies which
@@ -109,7 +127,18 @@ impl Resolver for DefaultResolver {
         // Determine if we want to use the async behavior.
         let use_sync = timeout.after.is_not_happening();
 
-        let iter = if use_sync {
+        let candidates = self.resolv_conf.candidates(authority.host());
+
+        let iter = if let Some(v) = resolve_mdns(uri, config, timeout) {
+            v
+        } else if !candidates.is_empty() {
+            // unwrap is ok because ensure_valid_url() above.
+            let port = authority
+                .port_u16()
+                .or_else(|| scheme.default_port())
+                .unwrap();
+            self.resolv_conf.resolve(&candidates, port, timeout)?
+        } else if use_sync {
             trace!("Resolve: {}", addr);
             // When timeout is not set, we do not spawn any threads.
             addr.to_socket_addrs()?
@@ -139,6 +168,42 @@ impl Resolver for DefaultResolver {
     }
 }
 
+/// Look up a `.local` host with mDNS, if enabled. `None` leaves it to the system.
+#[cfg(feature = "mdns")]
+fn resolve_mdns(uri: &Uri, config: &Config, timeout: NextTimeout) -> Option<IntoIter<SocketAddr>> {
+    let host = uri.host()?;
+    if !config.mdns || !mdns::is_local(host) {
+        return None;
+    }
+
+    let port = uri.port_u16().or_else(|| uri.scheme()?.default_port())?;
+    let wait = mdns::MDNS_TIMEOUT.min(*timeout.after);
+
+    trace!("Resolve with mDNS ({:?}): {}", wait, host);
+
+    let id = config.random_u64() as u16;
+
+    match mdns::resolve(host, config.ip_family, wait, id) {
+        Ok(ips) if !ips.is_empty() => {
+            let addrs: Vec<_> = mdns::with_port(ips, port).collect();
+            Some(addrs.into_iter())
+        }
+        Ok(_) => {
+            debug!("No mDNS answer, use system resolver: {}", host);
+            None
+        }
+        Err(e) => {
+            debug!("mDNS failed, use system resolver: {}", e);
+            None
+        }
+    }
+}
+
+#[cfg(not(feature = "mdns"))]
+fn resolve_mdns(_: &Uri, _: &Config, _: NextTimeout) -> Option<IntoIter<SocketAddr>> {
+    None
+}
+
 fn resolve_async(addr: String, timeout: NextTimeout) -> Result<IntoIter<SocketAddr>, Error> {
     // TODO(martin): On Linux we have getaddrinfo_a which is a libc async way of
     // doing host lookup. We should make a subcrate that uses a native async method
@@ -175,15 +240,216 @@ impl IpFamily {
     }
 }
 
+impl Default for DefaultResolver {
+    fn default() -> Self {
+        DefaultResolver {
+            resolv_conf: Arc::new(ResolvConf::default()),
+        }
+    }
+}
+
+/// Options of `resolv.conf` for looking up unqualified hosts.
+///
+/// A host with fewer dots than `ndots` is first looked up as is, and then with each
+/// of the `search` domains appended. Each of those lookups is limited to `timeout`
+/// and made up to `attempts` times, within the timeout of the request. Other hosts,
+/// hosts ending with a dot and `localhost` are left to the system resolver as is.
+///
+/// This is opt-in with [`DefaultResolver::with_resolv_conf()`].
+///
+/// ```
+/// use ureq::resolver::{DefaultResolver, ResolvConf};
+///
+/// let conf = ResolvConf::parse(
+///     "search default.svc.cluster.local svc.cluster.local\n\
+///      options ndots:5 timeout:2 attempts:3\n",
+/// );
+/// assert_eq!(conf.search(), ["default.svc.cluster.local", "svc.cluster.local"]);
+/// assert_eq!(conf.ndots(), 5);
+///
+/// let resolver = DefaultResolver::with_resolv_conf(conf);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvConf {
+    search: Vec<String>,
+    ndots: usize,
+    timeout: time::Duration,
+    attempts: u32,
+}
+
+impl ResolvConf {
+    /// Read `/etc/resolv.conf`.
+    ///
+    /// Empty if the file can't be read, such as on Windows.
+    pub fn from_system() -> Self {
+        match std::fs::read_to_string("/etc/resolv.conf") {
+            Ok(v) => Self::parse(&v),
+            Err(e) => {
+                debug!("No resolv.conf: {}", e);
+                Self::default()
+            }
+        }
+    }
+
+    /// Parse the contents of a `resolv.conf`.
+    ///
+    /// Only `search`, `domain` and the options `ndots`, `timeout` and `attempts` are
+    /// used. Like the system resolver, the last `search` or `domain` wins, and values
+    /// are capped to 15 dots, 30 seconds and 5 attempts.
+    pub fn parse(s: &str) -> Self {
+        let mut conf = Self::default();
+
+        for line in s.lines() {
+            let line = line
+                .split(|c| c == '#' || c == ';')
+                .next()
+                .unwrap_or_default();
+            let mut words = line.split_whitespace();
+
+            match words.next() {
+                Some("search") => conf.search = words.map(domain).collect(),
+                Some("domain") => conf.search = words.take(1).map(domain).collect(),
+                Some("options") => {
+                    for option in words {
+                        let Some((name, value)) = option.split_once(':') else {
+                            continue;
+                        };
+                        let Ok(value) = value.parse::<u32>() else {
+                            continue;
+                        };
+                        match name {
+                            "ndots" => conf.ndots = value.min(15) as usize,
+                            "timeout" => {
+                                conf.timeout = time::Duration::from_secs(value.clamp(1, 30).into())
+                            }
+                            "attempts" => conf.attempts = value.clamp(1, 5),
+                            _ => {}
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        conf
+    }
+
+    /// The search domains.
+    pub fn search(&self) -> &[String] {
+        &self.search
+    }
+
+    /// Hosts with fewer dots than this are looked up with the search domains.
+    pub fn ndots(&self) -> usize {
+        self.ndots
+    }
+
+    /// The timeout of each lookup.
+    pub fn timeout(&self) -> time::Duration {
+        self.timeout
+    }
+
+    /// The number of attempts of each lookup that times out.
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// The names to look up for `host`, or none to leave it to the system.
+    fn candidates(&self, host: &str) -> Vec<String> {
+        let is_ip = host.starts_with('[') || host.parse::<IpAddr>().is_ok();
+        let dots = host.matches('.').count();
+
+        // localhost is never a name in a search domain (RFC 6761).
+        let lower = host.to_ascii_lowercase();
+        let is_localhost = lower == "localhost" || lower.ends_with(".localhost");
+
+        if self.search.is_empty()
+            || is_ip
+            || is_localhost
+            || host.ends_with('.')
+            || dots >= self.ndots
+        {
+            return vec![];
+        }
+
+        // The name as is first, and the trailing dot keeps the system resolver from
+        // searching again.
+        let mut candidates = vec![host.to_string()];
+        candidates.extend(self.search.iter().map(|d| format!("{}.{}.", host, d)));
+
+        candidates
+    }
+
+    fn resolve(
+        &self,
+        candidates: &[String],
+        port: u16,
+        timeout: NextTimeout,
+    ) -> Result<IntoIter<SocketAddr>, Error> {
+        let start = time::Instant::now();
+        let mut last_err = Error::HostNotFound;
+
+        for name in candidates {
+            for attempt in 1..=self.attempts {
+                let left = timeout.after.saturating_sub(start.elapsed());
+                if left.is_zero() {
+                    return Err(Error::Timeout(timeout.reason));
+                }
+
+                let attempt_timeout = NextTimeout {
+                    after: self.timeout.min(left).into(),
+                    reason: timeout.reason,
+                };
+
+                trace!("Resolve {} (attempt {}): {}", name, attempt, port);
+
+                match resolve_async(format!("{}:{}", name, port), attempt_timeout) {
+                    Ok(v) => return Ok(v),
+                    // Only a timeout is worth another attempt.
+                    Err(e @ Error::Timeout(_)) => last_err = e,
+                    Err(e) => {
+                        last_err = e;
+                        break;
+                    }
+                }
+            }
+        }
+
+        Err(last_err)
+    }
+}
+
+impl Default for ResolvConf {
+    fn default() -> Self {
+        ResolvConf {
+            search: vec![],
+            ndots: 1,
+            timeout: time::Duration::from_secs(5),
+            attempts: 2,
+        }
+    }
+}
+
+fn domain(s: &str) -> String {
+    s.trim_end_matches('.').to_string()
+}
+
 impl fmt::Debug for DefaultResolver {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("DefaultResolver").finish()
+        f.debug_struct("DefaultResolver")
+            .field("resolv_conf", &self.resolv_conf)
+            .finish()
     }
 }
 
 #[cfg(test)]
 mod test {
+    use std::io;
+    use std::sync::Mutex;
+
     use crate::transport::time::Duration;
+    use crate::transport::{ConnectionDetails, Connector, DefaultConnector, Transport};
+    use crate::{Agent, Timeout};
 
     use super::*;
 
@@ -204,4 +470,134 @@ mod test {
         assert!(matches!(err, Error::BadUri(_)));
         assert_eq!(err.to_string(), "bad uri: unknown scheme: foo");
     }
+
+    #[test]
+    fn parse_resolv_conf() {
+        let conf = ResolvConf::parse(
+            "# generated\n\
+             nameserver 10.96.0.10\n\
+             domain example.com\n\
+             search default.svc.cluster.local. svc.cluster.local ; k8s\n\
+             options ndots:5 timeout:40 attempts:0 rotate\n",
+        );
+
+        assert_eq!(
+            conf.search(),
+            ["default.svc.cluster.local", "svc.cluster.local"]
+        );
+        assert_eq!(conf.ndots(), 5);
+        assert_eq!(conf.timeout(), time::Duration::from_secs(30));
+        assert_eq!(conf.attempts(), 1);
+
+        let conf = ResolvConf::parse("search a.test\ndomain b.test\n");
+        assert_eq!(conf.search(), ["b.test"]);
+        assert_eq!(conf.ndots(), 1);
+    }
+
+    #[test]
+    fn search_candidates() {
+        let conf = ResolvConf::parse("search ns.svc.local svc.local\noptions ndots:2\n");
+
+        assert_eq!(
+            conf.candidates("api"),
+            ["api", "api.ns.svc.local.", "api.svc.local."]
+        );
+        assert_eq!(conf.candidates("api.ns").len(), 3);
+
+        // Enough dots, absolute names and IPs are left to the system.
+        assert!(conf.candidates("api.ns.svc").is_empty());
+        assert!(conf.candidates("api.").is_empty());
+        assert!(conf.candidates("10.0.0.1").is_empty());
+        assert!(conf.candidates("[::1]").is_empty());
+        assert!(conf.candidates("localhost").is_empty());
+        assert!(conf.candidates("LocalHost").is_empty());
+        assert!(conf.candidates("app.localhost").is_empty());
+
+        assert!(ResolvConf::default().candidates("api").is_empty());
+    }
+
+    /// Resolver taking longer than any timeout, recording the timeouts it gets.
+    #[derive(Debug, Default, Clone)]
+    struct SlowResolver(Arc<Mutex<Vec<NextTimeout>>>);
+
+    impl Resolver for SlowResolver {
+        fn resolve(
+            &self,
+            _uri: &Uri,
+            _config: &Config,
+            timeout: NextTimeout,
+        ) -> Result<ResolvedSocketAddrs, Error> {
+            self.0.lock().unwrap().push(timeout);
+            thread::sleep(*timeout.after);
+            Err(Error::Timeout(timeout.reason))
+        }
+    }
+
+    #[test]
+    fn resolve_timeout() {
+        let resolver = SlowResolver::default();
+        let config = Config::builder()
+            .timeout_resolve(Some(time::Duration::from_millis(50)))
+            .timeout_connect(Some(time::Duration::from_secs(10)))
+            .build();
+        let agent = Agent::with_parts(config, DefaultConnector::new(), resolver.clone());
+
+        let err = agent.get("http://slow.test/").call().unwrap_err();
+        assert!(matches!(err, Error::Timeout(Timeout::Resolve)), "{:?}", err);
+
+        let timeouts = resolver.0.lock().unwrap();
+        assert_eq!(timeouts[0].reason, Timeout::Resolve);
+        assert!(*timeouts[0].after <= time::Duration::from_millis(50));
+    }
+
+    #[derive(Debug)]
+    struct Refuse;
+
+    impl Connector for Refuse {
+        fn connect(
+            &self,
+            _details: &ConnectionDetails,
+            _chained: Option<Box<dyn Transport>>,
+        ) -> Result<Option<Box<dyn Transport>>, Error> {
+            Err(io::Error::from(io::ErrorKind::ConnectionRefused).into())
+        }
+    }
+
+    /// Resolver answering right away, recording the timeouts it gets.
+    #[derive(Debug, Default, Clone)]
+    struct FastResolver(Arc<Mutex<Vec<NextTimeout>>>);
+
+    impl Resolver for FastResolver {
+        fn resolve(
+            &self,
+            _uri: &Uri,
+            _config: &Config,
+            timeout: NextTimeout,
+        ) -> Result<ResolvedSocketAddrs, Error> {
+            self.0.lock().unwrap().push(timeout);
+            Ok(ResolvedSocketAddrs::from_fn(|_| {
+                "10.0.0.1:80".parse().unwrap()
+            }))
+        }
+    }
+
+    #[test]
+    fn re_resolve_has_resolve_timeout() {
+        let resolver = FastResolver::default();
+        let config = Config::builder()
+            .re_resolve_on_failure(true)
+            .timeout_resolve(Some(time::Duration::from_secs(2)))
+            .timeout_connect(Some(time::Duration::from_secs(30)))
+            .build();
+        let agent = Agent::with_parts(config, Refuse, resolver.clone());
+
+        assert!(agent.get("http://refuse.test/").call().is_err());
+
+        let timeouts = resolver.0.lock().unwrap();
+        assert_eq!(timeouts.len(), 2);
+        for timeout in timeouts.iter() {
+            assert_eq!(timeout.reason, Timeout::Resolve);
+            assert!(*timeout.after <= time::Duration::from_secs(2));
+        }
+    }
 }
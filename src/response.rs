@@ -0,0 +1,408 @@
+use std::borrow::Cow;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use http::header::{AGE, CONTENT_LANGUAGE, CONTENT_LENGTH, CONTENT_TYPE};
+use http::{HeaderName, Response, StatusCode};
+
+use crate::body::split_content_type;
+use crate::retry;
+use crate::timings::Timings;
+#[cfg(feature = "_tls")]
+use crate::tls::Certificate;
+use crate::transport::ConnectionInfo;
+use crate::util::private::PrivateResponse;
+use crate::Error;
+
+/// Extension trait for [`http::Response`].
+///
+/// Typed access to response headers, without having to parse header
+/// values at every call site.
+///
+/// ```
+/// use ureq::ResponseExt;
+///
+/// let res = ureq::get("http://httpbin.org/get").call()?;
+///
+/// let length: Option<u64> = res.header_as("content-length")?;
+/// let remaining = res.ratelimit_remaining();
+/// # Ok::<_, ureq::Error>(())
+/// ```
+pub trait ResponseExt: PrivateResponse {
+    /// Get a header value parsed via [`FromStr`].
+    ///
+    /// Returns `Ok(None)` if the header is missing, and [`Error::BadHeader`] if
+    /// the value isn't valid utf-8 or fails to parse. Surrounding whitespace is
+    /// ignored. If the header is repeated, the first value is used.
+    ///
+    /// ```
+    /// use ureq::ResponseExt;
+    ///
+    /// let res = ureq::get("http://httpbin.org/get").call()?;
+    ///
+    /// if let Some(remaining) = res.header_as::<u64>("x-ratelimit-remaining")? {
+    ///     println!("{} requests left", remaining);
+    /// }
+    /// # Ok::<_, ureq::Error>(())
+    /// ```
+    fn header_as<T: FromStr>(&self, name: &str) -> Result<Option<T>, Error>;
+
+    /// The `content-length` header.
+    ///
+    /// This is the header as sent by the server. It might not correspond to the
+    /// length of the body if the body is decompressed.
+    fn content_length(&self) -> Option<u64>;
+
+    /// The `age` header, in seconds.
+    fn age(&self) -> Option<u64>;
+
+    /// The request quota, from `x-ratelimit-limit` or `ratelimit-limit`.
+    fn ratelimit_limit(&self) -> Option<u64>;
+
+    /// Remaining requests in the current window, from `x-ratelimit-remaining`
+    /// or `ratelimit-remaining`.
+    fn ratelimit_remaining(&self) -> Option<u64>;
+
+    /// When the current window resets, from `x-ratelimit-reset` or `ratelimit-reset`.
+    ///
+    /// Servers disagree on whether this is seconds until the reset or a unix
+    /// timestamp. The value is returned as is.
+    fn ratelimit_reset(&self) -> Option<u64>;
+
+    /// How long the server asks us to wait, from the `Retry-After` header.
+    ///
+    /// The header is either a number of seconds, or an HTTP-date which is converted
+    /// to the time remaining from now. A date in the past gives a zero duration.
+    ///
+    /// To retry automatically, see [`RetryPolicy`](crate::config::RetryPolicy).
+    fn retry_after(&self) -> Option<Duration>;
+
+    /// The connection that handled the request, such as the server address and
+    /// TLS version.
+    ///
+    /// See [`ConnectionInfo`]. This is `None` for responses not made by ureq.
+    fn connection_info(&self) -> Option<&ConnectionInfo>;
+
+    /// The certificate chain of the server, for `https`.
+    ///
+    /// The server's own certificate comes first. This is the chain as sent by the
+    /// server, such as for checking expiry or certificate transparency. See
+    /// [`ConnectionInfo::peer_certificates`].
+    ///
+    /// ```
+    /// use ureq::ResponseExt;
+    ///
+    /// let res = ureq::get("https://httpbin.org/get").call()?;
+    ///
+    /// if let Some(chain) = res.peer_certificates() {
+    ///     println!("{} certificates, {} bytes leaf", chain.len(), chain[0].der().len());
+    /// }
+    /// # Ok::<_, ureq::Error>(())
+    /// ```
+    #[cfg(feature = "_tls")]
+    fn peer_certificates(&self) -> Option<&[Certificate<'static>]>;
+
+    /// How long each phase of the call took, up to the response head.
+    ///
+    /// See [`Timings`]. This is `None` for responses not made by ureq.
+    fn timings(&self) -> Option<&Timings>;
+
+    /// The charset, from the `charset` parameter of the `content-type` header.
+    ///
+    /// This is parsed like [`Body::charset()`](crate::Body::charset), keeping the case
+    /// of the header, so compare it case-insensitively. Without the parameter, JSON
+    /// is `utf-8`, since it has no other encoding. Otherwise `None`.
+    ///
+    /// ```
+    /// use ureq::ResponseExt;
+    ///
+    /// let res = http::Response::builder()
+    ///     .header("content-type", "text/html; Charset=\"ISO-8859-1\"")
+    ///     .body(())?;
+    ///
+    /// assert_eq!(res.charset().as_deref(), Some("ISO-8859-1"));
+    /// # Ok::<_, http::Error>(())
+    /// ```
+    fn charset(&self) -> Option<Cow<'_, str>>;
+
+    /// The languages of the `content-language` header, such as `["en", "de-CH"]`.
+    ///
+    /// Repeated headers are combined. Empty if the header is missing.
+    fn content_languages(&self) -> Vec<String>;
+
+    /// A copy of the response head, without the body.
+    ///
+    /// The status, version, headers and extensions (such as the
+    /// [`connection_info()`](Self::connection_info)) are copied. This lets dispatch
+    /// code keep the head while handing the body to other code.
+    ///
+    /// ```
+    /// use ureq::ResponseExt;
+    ///
+    /// let res = ureq::get("http://httpbin.org/get").call()?;
+    ///
+    /// let head = res.peek_head();
+    /// let reader = res.into_body().into_reader();
+    ///
+    /// println!("{} {:?}", head.status(), head.headers().get("content-type"));
+    /// # Ok::<_, ureq::Error>(())
+    /// ```
+    fn peek_head(&self) -> Response<()>;
+
+    /// Whether the response has a body.
+    ///
+    /// Responses to `HEAD` requests, and responses with status 1xx, `204 No Content`
+    /// or `304 Not Modified` have none, whatever the headers say. For responses not
+    /// made by ureq, the request method isn't known and only the status counts.
+    fn has_body(&self) -> bool;
+}
+
+/// Whether the body of a response made by ureq has any bytes to read.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct HasBody(pub bool);
+
+impl<B> PrivateResponse for Response<B> {}
+
+impl<B> ResponseExt for Response<B> {
+    fn header_as<T: FromStr>(&self, name: &str) -> Result<Option<T>, Error> {
+        let Some(value) = self.headers().get(name) else {
+            return Ok(None);
+        };
+
+        let parsed = value.to_str().ok().and_then(|v| v.trim().parse::<T>().ok());
+
+        match parsed {
+            Some(v) => Ok(Some(v)),
+            None => Err(Error::BadHeader(name.to_ascii_lowercase())),
+        }
+    }
+
+    fn content_length(&self) -> Option<u64> {
+        numeric(self, &[CONTENT_LENGTH])
+    }
+
+    fn age(&self) -> Option<u64> {
+        numeric(self, &[AGE])
+    }
+
+    fn ratelimit_limit(&self) -> Option<u64> {
+        numeric(
+            self,
+            &[
+                HeaderName::from_static("x-ratelimit-limit"),
+                HeaderName::from_static("ratelimit-limit"),
+            ],
+        )
+    }
+
+    fn ratelimit_remaining(&self) -> Option<u64> {
+        numeric(
+            self,
+            &[
+                HeaderName::from_static("x-ratelimit-remaining"),
+                HeaderName::from_static("ratelimit-remaining"),
+            ],
+        )
+    }
+
+    fn ratelimit_reset(&self) -> Option<u64> {
+        numeric(
+            self,
+            &[
+                HeaderName::from_static("x-ratelimit-reset"),
+                HeaderName::from_static("ratelimit-reset"),
+            ],
+        )
+    }
+
+    fn retry_after(&self) -> Option<Duration> {
+        retry::retry_after(self, SystemTime::now())
+    }
+
+    fn connection_info(&self) -> Option<&ConnectionInfo> {
+        self.extensions().get::<Arc<ConnectionInfo>>().map(|i| &**i)
+    }
+
+    fn timings(&self) -> Option<&Timings> {
+        self.extensions().get()
+    }
+
+    #[cfg(feature = "_tls")]
+    fn peer_certificates(&self) -> Option<&[Certificate<'static>]> {
+        self.connection_info()?.peer_certificates.as_deref()
+    }
+
+    fn charset(&self) -> Option<Cow<'_, str>> {
+        let content_type = self.headers().get(CONTENT_TYPE)?.to_str().ok()?;
+        let (mime_type, charset, _) = split_content_type(content_type);
+
+        // JSON is always utf-8 (RFC 8259).
+        let mime_type = mime_type.to_ascii_lowercase();
+        let is_json = mime_type == "application/json" || mime_type.ends_with("+json");
+
+        charset
+            .filter(|v| !v.is_empty())
+            .or_else(|| is_json.then_some(Cow::Borrowed("utf-8")))
+    }
+
+    fn content_languages(&self) -> Vec<String> {
+        self.headers()
+            .get_all(CONTENT_LANGUAGE)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .map(String::from)
+            .collect()
+    }
+
+    fn peek_head(&self) -> Response<()> {
+        let mut head = Response::new(());
+        *head.status_mut() = self.status();
+        *head.version_mut() = self.version();
+        *head.headers_mut() = self.headers().clone();
+        *head.extensions_mut() = self.extensions().clone();
+        head
+    }
+
+    fn has_body(&self) -> bool {
+        if let Some(HasBody(v)) = self.extensions().get() {
+            return *v;
+        }
+
+        let status = self.status();
+        !(status.is_informational()
+            || status == StatusCode::NO_CONTENT
+            || status == StatusCode::NOT_MODIFIED)
+    }
+}
+
+/// The first of the headers that is present and parses as a number.
+fn numeric<B>(response: &Response<B>, names: &[HeaderName]) -> Option<u64> {
+    names.iter().find_map(|n| {
+        response
+            .headers()
+            .get(n)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse().ok())
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn response(headers: &[(&str, &str)]) -> Response<()> {
+        let mut builder = Response::builder();
+        for (k, v) in headers {
+            builder = builder.header(*k, *v);
+        }
+        builder.body(()).unwrap()
+    }
+
+    #[test]
+    fn header_as() {
+        let res = response(&[("x-count", " 42 "), ("x-bad", "nope")]);
+
+        assert_eq!(res.header_as::<u64>("x-count").unwrap(), Some(42));
+        assert_eq!(res.header_as::<u64>("x-missing").unwrap(), None);
+        assert_eq!(
+            res.header_as::<u64>("X-Bad").unwrap_err().to_string(),
+            "bad header: x-bad"
+        );
+    }
+
+    #[test]
+    fn numeric_getters() {
+        let res = response(&[
+            ("content-length", "123"),
+            ("age", "60"),
+            ("ratelimit-limit", "100"),
+            ("x-ratelimit-remaining", "99"),
+            ("x-ratelimit-reset", "soon"),
+        ]);
+
+        assert_eq!(res.content_length(), Some(123));
+        assert_eq!(res.age(), Some(60));
+        assert_eq!(res.ratelimit_limit(), Some(100));
+        assert_eq!(res.ratelimit_remaining(), Some(99));
+        assert_eq!(res.ratelimit_reset(), None);
+    }
+
+    #[test]
+    fn charset_and_languages() {
+        let res = response(&[
+            (
+                "content-type",
+                "text/plain; format=flowed; CHARSET=\"UTF-8\"",
+            ),
+            ("content-language", "en, de-CH"),
+            ("content-language", "fr"),
+        ]);
+        assert_eq!(res.charset().as_deref(), Some("UTF-8"));
+        assert_eq!(res.content_languages(), ["en", "de-CH", "fr"]);
+
+        let res = response(&[("content-type", "application/problem+json")]);
+        assert_eq!(res.charset().as_deref(), Some("utf-8"));
+
+        let res = response(&[("content-type", "text/html")]);
+        assert_eq!(res.charset(), None);
+        assert!(res.content_languages().is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "_tls")]
+    fn peer_certificates() {
+        let mut res = response(&[]);
+        assert!(res.peer_certificates().is_none());
+
+        let leaf = Certificate::from_der(b"leaf").to_owned();
+        let info = ConnectionInfo {
+            peer_certificates: Some(vec![leaf.clone()].into()),
+            ..Default::default()
+        };
+        res.extensions_mut().insert(Arc::new(info));
+
+        // Kept in a copy of the head.
+        let head = res.peek_head();
+        assert_eq!(head.peer_certificates(), Some(&[leaf][..]));
+    }
+
+    #[test]
+    fn peek_head_and_has_body() {
+        use crate::config::Config;
+        use crate::transport::MockConnector;
+        use crate::Agent;
+
+        let mock = MockConnector::new()
+            .route("/empty", "HTTP/1.1 204 No Content\r\n\r\n")
+            .route(
+                "/",
+                "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nX-Route: a\r\n\r\nok",
+            );
+        let agent = Agent::with_parts(Config::default(), mock.clone(), mock);
+
+        let res = agent.get("http://head.test/").call().unwrap();
+        assert!(res.has_body());
+
+        let head = res.peek_head();
+        let body = res.into_body().read_to_string().unwrap();
+        assert_eq!(body, "ok");
+        assert_eq!(head.headers()["x-route"], "a");
+        assert!(head.connection_info().is_some());
+        assert!(head.has_body());
+
+        // Despite the content-length.
+        let res = agent.head("http://head.test/").call().unwrap();
+        assert!(!res.has_body());
+        let res = agent.get("http://head.test/empty").call().unwrap();
+        assert!(!res.has_body());
+
+        let mut res = response(&[]);
+        assert!(res.has_body());
+        *res.status_mut() = StatusCode::NOT_MODIFIED;
+        assert!(!res.has_body());
+    }
+}
@@ -0,0 +1,421 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use http::{Method, Response, StatusCode};
+
+use crate::config::Config;
+use crate::util::{days_from_civil, fraction};
+use crate::{Error, Timeout};
+
+/// Policy for retrying requests that the server asks to come back later.
+///
+/// When a response has one of the retry statuses (by default `429 Too Many Requests`
+/// and `503 Service Unavailable`), ureq sleeps and makes the request again. The
+/// sleep respects the `Retry-After` header if the server sent one, and otherwise
+/// backs off exponentially. A bit of random jitter is added to avoid many clients
+/// retrying at the exact same moment.
+///
+/// Requests failing without a response, because the connection was reset or a
+/// timeout (other than the global and per call ones) was reached, are retried too,
+/// with the exponential backoff.
+///
+/// The [global timeout](crate::config::ConfigBuilder::timeout_global) covers all
+/// attempts and the sleeps between them. A delay that doesn't fit in what is left
+/// of it ends the retries.
+///
+/// Only idempotent requests (`GET`, `HEAD`, `PUT`, `DELETE`, `OPTIONS` and `TRACE`)
+/// are retried, and only if the request body can be sent again, which means no body
+/// or a body from a byte slice such as `&str` or `&[u8]`. Readers are not retried.
+///
+/// When the retries are exhausted or vetoed, the last response is handled as usual.
+///
+/// ```
+/// use std::time::Duration;
+/// use ureq::Agent;
+/// use ureq::config::RetryPolicy;
+///
+/// let policy = RetryPolicy::new()
+///     .max_retries(5)
+///     .max_sleep(Duration::from_secs(10))
+///     // Never sleep more than 2 seconds in interactive use.
+///     .allow_sleep(|delay, _attempt| delay <= Duration::from_secs(2));
+///
+/// let agent: Agent = Agent::config_builder()
+///     .retry(Some(policy))
+///     .build()
+///     .into();
+/// ```
+#[derive(Clone)]
+pub struct RetryPolicy {
+    max_retries: u32,
+    statuses: Vec<u16>,
+    base_delay: Duration,
+    max_sleep: Duration,
+    jitter: f64,
+    allow_sleep: Option<Arc<AllowSleep>>,
+}
+
+type AllowSleep = dyn Fn(Duration, u32) -> bool + Send + Sync + 'static;
+
+impl RetryPolicy {
+    /// A policy with the default settings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Max number of retries after the first attempt.
+    ///
+    /// Defaults to `3`.
+    pub fn max_retries(mut self, v: u32) -> Self {
+        self.max_retries = v;
+        self
+    }
+
+    /// Response statuses that cause a retry.
+    ///
+    /// Defaults to `429` and `503`.
+    pub fn statuses(mut self, v: &[u16]) -> Self {
+        self.statuses = v.to_vec();
+        self
+    }
+
+    /// Delay before the first retry when there is no `Retry-After` header.
+    ///
+    /// The delay doubles for each following retry.
+    ///
+    /// Defaults to 1 second.
+    pub fn base_delay(mut self, v: Duration) -> Self {
+        self.base_delay = v;
+        self
+    }
+
+    /// Longest time to sleep between attempts.
+    ///
+    /// Exponential backoff is capped to this value. If the server asks for a longer
+    /// wait with `Retry-After`, the request is not retried, since retrying earlier
+    /// than asked is unlikely to succeed.
+    ///
+    /// Defaults to 30 seconds.
+    pub fn max_sleep(mut self, v: Duration) -> Self {
+        self.max_sleep = v;
+        self
+    }
+
+    /// Random jitter as a fraction of the delay.
+    ///
+    /// `0.25` means sleeping up to 25% longer than the delay. The jitter is always
+    /// added, so a `Retry-After` is never cut short. Clamped to `0.0..=1.0`.
+    ///
+    /// Defaults to `0.25`.
+    pub fn jitter(mut self, v: f64) -> Self {
+        self.jitter = v.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Callback to approve each sleep before it happens.
+    ///
+    /// The arguments are the delay and the retry number, starting at 1. Returning
+    /// `false` stops retrying, and the last response is used as is.
+    pub fn allow_sleep(
+        mut self,
+        f: impl Fn(Duration, u32) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.allow_sleep = Some(Arc::new(f));
+        self
+    }
+
+    /// Whether requests with this method are safe to retry.
+    pub(crate) fn is_retryable_method(method: &Method) -> bool {
+        matches!(
+            *method,
+            Method::GET
+                | Method::HEAD
+                | Method::PUT
+                | Method::DELETE
+                | Method::OPTIONS
+                | Method::TRACE
+        )
+    }
+
+    /// Whether an error without a response is worth retrying.
+    ///
+    /// The global and per call timeouts are the budget of the entire call, and
+    /// are not retried.
+    pub(crate) fn is_retryable_error(error: &Error) -> bool {
+        match error.without_stats() {
+            Error::Io(e) => matches!(
+                e.kind(),
+                io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+                    | io::ErrorKind::BrokenPipe
+                    | io::ErrorKind::UnexpectedEof
+                    | io::ErrorKind::TimedOut
+            ),
+            Error::Timeout(t) => !matches!(t, Timeout::Global | Timeout::PerCall),
+            _ => false,
+        }
+    }
+
+    /// Time to sleep before retrying, or `None` if we should not retry.
+    ///
+    /// `attempt` is the number of retries made so far. The jitter comes from the
+    /// random numbers of `config`.
+    pub(crate) fn delay<B>(
+        &self,
+        response: &Response<B>,
+        attempt: u32,
+        config: &Config,
+    ) -> Option<Duration> {
+        if attempt >= self.max_retries || !self.statuses.contains(&response.status().as_u16()) {
+            return None;
+        }
+
+        match retry_after(response, SystemTime::now()) {
+            Some(v) if v > self.max_sleep => {
+                debug!("Retry-After {:?} exceeds max sleep", v);
+                None
+            }
+            Some(v) => self.approve(v, attempt, config),
+            None => self.backoff(attempt, config),
+        }
+    }
+
+    /// Time to sleep before retrying after a failure without a response, such as
+    /// a broken connection, or `None` if we should not retry.
+    pub(crate) fn backoff(&self, attempt: u32, config: &Config) -> Option<Duration> {
+        if attempt >= self.max_retries {
+            return None;
+        }
+
+        let factor = 2_u32.saturating_pow(attempt);
+        let delay = self
+            .base_delay
+            .checked_mul(factor)
+            .unwrap_or(self.max_sleep)
+            .min(self.max_sleep);
+
+        self.approve(delay, attempt, config)
+    }
+
+    /// Add jitter and ask the callback.
+    fn approve(&self, delay: Duration, attempt: u32, config: &Config) -> Option<Duration> {
+        let delay = delay.mul_f64(1.0 + self.jitter * fraction(config.random_u64()));
+
+        if let Some(allow) = &self.allow_sleep {
+            if !allow(delay, attempt + 1) {
+                debug!("Retry sleep of {:?} vetoed", delay);
+                return None;
+            }
+        }
+
+        Some(delay)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            statuses: vec![
+                StatusCode::TOO_MANY_REQUESTS.as_u16(),
+                StatusCode::SERVICE_UNAVAILABLE.as_u16(),
+            ],
+            base_delay: Duration::from_secs(1),
+            max_sleep: Duration::from_secs(30),
+            jitter: 0.25,
+            allow_sleep: None,
+        }
+    }
+}
+
+impl fmt::Debug for RetryPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetryPolicy")
+            .field("max_retries", &self.max_retries)
+            .field("statuses", &self.statuses)
+            .field("base_delay", &self.base_delay)
+            .field("max_sleep", &self.max_sleep)
+            .field("jitter", &self.jitter)
+            .field("allow_sleep", &self.allow_sleep.is_some())
+            .finish()
+    }
+}
+
+/// Parse the `Retry-After` header of a response into a delay from `now`.
+pub(crate) fn retry_after<B>(response: &Response<B>, now: SystemTime) -> Option<Duration> {
+    let value = response
+        .headers()
+        .get(http::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?;
+
+    parse_retry_after(value, now)
+}
+
+/// `Retry-After` is either delay-seconds or an HTTP-date.
+fn parse_retry_after(value: &str, now: SystemTime) -> Option<Duration> {
+    let value = value.trim();
+
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+
+    let at = parse_http_date(value)?;
+
+    // A date in the past means we can retry right away.
+    Some(at.duration_since(now).unwrap_or(Duration::ZERO))
+}
+
+/// Parse the three HTTP-date formats of RFC 9110.
+///
+/// * `Sun, 06 Nov 1994 08:49:37 GMT` (IMF-fixdate)
+/// * `Sunday, 06-Nov-94 08:49:37 GMT` (obsolete RFC 850)
+/// * `Sun Nov  6 08:49:37 1994` (obsolete asctime)
+fn parse_http_date(s: &str) -> Option<SystemTime> {
+    let (day, month, year, time) = if let Some((_, rest)) = s.split_once(',') {
+        let mut parts = rest
+            .split(|c| c == ' ' || c == '-')
+            .filter(|p| !p.is_empty());
+        let day = parts.next()?;
+        let month = parts.next()?;
+        let year = parts.next()?;
+        let time = parts.next()?;
+        if parts.next()? != "GMT" {
+            return None;
+        }
+        (day, month, year, time)
+    } else {
+        let mut parts = s.split(' ').filter(|p| !p.is_empty());
+        let _weekday = parts.next()?;
+        let month = parts.next()?;
+        let day = parts.next()?;
+        let time = parts.next()?;
+        let year = parts.next()?;
+        (day, month, year, time)
+    };
+
+    let day: u32 = day.parse().ok()?;
+    let month = match month {
+        "Jan" => 1,
+        "Feb" => 2,
+        "Mar" => 3,
+        "Apr" => 4,
+        "May" => 5,
+        "Jun" => 6,
+        "Jul" => 7,
+        "Aug" => 8,
+        "Sep" => 9,
+        "Oct" => 10,
+        "Nov" => 11,
+        "Dec" => 12,
+        _ => return None,
+    };
+    let year: i64 = match year.parse().ok()? {
+        // Two digit years from RFC 850.
+        y @ 0..=69 => 2000 + y,
+        y @ 70..=99 => 1900 + y,
+        y => y,
+    };
+
+    let mut hms = time.split(':').map(|p| p.parse::<u64>().ok());
+    let (h, m, sec) = (hms.next()??, hms.next()??, hms.next()??);
+    if hms.next().is_some() || !(1..=31).contains(&day) || h > 23 || m > 59 || sec > 60 {
+        return None;
+    }
+
+    let days = days_from_civil(year, month, day);
+    let secs = u64::try_from(days).ok()? * 86_400 + h * 3600 + m * 60 + sec;
+
+    Some(UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::Random;
+
+    fn response(status: u16, retry_after: Option<&str>) -> Response<()> {
+        let mut builder = Response::builder().status(status);
+        if let Some(v) = retry_after {
+            builder = builder.header("retry-after", v);
+        }
+        builder.body(()).unwrap()
+    }
+
+    #[test]
+    fn parse_seconds_and_dates() {
+        let now = UNIX_EPOCH + Duration::from_secs(784111777); // Sun, 06 Nov 1994 08:49:37 GMT
+
+        let p = |s| parse_retry_after(s, now);
+
+        assert_eq!(p("120"), Some(Duration::from_secs(120)));
+        assert_eq!(
+            p("Sun, 06 Nov 1994 08:50:37 GMT"),
+            Some(Duration::from_secs(60))
+        );
+        assert_eq!(
+            p("Sunday, 06-Nov-94 08:49:47 GMT"),
+            Some(Duration::from_secs(10))
+        );
+        assert_eq!(p("Sun Nov  6 08:49:38 1994"), Some(Duration::from_secs(1)));
+        assert_eq!(p("Sat, 05 Nov 1994 08:49:37 GMT"), Some(Duration::ZERO));
+        assert_eq!(p("tomorrow"), None);
+    }
+
+    #[test]
+    fn delay_respects_retry_after() {
+        let policy = RetryPolicy::new().jitter(0.0);
+        let config = Config::default();
+
+        let d = policy.delay(&response(503, Some("2")), 0, &config);
+        assert_eq!(d, Some(Duration::from_secs(2)));
+
+        // Longer than max_sleep means no retry.
+        let d = policy.delay(&response(503, Some("3600")), 0, &config);
+        assert_eq!(d, None);
+
+        // Not a retry status.
+        assert_eq!(policy.delay(&response(500, None), 0, &config), None);
+    }
+
+    #[test]
+    fn delay_backs_off_and_caps() {
+        let policy = RetryPolicy::new()
+            .jitter(0.0)
+            .max_retries(10)
+            .max_sleep(Duration::from_secs(5));
+        let config = Config::default();
+
+        let res = response(429, None);
+        assert_eq!(policy.delay(&res, 0, &config), Some(Duration::from_secs(1)));
+        assert_eq!(policy.delay(&res, 2, &config), Some(Duration::from_secs(4)));
+        assert_eq!(policy.delay(&res, 3, &config), Some(Duration::from_secs(5)));
+        assert_eq!(policy.delay(&res, 10, &config), None);
+    }
+
+    #[test]
+    fn delay_jitter_and_veto() {
+        let policy = RetryPolicy::new()
+            .jitter(0.5)
+            .allow_sleep(|d, _| d < Duration::from_secs(10));
+        let config = Config::default();
+
+        let d = policy.delay(&response(429, Some("4")), 0, &config).unwrap();
+        assert!(d >= Duration::from_secs(4) && d <= Duration::from_secs(6));
+
+        // The jitter follows the random numbers of the config.
+        let jitter = |n: u64| {
+            let config = Config::builder()
+                .random(Some(Random::new(move || n)))
+                .build();
+            policy.delay(&response(429, Some("4")), 0, &config).unwrap()
+        };
+        assert_eq!(jitter(0), Duration::from_secs(4));
+        assert_eq!(jitter(1 << 63), Duration::from_secs(5));
+
+        assert_eq!(policy.delay(&response(429, Some("20")), 0, &config), None);
+    }
+}
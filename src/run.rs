@@ -1,21 +1,33 @@
 use std::convert::TryFrom;
-use std::sync::Arc;
-use std::{io, mem};
+use std::io::IoSlice;
+use std::sync::{mpsc, Arc, Mutex};
+use std::{io, mem, thread};
 
+use base64::prelude::BASE64_STANDARD;
+use base64::Engine;
 use hoot::client::flow::state::{Await100, RecvBody, RecvResponse, Redirect, SendRequest};
 use hoot::client::flow::state::{Prepare, SendBody as SendBodyState};
 use hoot::client::flow::{Await100Result, RecvBodyResult, RecvResponseResult, SendRequestResult};
 use hoot::BodyMode;
-use http::uri::Scheme;
-use http::{header, HeaderValue, Request, Response, Uri};
+use http::uri::{Authority, Scheme};
+use http::{header, Extensions, HeaderValue, Method, Request, Response, StatusCode, Uri};
+use percent_encoding::percent_decode_str;
 
 use crate::body::ResponseInfo;
-use crate::config::{Config, RequestLevelConfig};
+use crate::codec::Codecs;
+use crate::config::{Config, Redaction, RequestLevelConfig, RetryPolicy};
+use crate::early_hints::EarlyHints;
+use crate::error::{ErrorBody, TransferStats};
+use crate::headers::{check_framing, fold_headers};
 use crate::pool::Connection;
+use crate::resolver::ResolvedSocketAddrs;
+use crate::response::HasBody;
+use crate::sign::{SignRequest, Signer};
 use crate::timings::{CallTimings, CurrentTime};
 use crate::transport::time::{Duration, Instant};
-use crate::transport::ConnectionDetails;
-use crate::util::{DebugRequest, DebugResponse, DebugUri, HeaderMapExt, UriExt};
+use crate::transport::{ConnectionDetails, NextTimeout};
+use crate::util::{glob_match, SchemeExt, UriExt};
+use crate::util::{AuthorityExt, DebugRequest, DebugResponse, DebugUri, HeaderMapExt};
 use crate::{Agent, Body, Error, SendBody, Timeout};
 
 type Flow<T> = hoot::client::flow::Flow<(), T>;
@@ -28,8 +40,6 @@ pub(crate) fn run(
     mut request: Request<()>,
     mut body: SendBody,
 ) -> Result<Response<Body>, Error> {
-    let mut redirect_count = 0;
-
     // Configuration on the request level overrides the agent level.
     let config = request
         .extensions_mut()
@@ -38,9 +48,180 @@ pub(crate) fn run(
         .map(Arc::new)
         .unwrap_or_else(|| agent.config.clone());
 
-    let timeouts = config.timeouts;
+    let extensions = request.extensions().clone();
+
+    let mut retries = 0;
+
+    // The global timeout is the budget of all attempts, and the delays between them.
+    let timings = CallTimings::new(config.timeouts, CurrentTime::default());
+
+    let response = loop {
+        let retry = config
+            .retry
+            .as_deref()
+            .filter(|_| RetryPolicy::is_retryable_method(request.method()))
+            .and_then(|policy| Some((policy, copy_request(&request), body.try_clone()?)));
+
+        let result = run_once(agent, &config, request, body, timings.fork().new_call());
+
+        let Some((policy, next_request, next_body)) = retry else {
+            break result?;
+        };
+
+        match result {
+            Ok(response) => {
+                let delay = policy.delay(&response, retries, &config);
+                let Some(delay) = delay.and_then(|d| retry_delay(d, &timings)) else {
+                    break response;
+                };
+
+                retries += 1;
+                debug!(
+                    "Retry {} in {:?} after status {}",
+                    retries,
+                    delay,
+                    response.status()
+                );
+
+                // Drop the response, and with it the connection, before sleeping.
+                drop(response);
+                std::thread::sleep(delay);
+            }
+            Err(e) => {
+                let delay = Some(&e)
+                    .filter(|e| RetryPolicy::is_retryable_error(e))
+                    .and_then(|_| policy.backoff(retries, &config))
+                    .and_then(|d| retry_delay(d, &timings));
+
+                let Some(delay) = delay else {
+                    return Err(e);
+                };
+
+                retries += 1;
+                debug!("Retry {} in {:?} after error: {}", retries, delay, e);
+
+                std::thread::sleep(delay);
+            }
+        }
+
+        request = next_request;
+        body = next_body;
+    };
+
+    let response = with_extensions(response, extensions);
+
+    status_as_error(&config, response)
+}
+
+/// The delay before retrying, capped at what is left of the global timeout.
+///
+/// `None` when the delay uses up the rest of the global timeout, since the retry
+/// would time out before it is sent.
+fn retry_delay(delay: std::time::Duration, timings: &CallTimings) -> Option<std::time::Duration> {
+    match timings.next_timeout(Timeout::Global).after {
+        Duration::Exact(left) if delay >= left => {
+            debug!("No retry, {:?} left of the global timeout", left);
+            None
+        }
+        Duration::Exact(_) | Duration::NotHappening => Some(delay),
+    }
+}
+
+/// Set the request extensions on the response, where those of the response take precedence.
+fn with_extensions(mut response: Response<Body>, mut extensions: Extensions) -> Response<Body> {
+    extensions.extend(std::mem::take(response.extensions_mut()));
+    *response.extensions_mut() = extensions;
+    response
+}
+
+/// Turn 4xx and 5xx responses into errors, when configured to.
+fn status_as_error(config: &Config, mut response: Response<Body>) -> Result<Response<Body>, Error> {
+    let status = response.status();
+    let is_err = status.is_client_error() || status.is_server_error();
+
+    if config.http_status_as_error && is_err {
+        if config.max_error_body_size > 0 {
+            let body = ErrorBody::read(response.body_mut(), config.max_error_body_size);
+            return Err(Error::StatusCodeBody(status.as_u16(), Box::new(body)));
+        }
+        return Err(Error::StatusCode(status.as_u16()));
+    }
+
+    Ok(response)
+}
+
+/// Copy of the request for sending it again.
+pub(crate) fn copy_request(request: &Request<()>) -> Request<()> {
+    let mut copy = Request::new(());
+    *copy.method_mut() = request.method().clone();
+    *copy.uri_mut() = request.uri().clone();
+    *copy.version_mut() = request.version();
+    *copy.headers_mut() = request.headers().clone();
+    *copy.extensions_mut() = request.extensions().clone();
+    copy
+}
+
+/// Connect to `uri` ahead of a request, and park the connection in the pool.
+pub(crate) fn preconnect(agent: &Agent, uri: &Uri) -> Result<(), Error> {
+    let config = agent.config();
+    let mut timings = CallTimings::new(config.timeouts, CurrentTime::default());
+
+    let connection = connect(agent, config, uri, None, &mut timings)?;
+
+    debug!("Preconnected: {:?}", DebugUri(uri, config.redaction()));
+    connection.park(timings.now());
+
+    Ok(())
+}
+
+/// Encode the request body with the codec of
+/// [`request_encoding`](crate::config::ConfigBuilder::request_encoding), if set.
+fn encode_body<'a>(
+    config: &Config,
+    request: &mut Request<()>,
+    body: SendBody<'a>,
+) -> Result<SendBody<'a>, Error> {
+    let Some(name) = config.request_encoding else {
+        return Ok(body);
+    };
+
+    let is_empty = body.as_slice().map(|s| s.is_empty()).unwrap_or(false);
+    if is_empty || request.headers().contains_key(header::CONTENT_ENCODING) {
+        return Ok(body);
+    }
+
+    let codec = config
+        .codecs
+        .get(name)
+        .ok_or_else(|| Error::UnknownCodec(name.to_string()))?;
 
-    let mut timings = CallTimings::new(timeouts, CurrentTime::default());
+    let value =
+        HeaderValue::from_str(codec.name()).map_err(|_| Error::UnknownCodec(name.to_string()))?;
+
+    debug!("Encoding request body with {}", codec.name());
+
+    let headers = request.headers_mut();
+    // The length is of the body before encoding.
+    headers.remove(header::CONTENT_LENGTH);
+    headers.insert(header::CONTENT_ENCODING, value);
+
+    Ok(body.encode(&**codec))
+}
+
+/// A single attempt of a request, following redirects.
+fn run_once(
+    agent: &Agent,
+    config: &Config,
+    mut request: Request<()>,
+    body: SendBody,
+    mut timings: CallTimings,
+) -> Result<Response<Body>, Error> {
+    let mut redirect_count = 0;
+
+    let mut body = encode_body(config, &mut request, body)?;
+
+    // A Referer of the request itself is kept, one of the policy is made per hop.
+    let has_referer = request.headers().contains_key(header::REFERER);
 
     let mut flow = Flow::new(request)?;
 
@@ -48,6 +229,9 @@ pub(crate) fn run(
         flow.send_body_despite_method();
     }
 
+    // Every (method, uri, cookies) we make a request with. Used to detect redirect loops.
+    let mut visited: Vec<(Method, Uri, String)> = vec![];
+
     let (response, handler) = loop {
         let timeout = timings.next_timeout(Timeout::Global);
         let timed_out = match timeout.after {
@@ -58,19 +242,32 @@ pub(crate) fn run(
             return Err(Error::Timeout(Timeout::Global));
         }
 
-        match flow_run(
-            agent,
-            &config,
-            flow,
-            &mut body,
-            redirect_count,
-            &mut timings,
-        )? {
+        let cookies = request_cookies(agent, config, flow.uri());
+        visited.push((flow.method().clone(), flow.uri().clone(), cookies));
+
+        match flow_run(agent, config, flow, &mut body, redirect_count, &mut timings)? {
             // Follow redirect
             FlowResult::Redirect(rflow, rtimings) => {
                 redirect_count += 1;
 
-                flow = handle_redirect(rflow, &config)?;
+                flow = handle_redirect(rflow, config)?;
+
+                if !config.redirect_schemes.allows(flow.uri()) {
+                    let uri = DebugUri(flow.uri(), config.redaction());
+                    return Err(Error::RedirectDenied(format!("{:?}", uri)));
+                }
+                // A redirect back to the same uri is fine if the cookies changed,
+                // as in a login setting a session cookie.
+                let cookies = request_cookies(agent, config, flow.uri());
+                ensure_no_redirect_loop(
+                    &visited,
+                    (flow.method(), flow.uri(), &cookies),
+                    config.redaction(),
+                )?;
+
+                if let Some((_, from, _)) = visited.last().filter(|_| !has_referer) {
+                    flow = set_referer(flow, config, from)?;
+                }
                 timings = rtimings.new_call();
             }
 
@@ -79,7 +276,11 @@ pub(crate) fn run(
         }
     };
 
-    let (parts, _) = response.into_parts();
+    Ok(with_body(config, response, handler))
+}
+
+fn with_body(config: &Config, response: Response<()>, handler: BodyHandler) -> Response<Body> {
+    let (mut parts, _) = response.into_parts();
 
     let recv_body_mode = handler
         .flow
@@ -87,40 +288,133 @@ pub(crate) fn run(
         .map(|f| f.body_mode())
         .unwrap_or(BodyMode::NoBody);
 
-    let info = ResponseInfo::new(&parts.headers, recv_body_mode);
+    let has_body = HasBody(!matches!(recv_body_mode, BodyMode::NoBody));
+    parts.extensions.insert(has_body);
 
-    let body = Body::new(handler, info);
+    let mut info = ResponseInfo::new(
+        &parts.headers,
+        recv_body_mode,
+        config.content_sniffing.clone(),
+        &config.codecs,
+    );
 
-    let response = Response::from_parts(parts, body);
-
-    let status = response.status();
-    let is_err = status.is_client_error() || status.is_server_error();
-
-    if config.http_status_as_error && is_err {
-        return Err(Error::StatusCode(status.as_u16()));
+    // A partial response has the checksum of the entire body, if any.
+    #[cfg(feature = "checksums")]
+    if config.verify_checksums && parts.status != StatusCode::PARTIAL_CONTENT {
+        info.verify_checksums(&parts.headers);
     }
 
-    Ok(response)
+    let body = Body::new(handler, info);
+
+    Response::from_parts(parts, body)
 }
 
 fn flow_run(
     agent: &Agent,
     config: &Config,
-    mut flow: Flow<Prepare>,
+    flow: Flow<Prepare>,
     body: &mut SendBody,
     redirect_count: u32,
     timings: &mut CallTimings,
 ) -> Result<FlowResult, Error> {
     let uri = flow.uri().clone();
-    info!("{} {:?}", flow.method(), &DebugUri(flow.uri()));
+    let redaction = config.redaction();
+
+    let mut head_body_sent = 0;
+    let (result, mut connection) =
+        send_head(agent, config, flow, body, timings, &mut head_body_sent)?;
+
+    // From here on the server might act on the request, so errors can tell how far we got.
+    let enabled = config.transfer_stats_on_error;
+    let mut stats = TransferStats {
+        head_sent: true,
+        body_sent: head_body_sent,
+        connection: enabled.then(|| (*connection.info()).clone()),
+        ..Default::default()
+    };
 
-    if config.https_only && uri.scheme() != Some(&Scheme::HTTPS) {
-        return Err(Error::RequireHttpsOnly(uri.to_string()));
+    let flow = match result {
+        SendRequestResult::Await100(flow) => {
+            let result = await_100(flow, &mut connection, timings)
+                .map_err(|e| stats.wrap(enabled, e, timings))?;
+            match result {
+                Await100Result::SendBody(flow) => {
+                    send_body(flow, body, &mut connection, timings, &mut stats.body_sent)
+                        .map_err(|e| stats.wrap(enabled, e, timings))?
+                }
+                Await100Result::RecvResponse(flow) => flow,
+            }
+        }
+        SendRequestResult::SendBody(flow) => {
+            send_body(flow, body, &mut connection, timings, &mut stats.body_sent)
+                .map_err(|e| stats.wrap(enabled, e, timings))?
+        }
+        SendRequestResult::RecvResponse(flow) => flow,
+    };
+
+    let (response, response_result) = recv_response(flow, &mut connection, config, &uri, timings)
+        .map_err(|e| stats.wrap(enabled, e, timings))?;
+
+    stats.head_received = true;
+
+    info!("{:?}", DebugResponse(&response, redaction));
+
+    store_cookies(agent, config, &response, &uri);
+
+    flow_result(
+        config,
+        response,
+        response_result,
+        connection,
+        redirect_count,
+        timings,
+        stats,
+    )
+}
+
+/// Prepare the request, connect and send the request head.
+fn send_head(
+    agent: &Agent,
+    config: &Config,
+    mut flow: Flow<Prepare>,
+    body: &mut SendBody,
+    timings: &mut CallTimings,
+    sent: &mut u64,
+) -> Result<(SendRequestResult<()>, Connection), Error> {
+    let uri = flow.uri().clone();
+    let redaction = config.redaction();
+    info!("{} {:?}", flow.method(), &DebugUri(flow.uri(), redaction));
+
+    let has_userinfo = uri.authority().and_then(|a| a.userinfo()).is_some();
+    if config.reject_uri_userinfo && has_userinfo {
+        return Err(Error::BadUri(format!(
+            "userinfo not allowed: {:?}",
+            DebugUri(&uri, redaction)
+        )));
     }
 
     add_headers(&mut flow, agent, config, body, &uri)?;
 
-    let mut connection = connect(agent, config, &uri, timings)?;
+    if let Some(signer) = &config.signer {
+        sign_request(&mut flow, signer.as_ref(), body, &uri)?;
+    }
+
+    let server_name = server_name_from_host_header(&flow, config);
+
+    // From connecting on, errors can tell how far the call got, such as which phase
+    // timed out, or the connection the head failed on.
+    let enabled = config.transfer_stats_on_error;
+    let mut connection =
+        connect(agent, config, &uri, server_name.as_deref(), timings).map_err(|e| match e {
+            // Refused by the config before connecting.
+            Error::RequireHttpsOnly(_) | Error::Offline(_) => e,
+            e => TransferStats::default().wrap(enabled, e, timings),
+        })?;
+
+    let stats = TransferStats {
+        connection: enabled.then(|| (*connection.info()).clone()),
+        ..Default::default()
+    };
 
     let mut flow = flow.proceed();
 
@@ -132,51 +426,45 @@ fn flow_run(
             uri: flow.uri(),
             version: flow.version(),
             headers,
+            redaction,
         };
         info!("{:?}", r);
     }
 
-    let flow = match send_request(flow, &mut connection, timings)? {
-        SendRequestResult::Await100(flow) => match await_100(flow, &mut connection, timings)? {
-            Await100Result::SendBody(flow) => send_body(flow, body, &mut connection, timings)?,
-            Await100Result::RecvResponse(flow) => flow,
-        },
-        SendRequestResult::SendBody(flow) => send_body(flow, body, &mut connection, timings)?,
-        SendRequestResult::RecvResponse(flow) => flow,
-    };
-
-    let (response, response_result) = recv_response(flow, &mut connection, config, timings)?;
-
-    info!("{:?}", DebugResponse(&response));
-
-    #[cfg(feature = "cookies")]
-    {
-        let mut jar = agent.cookie_jar_lock();
-
-        let iter = response
-            .headers()
-            .get_all(http::header::SET_COOKIE)
-            .iter()
-            .filter_map(|h| h.to_str().ok())
-            .filter_map(|s| crate::Cookie::parse(s, &uri).ok());
+    let result = send_request(flow, &mut connection, body, timings, sent).map_err(|e| {
+        let stats = TransferStats {
+            body_sent: *sent,
+            ..stats
+        };
+        stats.wrap(enabled, e, timings)
+    })?;
 
-        jar.store_response_cookies(iter, &uri);
-    }
+    Ok((result, connection))
+}
 
+/// Turn the received response into the result of [`flow_run`].
+fn flow_result(
+    config: &Config,
+    response: Response<()>,
+    response_result: RecvResponseResult<()>,
+    connection: Connection,
+    redirect_count: u32,
+    timings: &mut CallTimings,
+    stats: TransferStats,
+) -> Result<FlowResult, Error> {
     let ret = match response_result {
         RecvResponseResult::RecvBody(flow) => {
-            let timings = mem::take(timings);
-            let mut handler = BodyHandler {
-                flow: Some(flow),
-                connection: Some(connection),
-                timings,
-                ..Default::default()
-            };
+            let mut handler = BodyHandler::default();
+            handler.flow = Some(flow);
+            handler.connection = Some(connection);
+            handler.timings = mem::take(timings);
+            handler.max_drain = config.max_drain_on_drop;
+            handler.stats = config.transfer_stats_on_error.then_some(stats);
 
             if response.status().is_redirection() && redirect_count < config.max_redirects {
                 let flow = handler.consume_redirect_body()?;
 
-                FlowResult::Redirect(flow, handler.timings)
+                FlowResult::Redirect(flow, mem::take(&mut handler.timings))
             } else {
                 FlowResult::Response(response, handler)
             }
@@ -199,6 +487,27 @@ fn flow_run(
     Ok(ret)
 }
 
+fn store_cookies(agent: &Agent, config: &Config, response: &Response<()>, uri: &Uri) {
+    #[cfg(not(feature = "cookies"))]
+    {
+        let _ = (agent, config, response, uri);
+    }
+
+    #[cfg(feature = "cookies")]
+    if let Some(jar) = config.cookie_jar.jar(agent) {
+        let mut jar = jar.lock();
+
+        let iter = response
+            .headers()
+            .get_all(http::header::SET_COOKIE)
+            .iter()
+            .filter_map(|h| h.to_str().ok())
+            .filter_map(|s| crate::Cookie::parse(s, uri).ok());
+
+        jar.store_response_cookies(iter, uri, config.cookie_limits());
+    }
+}
+
 /// Return type of [`flow_run`].
 #[allow(clippy::large_enum_variant)]
 enum FlowResult {
@@ -209,6 +518,30 @@ enum FlowResult {
     Response(Response<()>, BodyHandler),
 }
 
+/// The built in encodings, followed by the added codecs.
+fn accept_encoding(codecs: &Codecs) -> Option<HeaderValue> {
+    #[allow(unused_mut)]
+    let mut names: Vec<&str> = vec![];
+    #[cfg(feature = "gzip")]
+    names.push("gzip");
+    #[cfg(feature = "brotli")]
+    names.push("br");
+
+    for name in codecs.names() {
+        let valid = HeaderValue::from_str(name).is_ok();
+        if valid && !names.iter().any(|n| n.eq_ignore_ascii_case(name)) {
+            names.push(name);
+        }
+    }
+
+    if names.is_empty() {
+        return None;
+    }
+
+    // unwrap is ok because the names are valid values on their own
+    Some(HeaderValue::from_str(&names.join(", ")).unwrap())
+}
+
 fn add_headers(
     flow: &mut Flow<Prepare>,
     agent: &Agent,
@@ -223,10 +556,10 @@ fn add_headers(
     } else {
         Some(body.body_mode())
     };
-    #[cfg(any(feature = "gzip", feature = "brotli"))]
     let has_header_accept_enc = headers.has_accept_encoding();
     let has_header_ua = headers.has_user_agent();
     let has_header_accept = headers.has_accept();
+    let has_header_auth = headers.contains_key(header::AUTHORIZATION);
 
     #[cfg(not(feature = "cookies"))]
     {
@@ -234,8 +567,8 @@ fn add_headers(
         let _ = uri;
     }
     #[cfg(feature = "cookies")]
-    {
-        let value = agent.jar.get_request_cookies(uri);
+    if let Some(jar) = config.cookie_jar.jar(agent) {
+        let value = jar.get_request_cookies(uri);
         if !value.is_empty() {
             let value = HeaderValue::from_str(&value)
                 .map_err(|_| Error::CookieValue("Cookie value is an invalid http-header"))?;
@@ -243,22 +576,8 @@ fn add_headers(
         }
     }
 
-    #[cfg(any(feature = "gzip", feature = "brotli"))]
-    {
-        use once_cell::sync::Lazy;
-        static ACCEPTS: Lazy<String> = Lazy::new(|| {
-            let mut value = String::with_capacity(10);
-            #[cfg(feature = "gzip")]
-            value.push_str("gzip");
-            #[cfg(all(feature = "gzip", feature = "brotli"))]
-            value.push_str(", ");
-            #[cfg(feature = "brotli")]
-            value.push_str("br");
-            value
-        });
-        // unwrap is ok because above ACCEPTS will produce a valid value
-        let value = HeaderValue::from_str(&ACCEPTS).unwrap();
-        if !has_header_accept_enc {
+    if !has_header_accept_enc {
+        if let Some(value) = accept_encoding(&config.codecs) {
             flow.header(header::ACCEPT_ENCODING, value)?;
         }
     }
@@ -289,13 +608,212 @@ fn add_headers(
         flow.header(header::ACCEPT, value)?;
     }
 
+    // Credentials in the uri are sent as basic auth. This only applies to the uri they are
+    // part of, a redirect to another host does not carry over the userinfo.
+    if !has_header_auth {
+        if let Some(value) = uri.authority().and_then(basic_auth_from_userinfo) {
+            flow.header(header::AUTHORIZATION, value)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Let the signer see the finished headers, and add its own.
+fn sign_request(
+    flow: &mut Flow<Prepare>,
+    signer: &dyn Signer,
+    body: &SendBody,
+    uri: &Uri,
+) -> Result<(), Error> {
+    // The Host header is otherwise added when writing the request. The signer
+    // needs to see it, so we add it here the same way.
+    if !flow.headers().contains_key(header::HOST) {
+        if let Some(value) = host_header_value(uri) {
+            flow.header(header::HOST, value)?;
+        }
+    }
+
+    let method = flow.method().clone();
+    let headers = flow.headers().clone();
+
+    let mut request = SignRequest::new(&method, uri, headers, body.as_slice());
+    signer.sign(&mut request)?;
+
+    for (name, value) in request.into_added() {
+        flow.header(name, value)?;
+    }
+
     Ok(())
 }
 
+/// Host and port (if not the default for the scheme), without userinfo.
+pub(crate) fn host_header_value(uri: &Uri) -> Option<HeaderValue> {
+    let authority = uri.authority()?;
+    let host = authority.host();
+
+    let value = match authority.port_u16() {
+        Some(port) if Some(port) != uri.scheme().and_then(|s| s.default_port()) => {
+            format!("{}:{}", host, port)
+        }
+        _ => host.to_string(),
+    };
+
+    HeaderValue::from_str(&value).ok()
+}
+
+/// The host of an explicit `Host` header, when configured to be used as TLS server name.
+fn server_name_from_host_header(flow: &Flow<Prepare>, config: &Config) -> Option<String> {
+    #[cfg(feature = "_tls")]
+    if config.tls_config.sni_from_host_header {
+        let value = flow.headers().get(header::HOST)?.to_str().ok()?;
+        let authority: Authority = value.parse().ok()?;
+        return Some(authority.host().to_string());
+    }
+
+    let _ = (flow, config);
+    None
+}
+
+fn basic_auth_from_userinfo(authority: &Authority) -> Option<HeaderValue> {
+    authority.userinfo()?;
+
+    let decode = |s: &str| percent_decode_str(s).decode_utf8_lossy().to_string();
+    let user = authority.username().map(decode).unwrap_or_default();
+    let pass = authority.password().map(decode).unwrap_or_default();
+
+    let creds = BASE64_STANDARD.encode(format!("{}:{}", user, pass));
+    let mut value = HeaderValue::from_str(&format!("Basic {}", creds)).ok()?;
+    value.set_sensitive(true);
+
+    Some(value)
+}
+
 fn connect(
     agent: &Agent,
     config: &Config,
     uri: &Uri,
+    server_name: Option<&str>,
+    timings: &mut CallTimings,
+) -> Result<Connection, Error> {
+    // Checked here, so a preconnect is held to them like a request.
+    let redaction = config.redaction();
+
+    if config.https_only && uri.scheme() != Some(&Scheme::HTTPS) {
+        return Err(Error::RequireHttpsOnly(format!(
+            "{:?}",
+            DebugUri(uri, redaction)
+        )));
+    }
+
+    if let Some(routes) = config.offline_routes {
+        let target = uri.to_string();
+        if !routes.iter().any(|r| glob_match(r, &target)) {
+            return Err(Error::Offline(format!("{:?}", DebugUri(uri, redaction))));
+        }
+    }
+
+    // A selector picks the proxy per destination. Otherwise hosts in the no proxy
+    // list are connected to directly.
+    let selected = if let Some(selector) = &config.proxy_selector {
+        let proxy = selector.select(uri);
+        debug!(
+            "Selected proxy {:?} for: {:?}",
+            proxy,
+            DebugUri(uri, config.redaction())
+        );
+        Some(proxy)
+    } else {
+        match &config.no_proxy {
+            Some(no_proxy) if config.proxy.is_some() && no_proxy.matches(uri) => {
+                debug!("Bypass proxy: {:?}", DebugUri(uri, config.redaction()));
+                Some(None)
+            }
+            _ => None,
+        }
+    };
+    let with_proxy;
+    let config = match selected {
+        Some(proxy) => {
+            with_proxy = Config {
+                proxy,
+                ..config.clone()
+            };
+            &with_proxy
+        }
+        None => config,
+    };
+
+    if config.race_direct && config.proxy.is_some() && config.pre_connected.is_none() {
+        return race_direct(agent, config, uri, server_name, timings);
+    }
+
+    connect_via(agent, config, uri, server_name, timings)
+}
+
+/// Connect via the proxy and directly at the same time, keeping the first to connect.
+fn race_direct(
+    agent: &Agent,
+    config: &Config,
+    uri: &Uri,
+    server_name: Option<&str>,
+    timings: &mut CallTimings,
+) -> Result<Connection, Error> {
+    let (tx, rx) = mpsc::channel();
+
+    let direct = Config {
+        proxy: None,
+        ..config.clone()
+    };
+
+    for config in [config.clone(), direct] {
+        let agent = agent.clone();
+        let uri = uri.clone();
+        let server_name = server_name.map(String::from);
+        let mut timings = timings.fork();
+        let tx = tx.clone();
+
+        thread::spawn(move || {
+            let proxied = config.proxy.is_some();
+            let result = connect_via(&agent, &config, &uri, server_name.as_deref(), &mut timings);
+            // Fails if the other attempt won, which closes the connection.
+            let _ = tx.send((proxied, result.map(|c| (c, timings))));
+        });
+    }
+    drop(tx);
+
+    let mut error = None;
+
+    for (proxied, result) in rx {
+        let route = if proxied { "proxied" } else { "direct" };
+        match result {
+            Ok((connection, won)) => {
+                debug!(
+                    "Race won by {} connection: {:?}",
+                    route,
+                    DebugUri(uri, config.redaction())
+                );
+                *timings = won;
+                return Ok(connection);
+            }
+            Err(e) => {
+                debug!("Race lost by failed {} connection: {}", route, e);
+                if proxied || error.is_none() {
+                    error = Some(e);
+                }
+            }
+        }
+    }
+
+    Err(error.unwrap_or(Error::ConnectionFailed))
+}
+
+/// Connect to `uri` with the proxy, if any, of `config`.
+fn connect_via(
+    agent: &Agent,
+    config: &Config,
+    uri: &Uri,
+    server_name: Option<&str>,
     timings: &mut CallTimings,
 ) -> Result<Connection, Error> {
     // If we're using a CONNECT proxy, we need to resolve that hostname.
@@ -307,48 +825,130 @@ fn connect(
     // cannot make requests with partial uri like "/path".
     effective_uri.ensure_valid_url()?;
 
-    let addrs = agent.resolver.resolve(
-        effective_uri,
-        config,
-        timings.next_timeout(Timeout::Resolve),
-    )?;
+    // A proxy on a unix socket is found by path, a SOCKS4a proxy resolves the
+    // host itself, a SOCKS proxy first in a chain connects to the next proxy, and
+    // a pre-connected transport is already connected. There is nothing to resolve.
+    let is_unix_proxy = config.proxy.as_ref().and_then(|p| p.unix_path()).is_some();
+    let is_remote_dns = config
+        .proxy
+        .as_ref()
+        .map(|p| p.proto().resolves_remotely() || (p.proto().is_socks() && p.tunnels()))
+        .unwrap_or(false);
+    let no_resolve = is_unix_proxy || is_remote_dns || config.pre_connected.is_some();
+
+    let timeout = timings.next_timeout(Timeout::Resolve);
+    let resolved = if no_resolve {
+        Ok(ResolvedSocketAddrs::from_fn(|_| {
+            "0.0.0.0:0".parse().unwrap()
+        }))
+    } else {
+        agent.resolver.resolve(effective_uri, config, timeout)
+    };
+
+    let addrs = match resolved {
+        Err(Error::HostNotFound) if config.re_resolve_on_failure => {
+            debug!(
+                "Host not found, resolve again: {:?}",
+                DebugUri(effective_uri, config.redaction())
+            );
+            let timeout = timings.next_timeout(Timeout::Resolve);
+            agent.resolver.resolve(effective_uri, config, timeout)?
+        }
+        r => r?,
+    };
 
     timings.record_time(Timeout::Resolve);
 
     let details = ConnectionDetails {
         uri,
         addrs,
+        server_name,
         resolver: &*agent.resolver,
         config,
         now: timings.now(),
         timeout: timings.next_timeout(Timeout::Connect),
     };
 
-    let connection = agent.pool.connect(&details, config.max_idle_age.into())?;
+    let connection = match agent.pool.connect(&details, config.max_idle_age.into()) {
+        Err(e) if config.re_resolve_on_failure && !no_resolve && is_connection_refused(&e) => {
+            // The addresses we got might be stale, such as during a DNS failover.
+            // Resolve again and make one more attempt with the fresh addresses.
+            debug!(
+                "Connection refused, resolve again: {:?}",
+                DebugUri(effective_uri, config.redaction())
+            );
+            let timeout = timings.restart_timeout(Timeout::Resolve);
+            let addrs = agent.resolver.resolve(effective_uri, config, timeout)?;
+
+            let details = ConnectionDetails {
+                addrs,
+                now: timings.now(),
+                timeout: timings.next_timeout(Timeout::Connect),
+                ..details
+            };
+
+            agent.pool.connect(&details, config.max_idle_age.into())?
+        }
+        r => r?,
+    };
 
     timings.record_time(Timeout::Connect);
 
     Ok(connection)
 }
 
+fn is_connection_refused(e: &Error) -> bool {
+    matches!(e, Error::Io(e) if e.kind() == io::ErrorKind::ConnectionRefused)
+}
+
 fn send_request(
     mut flow: Flow<SendRequest>,
     connection: &mut Connection,
+    body: &mut SendBody,
     timings: &mut CallTimings,
+    sent: &mut u64,
 ) -> Result<SendRequestResult<()>, Error> {
-    loop {
+    let amount = loop {
+        let buffers = connection.buffers();
+        let amount = flow.write(buffers.output())?;
+
         if flow.can_proceed() {
-            break;
+            break amount;
         }
 
-        let buffers = connection.buffers();
-        let amount = flow.write(buffers.output())?;
         let timeout = timings.next_timeout(Timeout::SendRequest);
         connection.transmit_output(amount, timeout)?;
+    };
+
+    let mut result = flow.proceed().unwrap();
+    let timeout = timings.next_timeout(Timeout::SendRequest);
+
+    // A body in memory, that isn't chunked, is sent in the same write as the end of
+    // the head. Not with 100-continue, which waits for the server before the body.
+    let output_len = connection.buffers().output().len();
+    let direct = match (&mut result, body.as_slice()) {
+        (SendRequestResult::SendBody(flow), Some(data)) if !data.is_empty() => {
+            if flow.calculate_output_overhead(output_len)? == 0 {
+                flow.consume_direct_write(data.len())?;
+                Some(data)
+            } else {
+                None
+            }
+        }
+        _ => None,
+    };
+
+    if let Some(data) = direct {
+        connection.transmit_output_vectored(amount, &[IoSlice::new(data)], timeout)?;
+        let n = data.len();
+        body.advance(n);
+        *sent += n as u64;
+    } else {
+        connection.transmit_output(amount, timeout)?;
     }
 
     timings.record_time(Timeout::SendRequest);
-    Ok(flow.proceed().unwrap())
+    Ok(result)
 }
 
 fn await_100(
@@ -392,7 +992,30 @@ fn send_body(
     body: &mut SendBody,
     connection: &mut Connection,
     timings: &mut CallTimings,
+    sent: &mut u64,
 ) -> Result<Flow<RecvResponse>, Error> {
+    // A file that isn't chunked goes straight to the connection, if the transport can.
+    if let Some((file, len)) = body.file() {
+        let output_len = connection.buffers().output().len();
+
+        if len > 0 && flow.calculate_output_overhead(output_len)? == 0 {
+            let timeout = timings.next_timeout(Timeout::SendBody);
+
+            if connection.send_file(file, len, timeout)? {
+                // The flow counts in usize, which may be 32 bit.
+                let mut left = len;
+                while left > 0 {
+                    let n = left.min(usize::MAX as u64) as usize;
+                    flow.consume_direct_write(n)?;
+                    left -= n as u64;
+                }
+
+                body.file_sent();
+                *sent += len;
+            }
+        }
+    }
+
     loop {
         if flow.can_proceed() {
             break;
@@ -408,7 +1031,7 @@ fn send_body(
         assert!(input_len > overhead);
         let max_input = input_len - overhead;
 
-        let output_used = if overhead == 0 {
+        let (input_used, output_used) = if overhead == 0 {
             // overhead == 0 means we are not doing chunked transfer. The body can be written
             // directly to the output. This optimizes away a memcopy if we were to go via
             // flow.write().
@@ -417,7 +1040,7 @@ fn send_body(
             // Size checking is still in the flow.
             flow.consume_direct_write(output_used)?;
 
-            output_used
+            (output_used, output_used)
         } else {
             let tmp = &mut tmp[..max_input];
             let n = body.read(tmp)?;
@@ -428,11 +1051,13 @@ fn send_body(
             // the entire input we read from the body should also be shipped to the output.
             assert!(input_used == n);
 
-            output_used
+            (input_used, output_used)
         };
 
         let timeout = timings.next_timeout(Timeout::SendBody);
         connection.transmit_output(output_used, timeout)?;
+
+        *sent += input_used as u64;
     }
 
     timings.record_time(Timeout::SendBody);
@@ -443,21 +1068,50 @@ fn recv_response(
     mut flow: Flow<RecvResponse>,
     connection: &mut Connection,
     config: &Config,
+    uri: &Uri,
     timings: &mut CallTimings,
 ) -> Result<(Response<()>, RecvResponseResult<()>), Error> {
-    let response = loop {
+    let mut early_hints = EarlyHints::default();
+    let max = config.max_response_header_size;
+
+    // Size of the interim responses read so far, which count towards the
+    // header size limit like the final response.
+    let mut interim_len = 0;
+
+    let mut response = loop {
+        let buffers = connection.buffers();
+        if buffers.input_append_buf().is_empty() {
+            // The head so far fills the input buffer, make room for the rest.
+            let len = buffers.input().len();
+            if len < max {
+                buffers.input_grow((len * 2).min(max));
+            }
+            if buffers.input_append_buf().is_empty() {
+                return Err(Error::LargeResponseHeader(interim_len + len, max));
+            }
+        }
+
         let timeout = timings.next_timeout(Timeout::RecvResponse);
         let made_progress = connection.await_input(timeout)?;
 
         let input = connection.buffers().input();
 
+        // Any 103 Early Hints precede the actual response.
+        let hints_amount = early_hints.try_consume(input, uri)?;
+        if hints_amount > 0 {
+            interim_len += hints_amount;
+            if interim_len > max {
+                return Err(Error::LargeResponseHeader(interim_len, max));
+            }
+            connection.consume_input(hints_amount);
+            continue;
+        }
+
         let (amount, maybe_response) = flow.try_response(input)?;
 
-        if input.len() > config.max_response_header_size {
-            return Err(Error::LargeResponseHeader(
-                input.len(),
-                config.max_response_header_size,
-            ));
+        let head_len = interim_len + input.len();
+        if head_len > max {
+            return Err(Error::LargeResponseHeader(head_len, max));
         }
 
         connection.consume_input(amount);
@@ -470,7 +1124,18 @@ fn recv_response(
         }
     };
 
+    check_framing(response.headers_mut(), config.lenient_framing)?;
+    fold_headers(response.headers_mut(), config.header_folding);
+
+    if !early_hints.is_empty() {
+        response.extensions_mut().insert(early_hints);
+    }
+
+    response.extensions_mut().insert(connection.info());
+
     timings.record_time(Timeout::RecvResponse);
+    response.extensions_mut().insert(timings.breakdown());
+
     Ok((response, flow.proceed().unwrap()))
 }
 
@@ -483,7 +1148,7 @@ fn handle_redirect(mut flow: Flow<Redirect>, config: &Config) -> Result<Flow<Pre
             "Redirect ({}): {} {:?}",
             status,
             flow.method(),
-            DebugUri(flow.uri())
+            DebugUri(flow.uri(), config.redaction())
         );
 
         Ok(flow)
@@ -492,6 +1157,87 @@ fn handle_redirect(mut flow: Flow<Redirect>, config: &Config) -> Result<Flow<Pre
     }
 }
 
+/// Set the Referer of a redirect from `from`, replacing the one of the previous hop.
+fn set_referer(
+    mut flow: Flow<Prepare>,
+    config: &Config,
+    from: &Uri,
+) -> Result<Flow<Prepare>, Error> {
+    // The redirect carries over the headers of the previous hop, including its Referer,
+    // which must not reach a host the policy gives none, such as after a downgrade.
+    if flow.headers().contains_key(header::REFERER) {
+        flow = without_referer(flow, config)?;
+    }
+
+    if let Some(referer) = config.referer_policy.referer(from, flow.uri()) {
+        // The uri was valid, and so is the referer made from it.
+        let value = HeaderValue::from_str(&referer).expect("valid referer");
+        flow.header(header::REFERER, value)?;
+    }
+
+    Ok(flow)
+}
+
+/// A new flow for the same request without the Referer, since hoot can't remove headers.
+fn without_referer(flow: Flow<Prepare>, config: &Config) -> Result<Flow<Prepare>, Error> {
+    let mut request = Request::new(());
+    *request.method_mut() = flow.method().clone();
+    *request.uri_mut() = flow.uri().clone();
+
+    let headers = request.headers_mut();
+    for (name, value) in flow.headers() {
+        if name != header::REFERER {
+            headers.append(name.clone(), value.clone());
+        }
+    }
+
+    let mut flow = Flow::new(request)?;
+
+    if config.force_send_body {
+        flow.send_body_despite_method();
+    }
+
+    Ok(flow)
+}
+
+/// The `Cookie` header value the agent's jar has for `uri`, empty without cookies.
+fn request_cookies(agent: &Agent, config: &Config, uri: &Uri) -> String {
+    #[cfg(feature = "cookies")]
+    if let Some(jar) = config.cookie_jar.jar(agent) {
+        return jar.get_request_cookies(uri);
+    }
+
+    #[cfg(not(feature = "cookies"))]
+    {
+        let _ = (agent, config, uri);
+    }
+
+    String::new()
+}
+
+fn ensure_no_redirect_loop(
+    visited: &[(Method, Uri, String)],
+    next: (&Method, &Uri, &String),
+    redaction: &Redaction,
+) -> Result<(), Error> {
+    let (method, uri, cookies) = next;
+    let Some(start) = visited
+        .iter()
+        .position(|(m, u, c)| m == method && u == uri && c == cookies)
+    else {
+        return Ok(());
+    };
+
+    let cycle = visited[start..]
+        .iter()
+        .map(|(m, u, _)| (m, u))
+        .chain([(method, uri)])
+        .map(|(m, u)| format!("{} {:?}", m, DebugUri(u, redaction)))
+        .collect();
+
+    Err(Error::RedirectLoop(cycle))
+}
+
 fn cleanup(connection: Connection, must_close: bool, now: Instant) {
     if must_close {
         connection.close();
@@ -500,6 +1246,190 @@ fn cleanup(connection: Connection, must_close: bool, now: Instant) {
     }
 }
 
+/// A single request made in phases, see [`BodyWriter`](crate::BodyWriter).
+///
+/// No middleware, retries or redirects.
+pub(crate) struct PhasedCall {
+    agent: Agent,
+    config: Arc<Config>,
+    uri: Uri,
+    flow: PhasedFlow,
+    connection: Connection,
+    timings: CallTimings,
+    stats: TransferStats,
+}
+
+enum PhasedFlow {
+    SendBody(Flow<SendBodyState>),
+    RecvResponse(Flow<RecvResponse>),
+}
+
+impl PhasedCall {
+    pub(crate) fn start(agent: &Agent, mut request: Request<()>) -> Result<Self, Error> {
+        let config = request
+            .extensions_mut()
+            .remove::<RequestLevelConfig>()
+            .map(|rl| Arc::new(rl.0))
+            .unwrap_or_else(|| agent.config.clone());
+
+        let mut timings = CallTimings::new(config.timeouts, CurrentTime::default());
+
+        let mut flow = Flow::new(request)?;
+
+        if config.force_send_body {
+            flow.send_body_despite_method();
+        }
+
+        let uri = flow.uri().clone();
+
+        // The body is streamed, which means chunked unless there is a content-length header.
+        let mut body = SendBody::from_owned_reader(io::empty());
+
+        let (result, mut connection) =
+            send_head(agent, &config, flow, &mut body, &mut timings, &mut 0)?;
+
+        let enabled = config.transfer_stats_on_error;
+        let stats = TransferStats {
+            head_sent: true,
+            connection: enabled.then(|| (*connection.info()).clone()),
+            ..Default::default()
+        };
+
+        let flow = match result {
+            SendRequestResult::Await100(flow) => {
+                let result = await_100(flow, &mut connection, &mut timings)
+                    .map_err(|e| stats.wrap(enabled, e, &timings))?;
+                match result {
+                    Await100Result::SendBody(flow) => PhasedFlow::SendBody(flow),
+                    Await100Result::RecvResponse(flow) => PhasedFlow::RecvResponse(flow),
+                }
+            }
+            SendRequestResult::SendBody(flow) => PhasedFlow::SendBody(flow),
+            SendRequestResult::RecvResponse(flow) => PhasedFlow::RecvResponse(flow),
+        };
+
+        Ok(PhasedCall {
+            agent: agent.clone(),
+            config,
+            uri,
+            flow,
+            connection,
+            timings,
+            stats,
+        })
+    }
+
+    pub(crate) fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        let PhasedFlow::SendBody(flow) = &mut self.flow else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "request has no body, or the server refused it",
+            )
+            .into());
+        };
+
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let (_, output) = self.connection.buffers().tmp_and_output();
+        let (input_used, output_used) = flow.write(buf, output)?;
+
+        let timeout = self.timings.next_timeout(Timeout::SendBody);
+        self.connection
+            .transmit_output(output_used, timeout)
+            .map_err(|e| {
+                let enabled = self.config.transfer_stats_on_error;
+                self.stats.wrap(enabled, e, &self.timings)
+            })?;
+
+        self.stats.body_sent += input_used as u64;
+
+        Ok(input_used)
+    }
+
+    pub(crate) fn response_started(&mut self, wait: std::time::Duration) -> Result<bool, Error> {
+        if self.connection.buffers().can_use_input() {
+            return Ok(true);
+        }
+
+        let timeout = crate::transport::NextTimeout {
+            after: wait.max(std::time::Duration::from_millis(1)).into(),
+            reason: Timeout::RecvResponse,
+        };
+
+        match self.connection.await_input(timeout) {
+            Ok(_) => Ok(!self.connection.buffers().input().is_empty()),
+            Err(Error::Timeout(_)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub(crate) fn read_head(mut self) -> Result<Response<Body>, Error> {
+        let enabled = self.config.transfer_stats_on_error;
+        let stats = self.stats;
+        let timings = &mut self.timings;
+
+        let flow = match self.flow {
+            PhasedFlow::SendBody(mut flow) => {
+                // End the body. For chunked, this sends the final chunk.
+                while !flow.can_proceed() {
+                    let (_, output) = self.connection.buffers().tmp_and_output();
+                    let (_, output_used) = flow.write(&[], output)?;
+
+                    if output_used == 0 {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            "request body shorter than content-length",
+                        )
+                        .into());
+                    }
+
+                    let timeout = timings.next_timeout(Timeout::SendBody);
+                    self.connection
+                        .transmit_output(output_used, timeout)
+                        .map_err(|e| stats.wrap(enabled, e, timings))?;
+                }
+
+                timings.record_time(Timeout::SendBody);
+                flow.proceed().unwrap()
+            }
+            PhasedFlow::RecvResponse(flow) => flow,
+        };
+
+        let config = &self.config;
+        let (response, response_result) =
+            recv_response(flow, &mut self.connection, config, &self.uri, timings)
+                .map_err(|e| stats.wrap(enabled, e, timings))?;
+
+        let stats = TransferStats {
+            head_received: true,
+            ..stats
+        };
+
+        info!("{:?}", DebugResponse(&response, config.redaction()));
+
+        store_cookies(&self.agent, config, &response, &self.uri);
+
+        // Passing max_redirects as count means redirects are returned as is.
+        let result = flow_result(
+            config,
+            response,
+            response_result,
+            self.connection,
+            config.max_redirects,
+            timings,
+            stats,
+        )?;
+
+        let FlowResult::Response(response, handler) = result else {
+            unreachable!("redirects not followed");
+        };
+
+        status_as_error(config, with_body(config, response, handler))
+    }
+}
+
 #[derive(Default)]
 pub(crate) struct BodyHandler {
     flow: Option<Flow<RecvBody>>,
@@ -507,9 +1437,93 @@ pub(crate) struct BodyHandler {
     timings: CallTimings,
     remote_closed: bool,
     redirect: Option<Flow<Redirect>>,
+    max_drain: u64,
+    read_timeout: ReadTimeout,
+    stats: Option<TransferStats>,
+}
+
+/// Timeout per read of the body, shared with the [`BodyReader`](crate::BodyReader).
+#[derive(Clone, Default)]
+pub(crate) struct ReadTimeout(Arc<Mutex<Option<Duration>>>);
+
+impl ReadTimeout {
+    pub fn set(&self, v: Option<Duration>) {
+        *self.0.lock().unwrap() = v;
+    }
+
+    fn get(&self) -> Option<Duration> {
+        *self.0.lock().unwrap()
+    }
 }
 
 impl BodyHandler {
+    pub(crate) fn read_timeout(&self) -> ReadTimeout {
+        self.read_timeout.clone()
+    }
+
+    /// Close the connection, unless the body is already read to the end.
+    pub(crate) fn close(&mut self) {
+        if let Some(connection) = self.connection.take() {
+            connection.drain_stats().closed();
+            connection.close();
+        }
+        self.flow = None;
+    }
+
+    /// Read and discard the rest of the body, to make the connection reusable.
+    ///
+    /// Gives up if the body is larger than max_drain.
+    fn drain(&mut self) -> bool {
+        let Some(stats) = self.connection.as_ref().map(|c| c.drain_stats()) else {
+            return false;
+        };
+
+        let mut buf = [0; 4096];
+        let mut drained = 0;
+
+        while drained <= self.max_drain {
+            // Read at most one byte past max_drain to know whether the body is larger.
+            let left = self.max_drain.saturating_add(1) - drained;
+            let amount = left.min(buf.len() as u64) as usize;
+
+            match self.do_read(&mut buf[..amount]) {
+                Ok(0) => break,
+                Ok(n) => drained += n as u64,
+                Err(e) => {
+                    debug!("Failed to drain body: {}", e);
+                    return false;
+                }
+            }
+        }
+
+        // The connection is returned to the pool when the body ends.
+        let ended = self.connection.is_none();
+
+        if ended {
+            debug!("Drained {} bytes of unread body", drained);
+            stats.drained(drained);
+        }
+
+        ended
+    }
+
+    /// Read, counting the bytes for [`TransferStats`] when enabled.
+    fn counted_read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        let result = self.do_read(buf);
+
+        let Some(stats) = &mut self.stats else {
+            return result;
+        };
+
+        match result {
+            Ok(n) => {
+                stats.body_received += n as u64;
+                Ok(n)
+            }
+            Err(e) => Err(stats.wrap(true, e, &self.timings)),
+        }
+    }
+
     fn do_read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
         let (Some(flow), Some(connection), timings) =
             (&mut self.flow, &mut self.connection, &mut self.timings)
@@ -517,6 +1531,8 @@ impl BodyHandler {
             return Ok(0);
         };
 
+        let read_timeout = self.read_timeout.get();
+
         loop {
             let body_fulfilled = match flow.body_mode() {
                 BodyMode::NoBody => unreachable!("must be a BodyMode for BodyHandler"),
@@ -537,7 +1553,7 @@ impl BodyHandler {
             // First try to use input already buffered
             if has_buffered_input {
                 let input = connection.buffers().input();
-                let (input_used, output_used) = flow.read(input, buf)?;
+                let (input_used, output_used) = flow.read(input, buf).map_err(framing_error)?;
                 connection.consume_input(input_used);
 
                 if output_used > 0 {
@@ -556,7 +1572,22 @@ impl BodyHandler {
                 return Ok(0);
             }
 
-            let timeout = timings.next_timeout(Timeout::RecvBody);
+            let timeout = match read_timeout {
+                // The read timeout replaces the one for the entire body, but the
+                // global and per call timeouts still apply.
+                Some(after) => {
+                    let call = timings.next_timeout(Timeout::Global);
+                    if after < call.after {
+                        NextTimeout {
+                            after,
+                            reason: Timeout::RecvBody,
+                        }
+                    } else {
+                        call
+                    }
+                }
+                None => timings.next_timeout(Timeout::RecvBody),
+            };
 
             let made_progress = match connection.await_input(timeout) {
                 Ok(v) => v,
@@ -575,7 +1606,7 @@ impl BodyHandler {
             let input = connection.buffers().input();
             let input_ended = input.is_empty();
 
-            let (input_used, output_used) = flow.read(input, buf)?;
+            let (input_used, output_used) = flow.read(input, buf).map_err(framing_error)?;
             connection.consume_input(input_used);
 
             if output_used > 0 {
@@ -622,7 +1653,7 @@ impl BodyHandler {
     fn consume_redirect_body(&mut self) -> Result<Flow<Redirect>, Error> {
         let mut buf = vec![0; 1024];
         loop {
-            let amount = self.do_read(&mut buf)?;
+            let amount = self.counted_read(&mut buf)?;
             if amount == 0 {
                 break;
             }
@@ -635,8 +1666,32 @@ impl BodyHandler {
     }
 }
 
+/// A chunk size that can't be read is a framing error, such as a size overflowing
+/// a `u64`, which a proxy might read differently.
+fn framing_error(e: hoot::Error) -> Error {
+    match e {
+        hoot::Error::ChunkLenNotANumber => Error::AmbiguousFraming("unreadable chunk size"),
+        e => e.into(),
+    }
+}
+
+impl Drop for BodyHandler {
+    fn drop(&mut self) {
+        if self.connection.is_none() {
+            // Body read to the end, or no body.
+            return;
+        }
+
+        if self.max_drain > 0 && self.drain() {
+            return;
+        }
+
+        self.close();
+    }
+}
+
 impl io::Read for BodyHandler {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        self.do_read(buf).map_err(|e| e.into_io())
+        self.counted_read(buf).map_err(|e| e.into_io())
     }
 }
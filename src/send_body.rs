@@ -1,8 +1,10 @@
 use std::fs::File;
-use std::io::{self, Read, Stdin};
+use std::io::{self, Read, Seek, Stdin};
 use std::net::TcpStream;
+use std::sync::mpsc::Receiver;
 
 use crate::body::{Body, BodyReader};
+use crate::codec::Codec;
 use crate::util::private::Private;
 
 /// Request body for sending data via POST, PUT and PATCH.
@@ -18,6 +20,10 @@ use crate::util::private::Private;
 pub struct SendBody<'a> {
     inner: BodyInner<'a>,
     ended: bool,
+    /// Length of a reader body, when known.
+    length: Option<u64>,
+    /// Bytes left to send of a reader with a known length.
+    left: Option<u64>,
 }
 
 impl<'a> SendBody<'a> {
@@ -36,6 +42,100 @@ impl<'a> SendBody<'a> {
         BodyInner::OwnedReader(Box::new(reader)).into()
     }
 
+    /// Creates a body from a shared [`Read`] impl with a known length.
+    ///
+    /// The body is sent with a `Content-Length` header instead of chunked. The length
+    /// is a `u64`, also on 32 bit targets, to allow bodies larger than 4GB. Reading
+    /// stops at the length, and it's an error if the reader ends before.
+    pub fn from_sized_reader(reader: &'a mut dyn Read, length: u64) -> SendBody<'a> {
+        let mut body: SendBody = BodyInner::Reader(reader).into();
+        body.length = Some(length);
+        body.left = Some(length);
+        body
+    }
+
+    /// Creates a body from an owned [`Read`] impl with a known length.
+    ///
+    /// See [`SendBody::from_sized_reader()`].
+    pub fn from_owned_sized_reader(reader: impl Read + 'static, length: u64) -> SendBody<'static> {
+        let mut body: SendBody = BodyInner::OwnedReader(Box::new(reader)).into();
+        body.length = Some(length);
+        body.left = Some(length);
+        body
+    }
+
+    /// Creates a body from a file, from its current position to the end.
+    ///
+    /// The body is sent with a `Content-Length` header. Unless it is encoded, the
+    /// file goes straight to the connection, if the transport can do that. On Linux,
+    /// plain TCP and Unix sockets use `copy_file_range` or `sendfile`, which avoids
+    /// copying the file through user space. See [`Transport::send_file()`].
+    ///
+    /// [`Transport::send_file()`]: crate::transport::Transport::send_file
+    pub fn from_file(file: &'a File) -> io::Result<SendBody<'a>> {
+        let mut position = file;
+        let length = file
+            .metadata()?
+            .len()
+            .saturating_sub(position.stream_position()?);
+
+        let mut body: SendBody = BodyInner::File(file).into();
+        body.length = Some(length);
+        body.left = Some(length);
+        Ok(body)
+    }
+
+    /// Creates a body from chunks produced by another thread.
+    ///
+    /// The producer sends `Some(chunk)` for each chunk, and `None` to end the body.
+    /// With a [`sync_channel()`](std::sync::mpsc::sync_channel), the producer waits
+    /// while the channel is full, which paces it to the upload. If all senders are
+    /// dropped before `None` is sent, the body fails rather than ending short.
+    ///
+    /// The body is sent chunked.
+    ///
+    /// ```no_run
+    /// use std::io::Read;
+    /// use std::process::{Command, Stdio};
+    /// use std::sync::mpsc;
+    /// use std::thread;
+    /// use ureq::SendBody;
+    ///
+    /// let (tx, rx) = mpsc::sync_channel(4);
+    ///
+    /// thread::spawn(move || {
+    ///     let mut child = Command::new("tar")
+    ///         .args(["-c", "logs"])
+    ///         .stdout(Stdio::piped())
+    ///         .spawn()?;
+    ///     let mut stdout = child.stdout.take().unwrap();
+    ///
+    ///     let mut buf = vec![0; 64 * 1024];
+    ///     loop {
+    ///         let n = stdout.read(&mut buf)?;
+    ///         if n == 0 {
+    ///             break;
+    ///         }
+    ///         tx.send(Some(buf[..n].to_vec())).ok();
+    ///     }
+    ///     tx.send(None).ok();
+    ///     Ok::<_, std::io::Error>(())
+    /// });
+    ///
+    /// ureq::put("https://backup.test/logs.tar")
+    ///     .send(SendBody::from_channel(rx))?;
+    /// # Ok::<_, ureq::Error>(())
+    /// ```
+    pub fn from_channel(receiver: Receiver<Option<Vec<u8>>>) -> SendBody<'static> {
+        let reader = ChannelReader {
+            receiver,
+            chunk: vec![],
+            pos: 0,
+            ended: false,
+        };
+        Self::from_owned_reader(reader)
+    }
+
     /// Creates a body to send as JSON from any [`Serialize`](serde::ser::Serialize) value.
     #[cfg(feature = "json")]
     pub fn from_json(
@@ -46,6 +146,20 @@ impl<'a> SendBody<'a> {
     }
 
     pub(crate) fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // The max buffer size is usize, which may be 32 bit.
+        let buf = match self.left {
+            Some(left) => {
+                let max = left.min(buf.len() as u64) as usize;
+                &mut buf[..max]
+            }
+            None => buf,
+        };
+
+        if buf.is_empty() && self.left == Some(0) {
+            self.ended = true;
+            return Ok(0);
+        }
+
         let n = match &mut self.inner {
             BodyInner::None => {
                 return Ok(0);
@@ -61,8 +175,19 @@ impl<'a> SendBody<'a> {
             BodyInner::Reader(v) => v.read(buf),
             BodyInner::OwnedReader(v) => v.read(buf),
             BodyInner::Body(v) => v.read(buf),
+            BodyInner::File(v) => v.read(buf),
         }?;
 
+        if let Some(left) = &mut self.left {
+            if n == 0 && !buf.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!("body ended {} bytes short of its length", left),
+                ));
+            }
+            *left -= n as u64;
+        }
+
         if n == 0 {
             self.ended = true;
         }
@@ -71,7 +196,110 @@ impl<'a> SendBody<'a> {
     }
 
     pub(crate) fn body_mode(&self) -> BodyMode {
-        self.inner.body_mode()
+        match self.length {
+            Some(v) => BodyMode::LengthDelimited(v),
+            None => self.inner.body_mode(),
+        }
+    }
+
+    /// The entire body, if it is known up front.
+    pub(crate) fn as_slice(&self) -> Option<&[u8]> {
+        match &self.inner {
+            BodyInner::None => Some(&[]),
+            BodyInner::ByteSlice(v) if !self.ended => Some(v),
+            _ => None,
+        }
+    }
+
+    /// The file and the bytes left to send of it, for a body from [`SendBody::from_file()`].
+    pub(crate) fn file(&self) -> Option<(&'a File, u64)> {
+        match (&self.inner, self.left) {
+            (BodyInner::File(v), Some(left)) => Some((*v, left)),
+            _ => None,
+        }
+    }
+
+    /// Mark the rest of the file of [`file()`](Self::file) as sent.
+    pub(crate) fn file_sent(&mut self) {
+        self.left = Some(0);
+        self.ended = true;
+    }
+
+    /// Mark `amount` of [`as_slice()`](Self::as_slice) as sent.
+    pub(crate) fn advance(&mut self, amount: usize) {
+        if let BodyInner::ByteSlice(v) = &mut self.inner {
+            *v = &v[amount..];
+            if v.is_empty() {
+                self.ended = true;
+            }
+        }
+    }
+
+    /// The body encoded by `codec`, which is sent chunked.
+    pub(crate) fn encode(self, codec: &dyn Codec) -> SendBody<'a> {
+        let reader = codec.encoder(Box::new(PlainReader(self)));
+        BodyInner::OwnedReader(reader).into()
+    }
+
+    /// A copy of the body for sending the request again, if possible.
+    ///
+    /// Only bodies that haven't started sending and can be read again are copied.
+    pub(crate) fn try_clone(&self) -> Option<SendBody<'a>> {
+        let inner = match &self.inner {
+            BodyInner::None => BodyInner::None,
+            BodyInner::ByteSlice(v) if !self.ended => BodyInner::ByteSlice(*v),
+            _ => return None,
+        };
+
+        Some(inner.into())
+    }
+}
+
+/// Reads the body before encoding.
+struct PlainReader<'a>(SendBody<'a>);
+
+impl<'a> Read for PlainReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+/// Reads the chunks of [`SendBody::from_channel()`].
+struct ChannelReader {
+    receiver: Receiver<Option<Vec<u8>>>,
+    chunk: Vec<u8>,
+    pos: usize,
+    ended: bool,
+}
+
+impl Read for ChannelReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Empty chunks would read as the end, skip them.
+        while self.pos == self.chunk.len() {
+            if self.ended {
+                return Ok(0);
+            }
+            match self.receiver.recv() {
+                Ok(Some(chunk)) => {
+                    self.chunk = chunk;
+                    self.pos = 0;
+                }
+                Ok(None) => self.ended = true,
+                Err(_) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "body channel closed before the end",
+                    ));
+                }
+            }
+        }
+
+        let chunk = &self.chunk[self.pos..];
+        let n = chunk.len().min(buf.len());
+        buf[..n].copy_from_slice(&chunk[..n]);
+        self.pos += n;
+
+        Ok(n)
     }
 }
 
@@ -142,8 +370,11 @@ impl<'a> AsSendBody for SendBody<'a> {
                 BodyInner::Reader(v) => BodyInner::Reader(v),
                 BodyInner::Body(v) => BodyInner::Reader(v),
                 BodyInner::OwnedReader(v) => BodyInner::Reader(v),
+                BodyInner::File(v) => BodyInner::File(v),
             },
             ended: self.ended,
+            length: self.length,
+            left: self.left,
         }
     }
 }
@@ -153,7 +384,8 @@ pub(crate) enum BodyInner<'a> {
     ByteSlice(&'a [u8]),
     Body(BodyReader<'a>),
     Reader(&'a mut dyn Read),
-    OwnedReader(Box<dyn Read>),
+    OwnedReader(Box<dyn Read + 'a>),
+    File(&'a File),
 }
 
 impl<'a> BodyInner<'a> {
@@ -164,6 +396,7 @@ impl<'a> BodyInner<'a> {
             BodyInner::Body(v) => v.body_mode(),
             BodyInner::Reader(_) => BodyMode::Chunked,
             BodyInner::OwnedReader(_) => BodyMode::Chunked,
+            BodyInner::File(_) => BodyMode::Chunked,
         }
     }
 }
@@ -217,6 +450,8 @@ impl<'a> From<BodyInner<'a>> for SendBody<'a> {
         SendBody {
             inner,
             ended: false,
+            length: None,
+            left: None,
         }
     }
 }
@@ -248,3 +483,96 @@ impl AsSendBody for () {
         BodyInner::None.into()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn mock_agent() -> (crate::Agent, crate::transport::MockConnector) {
+        use crate::config::Config;
+        use crate::transport::MockConnector;
+
+        let mock = MockConnector::new().route("/", "HTTP/1.1 204 No Content\r\n\r\n");
+        let agent = crate::Agent::with_parts(Config::default(), mock.clone(), mock.clone());
+        (agent, mock)
+    }
+
+    #[test]
+    fn sized_reader_request() {
+        let (agent, mock) = mock_agent();
+
+        // Reading stops at the length.
+        let body = SendBody::from_owned_sized_reader(&b"abcdefgh"[..], 5);
+        agent.post("http://sized.test/").send(body).unwrap();
+
+        let request = String::from_utf8(mock.requests().remove(0)).unwrap();
+        assert!(request.contains("\r\ncontent-length: 5\r\n"));
+        assert!(!request.contains("transfer-encoding"));
+        assert!(request.ends_with("\r\n\r\nabcde"));
+    }
+
+    #[test]
+    fn sized_reader_over_4gb() {
+        let (agent, mock) = mock_agent();
+
+        // The reader ends long before the length, without sending 5GB.
+        let length = 5 * 1024 * 1024 * 1024;
+        let body = SendBody::from_owned_sized_reader(io::repeat(0).take(10), length);
+        let err = agent.post("http://sized.test/").send(body).unwrap_err();
+        assert!(
+            matches!(&err, crate::Error::Io(e) if e.kind() == io::ErrorKind::UnexpectedEof),
+            "{:?}",
+            err
+        );
+
+        let request = String::from_utf8(mock.requests().remove(0)).unwrap();
+        assert!(request.contains("\r\ncontent-length: 5368709120\r\n"));
+    }
+
+    #[test]
+    fn sized_reader_too_short() {
+        let mut data: &[u8] = b"abc";
+        let mut body = SendBody::from_sized_reader(&mut data, 5);
+
+        let mut buf = [0; 10];
+        assert_eq!(body.read(&mut buf).unwrap(), 3);
+        let err = body.read(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn chunks_from_channel() {
+        use std::sync::mpsc;
+        use std::thread;
+
+        let read_all = |chunks: Vec<Option<&'static str>>| -> io::Result<Vec<u8>> {
+            let (tx, rx) = mpsc::sync_channel(1);
+            thread::spawn(move || {
+                for chunk in chunks {
+                    // Fails for chunks after the end, when the body is dropped.
+                    let _ = tx.send(chunk.map(|c| c.as_bytes().to_vec()));
+                }
+            });
+
+            let mut body = SendBody::from_channel(rx);
+            assert!(matches!(body.body_mode(), BodyMode::Chunked));
+
+            let mut read = vec![];
+            let mut buf = [0; 4];
+            loop {
+                let n = body.read(&mut buf)?;
+                if n == 0 {
+                    return Ok(read);
+                }
+                read.extend_from_slice(&buf[..n]);
+            }
+        };
+
+        let chunks = vec![Some("hello"), Some(""), Some(" world"), None, Some("!")];
+        assert_eq!(read_all(chunks).unwrap(), b"hello world");
+
+        // The producer went away without ending the body.
+        let err = read_all(vec![Some("hello")]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -0,0 +1,179 @@
+//! Signing of requests as they are sent.
+
+use std::fmt;
+
+use http::{HeaderMap, HeaderName, HeaderValue, Method, Uri};
+
+use crate::Error;
+
+/// Late hook to sign requests.
+///
+/// The signer is called after ureq has added all headers, including `Host`,
+/// `Content-Length` (or `Transfer-Encoding`), `User-Agent`, `Accept` and cookies,
+/// but before anything is written to the connection. This is what signature
+/// schemes such as AWS SigV4 or HTTP Message Signatures need, since they must
+/// cover exactly what is sent.
+///
+/// Middleware runs too early for this, since the headers are not finalized.
+///
+/// The signer is called for every request, including redirects.
+///
+/// # Signer as `fn`
+///
+/// The trait is implemented for functions with the signature
+/// `Fn(&mut SignRequest) -> Result<(), Error>`.
+///
+/// ```
+/// use ureq::Agent;
+/// use ureq::sign::SignRequest;
+///
+/// fn sign(req: &mut SignRequest) -> Result<(), ureq::Error> {
+///     let mut to_sign = format!("{} {}\n", req.method(), req.uri());
+///     for (name, value) in req.headers() {
+///         to_sign.push_str(&format!("{}: {:?}\n", name, value));
+///     }
+///     if let Some(body) = req.body() {
+///         to_sign.push_str(&format!("body-len: {}\n", body.len()));
+///     }
+///
+///     let signature = format!("{:x}", to_sign.len()); // use a real signature here
+///     req.add_header("x-signature", &signature)?;
+///     Ok(())
+/// }
+///
+/// let agent: Agent = Agent::config_builder()
+///     .signer(sign)
+///     .build()
+///     .into();
+/// ```
+pub trait Signer: Send + Sync + 'static {
+    /// Sign the request, typically by adding a header.
+    fn sign(&self, request: &mut SignRequest<'_>) -> Result<(), Error>;
+}
+
+impl<F> Signer for F
+where
+    F: Fn(&mut SignRequest<'_>) -> Result<(), Error> + Send + Sync + 'static,
+{
+    fn sign(&self, request: &mut SignRequest<'_>) -> Result<(), Error> {
+        (self)(request)
+    }
+}
+
+/// The request to sign, as it will be sent.
+pub struct SignRequest<'a> {
+    method: &'a Method,
+    uri: &'a Uri,
+    headers: HeaderMap,
+    body: Option<&'a [u8]>,
+    added: Vec<(HeaderName, HeaderValue)>,
+}
+
+impl<'a> SignRequest<'a> {
+    pub(crate) fn new(
+        method: &'a Method,
+        uri: &'a Uri,
+        headers: HeaderMap,
+        body: Option<&'a [u8]>,
+    ) -> Self {
+        SignRequest {
+            method,
+            uri,
+            headers,
+            body,
+            added: vec![],
+        }
+    }
+
+    /// The request method.
+    pub fn method(&self) -> &Method {
+        self.method
+    }
+
+    /// The request uri.
+    ///
+    /// The request line contains the path and query of this uri.
+    pub fn uri(&self) -> &Uri {
+        self.uri
+    }
+
+    /// All headers that will be sent, including those added by the signer.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// The body, when it is known up front.
+    ///
+    /// This is the case for bodies such as `&str`, `&[u8]` or no body at all (an empty
+    /// slice). Streamed bodies, such as from a `Read`, are `None` and need a signature
+    /// scheme that doesn't cover the body (such as `UNSIGNED-PAYLOAD` for AWS).
+    pub fn body(&self) -> Option<&[u8]> {
+        self.body
+    }
+
+    /// Add a header to the request.
+    ///
+    /// Headers can only be added, not changed or removed, since the others
+    /// are already decided.
+    pub fn add_header(&mut self, name: &str, value: &str) -> Result<(), Error> {
+        let name = HeaderName::from_bytes(name.as_bytes()).map_err(http::Error::from)?;
+        let value = HeaderValue::from_str(value).map_err(http::Error::from)?;
+        self.headers.append(name.clone(), value.clone());
+        self.added.push((name, value));
+        Ok(())
+    }
+
+    pub(crate) fn into_added(self) -> Vec<(HeaderName, HeaderValue)> {
+        self.added
+    }
+}
+
+impl fmt::Debug for SignRequest<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SignRequest")
+            .field("method", &self.method)
+            .field("body", &self.body.map(|b| b.len()))
+            .finish()
+    }
+}
+
+#[cfg(all(test, feature = "_test"))]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use crate::test::init_test_log;
+    use crate::Agent;
+
+    #[test]
+    fn signer_sees_final_headers() {
+        init_test_log();
+
+        let seen = Arc::new(Mutex::new(vec![]));
+        let seen2 = seen.clone();
+
+        let agent: Agent = Agent::config_builder()
+            .signer(move |req: &mut super::SignRequest| {
+                let mut seen = seen2.lock().unwrap();
+                for name in ["host", "content-length", "user-agent"] {
+                    if let Some(v) = req.headers().get(name) {
+                        seen.push(format!("{}: {}", name, v.to_str().unwrap()));
+                    }
+                }
+                seen.push(format!("body: {:?}", req.body()));
+                req.add_header("x-signature", "abc")
+            })
+            .build()
+            .into();
+
+        agent
+            .post("http://example.org:8080/post")
+            .send("hi")
+            .unwrap();
+
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen[0], "host: example.org:8080");
+        assert_eq!(seen[1], "content-length: 2");
+        assert!(seen[2].starts_with("user-agent: ureq/"));
+        assert_eq!(seen[3], "body: Some([104, 105])");
+    }
+}
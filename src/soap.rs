@@ -0,0 +1,440 @@
+//! SOAP 1.1 and 1.2 envelopes.
+//!
+//! Requires the **soap** feature.
+//!
+//! This is the minimum to talk to a SOAP service: wrapping a body in an envelope,
+//! with the `SOAPAction` of the version, and parsing faults. The XML of the body
+//! itself is left to the caller, which typically comes from the WSDL of the service.
+//!
+//! ```no_run
+//! use ureq::Agent;
+//!
+//! let agent = Agent::new_with_defaults();
+//!
+//! let body = agent
+//!     .soap("https://soap.example.test/StockQuote")
+//!     .action("http://example.test/GetLastTradePrice")
+//!     .header(r#"<t:Transaction xmlns:t="http://example.test/tx">5</t:Transaction>"#, true)
+//!     .call(r#"<m:GetLastTradePrice xmlns:m="http://example.test/stock">
+//!                <m:symbol>DIS</m:symbol>
+//!              </m:GetLastTradePrice>"#)?;
+//!
+//! println!("{}", body);
+//! # Ok::<_, ureq::Error>(())
+//! ```
+
+use std::fmt;
+
+use http::{header, Request, Uri};
+
+use crate::{Agent, Error, IntoUri};
+
+/// Prefix of the envelope elements in the request.
+const PREFIX: &str = "soap";
+
+/// The version of SOAP.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SoapVersion {
+    /// SOAP 1.1, `text/xml` with a `SOAPAction` header.
+    V11,
+    /// SOAP 1.2, `application/soap+xml` with the action as a parameter.
+    V12,
+}
+
+/// A call to a SOAP service.
+///
+/// Created by [`Agent::soap()`]. See the [module](self) documentation.
+#[derive(Debug)]
+pub struct Soap {
+    agent: Agent,
+    uri: Result<Uri, Error>,
+    version: SoapVersion,
+    action: Option<String>,
+    /// Header blocks, and whether they get the `mustUnderstand` attribute.
+    headers: Vec<(String, bool)>,
+}
+
+/// A fault response of a SOAP service.
+///
+/// See [`Error::SoapFault`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SoapFault {
+    /// The fault code, such as `soap:Server` (1.1) or `env:Receiver` (1.2).
+    pub code: String,
+    /// The human readable explanation of the fault.
+    pub reason: String,
+    /// The XML of the fault detail, if any.
+    pub detail: Option<String>,
+}
+
+impl SoapVersion {
+    fn namespace(&self) -> &'static str {
+        match self {
+            SoapVersion::V11 => "http://schemas.xmlsoap.org/soap/envelope/",
+            SoapVersion::V12 => "http://www.w3.org/2003/05/soap-envelope",
+        }
+    }
+}
+
+impl Soap {
+    pub(crate) fn new(agent: Agent, uri: impl IntoUri) -> Self {
+        Soap {
+            agent,
+            uri: uri.into_uri(),
+            version: SoapVersion::V11,
+            action: None,
+            headers: vec![],
+        }
+    }
+
+    /// The version of SOAP.
+    ///
+    /// Defaults to [`SoapVersion::V11`].
+    pub fn version(mut self, v: SoapVersion) -> Self {
+        self.version = v;
+        self
+    }
+
+    /// The action of the call, such as `http://example.test/GetLastTradePrice`.
+    ///
+    /// Sent as the `SOAPAction` header for SOAP 1.1, and as the `action` parameter
+    /// of the content type for SOAP 1.2.
+    pub fn action(mut self, v: &str) -> Self {
+        self.action = Some(v.to_string());
+        self
+    }
+
+    /// Add a header block to the envelope.
+    ///
+    /// With `must_understand`, the block gets the `mustUnderstand` attribute, and the
+    /// service must fault rather than ignore it. See [`SoapFault::is_must_understand()`].
+    pub fn header(mut self, block: &str, must_understand: bool) -> Self {
+        self.headers
+            .push((block.trim().to_string(), must_understand));
+        self
+    }
+
+    /// The envelope with `body` as the content of the SOAP body.
+    pub fn envelope(&self, body: &str) -> String {
+        let mut out = format!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<{p}:Envelope xmlns:{p}=\"{}\">",
+            self.version.namespace(),
+            p = PREFIX
+        );
+
+        if !self.headers.is_empty() {
+            out.push_str(&format!("<{}:Header>", PREFIX));
+            for (block, must_understand) in &self.headers {
+                if *must_understand {
+                    self.push_must_understand(&mut out, block);
+                } else {
+                    out.push_str(block);
+                }
+            }
+            out.push_str(&format!("</{}:Header>", PREFIX));
+        }
+
+        out.push_str(&format!(
+            "<{p}:Body>{}</{p}:Body></{p}:Envelope>",
+            body,
+            p = PREFIX
+        ));
+        out
+    }
+
+    /// Push `block` with the `mustUnderstand` attribute of the version.
+    fn push_must_understand(&self, out: &mut String, block: &str) {
+        let value = match self.version {
+            SoapVersion::V11 => "1",
+            SoapVersion::V12 => "true",
+        };
+
+        // After the name of the root element of the block.
+        let end = block
+            .char_indices()
+            .skip(1)
+            .find(|(_, c)| c.is_ascii_whitespace() || *c == '/' || *c == '>')
+            .map(|(i, _)| i)
+            .unwrap_or(block.len());
+
+        out.push_str(&block[..end]);
+        out.push_str(&format!(" {}:mustUnderstand=\"{}\"", PREFIX, value));
+        out.push_str(&block[end..]);
+    }
+
+    /// Make the call, and return the XML content of the response body.
+    ///
+    /// A fault is an [`Error::SoapFault`], also when it comes with a 500 status. A
+    /// successful response without a SOAP body is an [`Error::BadBody`].
+    pub fn call(self, body: &str) -> Result<String, Error> {
+        let envelope = self.envelope(body);
+        let action = self.action.as_deref().unwrap_or_default();
+
+        let builder = Request::post(self.uri?);
+        let builder = match self.version {
+            SoapVersion::V11 => builder
+                .header(header::CONTENT_TYPE, "text/xml; charset=utf-8")
+                .header("SOAPAction", format!("\"{}\"", action)),
+            SoapVersion::V12 if self.action.is_some() => builder.header(
+                header::CONTENT_TYPE,
+                format!("application/soap+xml; charset=utf-8; action=\"{}\"", action),
+            ),
+            SoapVersion::V12 => {
+                builder.header(header::CONTENT_TYPE, "application/soap+xml; charset=utf-8")
+            }
+        };
+
+        let request = self
+            .agent
+            .configure_request(builder.body(envelope)?)
+            .http_status_as_error(false)
+            .build();
+
+        let mut response = self.agent.run(request)?;
+        let status = response.status();
+
+        // Faults are sent with status 500.
+        if !status.is_success() && status.as_u16() != 500 {
+            return Err(Error::StatusCode(status.as_u16()));
+        }
+
+        let xml = response.body_mut().read_to_string()?;
+        let Some(body) = element(&xml, "Body") else {
+            if !status.is_success() {
+                return Err(Error::StatusCode(status.as_u16()));
+            }
+            return Err(Error::BadBody("SOAP response without a Body".into()));
+        };
+
+        if let Some(fault) = element(body, "Fault") {
+            return Err(Error::SoapFault(Box::new(parse_fault(fault))));
+        }
+
+        if !status.is_success() {
+            return Err(Error::StatusCode(status.as_u16()));
+        }
+
+        Ok(body.trim().to_string())
+    }
+}
+
+impl SoapFault {
+    /// Whether the service didn't understand a header block sent with `mustUnderstand`.
+    pub fn is_must_understand(&self) -> bool {
+        local_name(&self.code) == "MustUnderstand"
+    }
+
+    /// Whether the fault is the fault of the caller rather than the service.
+    ///
+    /// This is the code `Client` (1.1) or `Sender` (1.2).
+    pub fn is_client(&self) -> bool {
+        matches!(local_name(&self.code), "Client" | "Sender")
+    }
+}
+
+fn parse_fault(fault: &str) -> SoapFault {
+    let text = |xml: &str, name: &str| element(xml, name).map(|v| unescape(v.trim()));
+
+    // 1.2 has Code/Value and Reason/Text, where 1.1 has faultcode and faultstring.
+    let code = element(fault, "Code")
+        .and_then(|c| text(c, "Value"))
+        .or_else(|| text(fault, "faultcode"))
+        .unwrap_or_default();
+
+    let reason = element(fault, "Reason")
+        .and_then(|r| text(r, "Text"))
+        .or_else(|| text(fault, "faultstring"))
+        .unwrap_or_default();
+
+    let detail = element(fault, "Detail")
+        .or_else(|| element(fault, "detail"))
+        .map(|d| d.trim().to_string());
+
+    SoapFault {
+        code,
+        reason,
+        detail,
+    }
+}
+
+/// The content of the first element with the local name `name`, with any prefix.
+///
+/// This is just enough XML for the envelope, where elements of the same name
+/// don't nest.
+fn element<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let mut rest = xml;
+
+    while let Some(i) = rest.find('<') {
+        let tag = &rest[i + 1..];
+        rest = tag;
+
+        let end = tag.find(|c: char| c.is_ascii_whitespace() || c == '/' || c == '>')?;
+        let qname = &tag[..end];
+
+        if local_name(qname) != name || qname.starts_with(['/', '?', '!']) {
+            continue;
+        }
+
+        let start_end = tag.find('>')?;
+        if tag[..start_end].ends_with('/') {
+            return Some("");
+        }
+
+        let content = &tag[start_end + 1..];
+        let close = content.find(&format!("</{}>", qname))?;
+        return Some(&content[..close]);
+    }
+
+    None
+}
+
+fn local_name(qname: &str) -> &str {
+    qname.rsplit(':').next().unwrap_or(qname)
+}
+
+fn unescape(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+impl fmt::Display for SoapFault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.code, self.reason)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::Config;
+    use crate::transport::MockConnector;
+
+    fn response(status: &str, body: &str) -> String {
+        format!(
+            "HTTP/1.1 {}\r\n\
+            Content-Type: text/xml\r\n\
+            Content-Length: {}\r\n\
+            \r\n\
+            {}",
+            status,
+            body.len(),
+            body
+        )
+    }
+
+    #[test]
+    fn envelope_must_understand() {
+        let agent = Agent::new_with_defaults();
+
+        // The attribute is of the version, whenever it's set.
+        let soap = agent
+            .soap("http://example.test")
+            .header("<t:Tx xmlns:t=\"urn:t\">5</t:Tx>", true)
+            .header("<a/>", false)
+            .version(SoapVersion::V12);
+
+        assert_eq!(
+            soap.envelope("<m:Get/>"),
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+            <soap:Envelope xmlns:soap=\"http://www.w3.org/2003/05/soap-envelope\">\
+            <soap:Header>\
+            <t:Tx soap:mustUnderstand=\"true\" xmlns:t=\"urn:t\">5</t:Tx><a/>\
+            </soap:Header>\
+            <soap:Body><m:Get/></soap:Body></soap:Envelope>"
+        );
+    }
+
+    #[test]
+    fn call_and_fault() {
+        let mock = MockConnector::new()
+            .route(
+                "/ok",
+                response(
+                    "200 OK",
+                    "<?xml version=\"1.0\"?>\
+                    <s:Envelope xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\">\
+                    <s:Body><m:Price>34.5</m:Price></s:Body></s:Envelope>",
+                ),
+            )
+            .route(
+                "/fault11",
+                response(
+                    "500 Internal Server Error",
+                    "<s:Envelope xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\"><s:Body>\
+                    <s:Fault><faultcode>s:MustUnderstand</faultcode>\
+                    <faultstring>Tx &amp; more</faultstring></s:Fault>\
+                    </s:Body></s:Envelope>",
+                ),
+            )
+            .route(
+                "/fault12",
+                response(
+                    "500 Internal Server Error",
+                    "<env:Envelope xmlns:env=\"http://www.w3.org/2003/05/soap-envelope\"><env:Body>\
+                    <env:Fault><env:Code><env:Value>env:Sender</env:Value></env:Code>\
+                    <env:Reason><env:Text xml:lang=\"en\">Bad symbol</env:Text></env:Reason>\
+                    <env:Detail><e:Symbol>X</e:Symbol></env:Detail></env:Fault>\
+                    </env:Body></env:Envelope>",
+                ),
+            );
+        let agent = Agent::with_parts(Config::default(), mock.clone(), mock.clone());
+
+        let body = agent
+            .soap("http://example.test/ok")
+            .action("urn:GetPrice")
+            .call("<m:GetPrice/>")
+            .unwrap();
+        assert_eq!(body, "<m:Price>34.5</m:Price>");
+
+        let request = String::from_utf8(mock.requests().remove(0)).unwrap();
+        assert!(request.contains("soapaction: \"urn:GetPrice\"\r\n"));
+        assert!(request.contains("<soap:Body><m:GetPrice/></soap:Body>"));
+
+        let err = agent
+            .soap("http://example.test/fault11")
+            .call("<m:GetPrice/>")
+            .unwrap_err();
+        let Error::SoapFault(fault) = err else {
+            panic!("expected SoapFault");
+        };
+        assert!(fault.is_must_understand());
+        assert_eq!(fault.reason, "Tx & more");
+
+        let err = agent
+            .soap("http://example.test/fault12")
+            .version(SoapVersion::V12)
+            .call("<m:GetPrice/>")
+            .unwrap_err();
+        let Error::SoapFault(fault) = err else {
+            panic!("expected SoapFault");
+        };
+        assert!(fault.is_client());
+        assert_eq!(fault.code, "env:Sender");
+        assert_eq!(fault.reason, "Bad symbol");
+        assert_eq!(fault.detail.as_deref(), Some("<e:Symbol>X</e:Symbol>"));
+    }
+
+    #[test]
+    fn missing_body() {
+        let mock = MockConnector::new()
+            .route("/ok", response("200 OK", "<html>Maintenance</html>"))
+            .route("/err", response("500 Internal Server Error", "Oops"));
+        let agent = Agent::with_parts(Config::default(), mock.clone(), mock);
+
+        let err = agent
+            .soap("http://example.test/ok")
+            .call("<m:Get/>")
+            .unwrap_err();
+        assert!(matches!(err, Error::BadBody(_)), "{:?}", err);
+
+        let err = agent
+            .soap("http://example.test/err")
+            .call("<m:Get/>")
+            .unwrap_err();
+        assert!(matches!(err, Error::StatusCode(500)), "{:?}", err);
+    }
+}
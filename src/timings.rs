@@ -1,5 +1,5 @@
-use std::fmt;
 use std::sync::Arc;
+use std::{fmt, iter};
 
 use crate::config::Timeouts;
 use crate::transport::time::{Duration, Instant};
@@ -24,6 +24,10 @@ pub enum Timeout {
     /// Timeout while opening the connection.
     Connect,
 
+    /// Timeout while handshaking with a proxy server, which is part of opening
+    /// the connection.
+    Proxy,
+
     /// Timeout while sending the request headers.
     SendRequest,
 
@@ -40,6 +44,9 @@ pub enum Timeout {
 
     /// Timeout while receiving the response body.
     RecvBody,
+
+    /// Timeout while waiting for a connection to a host at its limit.
+    ConnectionWait,
 }
 
 impl Timeout {
@@ -62,7 +69,7 @@ impl Timeout {
     /// All timeouts to check
     fn timeouts_to_check(&self) -> impl Iterator<Item = Timeout> {
         // Always check Global and PerCall
-        self.preceeding().chain([Timeout::Global, Timeout::PerCall])
+        iter::once(*self).chain([Timeout::Global, Timeout::PerCall])
     }
 
     /// Get the corresponding configured timeout
@@ -77,11 +84,48 @@ impl Timeout {
             Timeout::SendBody => timeouts.send_body,
             Timeout::RecvResponse => timeouts.recv_response,
             Timeout::RecvBody => timeouts.recv_body,
+            // Part of the connect timeout.
+            Timeout::Proxy => None,
+            // Applied by the pool, see `Config::connection_wait`.
+            Timeout::ConnectionWait => None,
         }
         .map(Into::into)
     }
 }
 
+/// How long each phase of a call took.
+///
+/// Set on responses, see [`ResponseExt::timings()`](crate::ResponseExt::timings), and
+/// on errors, see [`TransferStats`](crate::TransferStats). Phases that weren't reached
+/// are `None`. After a redirect, the phases are those of the last call.
+///
+/// ```
+/// use ureq::ResponseExt;
+///
+/// let res = ureq::get("http://httpbin.org/get").call()?;
+///
+/// if let Some(t) = res.timings() {
+///     println!("connect: {:?}, wait: {:?}", t.connect, t.recv_response);
+/// }
+/// # Ok::<_, ureq::Error>(())
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Timings {
+    /// Resolving the host name.
+    pub resolve: Option<std::time::Duration>,
+    /// Opening the connection, including TLS and proxy handshakes.
+    pub connect: Option<std::time::Duration>,
+    /// Sending the request head.
+    pub send_request: Option<std::time::Duration>,
+    /// Sending the request body.
+    pub send_body: Option<std::time::Duration>,
+    /// Waiting for, and receiving, the response head.
+    pub recv_response: Option<std::time::Duration>,
+    /// Time since the request started, including redirects.
+    pub total: std::time::Duration,
+}
+
 #[derive(Debug)]
 pub(crate) struct CallTimings {
     timeouts: Timeouts,
@@ -129,6 +173,20 @@ impl CallTimings {
         }
     }
 
+    /// A copy to time a concurrent attempt, such as a connection race.
+    pub(crate) fn fork(&self) -> CallTimings {
+        let mut times = empty_times();
+        for time in self.times.iter() {
+            times.push(*time);
+        }
+
+        CallTimings {
+            timeouts: self.timeouts,
+            current_time: self.current_time.clone(),
+            times,
+        }
+    }
+
     pub(crate) fn now(&self) -> Instant {
         self.current_time.now()
     }
@@ -154,15 +212,58 @@ impl CallTimings {
         self.times.push((timeout, self.current_time.now()));
     }
 
+    /// The durations of the phases recorded so far.
+    pub(crate) fn breakdown(&self) -> Timings {
+        let phase = |timeout: Timeout| {
+            let at = self.time_of(timeout)?;
+            let since = timeout.preceeding().filter_map(|p| self.time_of(p)).max()?;
+            Some(*at.duration_since(since))
+        };
+
+        let start = self.time_of(Timeout::Global).unwrap_or_else(|| self.now());
+
+        Timings {
+            resolve: phase(Timeout::Resolve),
+            connect: phase(Timeout::Connect),
+            send_request: phase(Timeout::SendRequest),
+            send_body: phase(Timeout::SendBody),
+            recv_response: phase(Timeout::RecvResponse),
+            total: *self.now().duration_since(start),
+        }
+    }
+
     fn time_of(&self, timeout: Timeout) -> Option<Instant> {
         self.times.iter().find(|x| x.0 == timeout).map(|x| x.1)
     }
 
+    /// When the phase of `timeout` started, which is when the phase before it ended.
+    fn start_of(&self, timeout: Timeout) -> Option<Instant> {
+        match timeout {
+            Timeout::Global | Timeout::PerCall => self.time_of(timeout),
+            _ => timeout.preceeding().filter_map(|t| self.time_of(t)).max(),
+        }
+    }
+
+    /// The timeout of the phase `timeout`, counted from the start of the phase.
     pub(crate) fn next_timeout(&self, timeout: Timeout) -> NextTimeout {
+        self.timeout_from(timeout, self.start_of(timeout))
+    }
+
+    /// The timeout of the phase `timeout` when starting it over now, such as when
+    /// resolving a second time.
+    pub(crate) fn restart_timeout(&self, timeout: Timeout) -> NextTimeout {
+        self.timeout_from(timeout, Some(self.now()))
+    }
+
+    fn timeout_from(&self, timeout: Timeout, start: Option<Instant>) -> NextTimeout {
         let (reason, at) = timeout
             .timeouts_to_check()
             .filter_map(|to_check| {
-                let time = self.time_of(to_check)?;
+                let time = if to_check == timeout {
+                    start?
+                } else {
+                    self.time_of(to_check)?
+                };
                 let timeout = to_check.configured_timeout(&self.timeouts)?;
                 Some((to_check, time + timeout))
             })
@@ -225,11 +326,13 @@ impl fmt::Display for Timeout {
             Timeout::PerCall => "per call",
             Timeout::Resolve => "resolve",
             Timeout::Connect => "connect",
+            Timeout::Proxy => "proxy",
             Timeout::SendRequest => "send request",
             Timeout::SendBody => "send body",
             Timeout::Await100 => "await 100",
             Timeout::RecvResponse => "receive response",
             Timeout::RecvBody => "receive body",
+            Timeout::ConnectionWait => "connection wait",
         };
         write!(f, "{}", r)
     }
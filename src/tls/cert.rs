@@ -225,6 +225,14 @@ impl<'a> From<PrivateKey<'a>> for PemItem<'a> {
     }
 }
 
+impl<'a> PartialEq for Certificate<'a> {
+    fn eq(&self, other: &Self) -> bool {
+        self.der() == other.der()
+    }
+}
+
+impl<'a> Eq for Certificate<'a> {}
+
 impl<'a> fmt::Debug for Certificate<'a> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Certificate").finish()
@@ -1,7 +1,15 @@
 //! TLS for handling `https`.
 
-use std::fmt;
-use std::sync::Arc;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+use std::{env, fmt};
+
+use crate::config::Config;
+use crate::util::hex;
+use crate::Agent;
 
 mod cert;
 pub use cert::{parse_pem, Certificate, PemItem, PrivateKey};
@@ -92,11 +100,42 @@ pub struct TlsConfig {
     /// Defaults to `true`.
     pub(crate) use_sni: bool,
 
+    /// Whether to use the host in a `Host` header as the TLS server name (SNI).
+    ///
+    /// Normally the server name is the host of the request uri, also when the request
+    /// overrides the `Host` header. With this enabled, the server name follows the header.
+    ///
+    /// Defaults to `false`.
+    pub(crate) sni_from_host_header: bool,
+
     /// **WARNING** Disable all server certificate verification.
     ///
     /// This breaks encryption and leaks secrets. Must never be enabled for code where
     /// any level of security is required.
     pub(crate) disable_verification: bool,
+
+    /// Check server certificates against certificate revocation lists.
+    ///
+    /// Defaults to `None`.
+    pub(crate) revocation: Option<Revocation>,
+
+    /// Lowest TLS version to negotiate.
+    ///
+    /// Defaults to `None`, the lowest of the provider.
+    pub(crate) min_version: Option<TlsVersion>,
+
+    /// Highest TLS version to negotiate.
+    ///
+    /// Defaults to `None`, the highest of the provider.
+    pub(crate) max_version: Option<TlsVersion>,
+
+    /// Where to log the TLS session secrets.
+    ///
+    /// Defaults to `None`.
+    pub(crate) key_log: Option<KeyLog>,
+
+    /// Shared by the clones of this config, and new for every built config.
+    pub(crate) id: TlsConfigId,
 }
 
 impl TlsConfig {
@@ -132,6 +171,26 @@ impl TlsConfigBuilder {
 
     /// The set of trusted root certificates to use to validate server certificates.
     ///
+    /// This toggles between the roots of the system, via [`RootCerts::PlatformVerifier`],
+    /// and the roots bundled with ureq, via [`RootCerts::WebPki`]. Being part of the
+    /// config, each agent can use either.
+    ///
+    /// ```
+    /// use ureq::Agent;
+    /// use ureq::tls::{RootCerts, TlsConfig};
+    ///
+    /// let bundled = Agent::new_with_defaults();
+    ///
+    /// let system: Agent = Agent::config_builder()
+    ///     .tls_config(
+    ///         TlsConfig::builder()
+    ///             .root_certs(RootCerts::PlatformVerifier)
+    ///             .build(),
+    ///     )
+    ///     .build()
+    ///     .into();
+    /// ```
+    ///
     /// Defaults to `WebPki`.
     pub fn root_certs(mut self, v: RootCerts) -> Self {
         self.config.root_certs = v;
@@ -143,21 +202,91 @@ impl TlsConfigBuilder {
     /// This is used by the server to determine which domain/certificate we are connecting
     /// to for servers where multiple domains/sites are hosted on the same IP.
     ///
+    /// SNI is only for DNS names. For an IP address, such as `https://203.0.113.5/`
+    /// or `https://[2001:db8::1]/`, no SNI is sent and the server certificate must
+    /// hold the address as an IP subject alternative name. To send a DNS name and
+    /// validate against it instead, see [`sni_from_host_header`](Self::sni_from_host_header).
+    ///
     /// Defaults to `true`.
     pub fn use_sni(mut self, v: bool) -> Self {
         self.config.use_sni = v;
         self
     }
 
+    /// Whether to use the host in a `Host` header as the TLS server name (SNI).
+    ///
+    /// Normally the server name is the host of the request uri, also when the request
+    /// overrides the `Host` header. With this enabled, the server name follows the header.
+    ///
+    /// Defaults to `false`.
+    pub fn sni_from_host_header(mut self, v: bool) -> Self {
+        self.config.sni_from_host_header = v;
+        self
+    }
+
     /// **WARNING** Disable all server certificate verification.
     ///
     /// This breaks encryption and leaks secrets. Must never be enabled for code where
     /// any level of security is required.
+    ///
+    /// Meant for development servers with self-signed certificates. Trusting just
+    /// that certificate via [`RootCerts::Specific`] is the safer choice. A warning
+    /// is logged when a connector is set up with verification disabled.
+    ///
+    /// Defaults to `false`.
     pub fn disable_verification(mut self, v: bool) -> Self {
         self.config.disable_verification = v;
         self
     }
 
+    /// Check server certificates against certificate revocation lists.
+    ///
+    /// Only for **rustls** with root certs other than
+    /// [`RootCerts::PlatformVerifier`], where the platform checks revocation itself.
+    /// **native-tls** fails to connect with [`Error::Tls`](crate::Error::Tls) rather
+    /// than skip the check.
+    ///
+    /// Defaults to `None`.
+    pub fn revocation(mut self, v: Option<Revocation>) -> Self {
+        self.config.revocation = v;
+        self
+    }
+
+    /// Lowest TLS version to negotiate, such as [`TlsVersion::Tls13`] to forbid TLS 1.2.
+    ///
+    /// **native-tls** can't be limited to TLS 1.3, connecting then fails with
+    /// [`Error::Tls`](crate::Error::Tls).
+    ///
+    /// Defaults to `None`, which is TLS 1.2 for **rustls** and the platform default
+    /// for **native-tls**.
+    pub fn min_version(mut self, v: Option<TlsVersion>) -> Self {
+        self.config.min_version = v;
+        self
+    }
+
+    /// Highest TLS version to negotiate, such as [`TlsVersion::Tls12`] for servers
+    /// that break on TLS 1.3.
+    ///
+    /// Defaults to `None`, the highest the provider supports.
+    pub fn max_version(mut self, v: Option<TlsVersion>) -> Self {
+        self.config.max_version = v;
+        self
+    }
+
+    /// **WARNING** Log the TLS session secrets, to decrypt captured traffic.
+    ///
+    /// Anyone with the logged secrets can read the traffic. Meant for debugging,
+    /// such as with [`KeyLog::from_env()`] which writes to the file in the
+    /// `SSLKEYLOGFILE` environment variable for Wireshark.
+    ///
+    /// Only for **rustls**, **native-tls** has no way to get the secrets.
+    ///
+    /// Defaults to `None`.
+    pub fn key_log(mut self, v: Option<KeyLog>) -> Self {
+        self.config.key_log = v;
+        self
+    }
+
     /// Finalize the config
     pub fn build(self) -> TlsConfig {
         self.config
@@ -211,6 +340,179 @@ impl<I: IntoIterator<Item = Certificate<'static>>> From<I> for RootCerts {
     }
 }
 
+/// A TLS protocol version, for [`TlsConfigBuilder::min_version()`] and
+/// [`TlsConfigBuilder::max_version()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[non_exhaustive]
+pub enum TlsVersion {
+    /// TLS 1.2
+    Tls12,
+    /// TLS 1.3
+    Tls13,
+}
+
+/// Receiver of TLS session secrets, for [`TlsConfigBuilder::key_log()`].
+///
+/// The secrets are given as in the [NSS key log format]: a label, such as
+/// `CLIENT_TRAFFIC_SECRET_0`, the client random of the handshake, and the secret.
+///
+/// ```
+/// use ureq::tls::{KeyLog, TlsConfig};
+///
+/// let tls_config = TlsConfig::builder()
+///     .key_log(Some(KeyLog::from_env()))
+///     .build();
+/// ```
+///
+/// [NSS key log format]: https://firefox-source-docs.mozilla.org/security/nss/legacy/key_log_format/index.html
+#[derive(Clone)]
+pub struct KeyLog(Arc<dyn Fn(&str, &[u8], &[u8]) + Send + Sync>);
+
+impl KeyLog {
+    /// Pass the secrets to `f`, called with the label, client random and secret.
+    pub fn new(f: impl Fn(&str, &[u8], &[u8]) + Send + Sync + 'static) -> Self {
+        KeyLog(Arc::new(f))
+    }
+
+    /// Append the secrets to the file named by the `SSLKEYLOGFILE` environment variable.
+    ///
+    /// Logs nothing if the variable isn't set.
+    pub fn from_env() -> Self {
+        match env::var_os("SSLKEYLOGFILE") {
+            Some(path) => Self::file(path),
+            None => KeyLog::new(|_, _, _| {}),
+        }
+    }
+
+    /// Append the secrets to the file at `path`, in the NSS key log format.
+    ///
+    /// The file is created on the first secret. Failing to write is logged as a
+    /// warning, and doesn't fail the connection.
+    pub fn file(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let file: Mutex<Option<File>> = Mutex::new(None);
+
+        KeyLog::new(move |label, client_random, secret| {
+            let mut file = file.lock().unwrap();
+
+            if file.is_none() {
+                match OpenOptions::new().append(true).create(true).open(&path) {
+                    Ok(f) => *file = Some(f),
+                    Err(e) => {
+                        warn!("Failed to open key log {}: {}", path.display(), e);
+                        return;
+                    }
+                }
+            }
+
+            let line = format!("{} {} {}\n", label, hex(client_random), hex(secret));
+            if let Some(f) = file.as_mut() {
+                if let Err(e) = f.write_all(line.as_bytes()) {
+                    warn!("Failed to write key log {}: {}", path.display(), e);
+                }
+            }
+        })
+    }
+
+    pub(crate) fn log(&self, label: &str, client_random: &[u8], secret: &[u8]) {
+        (self.0)(label, client_random, secret)
+    }
+}
+
+impl fmt::Debug for KeyLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyLog").finish()
+    }
+}
+
+/// Revocation checking of server certificates.
+///
+/// The certificate revocation lists (CRLs) are fetched from the given urls before the
+/// first TLS connection of an agent, and fetched again on the first connection after
+/// the earliest `nextUpdate` of the CRLs. A failed fetch fails the connection, and is
+/// tried again on the next one.
+///
+/// The CRLs are fetched by a separate internal agent, configured by
+/// [`Revocation::fetch_config()`]. That agent never checks revocation itself, which
+/// would otherwise loop when a CRL is served over `https`. OCSP isn't checked.
+///
+/// ```
+/// use std::time::Duration;
+/// use ureq::Agent;
+/// use ureq::config::Config;
+/// use ureq::tls::{Revocation, TlsConfig};
+///
+/// let fetch_config = Config::builder()
+///     .timeout_global(Some(Duration::from_secs(10)))
+///     .build();
+///
+/// let revocation = Revocation::new(["http://crl.example.test/intermediate.crl"])
+///     .fetch_config(fetch_config);
+///
+/// let agent: Agent = Config::builder()
+///     .tls_config(TlsConfig::builder().revocation(Some(revocation)).build())
+///     .build()
+///     .into();
+/// ```
+#[derive(Debug, Clone)]
+pub struct Revocation {
+    pub(crate) crl_urls: Arc<Vec<String>>,
+    pub(crate) fetch_config: Option<Arc<Config>>,
+    pub(crate) allow_unknown_status: bool,
+}
+
+impl Revocation {
+    /// Check revocation against the CRLs at these urls.
+    ///
+    /// The CRLs can be DER or PEM.
+    pub fn new<I, S>(crl_urls: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Revocation {
+            crl_urls: Arc::new(crl_urls.into_iter().map(Into::into).collect()),
+            fetch_config: None,
+            allow_unknown_status: false,
+        }
+    }
+
+    /// Config of the agent fetching the CRLs, such as timeouts and proxy.
+    ///
+    /// Any revocation setting in this config is ignored.
+    ///
+    /// Defaults to the default [`Config`] with a global timeout of 30 seconds.
+    pub fn fetch_config(mut self, v: Config) -> Self {
+        self.fetch_config = Some(Arc::new(v));
+        self
+    }
+
+    /// Accept certificates whose issuer isn't covered by any of the CRLs.
+    ///
+    /// Defaults to `false`, which means there must be a CRL for every issuer in the
+    /// certificate chain.
+    pub fn allow_unknown_status(mut self, v: bool) -> Self {
+        self.allow_unknown_status = v;
+        self
+    }
+
+    /// The agent to fetch the CRLs with.
+    pub(crate) fn fetch_agent(&self) -> Agent {
+        let mut config = match &self.fetch_config {
+            Some(v) => (**v).clone(),
+            None => Config::builder()
+                .timeout_global(Some(Duration::from_secs(30)))
+                .build(),
+        };
+
+        // Loop protection, the fetches themselves are not checked.
+        config.tls_config.revocation = None;
+        config.tls_config.id = TlsConfigId::default();
+
+        Agent::new_with_config(config)
+    }
+}
+
 impl Default for TlsConfig {
     fn default() -> Self {
         let provider = TlsProvider::default();
@@ -219,11 +521,63 @@ impl Default for TlsConfig {
             client_cert: None,
             root_certs: RootCerts::WebPki,
             use_sni: true,
+            sni_from_host_header: false,
             disable_verification: false,
+            revocation: None,
+            min_version: None,
+            max_version: None,
+            key_log: None,
+            id: TlsConfigId::default(),
         }
     }
 }
 
+/// Identity of a [`TlsConfig`].
+///
+/// The connectors cache what they build from the config, and the pool keeps the
+/// connections apart, by this identity. The contents can't be compared, client
+/// certificates and key logs have no equality.
+#[derive(Clone, Default)]
+pub(crate) struct TlsConfigId(Arc<()>);
+
+impl PartialEq for TlsConfigId {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for TlsConfigId {}
+
+/// What a connector built for each [`TlsConfig`], kept while the config is in use.
+pub(crate) struct TlsConfigCache<T>(Mutex<Vec<(Weak<()>, Option<T>)>>);
+
+impl<T> TlsConfigCache<T> {
+    /// Call `f` with the cached value for `tls_config`, which `f` can fill in.
+    pub(crate) fn with<R>(&self, tls_config: &TlsConfig, f: impl FnOnce(&mut Option<T>) -> R) -> R {
+        let mut entries = self.0.lock().unwrap();
+
+        // Forget the configs no agent, request or pooled connection holds anymore.
+        entries.retain(|(id, _)| id.strong_count() > 0);
+
+        let id = Arc::as_ptr(&tls_config.id.0);
+        let index = match entries.iter().position(|(w, _)| w.as_ptr() == id) {
+            Some(i) => i,
+            None => {
+                entries.push((Arc::downgrade(&tls_config.id.0), None));
+                entries.len() - 1
+            }
+        };
+
+        f(&mut entries[index].1)
+    }
+}
+
+impl<T> Default for TlsConfigCache<T> {
+    fn default() -> Self {
+        TlsConfigCache(Mutex::new(Vec::new()))
+    }
+}
+
 impl Default for TlsProvider {
     fn default() -> Self {
         Self::Rustls
@@ -237,7 +591,12 @@ impl fmt::Debug for TlsConfig {
             .field("client_cert", &self.client_cert)
             .field("root_certs", &self.root_certs)
             .field("use_sni", &self.use_sni)
+            .field("sni_from_host_header", &self.sni_from_host_header)
             .field("disable_verification", &self.disable_verification)
+            .field("revocation", &self.revocation)
+            .field("min_version", &self.min_version)
+            .field("max_version", &self.max_version)
+            .field("key_log", &self.key_log)
             .finish()
     }
 }
@@ -252,4 +611,76 @@ mod test {
         let c = TlsConfig::default();
         assert_no_alloc(|| c.clone());
     }
+
+    #[test]
+    fn tls_host_of_ip_literal() {
+        use crate::resolver::DefaultResolver;
+        use crate::transport::time::{Duration, Instant};
+        use crate::transport::{ConnectionDetails, MockTransport, NextTimeout};
+        use crate::util::ArrayVec;
+        use crate::Timeout;
+
+        let tls_host = |uri: &str, server_name: Option<&str>| {
+            let uri = uri.parse().unwrap();
+            let config = Config::default();
+            let details = ConnectionDetails {
+                uri: &uri,
+                addrs: ArrayVec::from_fn(|_| "0.0.0.0:0".parse().unwrap()),
+                config: &config,
+                server_name,
+                resolver: &DefaultResolver::default(),
+                now: Instant::now(),
+                timeout: NextTimeout {
+                    after: Duration::NotHappening,
+                    reason: Timeout::Global,
+                },
+            };
+            let transport = MockTransport::new(&b""[..]);
+            details.tls_host(&transport).map(str::to_string)
+        };
+
+        assert_eq!(
+            tls_host("https://203.0.113.5/", None).unwrap(),
+            "203.0.113.5"
+        );
+        assert_eq!(
+            tls_host("https://[2001:db8::1]:8443/", None).unwrap(),
+            "2001:db8::1"
+        );
+        assert_eq!(
+            tls_host("https://[2001:db8::1]/", Some("api.test")).unwrap(),
+            "api.test"
+        );
+        assert_eq!(tls_host("http://[2001:db8::1]/", None), None);
+    }
+
+    #[test]
+    fn revocation_fetch_agent_does_not_check_revocation() {
+        let nested = TlsConfig::builder()
+            .revocation(Some(Revocation::new(["https://crl.test/b.crl"])))
+            .build();
+        let fetch_config = Config::builder().tls_config(nested).build();
+
+        let revocation = Revocation::new(["https://crl.test/a.crl"]).fetch_config(fetch_config);
+        let agent = revocation.fetch_agent();
+
+        assert!(agent.config().tls_config.revocation.is_none());
+    }
+
+    #[test]
+    fn key_log_file() {
+        let path = env::temp_dir().join(format!("ureq-keylog-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let key_log = KeyLog::file(&path);
+        key_log.log("CLIENT_RANDOM", &[0x01, 0xab], &[0xff]);
+        key_log.log("SERVER_TRAFFIC_SECRET_0", &[0x02], &[0x00, 0x10]);
+
+        let logged = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            logged,
+            "CLIENT_RANDOM 01ab ff\nSERVER_TRAFFIC_SECRET_0 02 0010\n"
+        );
+    }
 }
@@ -1,24 +1,24 @@
 use std::convert::TryFrom;
 use std::fmt;
 use std::io::{Read, Write};
+use std::net::SocketAddr;
 use std::sync::Arc;
 
-use crate::tls::{RootCerts, TlsProvider};
-use crate::{transport::*, Error};
+use crate::tls::{RootCerts, TlsProvider, TlsVersion};
+use crate::{transport::*, Error, Timeout};
 use der::pem::LineEnding;
 use der::Document;
-use native_tls::{Certificate, HandshakeError, Identity, TlsConnector};
+use native_tls::{Certificate, HandshakeError, Identity, Protocol, TlsConnector};
 use native_tls::{TlsConnectorBuilder, TlsStream};
-use once_cell::sync::OnceCell;
 
-use super::TlsConfig;
+use super::{TlsConfig, TlsConfigCache};
 
 /// Wrapper for TLS using native-tls.
 ///
 /// Requires feature flag **native-tls**.
 #[derive(Default)]
 pub struct NativeTlsConnector {
-    connector: OnceCell<Arc<TlsConnector>>,
+    connectors: TlsConfigCache<Arc<TlsConnector>>,
 }
 
 impl Connector for NativeTlsConnector {
@@ -32,11 +32,11 @@ impl Connector for NativeTlsConnector {
         };
 
         // Only add TLS if we are connecting via HTTPS and the transport isn't TLS
-        // already, otherwise use chained transport as is.
-        if !details.needs_tls() || transport.is_tls() {
+        // already, or for the proxy/server around a CONNECT tunnel.
+        let Some(host) = details.tls_host(&*transport) else {
             trace!("Skip");
             return Ok(Some(transport));
-        }
+        };
 
         if details.config.tls_config.provider != TlsProvider::NativeTls {
             debug!("Skip because config is not set to Native TLS");
@@ -47,35 +47,45 @@ impl Connector for NativeTlsConnector {
 
         let tls_config = &details.config.tls_config;
 
-        // Initialize the connector on first run.
-        let connector_ref = match self.connector.get() {
-            Some(v) => v,
-            None => {
-                // This is unlikely to be racy, but if it is, doesn't matter much.
-                let c = build_connector(tls_config)?;
-                // Maybe someone else set it first. Weird, but ok.
-                let _ = self.connector.set(c);
-                self.connector.get().unwrap()
+        // Build the connector on first use of the config.
+        let connector = self.connectors.with(tls_config, |cached| {
+            if let Some(c) = &*cached {
+                return Ok(c.clone());
             }
-        };
-        let connector = connector_ref.clone(); // cheap clone due to Arc
+            let c = build_connector(tls_config)?;
+            *cached = Some(c.clone());
+            Ok::<_, Error>(c)
+        })?;
 
-        let domain = details
-            .uri
-            .authority()
-            .expect("uri authority for tls")
-            .host()
-            .to_string();
+        let domain = host.to_string();
 
-        let adapter = TransportAdapter::new(transport);
-        let stream = LazyStream::Unstarted(Some((connector, domain, adapter)));
+        // The handshake is part of opening the connection, and charged to its timeout.
+        let mut adapter = TransportAdapter::new(transport);
+        adapter.set_timeout(details.remaining(Timeout::Connect)?);
+
+        let stream = connector.connect(&domain, adapter).map_err(|e| match e {
+            HandshakeError::Failure(e) => e,
+            HandshakeError::WouldBlock(_) => unreachable!(),
+        })?;
 
         let buffers = LazyBuffers::new(
             details.config.input_buffer_size,
             details.config.output_buffer_size,
         );
 
-        let transport = Box::new(NativeTlsTransport { buffers, stream });
+        // Copied once here, the certificate doesn't change after the handshake.
+        let peer_certificates = stream
+            .peer_certificate()
+            .ok()
+            .flatten()
+            .and_then(|c| c.to_der().ok())
+            .map(|der| vec![super::Certificate::from_der(&der).to_owned()].into());
+
+        let transport = Box::new(NativeTlsTransport {
+            buffers,
+            stream,
+            peer_certificates,
+        });
 
         debug!("Wrapped TLS");
 
@@ -86,8 +96,31 @@ impl Connector for NativeTlsConnector {
 fn build_connector(tls_config: &TlsConfig) -> Result<Arc<TlsConnector>, Error> {
     let mut builder = TlsConnector::builder();
 
+    // native-tls has no setting for TLS 1.3, and uses it when available.
+    match tls_config.min_version {
+        Some(TlsVersion::Tls13) => {
+            return Err(Error::Tls("native-tls can't require TLS 1.3"));
+        }
+        Some(TlsVersion::Tls12) => {
+            builder.min_protocol_version(Some(Protocol::Tlsv12));
+        }
+        None => {}
+    }
+    if tls_config.max_version == Some(TlsVersion::Tls12) {
+        builder.max_protocol_version(Some(Protocol::Tlsv12));
+    }
+
+    // Unlike the key log, ignoring this would silently weaken verification.
+    if tls_config.revocation.is_some() {
+        return Err(Error::Tls("native-tls can't check revocation with CRLs"));
+    }
+
+    if tls_config.key_log.is_some() {
+        warn!("native-tls can't log TLS secrets, ignoring key log");
+    }
+
     if tls_config.disable_verification {
-        debug!("Certificate verification disabled");
+        warn!("Certificate verification disabled");
         builder.danger_accept_invalid_certs(true);
         builder.danger_accept_invalid_hostnames(true);
     } else {
@@ -169,7 +202,8 @@ fn pemify(der: &[u8], label: &'static str) -> Result<String, Error> {
 
 struct NativeTlsTransport {
     buffers: LazyBuffers,
-    stream: LazyStream,
+    stream: TlsStream<TransportAdapter>,
+    peer_certificates: Option<Arc<[super::Certificate<'static>]>>,
 }
 
 impl Transport for NativeTlsTransport {
@@ -178,11 +212,10 @@ impl Transport for NativeTlsTransport {
     }
 
     fn transmit_output(&mut self, amount: usize, timeout: NextTimeout) -> Result<(), Error> {
-        let stream = self.stream.handshaken()?;
-        stream.get_mut().set_timeout(timeout);
+        self.stream.get_mut().set_timeout(timeout);
 
         let output = &self.buffers.output()[..amount];
-        stream.write_all(output)?;
+        self.stream.write_all(output)?;
 
         Ok(())
     }
@@ -192,52 +225,36 @@ impl Transport for NativeTlsTransport {
             return Ok(true);
         }
 
-        let stream = self.stream.handshaken()?;
-        stream.get_mut().set_timeout(timeout);
+        self.stream.get_mut().set_timeout(timeout);
 
         let input = self.buffers.input_append_buf();
-        let amount = stream.read(input)?;
+        let amount = self.stream.read(input)?;
         self.buffers.input_appended(amount);
 
         Ok(amount > 0)
     }
 
     fn is_open(&mut self) -> bool {
-        self.stream
-            .handshaken()
-            .map(|c| c.get_mut().get_mut().is_open())
-            .unwrap_or(false)
+        self.stream.get_mut().get_mut().is_open()
     }
 
     fn is_tls(&self) -> bool {
         true
     }
-}
 
-/// Helper to delay the handshake until we are starting IO.
-/// This normalizes native-tls to behave like rustls.
-enum LazyStream {
-    Unstarted(Option<(Arc<TlsConnector>, String, TransportAdapter)>),
-    Started(TlsStream<TransportAdapter>),
-}
+    fn info(&self) -> ConnectionInfo {
+        // native-tls doesn't tell the negotiated version and cipher, nor the chain
+        // beyond the server's certificate.
+        let mut info = self.stream.get_ref().get_ref().info();
+        info.peer_certificates = self.peer_certificates.clone();
+        info
+    }
 
-impl LazyStream {
-    fn handshaken(&mut self) -> Result<&mut TlsStream<TransportAdapter>, Error> {
-        match self {
-            LazyStream::Unstarted(v) => {
-                let (conn, domain, adapter) = v.take().unwrap();
-                let stream = conn.connect(&domain, adapter).map_err(|e| match e {
-                    HandshakeError::Failure(e) => e,
-                    HandshakeError::WouldBlock(_) => unreachable!(),
-                })?;
-                *self = LazyStream::Started(stream);
-                // Next time we hit the other match arm
-                return self.handshaken();
-            }
-            LazyStream::Started(v) => Ok(v),
-        }
+    fn peer_addr(&self) -> Option<SocketAddr> {
+        self.stream.get_ref().get_ref().peer_addr()
     }
 }
+
 impl fmt::Debug for NativeTlsConnector {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("NativeTlsConnector").finish()
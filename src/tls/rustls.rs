@@ -1,28 +1,66 @@
-use std::convert::TryInto;
+use std::convert::{TryFrom, TryInto};
 use std::fmt;
 use std::io::{Read, Write};
+use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use once_cell::sync::OnceCell;
 use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
-use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned, ALL_VERSIONS};
+use rustls::client::{WantsClientCert, WebPkiServerVerifier};
+use rustls::crypto::CryptoProvider;
+use rustls::{ClientConfig, ClientConnection, ProtocolVersion, RootCertStore, StreamOwned};
+use rustls::{ConfigBuilder, WantsVerifier, ALL_VERSIONS};
 use rustls_pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs1KeyDer, PrivatePkcs8KeyDer};
-use rustls_pki_types::{PrivateSec1KeyDer, ServerName};
+use rustls_pki_types::{CertificateRevocationListDer, PrivateSec1KeyDer, ServerName};
 
 use crate::tls::cert::KeyKind;
-use crate::tls::{RootCerts, TlsProvider};
+use crate::tls::Certificate;
+use crate::tls::{KeyLog, Revocation, RootCerts, TlsProvider, TlsVersion};
 use crate::transport::{Buffers, ConnectionDetails, Connector, LazyBuffers};
-use crate::transport::{NextTimeout, Transport, TransportAdapter};
-use crate::Error;
+use crate::transport::{ConnectionInfo, NextTimeout, Transport, TransportAdapter};
+use crate::util::days_from_civil;
+use crate::{Error, Timeout};
 
-use super::TlsConfig;
+use super::{TlsConfig, TlsConfigCache};
+
+/// CRLs of large CAs can be tens of megabytes.
+const MAX_CRL_SIZE: u64 = 100 * 1024 * 1024;
 
 /// Wrapper for TLS using rustls.
 ///
 /// Requires feature flag **rustls**.
 #[derive(Default)]
 pub struct RustlsConnector {
-    config: OnceCell<Arc<ClientConfig>>,
+    configs: TlsConfigCache<CachedConfig>,
+}
+
+struct CachedConfig {
+    config: Arc<ClientConfig>,
+    /// The earliest `nextUpdate` of the CRLs, after which they are fetched again.
+    refresh_at: Option<SystemTime>,
+}
+
+impl RustlsConnector {
+    /// The config built on first use of `tls_config`, and again when the CRLs are
+    /// due an update.
+    fn client_config(&self, tls_config: &TlsConfig) -> Result<Arc<ClientConfig>, Error> {
+        self.configs.with(tls_config, |cached| {
+            if let Some(c) = &*cached {
+                match c.refresh_at {
+                    Some(t) if SystemTime::now() >= t => debug!("CRLs are due an update"),
+                    _ => return Ok(c.config.clone()), // cheap clone due to Arc
+                }
+            }
+
+            // A failed refresh leaves the outdated config, to be refreshed on the next
+            // connection.
+            let new = build_config(tls_config)?;
+            let config = new.config.clone();
+            *cached = Some(new);
+
+            Ok(config)
+        })
+    }
 }
 
 impl Connector for RustlsConnector {
@@ -36,11 +74,11 @@ impl Connector for RustlsConnector {
         };
 
         // Only add TLS if we are connecting via HTTPS and the transport isn't TLS
-        // already, otherwise use chained transport as is.
-        if !details.needs_tls() || transport.is_tls() {
+        // already, or for the proxy/server around a CONNECT tunnel.
+        let Some(host) = details.tls_host(&*transport) else {
             trace!("Skip");
             return Ok(Some(transport));
-        }
+        };
 
         if details.config.tls_config.provider != TlsProvider::Rustls {
             debug!("Skip because config is not set to Rustls");
@@ -51,35 +89,47 @@ impl Connector for RustlsConnector {
 
         let tls_config = &details.config.tls_config;
 
-        // Initialize the config on first run.
-        let config_ref = self.config.get_or_init(|| build_config(tls_config));
-        let config = config_ref.clone(); // cheap clone due to Arc
-
-        let name_borrowed: ServerName<'_> = details
-            .uri
-            .authority()
-            .expect("uri authority for tls")
-            .host()
-            .try_into()
-            .map_err(|e| {
-                warn!("rustls invalid dns name: {}", e);
-                Error::Tls("Rustls invalid dns name error")
-            })?;
+        let config = self.client_config(tls_config)?;
+
+        let name_borrowed: ServerName<'_> = host.try_into().map_err(|e| {
+            warn!("rustls invalid dns name: {}", e);
+            Error::Tls("Rustls invalid dns name error")
+        })?;
 
         let name = name_borrowed.to_owned();
 
         let conn = ClientConnection::new(config, name)?;
-        let stream = StreamOwned {
+        let mut stream = StreamOwned {
             conn,
             sock: TransportAdapter::new(transport),
         };
 
+        // The handshake is part of opening the connection, and charged to its timeout.
+        stream
+            .sock
+            .set_timeout(details.remaining(Timeout::Connect)?);
+        while stream.conn.is_handshaking() {
+            stream.conn.complete_io(&mut stream.sock)?;
+        }
+
         let buffers = LazyBuffers::new(
             details.config.input_buffer_size,
             details.config.output_buffer_size,
         );
 
-        let transport = Box::new(RustlsTransport { buffers, stream });
+        // The chain doesn't change after the handshake, it's copied once here.
+        let peer_certificates = stream.conn.peer_certificates().map(|certs| {
+            certs
+                .iter()
+                .map(|c| Certificate::from_der(c.as_ref()).to_owned())
+                .collect()
+        });
+
+        let transport = Box::new(RustlsTransport {
+            buffers,
+            stream,
+            peer_certificates,
+        });
 
         debug!("Wrapped TLS");
 
@@ -87,19 +137,38 @@ impl Connector for RustlsConnector {
     }
 }
 
-fn build_config(tls_config: &TlsConfig) -> Arc<ClientConfig> {
+fn build_config(tls_config: &TlsConfig) -> Result<CachedConfig, Error> {
     // Improve chances of ureq working out-of-the-box by not requiring the user
     // to select a default crypto provider.
     let provider = rustls::crypto::CryptoProvider::get_default()
         .cloned()
         .unwrap_or(Arc::new(rustls::crypto::ring::default_provider()));
 
+    let versions: Vec<_> = ALL_VERSIONS
+        .iter()
+        .copied()
+        .filter(|v| {
+            let version = match v.version {
+                ProtocolVersion::TLSv1_2 => TlsVersion::Tls12,
+                _ => TlsVersion::Tls13,
+            };
+            tls_config.min_version.map(|m| version >= m).unwrap_or(true)
+                && tls_config.max_version.map(|m| version <= m).unwrap_or(true)
+        })
+        .collect();
+
+    if versions.is_empty() {
+        return Err(Error::Tls("No TLS version between min and max version"));
+    }
+
     let builder = ClientConfig::builder_with_provider(provider.clone())
-        .with_protocol_versions(ALL_VERSIONS)
-        .expect("all TLS versions");
+        .with_protocol_versions(&versions)
+        .expect("supported TLS versions");
+
+    let mut refresh_at = None;
 
     let builder = if tls_config.disable_verification {
-        debug!("Certificate verification disabled");
+        warn!("Certificate verification disabled");
         builder
             .dangerous()
             .with_custom_certificate_verifier(Arc::new(DisabledVerifier))
@@ -112,7 +181,7 @@ fn build_config(tls_config: &TlsConfig) -> Arc<ClientConfig> {
                 let (added, ignored) = root_store.add_parsable_certificates(root_certs);
                 debug!("Added {} and ignored {} root certs", added, ignored);
 
-                builder.with_root_certificates(root_store)
+                with_roots(builder, root_store, tls_config, provider, &mut refresh_at)?
             }
             #[cfg(not(feature = "platform-verifier"))]
             RootCerts::PlatformVerifier => {
@@ -129,7 +198,7 @@ fn build_config(tls_config: &TlsConfig) -> Arc<ClientConfig> {
                 let root_store = RootCertStore {
                     roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
                 };
-                builder.with_root_certificates(root_store)
+                with_roots(builder, root_store, tls_config, provider, &mut refresh_at)?
             }
         }
     };
@@ -161,12 +230,179 @@ fn build_config(tls_config: &TlsConfig) -> Arc<ClientConfig> {
         debug!("Disable SNI");
     }
 
-    Arc::new(config)
+    // HTTP/1.1 is the only protocol spoken. Offering it via ALPN lets servers
+    // that also speak HTTP/2 settle on it in the handshake.
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+
+    if let Some(key_log) = &tls_config.key_log {
+        warn!("Logging TLS secrets");
+        config.key_log = Arc::new(KeyLogAdapter(key_log.clone()));
+    }
+
+    Ok(CachedConfig {
+        config: Arc::new(config),
+        refresh_at,
+    })
+}
+
+/// Passes the secrets of rustls to a [`KeyLog`].
+#[derive(Debug)]
+struct KeyLogAdapter(KeyLog);
+
+impl rustls::KeyLog for KeyLogAdapter {
+    fn log(&self, label: &str, client_random: &[u8], secret: &[u8]) {
+        self.0.log(label, client_random, secret);
+    }
+}
+
+/// Verify with `roots`, checking revocation if configured.
+///
+/// Sets `refresh_at` to the earliest `nextUpdate` of the CRLs.
+fn with_roots(
+    builder: ConfigBuilder<ClientConfig, WantsVerifier>,
+    roots: RootCertStore,
+    tls_config: &TlsConfig,
+    provider: Arc<CryptoProvider>,
+    refresh_at: &mut Option<SystemTime>,
+) -> Result<ConfigBuilder<ClientConfig, WantsClientCert>, Error> {
+    let Some(revocation) = &tls_config.revocation else {
+        return Ok(builder.with_root_certificates(roots));
+    };
+
+    let crls = fetch_crls(revocation)?;
+    *refresh_at = crls.iter().filter_map(|c| crl_next_update(c)).min();
+    debug!(
+        "Check revocation with {} CRLs, next update at {:?}",
+        crls.len(),
+        refresh_at
+    );
+
+    let mut verifier =
+        WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider).with_crls(crls);
+
+    if revocation.allow_unknown_status {
+        verifier = verifier.allow_unknown_revocation_status();
+    }
+
+    let verifier = verifier.build().map_err(|e| {
+        warn!("rustls invalid CRL: {}", e);
+        Error::Tls("Rustls invalid CRL")
+    })?;
+
+    Ok(builder.with_webpki_verifier(verifier))
+}
+
+fn fetch_crls(
+    revocation: &Revocation,
+) -> Result<Vec<CertificateRevocationListDer<'static>>, Error> {
+    let agent = revocation.fetch_agent();
+    let mut crls = vec![];
+
+    for url in revocation.crl_urls.iter() {
+        debug!("Fetch CRL: {}", url);
+
+        let der = agent
+            .get(url)
+            .call()?
+            .body_mut()
+            .with_config()
+            .limit(MAX_CRL_SIZE)
+            .read_to_vec()?;
+
+        if der.starts_with(b"-----BEGIN") {
+            for crl in rustls_pemfile::crls(&mut der.as_slice()) {
+                crls.push(crl?);
+            }
+        } else {
+            crls.push(CertificateRevocationListDer::from(der));
+        }
+    }
+
+    Ok(crls)
+}
+
+/// The `nextUpdate` of a DER encoded CRL (RFC 5280, section 5.1).
+fn crl_next_update(der: &[u8]) -> Option<SystemTime> {
+    // CertificateList ::= SEQUENCE { tbsCertList, signatureAlgorithm, signature }
+    let (_, list, _) = der_element(der, 0x30)?;
+    let (_, tbs, _) = der_element(list, 0x30)?;
+
+    // Optional version, then signature and issuer.
+    let mut rest = tbs;
+    if let Some((_, _, after)) = der_element(rest, 0x02) {
+        rest = after;
+    }
+    for _ in 0..2 {
+        rest = der_element(rest, 0x30)?.2;
+    }
+
+    // thisUpdate, then the optional nextUpdate.
+    let (_, _, rest) = der_any(rest)?;
+    let (tag, time, _) = der_any(rest)?;
+    der_time(tag, time)
+}
+
+/// The first element of `data` if it has this tag.
+fn der_element(data: &[u8], tag: u8) -> Option<(u8, &[u8], &[u8])> {
+    der_any(data).filter(|(t, _, _)| *t == tag)
+}
+
+/// Tag, content and the data following the first element of `data`.
+fn der_any(data: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = data.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+
+    let (len, rest) = if first < 0x80 {
+        (first as usize, rest)
+    } else {
+        let n = (first & 0x7f) as usize;
+        if n == 0 || n > 4 || rest.len() < n {
+            return None;
+        }
+        let len = rest[..n].iter().fold(0, |acc, b| acc << 8 | *b as usize);
+        (len, &rest[n..])
+    };
+
+    if rest.len() < len {
+        return None;
+    }
+
+    Some((tag, &rest[..len], &rest[len..]))
+}
+
+/// A UTCTime (`YYMMDDHHMMSSZ`) or GeneralizedTime (`YYYYMMDDHHMMSSZ`).
+fn der_time(tag: u8, data: &[u8]) -> Option<SystemTime> {
+    let s = std::str::from_utf8(data).ok()?.strip_suffix('Z')?;
+    if !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    let (year, rest) = match (tag, s.len()) {
+        // Two digit years are 1950 to 2049 (RFC 5280, section 4.1.2.5.1).
+        (0x17, 12) => match s[..2].parse().ok()? {
+            y @ 0..=49 => (2000 + y, &s[2..]),
+            y => (1900 + y, &s[2..]),
+        },
+        (0x18, 14) => (s[..4].parse().ok()?, &s[4..]),
+        _ => return None,
+    };
+
+    let n = |i: usize| rest[i..i + 2].parse::<u32>().ok();
+    let (month, day, h, m, sec) = (n(0)?, n(2)?, n(4)?, n(6)?, n(8)?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || h > 23 || m > 59 || sec > 60 {
+        return None;
+    }
+
+    let days = days_from_civil(year, month, day);
+    let secs = u64::try_from(days).ok()? * 86_400 + (h * 3600 + m * 60 + sec) as u64;
+
+    Some(UNIX_EPOCH + Duration::from_secs(secs))
 }
 
 struct RustlsTransport {
     buffers: LazyBuffers,
     stream: StreamOwned<ClientConnection, TransportAdapter>,
+    peer_certificates: Option<Arc<[Certificate<'static>]>>,
 }
 
 impl Transport for RustlsTransport {
@@ -179,6 +415,7 @@ impl Transport for RustlsTransport {
 
         let output = &self.buffers.output()[..amount];
         self.stream.write_all(output)?;
+        self.stream.flush()?;
 
         Ok(())
     }
@@ -204,6 +441,28 @@ impl Transport for RustlsTransport {
     fn is_tls(&self) -> bool {
         true
     }
+
+    fn info(&self) -> ConnectionInfo {
+        let mut info = self.stream.get_ref().get_ref().info();
+        let conn = &self.stream.conn;
+
+        info.tls_version = conn.protocol_version().map(|v| match v {
+            ProtocolVersion::TLSv1_2 => "TLSv1.2".to_string(),
+            ProtocolVersion::TLSv1_3 => "TLSv1.3".to_string(),
+            v => format!("{:?}", v),
+        });
+        info.tls_cipher = conn
+            .negotiated_cipher_suite()
+            .map(|s| format!("{:?}", s.suite()));
+        info.alpn = conn.alpn_protocol().map(|p| p.to_vec());
+        info.peer_certificates = self.peer_certificates.clone();
+
+        info
+    }
+
+    fn peer_addr(&self) -> Option<SocketAddr> {
+        self.stream.get_ref().get_ref().peer_addr()
+    }
 }
 
 #[derive(Debug)]
@@ -257,3 +516,68 @@ impl fmt::Debug for RustlsTransport {
             .finish()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn min_and_max_version() {
+        let config = |min, max| {
+            let tls_config = TlsConfig::builder()
+                .min_version(min)
+                .max_version(max)
+                .build();
+            build_config(&tls_config).map(|c| c.config)
+        };
+
+        let both = config(None, None).unwrap();
+        assert!(both.supports_version(ProtocolVersion::TLSv1_2));
+        assert!(both.supports_version(ProtocolVersion::TLSv1_3));
+
+        let only_13 = config(Some(TlsVersion::Tls13), None).unwrap();
+        assert!(!only_13.supports_version(ProtocolVersion::TLSv1_2));
+
+        let only_12 = config(None, Some(TlsVersion::Tls12)).unwrap();
+        assert!(!only_12.supports_version(ProtocolVersion::TLSv1_3));
+
+        let err = config(Some(TlsVersion::Tls13), Some(TlsVersion::Tls12)).unwrap_err();
+        assert!(matches!(err, Error::Tls(_)));
+    }
+
+    #[test]
+    fn crl_next_update_times() {
+        fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+            let mut v = vec![tag, content.len() as u8];
+            v.extend_from_slice(content);
+            v
+        }
+
+        let crl = |next_update: &[u8]| {
+            let tbs = [
+                tlv(0x02, &[1]),
+                tlv(0x30, &[]),
+                tlv(0x30, &[]),
+                tlv(0x17, b"240101000000Z"),
+                next_update.to_vec(),
+            ]
+            .concat();
+            let list = [tlv(0x30, &tbs), tlv(0x30, &[]), tlv(0x03, &[0])].concat();
+            tlv(0x30, &list)
+        };
+
+        let at = |secs| Some(UNIX_EPOCH + Duration::from_secs(secs));
+
+        assert_eq!(
+            crl_next_update(&crl(&tlv(0x18, b"20300615120000Z"))),
+            at(1_907_755_200)
+        );
+        assert_eq!(
+            crl_next_update(&crl(&tlv(0x17, b"491231000000Z"))),
+            at(2_524_521_600)
+        );
+        // Without nextUpdate, the revoked certificates follow.
+        assert_eq!(crl_next_update(&crl(&tlv(0x30, &[]))), None);
+        assert_eq!(crl_next_update(b"\x30\x05"), None);
+    }
+}
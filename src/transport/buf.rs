@@ -54,6 +54,15 @@ pub trait Buffers {
     /// cannot parse it until we got the entire buffer. In this case the transport must
     /// read more data first.
     fn can_use_input(&self) -> bool;
+
+    /// Grow the input buffer to hold `size` bytes, if it is smaller.
+    ///
+    /// Used to receive a response head that doesn't fit the input buffer, up to
+    /// [`max_response_header_size`](crate::config::ConfigBuilder::max_response_header_size).
+    /// Defaults to not growing, which fails such responses.
+    fn input_grow(&mut self, size: usize) {
+        let _ = size;
+    }
 }
 
 /// Default buffer implementation.
@@ -95,7 +104,8 @@ impl LazyBuffers {
         if self.output.len() < self.output_size {
             self.output.resize(self.output_size, 0);
         }
-        if self.input.unconsumed().len() < self.input_size {
+        // Only ever grow, the input might have been grown beyond the size.
+        if self.input.capacity() < self.input_size {
             self.input.resize(self.input_size);
         }
     }
@@ -147,4 +157,11 @@ impl Buffers for LazyBuffers {
     fn can_use_input(&self) -> bool {
         !self.input.unconsumed().is_empty() && self.progress
     }
+
+    fn input_grow(&mut self, size: usize) {
+        self.ensure_allocation();
+        if self.input.capacity() < size {
+            self.input.resize(size);
+        }
+    }
 }
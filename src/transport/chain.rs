@@ -1,3 +1,5 @@
+use http::Uri;
+
 use crate::Error;
 
 use super::{ConnectionDetails, Connector, Transport};
@@ -14,6 +16,10 @@ use super::{ConnectionDetails, Connector, Transport};
 /// transport will be provided to the next as an argument in [`Connector::connect()`].
 ///
 /// The chain is always looped fully. There is no early return.
+///
+/// What each connector did is reported to
+/// [`ConfigBuilder::on_connector_step()`][crate::config::ConfigBuilder::on_connector_step],
+/// and logged at trace level.
 #[derive(Debug)]
 pub struct ChainedConnector {
     chain: Vec<Box<dyn Connector>>,
@@ -31,6 +37,23 @@ impl ChainedConnector {
             chain: chain.into_iter().collect(),
         }
     }
+
+    /// Add a connector first in the chain.
+    ///
+    /// The connector is called before all others, without a chained [`Transport`].
+    pub fn prepend(mut self, connector: impl Connector) -> Self {
+        self.chain.insert(0, connector.boxed());
+        self
+    }
+
+    /// Add a connector last in the chain.
+    ///
+    /// The connector is called after all others, with the resulting [`Transport`]
+    /// to keep, wrap or replace.
+    pub fn append(mut self, connector: impl Connector) -> Self {
+        self.chain.push(connector.boxed());
+        self
+    }
 }
 
 impl Connector for ChainedConnector {
@@ -41,10 +64,301 @@ impl Connector for ChainedConnector {
     ) -> Result<Option<Box<dyn Transport>>, Error> {
         let mut conn = chained;
 
-        for connector in &self.chain {
-            conn = connector.connect(details, conn)?;
+        for (index, connector) in self.chain.iter().enumerate() {
+            let before = conn.as_deref().map(Identity::of);
+
+            let result = connector.connect(details, conn);
+
+            let outcome = match &result {
+                Ok(after) => match (before, after.as_deref().map(Identity::of)) {
+                    (b, a) if b == a => ConnectorOutcome::Skipped,
+                    (_, None) => ConnectorOutcome::Dropped,
+                    (None, Some(_)) => ConnectorOutcome::Connected,
+                    (Some(_), Some(_)) => ConnectorOutcome::Wrapped,
+                },
+                Err(e) => ConnectorOutcome::Failed(e),
+            };
+
+            let step = ConnectorStep {
+                uri: details.uri,
+                index,
+                name: connector.name(),
+                outcome,
+            };
+
+            trace!("{} {}: {:?}", step.index, step.name, step.outcome);
+
+            if let Some(hook) = &details.config.on_connector_step {
+                hook(&step);
+            }
+
+            conn = result?;
         }
 
         Ok(conn)
     }
 }
+
+/// Tells whether a connector passed on the transport it was given.
+///
+/// The address alone isn't enough, since a transport without fields has the
+/// same address as any other, and a replaced transport may reuse the address.
+#[derive(Debug, PartialEq, Eq)]
+struct Identity {
+    addr: *const (),
+    type_name: &'static str,
+    is_tls: bool,
+    is_tunnel: bool,
+}
+
+impl Identity {
+    fn of(t: &dyn Transport) -> Self {
+        Identity {
+            addr: t as *const dyn Transport as *const (),
+            type_name: t.type_name(),
+            is_tls: t.is_tls(),
+            is_tunnel: t.is_tunnel(),
+        }
+    }
+}
+
+/// What a connector in a [`ChainedConnector`] did for a connection.
+///
+/// See [`ConfigBuilder::on_connector_step()`][crate::config::ConfigBuilder::on_connector_step].
+#[derive(Debug, Clone, Copy)]
+#[non_exhaustive]
+pub struct ConnectorStep<'a> {
+    /// The uri being connected to.
+    pub uri: &'a Uri,
+    /// Position of the connector in the chain.
+    pub index: usize,
+    /// The [name](Connector::name) of the connector.
+    pub name: &'a str,
+    /// What the connector did.
+    pub outcome: ConnectorOutcome<'a>,
+}
+
+/// Outcome of a [`ConnectorStep`].
+#[derive(Debug, Clone, Copy)]
+#[non_exhaustive]
+pub enum ConnectorOutcome<'a> {
+    /// Passed on the chained transport as is, or nothing if there was none.
+    Skipped,
+    /// Made a transport where there was none.
+    Connected,
+    /// Wrapped or replaced the chained transport, such as with TLS.
+    Wrapped,
+    /// Dropped the chained transport without making another.
+    Dropped,
+    /// Failed, which ends the chain.
+    Failed(&'a Error),
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::config::Config;
+    use crate::resolver::DefaultResolver;
+    use crate::transport::time::{Duration, Instant};
+    use crate::transport::{MockTransport, NextTimeout};
+    use crate::util::ArrayVec;
+    use crate::Timeout;
+
+    #[derive(Debug)]
+    struct Named(&'static str, Arc<Mutex<Vec<&'static str>>>);
+
+    impl Connector for Named {
+        fn connect(
+            &self,
+            _: &ConnectionDetails,
+            chained: Option<Box<dyn Transport>>,
+        ) -> Result<Option<Box<dyn Transport>>, Error> {
+            self.1.lock().unwrap().push(self.0);
+            Ok(chained)
+        }
+    }
+
+    #[test]
+    fn prepend_and_append() {
+        let order = Arc::new(Mutex::new(vec![]));
+        let named = |n| Named(n, order.clone());
+
+        let chain = ChainedConnector::new([named("b").boxed()])
+            .prepend(named("a"))
+            .append(named("c"));
+
+        let uri = "http://example.test".parse().unwrap();
+        let config = Config::default();
+        let details = ConnectionDetails {
+            uri: &uri,
+            addrs: ArrayVec::from_fn(|_| "0.0.0.0:0".parse().unwrap()),
+            config: &config,
+            server_name: None,
+            resolver: &DefaultResolver::default(),
+            now: Instant::now(),
+            timeout: NextTimeout {
+                after: Duration::NotHappening,
+                reason: Timeout::Global,
+            },
+        };
+
+        chain.connect(&details, None).unwrap();
+        assert_eq!(*order.lock().unwrap(), ["a", "b", "c"]);
+    }
+
+    #[derive(Debug)]
+    struct Connect;
+
+    impl Connector for Connect {
+        fn name(&self) -> &str {
+            "connect"
+        }
+
+        fn connect(
+            &self,
+            _: &ConnectionDetails,
+            chained: Option<Box<dyn Transport>>,
+        ) -> Result<Option<Box<dyn Transport>>, Error> {
+            match chained {
+                Some(_) => Err(Error::ConnectionFailed),
+                None => Ok(Some(Box::new(MockTransport::new(vec![])))),
+            }
+        }
+    }
+
+    #[derive(Debug)]
+    struct Wrap;
+
+    impl Connector for Wrap {
+        fn connect(
+            &self,
+            _: &ConnectionDetails,
+            chained: Option<Box<dyn Transport>>,
+        ) -> Result<Option<Box<dyn Transport>>, Error> {
+            Ok(chained.map(|_| Box::new(MockTransport::new(vec![])) as Box<dyn Transport>))
+        }
+    }
+
+    #[test]
+    fn report_steps() {
+        let steps = Arc::new(Mutex::new(vec![]));
+        let s = steps.clone();
+        let config = Config::builder()
+            .on_connector_step(move |step| {
+                let outcome = format!("{:?}", step.outcome);
+                s.lock().unwrap().push(format!("{} {}", step.name, outcome));
+            })
+            .build();
+
+        let order = Arc::new(Mutex::new(vec![]));
+        let chain = ChainedConnector::new([
+            Named("a", order.clone()).boxed(),
+            Connect.boxed(),
+            Wrap.boxed(),
+            Named("b", order).boxed(),
+            Connect.boxed(),
+            Wrap.boxed(),
+        ]);
+
+        let uri = "http://example.test".parse().unwrap();
+        let details = ConnectionDetails {
+            uri: &uri,
+            addrs: ArrayVec::from_fn(|_| "0.0.0.0:0".parse().unwrap()),
+            config: &config,
+            server_name: None,
+            resolver: &DefaultResolver::default(),
+            now: Instant::now(),
+            timeout: NextTimeout {
+                after: Duration::NotHappening,
+                reason: Timeout::Global,
+            },
+        };
+
+        assert!(chain.connect(&details, None).is_err());
+
+        // The failure ends the chain.
+        assert_eq!(
+            *steps.lock().unwrap(),
+            [
+                "Named Skipped",
+                "connect Connected",
+                "Wrap Wrapped",
+                "Named Skipped",
+                "connect Failed(ConnectionFailed)",
+            ]
+        );
+    }
+
+    /// A transport without fields, a tunnel or not.
+    #[derive(Debug)]
+    struct Empty<const TUNNEL: bool>;
+
+    impl<const TUNNEL: bool> Transport for Empty<TUNNEL> {
+        fn buffers(&mut self) -> &mut dyn crate::transport::Buffers {
+            unreachable!()
+        }
+
+        fn transmit_output(&mut self, _: usize, _: NextTimeout) -> Result<(), Error> {
+            unreachable!()
+        }
+
+        fn await_input(&mut self, _: NextTimeout) -> Result<bool, Error> {
+            unreachable!()
+        }
+
+        fn is_open(&mut self) -> bool {
+            true
+        }
+
+        fn is_tunnel(&self) -> bool {
+            TUNNEL
+        }
+    }
+
+    #[test]
+    fn identity_of_empty_transports() {
+        let empty: Box<dyn Transport> = Box::new(Empty::<false>);
+        let tunnel: Box<dyn Transport> = Box::new(Empty::<true>);
+
+        // Both are at the same dangling address.
+        assert_eq!(
+            &*empty as *const dyn Transport as *const (),
+            &*tunnel as *const dyn Transport as *const ()
+        );
+        assert_ne!(Identity::of(&*empty), Identity::of(&*tunnel));
+        assert_eq!(Identity::of(&*empty), Identity::of(&*empty));
+    }
+
+    #[test]
+    fn remaining_budget() {
+        let uri = "http://example.test".parse().unwrap();
+        let config = Config::default();
+        let mut details = ConnectionDetails {
+            uri: &uri,
+            addrs: ArrayVec::from_fn(|_| "0.0.0.0:0".parse().unwrap()),
+            config: &config,
+            server_name: None,
+            resolver: &DefaultResolver::default(),
+            now: Instant::now(),
+            timeout: NextTimeout {
+                after: Duration::from_secs(10),
+                reason: Timeout::Resolve,
+            },
+        };
+
+        let next = details.remaining(Timeout::Proxy).unwrap();
+        assert_eq!(next.reason, Timeout::Proxy);
+        assert!(next.after <= Duration::from_secs(10));
+
+        // A previous step used up the budget.
+        details.now = Instant::AlreadyHappened;
+        let err = details.remaining(Timeout::Proxy).unwrap_err();
+        assert!(matches!(err, Error::Timeout(Timeout::Proxy)));
+
+        details.timeout.reason = Timeout::Global;
+        let err = details.remaining(Timeout::Proxy).unwrap_err();
+        assert!(matches!(err, Error::Timeout(Timeout::Global)));
+    }
+}
@@ -0,0 +1,408 @@
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::{fmt, io, thread, time};
+
+use crate::util::{fraction, random_u64, Rng};
+use crate::Error;
+
+use super::{Buffers, ConnectionDetails, ConnectionInfo, Connector, NextTimeout, Transport};
+
+/// A network failure injected by the [`FaultConnector`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Fault {
+    /// Refuse the connection, before anything is sent.
+    Refuse,
+    /// Pause before sending or receiving. A pause longer than the timeout ends
+    /// in [`Error::Timeout`].
+    Delay(time::Duration),
+    /// Reset the connection while sending or receiving.
+    Reset,
+    /// Stop receiving, as if the server closed the connection mid-response. Bytes
+    /// already received are kept, the rest is dropped.
+    Truncate,
+}
+
+/// Connector injecting network failures, for testing resilience.
+///
+/// Wraps the chained transport in one that fails according to a probability per
+/// send or receive (per connection for [`Fault::Refuse`]), or on a schedule of
+/// connections. This lets retry logic be tested against failures that are hard
+/// to provoke with a real network.
+///
+/// ```
+/// use std::time::Duration;
+/// use ureq::Agent;
+/// use ureq::config::Config;
+/// use ureq::resolver::DefaultResolver;
+/// use ureq::transport::{DefaultConnector, Fault, FaultConnector};
+///
+/// let faults = FaultConnector::new()
+///     // The first connection is reset.
+///     .schedule(0, Fault::Reset)
+///     // After that, 1 in 20 sends or receives is slow.
+///     .probability(Fault::Delay(Duration::from_secs(2)), 0.05)
+///     .seed(42);
+///
+/// let connector = DefaultConnector::new().append(faults);
+/// let agent = Agent::with_parts(Config::default(), connector, DefaultResolver::default());
+/// ```
+///
+/// Faults are applied to the transport at the end of the chain, which for `https`
+/// is after TLS.
+#[derive(Clone)]
+pub struct FaultConnector {
+    probabilities: Vec<(Fault, f64)>,
+    schedule: Vec<(usize, Fault)>,
+    connections: Arc<AtomicUsize>,
+    rng: Arc<Mutex<Rng>>,
+}
+
+impl FaultConnector {
+    /// Creates a connector without faults.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Inject `fault` with a `probability` between 0.0 and 1.0.
+    ///
+    /// [`Fault::Refuse`] is tried once per connection, the others on each send or
+    /// receive.
+    pub fn probability(mut self, fault: Fault, probability: f64) -> Self {
+        self.probabilities
+            .push((fault, probability.clamp(0.0, 1.0)));
+        self
+    }
+
+    /// Inject `fault` on the connection with this index, counting from 0.
+    ///
+    /// The fault happens once, at the first receive after the request is sent, or
+    /// when connecting for [`Fault::Refuse`].
+    pub fn schedule(mut self, connection: usize, fault: Fault) -> Self {
+        self.schedule.push((connection, fault));
+        self
+    }
+
+    /// Seed the random numbers, to make the injected faults reproducible.
+    ///
+    /// Defaults to a random seed.
+    pub fn seed(self, seed: u64) -> Self {
+        *self.rng.lock().unwrap() = Rng(seed);
+        self
+    }
+
+    /// Number of connections made so far.
+    pub fn connections(&self) -> usize {
+        self.connections.load(Ordering::Relaxed)
+    }
+}
+
+impl Default for FaultConnector {
+    fn default() -> Self {
+        FaultConnector {
+            probabilities: vec![],
+            schedule: vec![],
+            connections: Arc::default(),
+            rng: Arc::new(Mutex::new(Rng(random_u64()))),
+        }
+    }
+}
+
+impl Connector for FaultConnector {
+    fn connect(
+        &self,
+        _details: &ConnectionDetails,
+        chained: Option<Box<dyn Transport>>,
+    ) -> Result<Option<Box<dyn Transport>>, Error> {
+        let Some(transport) = chained else {
+            return Ok(None);
+        };
+
+        let index = self.connections.fetch_add(1, Ordering::Relaxed);
+
+        let scheduled = self
+            .schedule
+            .iter()
+            .find(|(i, _)| *i == index)
+            .map(|(_, f)| *f);
+
+        let refuse = self
+            .probabilities
+            .iter()
+            .any(|(fault, p)| *fault == Fault::Refuse && self.rng.lock().unwrap().roll(*p));
+
+        if refuse || scheduled == Some(Fault::Refuse) {
+            debug!("Inject refused connection {}", index);
+            return Err(io::Error::from(io::ErrorKind::ConnectionRefused).into());
+        }
+
+        let transport = FaultTransport {
+            transport,
+            probabilities: self
+                .probabilities
+                .iter()
+                .filter(|(f, _)| *f != Fault::Refuse)
+                .copied()
+                .collect(),
+            scheduled,
+            rng: self.rng.clone(),
+            truncated: false,
+        };
+
+        Ok(Some(Box::new(transport)))
+    }
+}
+
+/// Transport injecting the faults of a [`FaultConnector`].
+struct FaultTransport {
+    transport: Box<dyn Transport>,
+    probabilities: Vec<(Fault, f64)>,
+    scheduled: Option<Fault>,
+    rng: Arc<Mutex<Rng>>,
+    truncated: bool,
+}
+
+impl FaultTransport {
+    /// Roll for a fault on sending or receiving.
+    fn next_fault(&mut self, receive: bool) -> Option<Fault> {
+        if receive {
+            if let Some(fault) = self.scheduled.take() {
+                return Some(fault);
+            }
+        }
+
+        let mut rng = self.rng.lock().unwrap();
+
+        self.probabilities
+            .iter()
+            .filter(|(f, _)| receive || *f != Fault::Truncate)
+            .find(|(_, p)| rng.roll(*p))
+            .map(|(f, _)| *f)
+    }
+
+    fn inject(&mut self, fault: Option<Fault>, timeout: NextTimeout) -> Result<(), Error> {
+        match fault {
+            Some(Fault::Delay(d)) => {
+                debug!("Inject delay {:?}", d);
+                if d >= *timeout.after {
+                    thread::sleep(*timeout.after);
+                    return Err(Error::Timeout(timeout.reason));
+                }
+                thread::sleep(d);
+            }
+            Some(Fault::Reset) => {
+                debug!("Inject reset");
+                self.truncated = true;
+                return Err(io::Error::from(io::ErrorKind::ConnectionReset).into());
+            }
+            Some(Fault::Truncate) => {
+                debug!("Inject truncate");
+                self.truncated = true;
+            }
+            Some(Fault::Refuse) | None => {}
+        }
+
+        Ok(())
+    }
+}
+
+impl Transport for FaultTransport {
+    fn buffers(&mut self) -> &mut dyn Buffers {
+        self.transport.buffers()
+    }
+
+    fn transmit_output(&mut self, amount: usize, timeout: NextTimeout) -> Result<(), Error> {
+        let fault = self.next_fault(false);
+        self.inject(fault, timeout)?;
+        self.transport.transmit_output(amount, timeout)
+    }
+
+    fn await_input(&mut self, timeout: NextTimeout) -> Result<bool, Error> {
+        if !self.truncated {
+            let fault = self.next_fault(true);
+            self.inject(fault, timeout)?;
+        }
+
+        if self.truncated {
+            // Like a closed connection, nothing more arrives.
+            return Ok(false);
+        }
+
+        self.transport.await_input(timeout)
+    }
+
+    fn is_open(&mut self) -> bool {
+        !self.truncated && self.transport.is_open()
+    }
+
+    fn is_tls(&self) -> bool {
+        self.transport.is_tls()
+    }
+
+    fn is_tunnel(&self) -> bool {
+        self.transport.is_tunnel()
+    }
+
+    fn info(&self) -> ConnectionInfo {
+        self.transport.info()
+    }
+
+    fn peer_addr(&self) -> Option<SocketAddr> {
+        self.transport.peer_addr()
+    }
+}
+
+impl Rng {
+    /// True with the `probability`.
+    fn roll(&mut self, probability: f64) -> bool {
+        fraction(self.next_u64()) < probability
+    }
+}
+
+impl fmt::Debug for FaultConnector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FaultConnector")
+            .field("probabilities", &self.probabilities)
+            .field("schedule", &self.schedule)
+            .finish()
+    }
+}
+
+impl fmt::Debug for FaultTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FaultTransport")
+            .field("transport", &self.transport)
+            .field("truncated", &self.truncated)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::Config;
+    use crate::transport::{ChainedConnector, MockConnector};
+    use crate::Agent;
+
+    fn mock_agent(faults: FaultConnector) -> Agent {
+        let mock =
+            MockConnector::new().route("/", "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok");
+        let connector = ChainedConnector::new([mock.clone().boxed(), faults.boxed()]);
+        Agent::with_parts(Config::default(), connector, mock)
+    }
+
+    fn is_io(err: &Error, kind: io::ErrorKind) -> bool {
+        matches!(err, Error::Io(e) if e.kind() == kind)
+    }
+
+    #[test]
+    fn scheduled_faults() {
+        let faults = FaultConnector::new()
+            .schedule(0, Fault::Refuse)
+            .schedule(1, Fault::Reset)
+            .schedule(2, Fault::Truncate);
+        let agent = mock_agent(faults.clone());
+
+        let call = || agent.get("http://fault.test/").call();
+
+        let err = call().unwrap_err();
+        assert!(is_io(&err, io::ErrorKind::ConnectionRefused), "{:?}", err);
+        let err = call().unwrap_err();
+        assert!(is_io(&err, io::ErrorKind::ConnectionReset), "{:?}", err);
+        assert!(call().is_err());
+
+        let mut res = call().unwrap();
+        assert_eq!(res.body_mut().read_to_string().unwrap(), "ok");
+        assert_eq!(faults.connections(), 4);
+    }
+
+    #[test]
+    fn retry_after_reset() {
+        use crate::config::RetryPolicy;
+        use std::time::Duration;
+
+        let retrying_agent = |faults: FaultConnector| {
+            let mock =
+                MockConnector::new().route("/", "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok");
+            let connector = ChainedConnector::new([mock.clone().boxed(), faults.boxed()]);
+            let policy = RetryPolicy::new().base_delay(Duration::from_millis(1));
+            let config = Config::builder().retry(Some(policy)).build();
+            Agent::with_parts(config, connector, mock)
+        };
+
+        let faults = FaultConnector::new().schedule(0, Fault::Reset);
+        let agent = retrying_agent(faults.clone());
+        let mut res = agent.get("http://fault.test/").call().unwrap();
+        assert_eq!(res.body_mut().read_to_string().unwrap(), "ok");
+        assert_eq!(faults.connections(), 2);
+
+        // Not idempotent, not retried.
+        let faults = FaultConnector::new().schedule(0, Fault::Reset);
+        let agent = retrying_agent(faults.clone());
+        let err = agent.post("http://fault.test/").send("x").unwrap_err();
+        assert!(is_io(&err, io::ErrorKind::ConnectionReset), "{:?}", err);
+        assert_eq!(faults.connections(), 1);
+    }
+
+    #[test]
+    fn no_retry_past_global_timeout() {
+        use crate::config::RetryPolicy;
+        use std::time::{Duration, Instant};
+
+        let mock = MockConnector::new()
+            .route(
+                "/busy",
+                "HTTP/1.1 503 Service Unavailable\r\nRetry-After: 20\r\n\r\n",
+            )
+            .route("/", "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok");
+        let faults = FaultConnector::new().schedule(0, Fault::Reset);
+        let connector = ChainedConnector::new([mock.clone().boxed(), faults.clone().boxed()]);
+        let policy = RetryPolicy::new()
+            .base_delay(Duration::from_secs(10))
+            .max_sleep(Duration::from_secs(60));
+        let config = Config::builder()
+            .retry(Some(policy))
+            .timeout_global(Some(Duration::from_secs(1)))
+            .http_status_as_error(false)
+            .build();
+        let agent = Agent::with_parts(config, connector, mock);
+
+        let start = Instant::now();
+
+        // The backoff after the reset doesn't fit in the global timeout.
+        let err = agent.get("http://fault.test/").call().unwrap_err();
+        assert!(is_io(&err, io::ErrorKind::ConnectionReset), "{:?}", err);
+        assert_eq!(faults.connections(), 1);
+
+        // Neither does the Retry-After, the 503 is returned.
+        let res = agent.get("http://fault.test/busy").call().unwrap();
+        assert_eq!(res.status(), 503);
+        assert_eq!(faults.connections(), 2);
+
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn probabilities_are_reproducible() {
+        let outcomes = |seed| {
+            let faults = FaultConnector::new()
+                .probability(Fault::Refuse, 0.5)
+                .seed(seed);
+            let agent = mock_agent(faults);
+            (0..20)
+                .map(|_| agent.get("http://fault.test/").call().is_ok())
+                .collect::<Vec<_>>()
+        };
+
+        let first = outcomes(7);
+        assert_eq!(first, outcomes(7));
+        assert!(first.contains(&true) && first.contains(&false));
+
+        // Never and always.
+        let agent = mock_agent(FaultConnector::new().probability(Fault::Reset, 0.0));
+        assert!(agent.get("http://fault.test/").call().is_ok());
+        let agent = mock_agent(FaultConnector::new().probability(Fault::Reset, 1.0));
+        assert!(agent.get("http://fault.test/").call().is_err());
+    }
+}
@@ -0,0 +1,223 @@
+use std::fmt;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+
+use http::Uri;
+
+use crate::config::Config;
+use crate::resolver::{ResolvedSocketAddrs, Resolver};
+use crate::util::ArrayVec;
+use crate::Error;
+
+use super::{Buffers, ConnectionDetails, Connector, LazyBuffers, NextTimeout, Transport};
+
+/// Connector serving canned responses, for testing.
+///
+/// Lets code built on [`Agent`](crate::Agent) be unit tested without a local
+/// HTTP server. Responses are raw HTTP/1.1 bytes, picked by the first route with
+/// a pattern contained in the request uri. Requests without a matching route
+/// fail with [`Error::ConnectionFailed`].
+///
+/// The connector is also a [`Resolver`] that doesn't touch the network, and
+/// clones share routes and the log of requests.
+///
+/// ```
+/// use ureq::Agent;
+/// use ureq::config::Config;
+/// use ureq::transport::MockConnector;
+///
+/// let mock = MockConnector::new()
+///     .route("/hello", "HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello");
+///
+/// let agent = Agent::with_parts(Config::default(), mock.clone(), mock.clone());
+///
+/// let body = agent.get("http://example.test/hello")
+///     .call()?
+///     .body_mut()
+///     .read_to_string()?;
+///
+/// assert_eq!(body, "hello");
+/// assert!(mock.requests()[0].starts_with(b"GET /hello HTTP/1.1\r\n"));
+/// # Ok::<_, ureq::Error>(())
+/// ```
+#[derive(Clone, Default)]
+pub struct MockConnector {
+    // Pattern, response and whether the route is removed after one use.
+    routes: Arc<Mutex<Vec<(String, Arc<[u8]>, bool)>>>,
+    requests: Arc<Mutex<Vec<Arc<Mutex<Vec<u8>>>>>>,
+}
+
+impl MockConnector {
+    /// Creates a connector without routes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serve `response` for requests with `pattern` in the uri.
+    ///
+    /// Routes are tried in the order they were added.
+    pub fn route(self, pattern: impl Into<String>, response: impl AsRef<[u8]>) -> Self {
+        let route = (pattern.into(), response.as_ref().into(), false);
+        self.routes.lock().unwrap().push(route);
+        self
+    }
+
+    /// Serve `response` for the next request with `pattern` in the uri, once.
+    ///
+    /// The route is removed after one use, which lets a sequence of responses be
+    /// served for the same uri by adding them in order.
+    pub fn route_once(self, pattern: impl Into<String>, response: impl AsRef<[u8]>) -> Self {
+        let route = (pattern.into(), response.as_ref().into(), true);
+        self.routes.lock().unwrap().push(route);
+        self
+    }
+
+    /// The raw bytes of each request made, in order.
+    pub fn requests(&self) -> Vec<Vec<u8>> {
+        let requests = self.requests.lock().unwrap();
+        requests.iter().map(|r| r.lock().unwrap().clone()).collect()
+    }
+}
+
+impl Connector for MockConnector {
+    fn connect(
+        &self,
+        details: &ConnectionDetails,
+        chained: Option<Box<dyn Transport>>,
+    ) -> Result<Option<Box<dyn Transport>>, Error> {
+        if chained.is_some() {
+            trace!("Skip");
+            return Ok(chained);
+        }
+
+        let uri = details.uri.to_string();
+
+        let mut routes = self.routes.lock().unwrap();
+        let Some(index) = routes.iter().position(|(p, _, _)| uri.contains(p.as_str())) else {
+            debug!("No mock route for: {}", uri);
+            return Err(Error::ConnectionFailed);
+        };
+
+        let response = if routes[index].2 {
+            routes.remove(index).1
+        } else {
+            routes[index].1.clone()
+        };
+
+        let config = details.config;
+        let mut transport = MockTransport::new(response.to_vec());
+        transport.buffers = LazyBuffers::new(config.input_buffer_size, config.output_buffer_size);
+
+        self.requests
+            .lock()
+            .unwrap()
+            .push(transport.written.clone());
+
+        Ok(Some(Box::new(transport)))
+    }
+}
+
+impl Resolver for MockConnector {
+    fn resolve(
+        &self,
+        _uri: &Uri,
+        _config: &Config,
+        _timeout: NextTimeout,
+    ) -> Result<ResolvedSocketAddrs, Error> {
+        let mut v = ArrayVec::from_fn(|_| SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)));
+        v.push(SocketAddr::from((Ipv4Addr::LOCALHOST, 80)));
+        Ok(v)
+    }
+}
+
+/// Transport serving a canned response, for testing.
+///
+/// Reading gives the response followed by end of file, regardless of what is
+/// written. The transport is never reused.
+///
+/// Used by [`MockConnector`], or on its own via
+/// [`Agent::request_on()`](crate::Agent::request_on).
+pub struct MockTransport {
+    buffers: LazyBuffers,
+    response: Vec<u8>,
+    read: usize,
+    written: Arc<Mutex<Vec<u8>>>,
+}
+
+impl MockTransport {
+    /// Creates a transport that responds with `response`.
+    pub fn new(response: impl Into<Vec<u8>>) -> Self {
+        MockTransport {
+            buffers: LazyBuffers::new(16 * 1024, 16 * 1024),
+            response: response.into(),
+            read: 0,
+            written: Arc::default(),
+        }
+    }
+}
+
+impl Transport for MockTransport {
+    fn buffers(&mut self) -> &mut dyn Buffers {
+        &mut self.buffers
+    }
+
+    fn transmit_output(&mut self, amount: usize, _timeout: NextTimeout) -> Result<(), Error> {
+        let output = &self.buffers.output()[..amount];
+        self.written.lock().unwrap().extend_from_slice(output);
+        Ok(())
+    }
+
+    fn await_input(&mut self, _timeout: NextTimeout) -> Result<bool, Error> {
+        if self.buffers.can_use_input() {
+            return Ok(true);
+        }
+
+        let left = &self.response[self.read..];
+        let input = self.buffers.input_append_buf();
+        let amount = left.len().min(input.len());
+        input[..amount].copy_from_slice(&left[..amount]);
+        self.buffers.input_appended(amount);
+        self.read += amount;
+
+        Ok(amount > 0)
+    }
+
+    fn is_open(&mut self) -> bool {
+        // One response per connection.
+        false
+    }
+}
+
+impl fmt::Debug for MockConnector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MockConnector").finish()
+    }
+}
+
+impl fmt::Debug for MockTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MockTransport")
+            .field("len", &self.response.len())
+            .field("read", &self.read)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Agent;
+
+    #[test]
+    fn mock_no_route() {
+        let mock = MockConnector::new().route("/a", "HTTP/1.1 204 No Content\r\n\r\n");
+        let agent = Agent::with_parts(Config::default(), mock.clone(), mock.clone());
+
+        let res = agent.get("http://example.test/a").call().unwrap();
+        assert_eq!(res.status(), 204);
+
+        let err = agent.get("http://example.test/b").call().unwrap_err();
+        assert!(matches!(err, Error::ConnectionFailed));
+        assert_eq!(mock.requests().len(), 1);
+    }
+}
@@ -22,6 +22,11 @@
 //! up a chain of concrete connectors.
 
 use std::fmt::Debug;
+use std::fs::File;
+use std::io::IoSlice;
+use std::net::SocketAddr;
+#[cfg(feature = "_tls")]
+use std::sync::Arc;
 
 use http::uri::Scheme;
 use http::Uri;
@@ -29,7 +34,9 @@ use http::Uri;
 use crate::config::Config;
 use crate::proxy::Proto;
 use crate::resolver::{ResolvedSocketAddrs, Resolver};
-use crate::Error;
+#[cfg(feature = "_tls")]
+use crate::tls::Certificate;
+use crate::{Error, Timeout};
 
 pub use self::tcp::TcpConnector;
 use self::time::Instant;
@@ -43,18 +50,32 @@ mod io;
 pub use io::TransportAdapter;
 
 mod chain;
-pub use chain::ChainedConnector;
+pub use chain::{ChainedConnector, ConnectorOutcome, ConnectorStep};
+
+mod throttle;
+pub use throttle::{ThrottleConnector, ThrottledTransport};
+
+mod fault;
+pub use fault::{Fault, FaultConnector};
+
+mod mock;
+pub use mock::{MockConnector, MockTransport};
 
 #[cfg(feature = "_test")]
 mod test;
 #[cfg(feature = "_test")]
-pub use test::set_handler;
+pub use test::{set_handler, set_handler_fn};
 
 #[cfg(feature = "socks-proxy")]
 mod socks;
 #[cfg(feature = "socks-proxy")]
 pub use self::socks::SocksConnector;
 
+#[cfg(unix)]
+mod unix;
+#[cfg(unix)]
+pub use self::unix::UnixProxyConnector;
+
 pub use crate::proxy::ConnectProxyConnector;
 
 pub mod time;
@@ -75,6 +96,15 @@ pub use crate::timings::NextTimeout;
 ///
 /// The built-in connectors provide SOCKS, TCP sockets and TLS wrapping.
 pub trait Connector: Debug + Send + Sync + 'static {
+    /// Name of the connector, as reported by [`ChainedConnector`].
+    ///
+    /// Defaults to the name of the type, such as `TcpConnector`.
+    fn name(&self) -> &str {
+        let name = std::any::type_name::<Self>();
+        let name = name.split('<').next().unwrap_or(name);
+        name.rsplit("::").next().unwrap_or(name)
+    }
+
     /// Helper to quickly box a transport.
     #[doc(hidden)]
     fn boxed(self) -> Box<dyn Connector>
@@ -115,6 +145,12 @@ pub struct ConnectionDetails<'a> {
     /// The Agent configuration.
     pub config: &'a Config,
 
+    /// TLS server name (SNI) to use instead of the host in `uri`, if any.
+    ///
+    /// This is set when the request has a `Host` header and the TLS config
+    /// has `sni_from_host_header` enabled.
+    pub server_name: Option<&'a str>,
+
     /// The resolver configured on [`Agent`](crate::Agent).
     ///
     /// Typically the IP address of the host in the uri is already resolved to the `addr`
@@ -126,7 +162,9 @@ pub struct ConnectionDetails<'a> {
     pub now: Instant,
 
     /// The next timeout for making the connection.
-    // TODO(martin): Make mechanism to lower duration for each step in the connector chain.
+    ///
+    /// This is the budget of the entire connector chain, counted from `now`. See
+    /// [`ConnectionDetails::remaining()`] for what is left of it in a step of the chain.
     pub timeout: NextTimeout,
 }
 
@@ -144,6 +182,57 @@ impl<'a> ConnectionDetails<'a> {
 
         self.uri.scheme() == Some(&Scheme::HTTPS)
     }
+
+    /// The host to wrap the `chained` transport in TLS for, if any.
+    ///
+    /// Through a CONNECT proxy, that is the proxy for an `https` proxy before the
+    /// tunnel, and the server for an `https` uri on top of the tunnel. Proxies
+    /// [chained](crate::Proxy::chain) after the first are never TLS.
+    ///
+    /// IPv6 addresses are without the brackets of the uri, which makes the TLS
+    /// connectors treat them as IP addresses rather than DNS names.
+    pub(crate) fn tls_host(&self, chained: &dyn Transport) -> Option<&'a str> {
+        if chained.is_tls() {
+            return None;
+        }
+
+        if let Some(p) = &self.config.proxy {
+            if p.tunnels() && !chained.is_tunnel() {
+                let proxy_host = p.uri().host();
+                return proxy_host
+                    .filter(|_| p.proto() == Proto::Https)
+                    .map(unbracket);
+            }
+        }
+
+        if self.uri.scheme() != Some(&Scheme::HTTPS) {
+            return None;
+        }
+
+        self.server_name.or_else(|| self.uri.host()).map(unbracket)
+    }
+
+    /// The time left of the [`timeout`](Self::timeout) for a step in the connector chain.
+    ///
+    /// All connectors share one deadline, so a slow step, such as a TCP connect, leaves
+    /// less time to the steps after it. Unless the global or per call timeout is the one
+    /// running out, the reason of the timeout is `phase`, which tells which step was too
+    /// slow. Errors with [`Error::Timeout`] if the deadline has already passed.
+    pub fn remaining(&self, phase: Timeout) -> Result<NextTimeout, Error> {
+        let reason = match self.timeout.reason {
+            r @ (Timeout::Global | Timeout::PerCall) => r,
+            _ => phase,
+        };
+
+        let deadline = self.now + self.timeout.after;
+        let after = deadline.duration_since(Instant::now());
+
+        if after.is_zero() {
+            return Err(Error::Timeout(reason));
+        }
+
+        Ok(NextTimeout { after, reason })
+    }
 }
 
 /// Transport of HTTP/1.1 as created by a [`Connector`].
@@ -185,6 +274,59 @@ pub trait Transport: Debug + Send + Sync {
     /// If that happens the transport must return an [`Error::Timeout`] instance.
     fn transmit_output(&mut self, amount: usize, timeout: NextTimeout) -> Result<(), Error>;
 
+    /// Transmit `amount` of the output buffer followed by `bufs`, preferably in one write.
+    ///
+    /// ureq uses this to send the end of the request head together with a body that is
+    /// in memory, which saves copying the body to the output buffer, and sending the
+    /// head in a small TCP segment of its own. Transports on top of a socket should
+    /// override this with a vectored write.
+    ///
+    /// The default implementation copies `bufs` to the output buffer after `amount`,
+    /// and makes a [`Transport::transmit_output()`] for each time the buffer is full.
+    fn transmit_output_vectored(
+        &mut self,
+        amount: usize,
+        bufs: &[IoSlice<'_>],
+        timeout: NextTimeout,
+    ) -> Result<(), Error> {
+        let mut amount = amount;
+
+        for buf in bufs {
+            let mut data: &[u8] = buf;
+
+            while !data.is_empty() {
+                let output = &mut self.buffers().output()[amount..];
+
+                if output.is_empty() {
+                    self.transmit_output(amount, timeout)?;
+                    amount = 0;
+                    continue;
+                }
+
+                let n = output.len().min(data.len());
+                output[..n].copy_from_slice(&data[..n]);
+                amount += n;
+                data = &data[n..];
+            }
+        }
+
+        self.transmit_output(amount, timeout)
+    }
+
+    /// Send `len` bytes of `file`, from its current position, straight to the connection.
+    ///
+    /// Returns `false`, without sending anything, if the transport can't. ureq then reads
+    /// the file via the buffers. This is used for bodies from
+    /// [`SendBody::from_file()`](crate::SendBody::from_file) that aren't chunked.
+    ///
+    /// Transports directly on a socket can override this with [`std::io::copy()`], which
+    /// on Linux uses `copy_file_range` or `sendfile` to avoid copying through user space.
+    /// It's an error if the file ends before `len`. Defaults to `false`.
+    fn send_file(&mut self, file: &File, len: u64, timeout: NextTimeout) -> Result<bool, Error> {
+        let _ = (file, len, timeout);
+        Ok(false)
+    }
+
     /// Await input from the transport. The transport should internally use
     /// [`Buffers::input_append_buf()`] followed by [`Buffers::input_appended()`] to
     /// store the incoming data.
@@ -200,6 +342,91 @@ pub trait Transport: Debug + Send + Sync {
     fn is_tls(&self) -> bool {
         false
     }
+
+    /// Whether the transport is a tunnel through a CONNECT proxy.
+    ///
+    /// TLS below the tunnel is for the proxy, TLS on top of it for the server.
+    /// Defaults to `false`.
+    fn is_tunnel(&self) -> bool {
+        false
+    }
+
+    /// What is known about the connection, such as the peer address and TLS version.
+    ///
+    /// Wrapping transports, like TLS, should fill in their part of the info of the
+    /// transport they wrap. Defaults to no info.
+    fn info(&self) -> ConnectionInfo {
+        ConnectionInfo::default()
+    }
+
+    /// Address of the server, or of the proxy when using one.
+    ///
+    /// The same as the `peer_addr` of [`Transport::info()`], without making the
+    /// rest of the info. Wrapping transports should pass it on.
+    fn peer_addr(&self) -> Option<SocketAddr> {
+        self.info().peer_addr
+    }
+
+    /// The name of the transport type, to tell whether a connector in a chain
+    /// passed on the transport it was given.
+    ///
+    /// Not meant to be overridden.
+    #[doc(hidden)]
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+}
+
+/// Details of a connection, as negotiated with the server.
+///
+/// Provided by [`Transport::info()`], and set on each response as an extension, which
+/// is useful to log which server and protocol handled a request.
+///
+/// ```
+/// use ureq::ResponseExt;
+///
+/// let response = ureq::get("https://httpbin.org/get").call()?;
+///
+/// if let Some(info) = response.connection_info() {
+///     println!("{:?} via {:?}", info.peer_addr, info.tls_version);
+/// }
+/// # Ok::<_, ureq::Error>(())
+/// ```
+///
+/// Any part that isn't known is `None`. For instance, native-tls doesn't tell the
+/// TLS version and cipher. Through a CONNECT proxy, the TLS parts are of the
+/// connection to the server.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ConnectionInfo {
+    /// Address of the server, or of the proxy when using one.
+    pub peer_addr: Option<SocketAddr>,
+
+    /// Local address of the socket.
+    pub local_addr: Option<SocketAddr>,
+
+    /// The TLS protocol version, such as `TLSv1.3`.
+    pub tls_version: Option<String>,
+
+    /// The TLS cipher suite, such as `TLS13_AES_128_GCM_SHA256`.
+    pub tls_cipher: Option<String>,
+
+    /// The protocol agreed via ALPN, such as `http/1.1`.
+    pub alpn: Option<Vec<u8>>,
+
+    /// The certificate chain of the server, starting with its own certificate.
+    ///
+    /// native-tls only gives the server's own certificate. Copied once per connection,
+    /// and shared by its responses.
+    #[cfg(feature = "_tls")]
+    pub peer_certificates: Option<Arc<[Certificate<'static>]>>,
+}
+
+/// The host without the brackets of an IPv6 address, such as `::1` for `[::1]`.
+fn unbracket(host: &str) -> &str {
+    host.strip_prefix('[')
+        .and_then(|h| h.strip_suffix(']'))
+        .unwrap_or(host)
 }
 
 /// Default connector providing TCP sockets, TLS and SOCKS proxy.
@@ -207,14 +434,21 @@ pub trait Transport: Debug + Send + Sync {
 /// This connector is a [`ChainedConnector`] with the following chain:
 ///
 /// 1. [`SocksConnector`] to handle proxy settings if set.
-/// 2. [`TcpConnector`] to open a socket directly if a proxy is not used.
-/// 3. [`RustlsConnector`](crate::tls::RustlsConnector) which wraps the
-///    connection from 1 or 2 in TLS if the scheme is `https` and the
+/// 2. `UnixProxyConnector` to open the socket of a proxy on a Unix socket (unix only).
+/// 3. [`TcpConnector`] to open a socket directly if a proxy is not used.
+/// 4. [`ThrottleConnector`] to limit the rate of the socket, if configured.
+/// 5. [`RustlsConnector`](crate::tls::RustlsConnector) which wraps the
+///    connection from 1 to 3 in TLS if the scheme is `https` and the
 ///    [`TlsConfig`](crate::tls::TlsConfig) indicate we are using **rustls**.
 ///    This is the default TLS provider.
-/// 4. [`NativeTlsConnector`](crate::tls::NativeTlsConnector) which wraps
-///    the connection from 1 or 2 in TLS if the scheme is `https` and
+/// 6. [`NativeTlsConnector`](crate::tls::NativeTlsConnector) which wraps
+///    the connection from 1 to 3 in TLS if the scheme is `https` and
 ///    [`TlsConfig`](crate::tls::TlsConfig) indicate we are using **native-tls**.
+/// 7. [`ConnectProxyConnector`] to do the CONNECT of an HTTP proxy. TLS in 5 and 6
+///    is then for an `https` proxy.
+/// 8. [`RustlsConnector`](crate::tls::RustlsConnector) and
+///    [`NativeTlsConnector`](crate::tls::NativeTlsConnector) again, to wrap the
+///    tunnel of 7 in TLS if the scheme is `https`.
 ///
 #[derive(Debug)]
 pub struct DefaultConnector {
@@ -226,6 +460,36 @@ impl DefaultConnector {
     pub fn new() -> Self {
         Self::default()
     }
+
+    /// Add a connector before the default chain.
+    ///
+    /// A connector that returns a [`Transport`] here, such as a tunnel, is used
+    /// instead of the TCP socket, and is still wrapped in TLS for `https`.
+    ///
+    /// ```
+    /// use ureq::Agent;
+    /// use ureq::config::Config;
+    /// use ureq::resolver::DefaultResolver;
+    /// use ureq::transport::{DefaultConnector, MockConnector};
+    ///
+    /// let connector = DefaultConnector::new().prepend(MockConnector::new());
+    /// let agent = Agent::with_parts(Config::default(), connector, DefaultResolver::default());
+    /// ```
+    pub fn prepend(self, connector: impl Connector) -> Self {
+        DefaultConnector {
+            chain: self.chain.prepend(connector),
+        }
+    }
+
+    /// Add a connector after the default chain.
+    ///
+    /// The connector gets the finished [`Transport`], for instance to wrap it with
+    /// logging.
+    pub fn append(self, connector: impl Connector) -> Self {
+        DefaultConnector {
+            chain: self.chain.append(connector),
+        }
+    }
 }
 
 impl Default for DefaultConnector {
@@ -246,9 +510,16 @@ impl Default for DefaultConnector {
             #[cfg(not(feature = "socks-proxy"))]
             no_proxy::WarnOnNoSocksConnector.boxed(),
             //
+            // A CONNECT proxy on a unix socket, the CONNECT itself is done last.
+            #[cfg(unix)]
+            UnixProxyConnector::default().boxed(),
+            //
             // If we didn't get a socks-proxy, open a Tcp connection
             TcpConnector::default().boxed(),
             //
+            // Limit the rate of the socket, before any TLS.
+            ThrottleConnector.boxed(),
+            //
             // If rustls is enabled, prefer that
             #[cfg(feature = "rustls")]
             crate::tls::RustlsConnector::default().boxed(),
@@ -267,8 +538,14 @@ impl Default for DefaultConnector {
             #[cfg(feature = "_tls")]
             no_tls::WarnOnMissingTlsProvider(crate::tls::TlsProvider::NativeTls).boxed(),
             //
-            // Do the final CONNECT proxy on top of the connection if indicated by config.
+            // Do the CONNECT proxy on top of the connection if indicated by config.
             ConnectProxyConnector.boxed(),
+            //
+            // TLS to the server through the CONNECT tunnel.
+            #[cfg(feature = "rustls")]
+            crate::tls::RustlsConnector::default().boxed(),
+            #[cfg(feature = "native-tls")]
+            crate::tls::NativeTlsConnector::default().boxed(),
         ]);
 
         DefaultConnector { chain }
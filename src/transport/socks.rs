@@ -1,15 +1,16 @@
 use std::fmt;
-use std::net::{SocketAddr, TcpStream};
+use std::net::{IpAddr, SocketAddr, TcpStream};
 use std::sync::mpsc::{self, RecvTimeoutError};
 use std::{io, thread};
 
-use socks::{Socks4Stream, Socks5Stream};
+use socks::{Socks4Stream, Socks5Stream, TargetAddr};
 
-use crate::proxy::{Proto, Proxy};
+use crate::proxy::{Proto, Proxy, ProxyCredentials};
 use crate::resolver::ResolvedSocketAddrs;
 use crate::transport::tcp::TcpTransport;
 use crate::transport::LazyBuffers;
-use crate::Error;
+use crate::util::SchemeExt;
+use crate::{Error, Timeout};
 
 use super::{ConnectionDetails, Connector, NextTimeout, Transport};
 
@@ -42,11 +43,26 @@ impl Connector for SocksConnector {
             return Ok(chained);
         }
 
-        let proxy_addrs = details
-            .resolver
-            .resolve(proxy.uri(), details.config, details.timeout)?;
+        let proxy_addrs = details.resolver.resolve(
+            proxy.uri(),
+            details.config,
+            details.remaining(Timeout::Proxy)?,
+        )?;
 
-        let stream = try_connect(&proxy_addrs, &details.addrs, proxy, details.timeout)?;
+        let creds = match proxy.proto() {
+            Proto::Socks5 => ProxyCredentials::initial(proxy, details.config.proxy_auth.as_deref()),
+            _ => None,
+        };
+
+        let targets = targets(details, proxy)?;
+
+        let stream = try_connect(
+            &proxy_addrs,
+            &targets,
+            proxy,
+            creds.as_ref(),
+            details.remaining(Timeout::Proxy)?,
+        )?;
 
         if details.config.no_delay {
             stream.set_nodelay(true)?;
@@ -62,25 +78,87 @@ impl Connector for SocksConnector {
     }
 }
 
+/// What to ask the proxy to connect to.
+///
+/// That is the server, or the next proxy when [chained](Proxy::chain).
+fn targets(details: &ConnectionDetails, proxy: &Proxy) -> Result<Vec<TargetAddr>, Error> {
+    let next = proxy.chained().first();
+
+    if proxy.proto().resolves_remotely() {
+        let (host, port) = match next {
+            Some(next) => (next.host(), next.port()),
+            None => {
+                let uri = details.uri;
+                let host = uri.host().unwrap_or_default();
+                let port = uri
+                    .port_u16()
+                    .or_else(|| uri.scheme().and_then(|s| s.default_port()))
+                    .unwrap_or(80);
+                (host, port)
+            }
+        };
+
+        let target = match host
+            .trim_matches(|c| c == '[' || c == ']')
+            .parse::<IpAddr>()
+        {
+            Ok(ip) => TargetAddr::Ip(SocketAddr::new(ip, port)),
+            // The proxy resolves the host, also when it's not in our DNS.
+            Err(_) => TargetAddr::Domain(host.to_string(), port),
+        };
+
+        return Ok(vec![target]);
+    }
+
+    let resolved;
+    let addrs: &ResolvedSocketAddrs = match next {
+        Some(next) => {
+            let timeout = details.remaining(Timeout::Proxy)?;
+            resolved = details
+                .resolver
+                .resolve(next.uri(), details.config, timeout)?;
+            &resolved
+        }
+        None => &details.addrs,
+    };
+
+    let targets: Vec<TargetAddr> = addrs
+        .into_iter()
+        // SOCKS4 only has IPv4 addresses.
+        .filter(|a| proxy.proto() != Proto::Socks4 || a.is_ipv4())
+        .map(|a| TargetAddr::Ip(*a))
+        .collect();
+
+    if targets.is_empty() && addrs.len() > 0 {
+        return Err(Error::Io(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "SOCKS4 does not support IPv6, use socks4a://",
+        )));
+    }
+
+    Ok(targets)
+}
+
 fn try_connect(
     proxy_addrs: &ResolvedSocketAddrs,
-    target_addrs: &ResolvedSocketAddrs,
+    target_addrs: &[TargetAddr],
     proxy: &Proxy,
+    creds: Option<&ProxyCredentials>,
     timeout: NextTimeout,
 ) -> Result<TcpStream, Error> {
     for target_addr in target_addrs {
         for proxy_addr in proxy_addrs {
             trace!(
-                "Try connect {} {} -> {}",
+                "Try connect {} {} -> {:?}",
                 proxy.proto(),
                 proxy_addr,
                 target_addr
             );
 
-            match try_connect_single(*proxy_addr, *target_addr, proxy, timeout) {
+            match try_connect_single(*proxy_addr, target_addr, proxy, creds, timeout) {
                 Ok(v) => {
                     debug!(
-                        "{} connected {} -> {}",
+                        "{} connected {} -> {:?}",
                         proxy.proto(),
                         proxy_addr,
                         target_addr
@@ -89,7 +167,11 @@ fn try_connect(
                 }
                 // Intercept ConnectionRefused to try next addrs
                 Err(Error::Io(e)) if e.kind() == io::ErrorKind::ConnectionRefused => {
-                    trace!("{} -> {} proxy connection refused", proxy_addr, target_addr);
+                    trace!(
+                        "{} -> {:?} proxy connection refused",
+                        proxy_addr,
+                        target_addr
+                    );
                     continue;
                 }
                 // Other errors bail
@@ -107,20 +189,30 @@ fn try_connect(
 
 fn try_connect_single(
     proxy_addr: SocketAddr,
-    target_addr: SocketAddr,
+    target_addr: &TargetAddr,
     proxy: &Proxy,
+    creds: Option<&ProxyCredentials>,
     timeout: NextTimeout,
 ) -> Result<TcpStream, Error> {
     // The async behavior is only used if we want to time cap connecting.
     let use_sync = timeout.after.is_not_happening();
 
     if use_sync {
-        connect_proxy(proxy, proxy_addr, target_addr)
+        connect_proxy(proxy, creds, proxy_addr, target_addr)
     } else {
         let (tx, rx) = mpsc::sync_channel(1);
         let proxy = proxy.clone();
+        let creds = creds.cloned();
+        let target_addr = target_addr.clone();
 
-        thread::spawn(move || tx.send(connect_proxy(&proxy, proxy_addr, target_addr)));
+        thread::spawn(move || {
+            tx.send(connect_proxy(
+                &proxy,
+                creds.as_ref(),
+                proxy_addr,
+                &target_addr,
+            ))
+        });
 
         match rx.recv_timeout(*timeout.after) {
             Ok(v) => v,
@@ -132,21 +224,27 @@ fn try_connect_single(
 
 fn connect_proxy(
     proxy: &Proxy,
+    creds: Option<&ProxyCredentials>,
     proxy_addr: SocketAddr,
-    target_addr: SocketAddr,
+    target_addr: &TargetAddr,
 ) -> Result<TcpStream, Error> {
+    let target_addr = target_addr.clone();
+
     let stream = match proxy.proto() {
         Proto::Socks4 | Proto::Socks4A => {
-            if proxy.username().is_some() {
-                warn!("SOCKS4 does not support username/password");
+            if proxy.password().is_some() {
+                warn!("SOCKS4 does not support passwords, only a user id");
             }
 
-            Socks4Stream::connect(proxy_addr, target_addr, "")?.into_inner()
+            // SOCKS4a sends a domain target as is, SOCKS4 only has addresses.
+            let user_id = proxy.username().unwrap_or_default();
+
+            Socks4Stream::connect(proxy_addr, target_addr, user_id)?.into_inner()
         }
         Proto::Socks5 => {
-            if let Some(username) = proxy.username() {
-                // Connect with authentication.
-                let password = proxy.password().unwrap_or("");
+            if let Some(creds) = creds {
+                // Offer username/password authentication, the proxy decides.
+                let (username, password) = (creds.username(), creds.password());
 
                 Socks5Stream::connect_with_password(proxy_addr, target_addr, username, password)?
             } else {
@@ -165,3 +263,36 @@ impl fmt::Debug for SocksConnector {
         f.debug_struct("SocksConnector").finish()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    #[test]
+    fn socks4a_sends_domain() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let proxy_addr = listener.local_addr().unwrap();
+
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 64];
+            let n = stream.read(&mut buf).unwrap();
+            // Request granted.
+            stream.write_all(&[0, 90, 0, 0, 0, 0, 0, 0]).unwrap();
+            buf[..n].to_vec()
+        });
+
+        let proxy = Proxy::new(&format!("socks4a://me@{}", proxy_addr)).unwrap();
+        let target = TargetAddr::Domain("example.test".into(), 8080);
+
+        connect_proxy(&proxy, None, proxy_addr, &target).unwrap();
+
+        // Version 4, connect, port 8080, ip 0.0.0.1, user id and the domain.
+        assert_eq!(
+            server.join().unwrap(),
+            b"\x04\x01\x1f\x90\0\0\0\x01me\0example.test\0"
+        );
+    }
+}
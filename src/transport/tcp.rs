@@ -1,14 +1,20 @@
-use std::io::{Read, Write};
-use std::net::{SocketAddr, TcpStream};
-use std::{fmt, io, time};
+use std::fs::File;
+use std::io::{IoSlice, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpStream};
+use std::sync::{mpsc, Arc};
+use std::{fmt, io, thread, time};
+
+use socket2::{SockRef, TcpKeepalive};
 
 use crate::config::Config;
+use crate::family::{self, Family, FamilyEvent};
 use crate::resolver::ResolvedSocketAddrs;
-use crate::transport::time::Duration;
-use crate::util::IoResultExt;
-use crate::Error;
+use crate::transport::time::{Duration, Instant};
+use crate::util::{write_all_vectored, IoResultExt};
+use crate::{Error, Timeout};
 
-use super::{Buffers, ConnectionDetails, Connector, LazyBuffers, NextTimeout, Transport};
+use super::{Buffers, ConnectionDetails, ConnectionInfo, Connector, LazyBuffers};
+use super::{NextTimeout, Transport};
 
 #[derive(Default)]
 /// Connector for regular TCP sockets.
@@ -28,7 +34,7 @@ impl Connector for TcpConnector {
         }
 
         let config = &details.config;
-        let stream = try_connect(&details.addrs, details.timeout, config)?;
+        let stream = try_connect(&details.addrs, details.remaining(Timeout::Connect)?, config)?;
 
         let buffers = LazyBuffers::new(config.input_buffer_size, config.output_buffer_size);
         let transport = TcpTransport::new(stream, buffers);
@@ -42,17 +48,54 @@ fn try_connect(
     timeout: NextTimeout,
     config: &Config,
 ) -> Result<TcpStream, Error> {
+    let has_ipv4 = addrs.into_iter().any(|a| a.is_ipv4());
+    let has_ipv6 = addrs.into_iter().any(|a| a.is_ipv6());
+
+    if let Some(delay) = config.happy_eyeballs.filter(|_| has_ipv4 && has_ipv6) {
+        return race(interleave(addrs), delay, timeout, config);
+    }
+
+    let counters = &config.family_counters;
+
+    // Last family that failed, to tell when we fall back to the other.
+    let mut failed = None;
+
     for addr in addrs {
+        let family = Family::of(addr);
+
         match try_connect_single(*addr, timeout, config) {
             // First that connects
-            Ok(v) => return Ok(v),
+            Ok(v) => {
+                counters.connected(family);
+
+                if let Some(f) = failed.filter(|f| *f != family) {
+                    family::emit(
+                        config,
+                        FamilyEvent::Fallback {
+                            failed: f,
+                            used: family,
+                        },
+                    );
+                }
+
+                return Ok(v);
+            }
             // Intercept ConnectionRefused to try next addrs
             Err(Error::Io(e)) if e.kind() == io::ErrorKind::ConnectionRefused => {
                 trace!("{} connection refused", addr);
+                if let Some(event) = counters.failed(family) {
+                    family::emit(config, event);
+                }
+                failed = Some(family);
                 continue;
             }
             // Other errors bail
-            Err(e) => return Err(e),
+            Err(e) => {
+                if let Some(event) = counters.failed(family) {
+                    family::emit(config, event);
+                }
+                return Err(e);
+            }
         }
     }
 
@@ -63,6 +106,149 @@ fn try_connect(
     )))
 }
 
+fn shut_down_loser(addr: SocketAddr, stream: TcpStream) {
+    trace!("Happy eyeballs shut down losing connection to {}", addr);
+    let _ = stream.shutdown(Shutdown::Both);
+}
+
+/// Alternate the address families, starting with that of the first address.
+///
+/// As recommended by RFC 8305 section 4.
+fn interleave(addrs: &ResolvedSocketAddrs) -> Vec<SocketAddr> {
+    let Some(first) = addrs.into_iter().next().map(Family::of) else {
+        return vec![];
+    };
+
+    let (preferred, other): (Vec<SocketAddr>, Vec<SocketAddr>) = addrs
+        .into_iter()
+        .copied()
+        .partition(|a| Family::of(a) == first);
+
+    let mut preferred = preferred.into_iter();
+    let mut other = other.into_iter();
+
+    let mut out = vec![];
+    loop {
+        match (preferred.next(), other.next()) {
+            (None, None) => break,
+            (a, b) => out.extend(a.into_iter().chain(b)),
+        }
+    }
+    out
+}
+
+/// Happy Eyeballs (RFC 8305). Start a connection attempt every `delay`, or as soon as
+/// the previous attempt fails, and use the first that connects.
+///
+/// Attempts still connecting when another wins are left to finish in the background,
+/// and shut down if they connect.
+fn race(
+    addrs: Vec<SocketAddr>,
+    delay: time::Duration,
+    timeout: NextTimeout,
+    config: &Config,
+) -> Result<TcpStream, Error> {
+    let counters = &config.family_counters;
+    let deadline = Instant::now() + timeout.after;
+
+    let (tx, rx) = mpsc::channel();
+
+    // One copy for all attempts, rather than one per thread.
+    let shared = Arc::new(config.clone());
+
+    let mut addrs = addrs.into_iter();
+    let mut next = addrs.next();
+    let mut pending = 0;
+
+    let mut failed = None;
+    let mut last_err = None;
+
+    loop {
+        if let Some(addr) = next.take() {
+            let timeout = NextTimeout {
+                after: deadline.duration_since(Instant::now()),
+                reason: timeout.reason,
+            };
+            let tx = tx.clone();
+            let config = shared.clone();
+
+            thread::spawn(move || {
+                let result = try_connect_single(addr, timeout, &config);
+                // The receiver is gone if another attempt won the race.
+                if let Err(mpsc::SendError((_, Ok(stream)))) = tx.send((addr, result)) {
+                    shut_down_loser(addr, stream);
+                }
+            });
+
+            pending += 1;
+        }
+
+        if pending == 0 {
+            break;
+        }
+
+        let received = if addrs.len() > 0 {
+            rx.recv_timeout(delay).ok()
+        } else {
+            // Nothing more to start, wait for the pending attempts.
+            rx.recv().ok()
+        };
+
+        let Some((addr, result)) = received else {
+            trace!("Happy eyeballs delay passed, start next attempt");
+            next = addrs.next();
+            continue;
+        };
+
+        pending -= 1;
+        let family = Family::of(&addr);
+
+        match result {
+            Ok(stream) => {
+                counters.connected(family);
+
+                if let Some(f) = failed.filter(|f| *f != family) {
+                    family::emit(
+                        config,
+                        FamilyEvent::Fallback {
+                            failed: f,
+                            used: family,
+                        },
+                    );
+                }
+
+                debug!("Happy eyeballs connected to {}", addr);
+
+                // Attempts that connected while we were busy with this one.
+                for (addr, result) in rx.try_iter() {
+                    if let Ok(stream) = result {
+                        shut_down_loser(addr, stream);
+                    }
+                }
+
+                return Ok(stream);
+            }
+            Err(e) => {
+                trace!("{} failed: {}", addr, e);
+                if let Some(event) = counters.failed(family) {
+                    family::emit(config, event);
+                }
+                failed = Some(family);
+                last_err = Some(e);
+                next = addrs.next();
+            }
+        }
+    }
+
+    debug!("Failed to connect to any resolved address");
+    Err(last_err.unwrap_or_else(|| {
+        Error::Io(io::Error::new(
+            io::ErrorKind::ConnectionRefused,
+            "Connection refused",
+        ))
+    }))
+}
+
 fn try_connect_single(
     addr: SocketAddr,
     timeout: NextTimeout,
@@ -89,11 +275,49 @@ fn try_connect_single(
         stream.set_nodelay(true)?;
     }
 
+    if let Some(time) = config.tcp_keepalive {
+        set_keepalive(&stream, time, config)?;
+    }
+
     debug!("Connected TcpStream to {}", addr);
 
     Ok(stream)
 }
 
+fn set_keepalive(stream: &TcpStream, time: time::Duration, config: &Config) -> io::Result<()> {
+    #[allow(unused_mut)]
+    let mut keepalive = TcpKeepalive::new().with_time(time);
+
+    #[cfg(any(
+        target_os = "android",
+        target_os = "freebsd",
+        target_os = "ios",
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "netbsd",
+        target_os = "windows",
+    ))]
+    if let Some(interval) = config.tcp_keepalive_interval {
+        keepalive = keepalive.with_interval(interval);
+    }
+
+    #[cfg(any(
+        target_os = "android",
+        target_os = "freebsd",
+        target_os = "ios",
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "netbsd",
+    ))]
+    if let Some(retries) = config.tcp_keepalive_retries {
+        keepalive = keepalive.with_retries(retries);
+    }
+
+    let _ = config;
+
+    SockRef::from(stream).set_tcp_keepalive(&keepalive)
+}
+
 pub struct TcpTransport {
     stream: TcpStream,
     buffers: LazyBuffers,
@@ -152,6 +376,55 @@ impl Transport for TcpTransport {
         Ok(())
     }
 
+    fn transmit_output_vectored(
+        &mut self,
+        amount: usize,
+        bufs: &[IoSlice<'_>],
+        timeout: NextTimeout,
+    ) -> Result<(), Error> {
+        maybe_update_timeout(
+            timeout,
+            &mut self.timeout_write,
+            &self.stream,
+            TcpStream::set_write_timeout,
+        )?;
+
+        let mut all: Vec<&[u8]> = vec![&self.buffers.output()[..amount]];
+        all.extend(bufs.iter().map(|b| &**b));
+
+        match write_all_vectored(&mut self.stream, &all).normalize_would_block() {
+            Ok(v) => Ok(v),
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => Err(Error::Timeout(timeout.reason)),
+            Err(e) => Err(e.into()),
+        }?;
+
+        Ok(())
+    }
+
+    fn send_file(&mut self, file: &File, len: u64, timeout: NextTimeout) -> Result<bool, Error> {
+        maybe_update_timeout(
+            timeout,
+            &mut self.timeout_write,
+            &self.stream,
+            TcpStream::set_write_timeout,
+        )?;
+
+        // On Linux, std copies from file to socket with copy_file_range or sendfile.
+        let mut reader = file.take(len);
+        let sent = match io::copy(&mut reader, &mut self.stream).normalize_would_block() {
+            Ok(v) => Ok(v),
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => Err(Error::Timeout(timeout.reason)),
+            Err(e) => Err(e.into()),
+        }?;
+
+        if sent < len {
+            let msg = format!("file ended {} bytes short of its length", len - sent);
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, msg).into());
+        }
+
+        Ok(true)
+    }
+
     fn await_input(&mut self, timeout: NextTimeout) -> Result<bool, Error> {
         if self.buffers.can_use_input() {
             return Ok(true);
@@ -179,6 +452,18 @@ impl Transport for TcpTransport {
     fn is_open(&mut self) -> bool {
         probe_tcp_stream(&mut self.stream).unwrap_or(false)
     }
+
+    fn info(&self) -> ConnectionInfo {
+        ConnectionInfo {
+            peer_addr: self.stream.peer_addr().ok(),
+            local_addr: self.stream.local_addr().ok(),
+            ..Default::default()
+        }
+    }
+
+    fn peer_addr(&self) -> Option<SocketAddr> {
+        self.stream.peer_addr().ok()
+    }
 }
 
 fn probe_tcp_stream(stream: &mut TcpStream) -> Result<bool, Error> {
@@ -219,3 +504,211 @@ impl fmt::Debug for TcpTransport {
             .finish()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::ArrayVec;
+    use std::net::TcpListener;
+
+    #[test]
+    fn interleave_families() {
+        let mut addrs: ResolvedSocketAddrs = ArrayVec::from_fn(|_| "0.0.0.0:0".parse().unwrap());
+        for a in ["[::1]:80", "[::2]:80", "[::3]:80", "10.0.0.1:80"] {
+            addrs.push(a.parse().unwrap());
+        }
+
+        let order: Vec<String> = interleave(&addrs).iter().map(|a| a.to_string()).collect();
+        assert_eq!(order, ["[::1]:80", "10.0.0.1:80", "[::2]:80", "[::3]:80"]);
+    }
+
+    fn timeout() -> NextTimeout {
+        NextTimeout {
+            after: Duration::Exact(time::Duration::from_secs(10)),
+            reason: Timeout::Connect,
+        }
+    }
+
+    /// An address nothing listens on, or `None` if the address family isn't available.
+    fn closed_port(ip: &str) -> Option<SocketAddr> {
+        let listener = TcpListener::bind((ip, 0)).ok()?;
+        listener.local_addr().ok()
+    }
+
+    #[test]
+    fn race_uses_first_to_connect() {
+        let a = TcpListener::bind("127.0.0.1:0").unwrap();
+        let b = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addrs = vec![a.local_addr().unwrap(), b.local_addr().unwrap()];
+
+        let delay = time::Duration::from_secs(10);
+        let stream = race(addrs.clone(), delay, timeout(), &Config::default()).unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), addrs[0]);
+    }
+
+    #[test]
+    fn race_moves_on_after_failure() {
+        let open = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addrs = vec![
+            closed_port("127.0.0.1").unwrap(),
+            open.local_addr().unwrap(),
+        ];
+
+        // The next attempt starts when the first fails, without waiting for the delay.
+        let start = time::Instant::now();
+        let delay = time::Duration::from_secs(10);
+        let stream = race(addrs.clone(), delay, timeout(), &Config::default()).unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), addrs[1]);
+        assert!(start.elapsed() < delay);
+    }
+
+    #[test]
+    fn race_family_fallback() {
+        use std::sync::Mutex;
+
+        let Some(closed) = closed_port("::1") else {
+            // No IPv6 on this host.
+            return;
+        };
+        let open = TcpListener::bind("127.0.0.1:0").unwrap();
+
+        let mut addrs: ResolvedSocketAddrs = ArrayVec::from_fn(|_| "0.0.0.0:0".parse().unwrap());
+        addrs.push(closed);
+        addrs.push(open.local_addr().unwrap());
+
+        let events = Arc::new(Mutex::new(vec![]));
+        let events2 = events.clone();
+        let config = Config::builder()
+            .happy_eyeballs(Some(time::Duration::from_secs(10)))
+            .on_family_event(move |e| events2.lock().unwrap().push(*e))
+            .build();
+
+        let stream = try_connect(&addrs, timeout(), &config).unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), open.local_addr().unwrap());
+        assert_eq!(
+            *events.lock().unwrap(),
+            [FamilyEvent::Fallback {
+                failed: Family::Ipv6,
+                used: Family::Ipv4
+            }]
+        );
+
+        let stats = config.family_counters.stats();
+        assert_eq!(stats.ipv6_failures, 1);
+        assert_eq!(stats.ipv4_connects, 1);
+    }
+
+    #[test]
+    fn race_shuts_down_losers() {
+        let mut losers = 0;
+
+        // Which attempt wins, and whether the second even starts, is up to timing.
+        for _ in 0..10 {
+            let a = TcpListener::bind("127.0.0.1:0").unwrap();
+            let b = TcpListener::bind("127.0.0.1:0").unwrap();
+            let addrs = vec![a.local_addr().unwrap(), b.local_addr().unwrap()];
+
+            // Without delay, the attempts start at once.
+            let delay = time::Duration::ZERO;
+            let winner = race(addrs.clone(), delay, timeout(), &Config::default()).unwrap();
+            let loser = if winner.peer_addr().unwrap() == addrs[0] {
+                b
+            } else {
+                a
+            };
+
+            loser.set_nonblocking(true).unwrap();
+            let deadline = time::Instant::now() + time::Duration::from_millis(200);
+            let accepted = loop {
+                match loser.accept() {
+                    Ok((v, _)) => break Some(v),
+                    Err(_) if time::Instant::now() < deadline => {
+                        thread::sleep(time::Duration::from_millis(5))
+                    }
+                    Err(_) => break None,
+                }
+            };
+
+            let Some(mut accepted) = accepted else {
+                continue;
+            };
+
+            losers += 1;
+            accepted.set_nonblocking(false).unwrap();
+            accepted
+                .set_read_timeout(Some(time::Duration::from_secs(5)))
+                .unwrap();
+            assert_eq!(accepted.read(&mut [0; 1]).unwrap(), 0);
+        }
+
+        assert!(losers > 0);
+    }
+
+    #[test]
+    fn info_has_addresses() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (accepted, _) = listener.accept().unwrap();
+
+        let transport = TcpTransport::new(stream, LazyBuffers::new(1024, 1024));
+        let info = transport.info();
+
+        assert_eq!(info.peer_addr, listener.local_addr().ok());
+        assert_eq!(transport.peer_addr(), info.peer_addr);
+        assert_eq!(info.local_addr, accepted.peer_addr().ok());
+        assert_eq!(info.tls_version, None);
+    }
+
+    #[test]
+    fn transmit_vectored() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut accepted, _) = listener.accept().unwrap();
+
+        let mut transport = TcpTransport::new(stream, LazyBuffers::new(1024, 1024));
+        transport.buffers().output()[..5].copy_from_slice(b"head ");
+
+        let timeout = NextTimeout {
+            after: Duration::NotHappening,
+            reason: Timeout::SendRequest,
+        };
+        let bufs = [IoSlice::new(b"body"), IoSlice::new(b""), IoSlice::new(b"!")];
+        transport
+            .transmit_output_vectored(5, &bufs, timeout)
+            .unwrap();
+        drop(transport);
+
+        let mut received = String::new();
+        accepted.read_to_string(&mut received).unwrap();
+        assert_eq!(received, "head body!");
+    }
+
+    #[test]
+    fn send_file() {
+        let path = std::env::temp_dir().join(format!("ureq-sendfile-{}", std::process::id()));
+        std::fs::write(&path, "hello file").unwrap();
+        let file = File::open(&path).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut accepted, _) = listener.accept().unwrap();
+
+        let mut transport = TcpTransport::new(stream, LazyBuffers::new(1024, 1024));
+        let timeout = NextTimeout {
+            after: Duration::NotHappening,
+            reason: Timeout::SendBody,
+        };
+
+        assert!(transport.send_file(&file, 5, timeout).unwrap());
+        // Only 5 bytes are left.
+        let err = transport.send_file(&file, 10, timeout).unwrap_err();
+        assert!(matches!(err, Error::Io(ref e) if e.kind() == io::ErrorKind::UnexpectedEof));
+        drop(transport);
+
+        let mut received = String::new();
+        accepted.read_to_string(&mut received).unwrap();
+        assert_eq!(received, "hello file");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -92,6 +92,15 @@ pub fn set_handler(pattern: &'static str, status: u16, headers: &[(&str, &str)],
     HANDLERS.with(|h| (*h).borrow_mut().push(handler));
 }
 
+/// Helper for **_test** feature tests, with a handler writing the response to each request.
+pub fn set_handler_fn(
+    pattern: &'static str,
+    handler: impl Fn(Uri, Request<()>, &mut dyn Write) -> io::Result<()> + Send + Sync + 'static,
+) {
+    let handler = TestHandler::new(pattern, handler);
+    HANDLERS.with(|h| (*h).borrow_mut().push(handler));
+}
+
 #[derive(Clone)]
 struct TestHandler {
     pattern: &'static str,
@@ -144,12 +153,12 @@ fn setup_default_handlers(handlers: &mut Vec<TestHandler>) {
                 "HTTP/1.1 200 OK\r\n\
                 Content-Type: text/html;charset=ISO-8859-1\r\n\
                 set-cookie: AEC=AVYB7cpadYFS8ZgaioQ17NnxHl1QcSQ_2aH2WEIg1KGDXD5kjk2HhpGVhfk; \
-                    expires=Mon, 14-Apr-2025 17:23:39 GMT; path=/; domain=.google.com; \
+                    expires=Tue, 14-Apr-2099 17:23:39 GMT; path=/; domain=.google.com; \
                     Secure; HttpOnly; SameSite=lax\r\n\
                 set-cookie: __Secure-ENID=23.SE=WaDe-mOBoV2nk-IwHr73boNt6dYcjzQh1X_k8zv2UmUXBL\
                     m80a3pzLJyx1N1NOqBxDDOR8OJyvuNYw5phFf0VnbqzVtcKPijo2FY8O_vymzyc7x2VwFhGlgU\
                     WXSWYinjWL7Zvz_EOcA4kfnEXweW5ZDzLrvaLuBIrz5CA_-454AMIXpDiZAVPChCawbkzMptAr\
-                    lMTikkon2EQVXsicqq1XnrMEMPZR5Ld2JC6lpBM8A; expires=Sun, 16-Nov-2025 09:41:57 \
+                    lMTikkon2EQVXsicqq1XnrMEMPZR5Ld2JC6lpBM8A; expires=Mon, 16-Nov-2099 09:41:57 \
                     GMT; path=/; domain=.google.com; Secure; HttpOnly; SameSite=lax\r\n\
                 \r\n\
                 ureq test server here"
@@ -323,6 +332,49 @@ fn setup_default_handlers(handlers: &mut Vec<TestHandler>) {
         handlers,
     );
 
+    maybe_add(
+        TestHandler::new("/ranged/1000", |_uri, req, w| {
+            let data: Vec<u8> = (0..1000).map(|i| (i % 251) as u8).collect();
+
+            let range = req
+                .headers()
+                .get("range")
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.strip_prefix("bytes="))
+                .and_then(|v| v.split_once('-'))
+                .map(|(a, b)| (a.parse::<usize>().unwrap(), b.parse::<usize>().unwrap()));
+
+            let Some((start, end)) = range else {
+                write!(
+                    w,
+                    "HTTP/1.1 200 OK\r\n\
+                    Accept-Ranges: bytes\r\n\
+                    ETag: \"r1000\"\r\n\
+                    Content-Length: 1000\r\n\
+                    \r\n"
+                )?;
+                if req.method() != Method::HEAD {
+                    w.write_all(&data)?;
+                }
+                return Ok(());
+            };
+
+            write!(
+                w,
+                "HTTP/1.1 206 Partial Content\r\n\
+                ETag: \"r1000\"\r\n\
+                Content-Range: bytes {}-{}/1000\r\n\
+                Content-Length: {}\r\n\
+                \r\n",
+                start,
+                end,
+                end - start + 1
+            )?;
+            w.write_all(&data[start..=end])
+        }),
+        handlers,
+    );
+
     #[cfg(feature = "charset")]
     {
         let (cow, _, _) =
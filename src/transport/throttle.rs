@@ -0,0 +1,193 @@
+use std::net::SocketAddr;
+use std::{fmt, thread, time};
+
+use crate::Error;
+
+use super::{Buffers, ConnectionDetails, ConnectionInfo, Connector, NextTimeout, Transport};
+
+/// Connector wrapping the chained transport in a [`ThrottledTransport`].
+///
+/// This uses [`ConfigBuilder::max_upload_rate()`][crate::config::ConfigBuilder::max_upload_rate]
+/// and [`ConfigBuilder::max_download_rate()`][crate::config::ConfigBuilder::max_download_rate].
+/// Without any of them, the chained transport is kept as is.
+#[derive(Debug, Default)]
+pub struct ThrottleConnector;
+
+impl Connector for ThrottleConnector {
+    fn connect(
+        &self,
+        details: &ConnectionDetails,
+        chained: Option<Box<dyn Transport>>,
+    ) -> Result<Option<Box<dyn Transport>>, Error> {
+        let config = details.config;
+
+        let Some(transport) = chained else {
+            return Ok(None);
+        };
+
+        if config.max_upload_rate.is_none() && config.max_download_rate.is_none() {
+            trace!("Skip");
+            return Ok(Some(transport));
+        }
+
+        let throttled =
+            ThrottledTransport::new(transport, config.max_upload_rate, config.max_download_rate);
+
+        debug!("Throttled {:?}", throttled);
+
+        Ok(Some(Box::new(throttled)))
+    }
+}
+
+/// Transport limiting the bytes per second of another transport.
+///
+/// The rate is kept by pausing after a transfer that went ahead of it, which means
+/// single transfers can be larger than the rate, but on average it's kept. A
+/// transport that was idle doesn't get to catch up on the time it was idle.
+pub struct ThrottledTransport {
+    transport: Box<dyn Transport>,
+    upload: Option<Pacer>,
+    download: Option<Pacer>,
+}
+
+impl ThrottledTransport {
+    /// Wrap `transport`, limiting it to the given bytes per second.
+    ///
+    /// `None`, or a rate of 0, means no limit for that direction.
+    pub fn new(transport: Box<dyn Transport>, upload: Option<u64>, download: Option<u64>) -> Self {
+        ThrottledTransport {
+            transport,
+            upload: upload.filter(|r| *r > 0).map(Pacer::new),
+            download: download.filter(|r| *r > 0).map(Pacer::new),
+        }
+    }
+}
+
+impl Transport for ThrottledTransport {
+    fn buffers(&mut self) -> &mut dyn Buffers {
+        self.transport.buffers()
+    }
+
+    fn transmit_output(&mut self, amount: usize, timeout: NextTimeout) -> Result<(), Error> {
+        self.transport.transmit_output(amount, timeout)?;
+
+        if let Some(pacer) = &mut self.upload {
+            pacer.pause(amount, timeout);
+        }
+
+        Ok(())
+    }
+
+    fn await_input(&mut self, timeout: NextTimeout) -> Result<bool, Error> {
+        let before = self.transport.buffers().input().len();
+        let made_progress = self.transport.await_input(timeout)?;
+        let after = self.transport.buffers().input().len();
+
+        if let Some(pacer) = &mut self.download {
+            pacer.pause(after.saturating_sub(before), timeout);
+        }
+
+        Ok(made_progress)
+    }
+
+    fn is_open(&mut self) -> bool {
+        self.transport.is_open()
+    }
+
+    fn is_tls(&self) -> bool {
+        self.transport.is_tls()
+    }
+
+    fn info(&self) -> ConnectionInfo {
+        self.transport.info()
+    }
+
+    fn peer_addr(&self) -> Option<SocketAddr> {
+        self.transport.peer_addr()
+    }
+}
+
+/// Keeps track of how far ahead of the rate the transfers are.
+struct Pacer {
+    rate: u64,
+    start: Option<time::Instant>,
+    bytes: u64,
+}
+
+impl Pacer {
+    fn new(rate: u64) -> Self {
+        Pacer {
+            rate,
+            start: None,
+            bytes: 0,
+        }
+    }
+
+    fn pause(&mut self, amount: usize, timeout: NextTimeout) {
+        let delay = self.delay(amount, time::Instant::now());
+
+        if !delay.is_zero() {
+            // Not pausing past the timeout, the next transfer will catch up.
+            thread::sleep(delay.min(*timeout.after));
+        }
+    }
+
+    /// How long to pause after transferring `amount` bytes.
+    fn delay(&mut self, amount: usize, now: time::Instant) -> time::Duration {
+        if amount == 0 {
+            return time::Duration::ZERO;
+        }
+
+        let rate = self.rate as f64;
+        let due = |bytes: u64| time::Duration::from_secs_f64(bytes as f64 / rate);
+
+        // Behind the rate means the transport has been idle. Start over.
+        let start = match self.start {
+            Some(v) if now.duration_since(v) <= due(self.bytes) => v,
+            _ => {
+                self.bytes = 0;
+                *self.start.insert(now)
+            }
+        };
+
+        self.bytes += amount as u64;
+
+        due(self.bytes).saturating_sub(now.duration_since(start))
+    }
+}
+
+impl fmt::Debug for ThrottledTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ThrottledTransport")
+            .field("upload", &self.upload.as_ref().map(|p| p.rate))
+            .field("download", &self.download.as_ref().map(|p| p.rate))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn pace_to_rate() {
+        let mut pacer = Pacer::new(1000);
+        let t0 = time::Instant::now();
+
+        // 500 bytes at 1000 bytes/s is due in 500ms.
+        assert_eq!(pacer.delay(500, t0), Duration::from_millis(500));
+
+        // After the pause, another 500 is due in another 500ms.
+        let t1 = t0 + Duration::from_millis(500);
+        assert_eq!(pacer.delay(500, t1), Duration::from_millis(500));
+
+        // A transfer that itself was slow needs a shorter pause.
+        let t2 = t0 + Duration::from_millis(900);
+        assert_eq!(pacer.delay(100, t2), Duration::from_millis(200));
+
+        // Idle for long, no catching up.
+        let t3 = t0 + Duration::from_secs(60);
+        assert_eq!(pacer.delay(250, t3), Duration::from_millis(250));
+    }
+}
@@ -0,0 +1,220 @@
+use std::fs::File;
+use std::io::{IoSlice, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::{fmt, io, time};
+
+use crate::transport::time::Duration;
+use crate::util::{write_all_vectored, IoResultExt};
+use crate::Error;
+
+use super::{Buffers, ConnectionDetails, Connector, LazyBuffers, NextTimeout, Transport};
+
+/// Connector for HTTP proxies on Unix sockets.
+///
+/// Opens the socket for a proxy configured as `unix:///path/to/proxy.sock`. The
+/// CONNECT is then made over that socket by the
+/// [`ConnectProxyConnector`](super::ConnectProxyConnector) further down the chain.
+#[derive(Default)]
+pub struct UnixProxyConnector(());
+
+impl Connector for UnixProxyConnector {
+    fn connect(
+        &self,
+        details: &ConnectionDetails,
+        chained: Option<Box<dyn Transport>>,
+    ) -> Result<Option<Box<dyn Transport>>, Error> {
+        if chained.is_some() {
+            trace!("Skip");
+            return Ok(chained);
+        }
+
+        let Some(path) = details.config.proxy.as_ref().and_then(|p| p.unix_path()) else {
+            trace!("Unix socket proxy not configured");
+            return Ok(chained);
+        };
+
+        trace!("Try connect UnixStream to {:?}", path);
+
+        let stream = UnixStream::connect(path)?;
+
+        debug!("Connected UnixStream to {:?}", path);
+
+        let config = &details.config;
+        let buffers = LazyBuffers::new(config.input_buffer_size, config.output_buffer_size);
+        let transport = UnixTransport::new(stream, buffers);
+
+        Ok(Some(Box::new(transport)))
+    }
+}
+
+struct UnixTransport {
+    stream: UnixStream,
+    buffers: LazyBuffers,
+    timeout_write: Option<Duration>,
+    timeout_read: Option<Duration>,
+}
+
+impl UnixTransport {
+    fn new(stream: UnixStream, buffers: LazyBuffers) -> UnixTransport {
+        UnixTransport {
+            stream,
+            buffers,
+            timeout_read: None,
+            timeout_write: None,
+        }
+    }
+}
+
+// The goal here is to only cause a syscall to set the timeout if it's necessary.
+fn maybe_update_timeout(
+    timeout: NextTimeout,
+    previous: &mut Option<Duration>,
+    stream: &UnixStream,
+    f: impl Fn(&UnixStream, Option<time::Duration>) -> io::Result<()>,
+) -> io::Result<()> {
+    let maybe_timeout = timeout.not_zero();
+
+    if maybe_timeout != *previous {
+        (f)(stream, maybe_timeout.map(|t| *t))?;
+        *previous = maybe_timeout;
+    }
+
+    Ok(())
+}
+
+impl Transport for UnixTransport {
+    fn buffers(&mut self) -> &mut dyn Buffers {
+        &mut self.buffers
+    }
+
+    fn transmit_output(&mut self, amount: usize, timeout: NextTimeout) -> Result<(), Error> {
+        maybe_update_timeout(
+            timeout,
+            &mut self.timeout_write,
+            &self.stream,
+            UnixStream::set_write_timeout,
+        )?;
+
+        let output = &self.buffers.output()[..amount];
+        match self.stream.write_all(output).normalize_would_block() {
+            Ok(v) => Ok(v),
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => Err(Error::Timeout(timeout.reason)),
+            Err(e) => Err(e.into()),
+        }?;
+
+        Ok(())
+    }
+
+    fn transmit_output_vectored(
+        &mut self,
+        amount: usize,
+        bufs: &[IoSlice<'_>],
+        timeout: NextTimeout,
+    ) -> Result<(), Error> {
+        maybe_update_timeout(
+            timeout,
+            &mut self.timeout_write,
+            &self.stream,
+            UnixStream::set_write_timeout,
+        )?;
+
+        let mut all: Vec<&[u8]> = vec![&self.buffers.output()[..amount]];
+        all.extend(bufs.iter().map(|b| &**b));
+
+        match write_all_vectored(&mut self.stream, &all).normalize_would_block() {
+            Ok(v) => Ok(v),
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => Err(Error::Timeout(timeout.reason)),
+            Err(e) => Err(e.into()),
+        }?;
+
+        Ok(())
+    }
+
+    fn send_file(&mut self, file: &File, len: u64, timeout: NextTimeout) -> Result<bool, Error> {
+        maybe_update_timeout(
+            timeout,
+            &mut self.timeout_write,
+            &self.stream,
+            UnixStream::set_write_timeout,
+        )?;
+
+        // On Linux, std copies from file to socket with copy_file_range or sendfile.
+        let mut reader = file.take(len);
+        let sent = match io::copy(&mut reader, &mut self.stream).normalize_would_block() {
+            Ok(v) => Ok(v),
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => Err(Error::Timeout(timeout.reason)),
+            Err(e) => Err(e.into()),
+        }?;
+
+        if sent < len {
+            let msg = format!("file ended {} bytes short of its length", len - sent);
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, msg).into());
+        }
+
+        Ok(true)
+    }
+
+    fn await_input(&mut self, timeout: NextTimeout) -> Result<bool, Error> {
+        if self.buffers.can_use_input() {
+            return Ok(true);
+        }
+
+        maybe_update_timeout(
+            timeout,
+            &mut self.timeout_read,
+            &self.stream,
+            UnixStream::set_read_timeout,
+        )?;
+
+        let input = self.buffers.input_append_buf();
+        let amount = match self.stream.read(input).normalize_would_block() {
+            Ok(v) => Ok(v),
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => Err(Error::Timeout(timeout.reason)),
+            Err(e) => Err(e.into()),
+        }?;
+        self.buffers.input_appended(amount);
+
+        Ok(amount > 0)
+    }
+
+    fn is_open(&mut self) -> bool {
+        probe_unix_stream(&mut self.stream).unwrap_or(false)
+    }
+}
+
+fn probe_unix_stream(stream: &mut UnixStream) -> Result<bool, Error> {
+    // Temporary do non-blocking IO
+    stream.set_nonblocking(true)?;
+
+    let mut buf = [0];
+    match stream.read(&mut buf) {
+        // No waiting bytes is the correct condition.
+        Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+        // Any bytes read means the proxy sent some garbage we didn't ask for
+        Ok(_) => {
+            info!("Unexpected bytes from proxy. Closing connection");
+            return Ok(false);
+        }
+        // Errors such as closed connection
+        Err(_) => return Ok(false),
+    };
+
+    // Reset back to blocking
+    stream.set_nonblocking(false)?;
+
+    Ok(true)
+}
+
+impl fmt::Debug for UnixProxyConnector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UnixProxyConnector").finish()
+    }
+}
+
+impl fmt::Debug for UnixTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UnixTransport")
+            .field("addr", &self.stream.peer_addr().ok())
+            .finish()
+    }
+}
@@ -0,0 +1,623 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use base64::prelude::BASE64_STANDARD;
+use base64::Engine;
+use http::{header, Method, Request, Response, StatusCode, Uri};
+
+use crate::config::RetryPolicy;
+use crate::early_hints::resolve;
+use crate::{Agent, AsSendBody, Body, Error, IntoUri, ResponseExt};
+
+/// Protocol for a resumable upload. See [`Upload`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum UploadProtocol {
+    /// The [tus](https://tus.io/protocols/resumable-upload) 1.0 protocol.
+    Tus,
+
+    /// Google style resumable uploads, as used by Google Cloud Storage and Drive.
+    ///
+    /// The uri to create the session is typically something like
+    /// `https://storage.googleapis.com/upload/storage/v1/b/<bucket>/o?uploadType=resumable`.
+    Google,
+}
+
+/// A resumable upload session.
+///
+/// Created by [`Agent::upload()`]. Resumable uploads send a large file in chunks,
+/// so that a failure only means resending the current chunk rather than the
+/// entire file.
+///
+/// 1. [`Upload::create()`] asks the server for a session.
+/// 2. [`UploadSession::upload()`] sends the chunks. When a chunk fails, the server is
+///    asked how much it received, and the upload continues from there.
+/// 3. The response to the last chunk finalizes the upload.
+///
+/// The failures of a chunk are retried according to the
+/// [`RetryPolicy`](crate::config::RetryPolicy) of the agent. Without a retry policy,
+/// the first failure ends the upload. The session can still be resumed later, also
+/// from another process, using the [`UploadSession::location()`].
+///
+/// ```no_run
+/// use std::fs::File;
+/// use ureq::{Agent, UploadProtocol};
+///
+/// let agent = Agent::new_with_defaults();
+///
+/// let file = File::open("big.iso")?;
+/// let length = file.metadata()?.len();
+///
+/// let mut session = agent
+///     .upload(UploadProtocol::Tus, "https://tus.example.test/files/")
+///     .metadata("filename", "big.iso")
+///     .create(length)?;
+///
+/// // Store this to resume in case we crash.
+/// println!("Session: {}", session.location());
+///
+/// let response = session.upload(file)?;
+/// # Ok::<_, ureq::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct Upload {
+    agent: Agent,
+    protocol: UploadProtocol,
+    uri: Result<Uri, Error>,
+    chunk_size: usize,
+    content_type: Option<String>,
+    metadata: Vec<(String, String)>,
+}
+
+/// An upload session created by [`Upload::create()`] or [`Upload::resume()`].
+#[derive(Debug)]
+pub struct UploadSession {
+    agent: Agent,
+    protocol: UploadProtocol,
+    location: Uri,
+    length: u64,
+    offset: u64,
+    chunk_size: usize,
+}
+
+const TUS_VERSION: &str = "1.0.0";
+
+/// Google requires chunks to be a multiple of this.
+const GOOGLE_CHUNK_MULTIPLE: usize = 256 * 1024;
+
+/// Outcome of sending one chunk.
+enum Sent {
+    /// The server has this many bytes.
+    Partial(u64),
+    /// The upload is complete.
+    Done(Response<Body>),
+}
+
+impl Upload {
+    pub(crate) fn new(agent: Agent, protocol: UploadProtocol, uri: impl IntoUri) -> Self {
+        Upload {
+            agent,
+            protocol,
+            uri: uri.into_uri(),
+            chunk_size: 8 * 1024 * 1024,
+            content_type: None,
+            metadata: vec![],
+        }
+    }
+
+    /// Size of each chunk.
+    ///
+    /// For [`UploadProtocol::Google`], this is rounded up to a multiple of 256KB.
+    ///
+    /// Defaults to 8MB.
+    pub fn chunk_size(mut self, v: usize) -> Self {
+        self.chunk_size = v.max(1);
+        self
+    }
+
+    /// Content type of the uploaded file.
+    ///
+    /// Sent as `X-Upload-Content-Type` for Google, and as the `filetype` metadata
+    /// for tus.
+    pub fn content_type(mut self, v: &str) -> Self {
+        self.content_type = Some(v.to_string());
+        self
+    }
+
+    /// Metadata for the upload, such as the file name.
+    ///
+    /// Sent in the `Upload-Metadata` header for tus, and as a JSON object in the
+    /// body of the session request for Google.
+    pub fn metadata(mut self, key: &str, value: &str) -> Self {
+        self.metadata.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Create an upload session for `length` bytes.
+    pub fn create(self, length: u64) -> Result<UploadSession, Error> {
+        let uri = self.uri?;
+
+        let response = match self.protocol {
+            UploadProtocol::Tus => {
+                let mut metadata = self.metadata.clone();
+                if let Some(t) = &self.content_type {
+                    metadata.push(("filetype".to_string(), t.clone()));
+                }
+                let metadata: Vec<_> = metadata
+                    .iter()
+                    .map(|(k, v)| format!("{} {}", k, BASE64_STANDARD.encode(v)))
+                    .collect();
+
+                let mut builder = Request::post(uri.clone())
+                    .header("tus-resumable", TUS_VERSION)
+                    .header("upload-length", length);
+                if !metadata.is_empty() {
+                    builder = builder.header("upload-metadata", metadata.join(","));
+                }
+
+                run(&self.agent, builder.body(())?)?
+            }
+            UploadProtocol::Google => {
+                let mut builder = Request::post(uri.clone())
+                    .header("x-upload-content-length", length)
+                    .header(header::CONTENT_TYPE, "application/json; charset=UTF-8");
+                if let Some(t) = &self.content_type {
+                    builder = builder.header("x-upload-content-type", t);
+                }
+
+                run(&self.agent, builder.body(json_object(&self.metadata))?)?
+            }
+        };
+
+        let location = response
+            .headers()
+            .get(header::LOCATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| resolve(v, &uri))
+            .ok_or_else(|| Error::Upload("session response has no location".into()))?;
+
+        debug!("Upload session created");
+
+        Ok(UploadSession {
+            agent: self.agent,
+            protocol: self.protocol,
+            location,
+            length,
+            offset: 0,
+            chunk_size: self.chunk_size,
+        })
+    }
+
+    /// Resume a session created earlier, at `location`, for `length` bytes.
+    ///
+    /// This asks the server how much it has received.
+    pub fn resume(self, location: impl IntoUri, length: u64) -> Result<UploadSession, Error> {
+        let mut session = UploadSession {
+            agent: self.agent,
+            protocol: self.protocol,
+            location: location.into_uri()?,
+            length,
+            offset: 0,
+            chunk_size: self.chunk_size,
+        };
+
+        session.query_offset()?;
+
+        Ok(session)
+    }
+}
+
+impl UploadSession {
+    /// The uri of the session, which is needed to resume it.
+    pub fn location(&self) -> &Uri {
+        &self.location
+    }
+
+    /// How many bytes the server has received, as far as we know.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Ask the server how many bytes it has received.
+    pub fn query_offset(&mut self) -> Result<u64, Error> {
+        self.offset = match self.protocol {
+            UploadProtocol::Tus => {
+                let request = Request::head(self.location.clone())
+                    .header("tus-resumable", TUS_VERSION)
+                    .body(())?;
+                let response = run(&self.agent, request)?;
+                tus_offset(&response)?
+            }
+            UploadProtocol::Google => match self.send_google(&[])? {
+                Sent::Partial(v) => v,
+                Sent::Done(_) => self.length,
+            },
+        };
+
+        Ok(self.offset)
+    }
+
+    /// Upload the data from `source`, starting at the current offset.
+    ///
+    /// The `source` must contain the entire file, since the upload might need to go
+    /// back to an earlier offset after a failure. Returns the response to the
+    /// final chunk, or [`Error::Upload`] if the server answers a chunk with an
+    /// offset that doesn't advance.
+    pub fn upload(&mut self, mut source: impl Read + Seek) -> Result<Response<Body>, Error> {
+        let retry = self.agent.config().retry.clone();
+        let mut retries = 0;
+
+        let chunk_size = match self.protocol {
+            UploadProtocol::Tus => self.chunk_size,
+            UploadProtocol::Google => {
+                let n = (self.chunk_size + GOOGLE_CHUNK_MULTIPLE - 1) / GOOGLE_CHUNK_MULTIPLE;
+                n * GOOGLE_CHUNK_MULTIPLE
+            }
+        };
+
+        let mut buf = vec![0; chunk_size];
+
+        source.seek(SeekFrom::Start(self.offset))?;
+
+        loop {
+            let Some(left) = self.length.checked_sub(self.offset) else {
+                return Err(Error::Upload(format!(
+                    "server offset {} is past the length {}",
+                    self.offset, self.length
+                )));
+            };
+            let max = left.min(chunk_size as u64) as usize;
+            source.read_exact(&mut buf[..max])?;
+
+            let result = match self.protocol {
+                UploadProtocol::Tus => self.send_tus(&buf[..max]),
+                UploadProtocol::Google => self.send_google(&buf[..max]),
+            };
+
+            let err = match result {
+                Ok(Sent::Done(response)) => {
+                    self.offset = self.length;
+                    return Ok(response);
+                }
+                Ok(Sent::Partial(offset)) => {
+                    if offset <= self.offset {
+                        // Without progress we would send the same chunk forever.
+                        return Err(Error::Upload(format!(
+                            "server offset {} did not advance from {}",
+                            offset, self.offset
+                        )));
+                    }
+                    let expected = self.offset + max as u64;
+                    self.offset = offset;
+                    retries = 0;
+                    if offset != expected {
+                        // The server didn't take the whole chunk.
+                        source.seek(SeekFrom::Start(offset))?;
+                    }
+                    continue;
+                }
+                Err(e) => e,
+            };
+
+            let delay = retry
+                .as_deref()
+                .filter(|_| is_retryable(&err))
+                .and_then(|r| r.backoff(retries, self.agent.config()));
+
+            let Some(delay) = delay else {
+                return Err(err);
+            };
+
+            retries += 1;
+            debug!(
+                "Upload chunk failed, retry {} in {:?}: {}",
+                retries, delay, err
+            );
+            std::thread::sleep(delay);
+
+            self.query_offset()?;
+            source.seek(SeekFrom::Start(self.offset))?;
+        }
+    }
+
+    fn send_tus(&self, chunk: &[u8]) -> Result<Sent, Error> {
+        let request = Request::builder()
+            .method(Method::PATCH)
+            .uri(self.location.clone())
+            .header("tus-resumable", TUS_VERSION)
+            .header("upload-offset", self.offset)
+            .header(header::CONTENT_TYPE, "application/offset+octet-stream")
+            .body(chunk)?;
+
+        let response = run(&self.agent, request)?;
+        let offset = tus_offset(&response)?;
+
+        if offset >= self.length {
+            Ok(Sent::Done(response))
+        } else {
+            Ok(Sent::Partial(offset))
+        }
+    }
+
+    /// Send a chunk. An empty chunk asks for the status of the upload.
+    fn send_google(&self, chunk: &[u8]) -> Result<Sent, Error> {
+        let range = if chunk.is_empty() {
+            format!("bytes */{}", self.length)
+        } else {
+            let end = self.offset + chunk.len() as u64 - 1;
+            format!("bytes {}-{}/{}", self.offset, end, self.length)
+        };
+
+        let request = Request::put(self.location.clone())
+            .header(header::CONTENT_RANGE, range)
+            .body(chunk)?;
+
+        let response = run(&self.agent, request)?;
+
+        // 308 Resume Incomplete, with the received bytes like "Range: bytes=0-1234".
+        if response.status() != StatusCode::PERMANENT_REDIRECT {
+            return Ok(Sent::Done(response));
+        }
+
+        let Some(range) = response.headers().get(header::RANGE) else {
+            // Nothing received yet.
+            return Ok(Sent::Partial(0));
+        };
+
+        range
+            .to_str()
+            .ok()
+            .and_then(|r| r.strip_prefix("bytes=0-"))
+            .and_then(|r| r.parse::<u64>().ok())
+            .map(|end| Sent::Partial(end + 1))
+            .ok_or_else(|| Error::Upload("bad range in upload status".into()))
+    }
+}
+
+/// Run with redirects off, since Google uses 308 for incomplete uploads.
+fn run(agent: &Agent, request: Request<impl AsSendBody>) -> Result<Response<Body>, Error> {
+    let request = agent.configure_request(request).max_redirects(0).build();
+    agent.run(request)
+}
+
+fn tus_offset(response: &Response<Body>) -> Result<u64, Error> {
+    response
+        .header_as::<u64>("upload-offset")?
+        .ok_or_else(|| Error::Upload("missing Upload-Offset".into()))
+}
+
+/// Failures worth trying again, as opposed to a server refusing the upload.
+fn is_retryable(err: &Error) -> bool {
+    let status = match err.without_stats() {
+        Error::StatusCode(v) => *v,
+        Error::StatusCodeBody(v, _) => *v,
+        Error::ConnectionFailed => return true,
+        e if RetryPolicy::is_retryable_error(e) => return true,
+        _ => return false,
+    };
+    status >= 500 || status == 408 || status == 429
+}
+
+/// A flat JSON object of strings.
+fn json_object(pairs: &[(String, String)]) -> Vec<u8> {
+    fn escape(s: &str, out: &mut String) {
+        out.push('"');
+        for c in s.chars() {
+            match c {
+                '"' => out.push_str("\\\""),
+                '\\' => out.push_str("\\\\"),
+                c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+                c => out.push(c),
+            }
+        }
+        out.push('"');
+    }
+
+    let mut out = String::from("{");
+    for (i, (k, v)) in pairs.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        escape(k, &mut out);
+        out.push(':');
+        escape(v, &mut out);
+    }
+    out.push('}');
+    out.into_bytes()
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::config::Config;
+    use crate::transport::{ChainedConnector, Connector, Fault, FaultConnector, MockConnector};
+
+    #[test]
+    fn json_metadata() {
+        let pairs = vec![
+            ("name".to_string(), "a \"b\".txt".to_string()),
+            ("x".to_string(), "\n".to_string()),
+        ];
+        assert_eq!(
+            String::from_utf8(json_object(&pairs)).unwrap(),
+            r#"{"name":"a \"b\".txt","x":"\u000a"}"#
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "_test")]
+    fn tus_upload() {
+        use crate::test::init_test_log;
+        use crate::transport::set_handler;
+
+        init_test_log();
+        set_handler(
+            "/tus_create",
+            201,
+            &[("Location", "/tus_session/1"), ("Content-Length", "0")],
+            &[],
+        );
+        set_handler(
+            "/tus_session/1",
+            204,
+            &[("Tus-Resumable", "1.0.0"), ("Upload-Offset", "10")],
+            &[],
+        );
+
+        let agent = Agent::new_with_defaults();
+        let mut session = agent
+            .upload(UploadProtocol::Tus, "http://example.org/tus_create")
+            .metadata("filename", "x.txt")
+            .create(10)
+            .unwrap();
+
+        assert_eq!(
+            session.location().to_string(),
+            "http://example.org/tus_session/1"
+        );
+
+        let response = session.upload(std::io::Cursor::new(b"0123456789")).unwrap();
+        assert_eq!(response.status(), 204);
+        assert_eq!(session.offset(), 10);
+    }
+
+    #[test]
+    #[cfg(feature = "_test")]
+    fn tus_upload_without_progress() {
+        use crate::test::init_test_log;
+        use crate::transport::set_handler;
+
+        init_test_log();
+        set_handler(
+            "/tus_stuck_create",
+            201,
+            &[("Location", "/tus_stuck/1"), ("Content-Length", "0")],
+            &[],
+        );
+        set_handler(
+            "/tus_stuck/1",
+            204,
+            &[("Tus-Resumable", "1.0.0"), ("Upload-Offset", "0")],
+            &[],
+        );
+
+        let agent = Agent::new_with_defaults();
+        let mut session = agent
+            .upload(UploadProtocol::Tus, "http://example.org/tus_stuck_create")
+            .create(10)
+            .unwrap();
+
+        let err = session
+            .upload(std::io::Cursor::new(b"0123456789"))
+            .unwrap_err();
+        assert!(matches!(err, Error::Upload(_)), "{:?}", err);
+        assert_eq!(session.offset(), 0);
+    }
+
+    #[test]
+    fn retryable_errors() {
+        assert!(is_retryable(&Error::StatusCode(503)));
+        assert!(is_retryable(&Error::ConnectionFailed));
+        assert!(is_retryable(&Error::Io(
+            std::io::ErrorKind::ConnectionReset.into()
+        )));
+        assert!(!is_retryable(&Error::StatusCode(404)));
+        assert!(!is_retryable(&Error::Upload("bad".into())));
+        assert!(!is_retryable(&Error::BadUri("bad".into())));
+        assert!(!is_retryable(&Error::Timeout(crate::Timeout::Global)));
+    }
+
+    fn mock_agent(mock: MockConnector, faults: FaultConnector) -> Agent {
+        let connector = ChainedConnector::new([mock.clone().boxed(), faults.boxed()]);
+        let policy = RetryPolicy::new().base_delay(Duration::from_millis(1));
+        let config = Config::builder().retry(Some(policy)).build();
+        Agent::with_parts(config, connector, mock)
+    }
+
+    fn requests(mock: &MockConnector) -> Vec<String> {
+        let requests = mock.requests().into_iter();
+        requests.map(|r| String::from_utf8(r).unwrap()).collect()
+    }
+
+    #[test]
+    fn tus_retry_after_reset() {
+        const OFFSET: &str = "HTTP/1.1 204 No Content\r\nTus-Resumable: 1.0.0\r\nUpload-Offset: ";
+
+        let mock = MockConnector::new()
+            .route_once(
+                "/files",
+                "HTTP/1.1 201 Created\r\nLocation: /files/1\r\nContent-Length: 0\r\n\r\n",
+            )
+            .route_once("/files/1", format!("{}4\r\n\r\n", OFFSET))
+            // The connection of the second chunk is reset.
+            .route_once("/files/1", format!("{}8\r\n\r\n", OFFSET))
+            // The server got part of it.
+            .route_once("/files/1", format!("{}6\r\n\r\n", OFFSET))
+            .route_once("/files/1", format!("{}10\r\n\r\n", OFFSET));
+        let faults = FaultConnector::new().schedule(2, Fault::Reset);
+        let agent = mock_agent(mock.clone(), faults.clone());
+
+        let mut session = agent
+            .upload(UploadProtocol::Tus, "http://up.test/files")
+            .chunk_size(4)
+            .create(10)
+            .unwrap();
+
+        let response = session.upload(Cursor::new(b"0123456789")).unwrap();
+        assert_eq!(response.status(), 204);
+        assert_eq!(session.offset(), 10);
+        assert_eq!(faults.connections(), 5);
+
+        let requests = requests(&mock);
+        assert!(requests[1].starts_with("PATCH /files/1 "));
+        assert!(requests[1].contains("upload-offset: 0\r\n"));
+        assert!(requests[1].ends_with("\r\n\r\n0123"));
+        assert!(requests[3].starts_with("HEAD /files/1 "));
+        assert!(requests[4].contains("upload-offset: 6\r\n"));
+        assert!(requests[4].ends_with("\r\n\r\n6789"));
+    }
+
+    #[test]
+    fn google_resume() {
+        let mock = MockConnector::new()
+            .route_once(
+                "/session",
+                "HTTP/1.1 308 Resume Incomplete\r\nRange: bytes=0-5\r\nContent-Length: 0\r\n\r\n",
+            )
+            .route_once("/session", "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\n{}");
+        let agent = mock_agent(mock.clone(), FaultConnector::new());
+
+        let mut session = agent
+            .upload(UploadProtocol::Google, "http://up.test/create")
+            .resume("http://up.test/session", 10)
+            .unwrap();
+        assert_eq!(session.offset(), 6);
+
+        let response = session.upload(Cursor::new(b"0123456789")).unwrap();
+        assert_eq!(response.status(), 200);
+
+        let requests = requests(&mock);
+        assert!(requests[0].contains("content-range: bytes */10\r\n"));
+        assert!(requests[1].contains("content-range: bytes 6-9/10\r\n"));
+        assert!(requests[1].ends_with("\r\n\r\n6789"));
+    }
+
+    #[test]
+    fn resume_past_length() {
+        let mock = MockConnector::new().route(
+            "/files/1",
+            "HTTP/1.1 200 OK\r\nTus-Resumable: 1.0.0\r\nUpload-Offset: 12\r\n\r\n",
+        );
+        let agent = mock_agent(mock, FaultConnector::new());
+
+        let mut session = agent
+            .upload(UploadProtocol::Tus, "http://up.test/files")
+            .resume("http://up.test/files/1", 10)
+            .unwrap();
+
+        let err = session.upload(Cursor::new(b"0123456789")).unwrap_err();
+        assert!(matches!(err, Error::Upload(_)), "{:?}", err);
+    }
+}
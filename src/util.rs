@@ -1,6 +1,8 @@
+use std::collections::hash_map::RandomState;
 use std::convert::TryFrom;
 use std::fmt;
-use std::io::{self, ErrorKind};
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, ErrorKind, IoSlice, Write};
 
 use http::header::{ACCEPT, ACCEPT_CHARSET, ACCEPT_ENCODING};
 use http::header::{CONNECTION, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE};
@@ -8,6 +10,7 @@ use http::header::{DATE, HOST, LOCATION, SERVER, TRANSFER_ENCODING, USER_AGENT};
 use http::uri::{Authority, Scheme};
 use http::{HeaderMap, HeaderName, HeaderValue, Method, Response, Uri, Version};
 
+use crate::config::Redaction;
 use crate::proxy::Proto;
 use crate::Error;
 
@@ -15,6 +18,37 @@ pub use hoot::ArrayVec;
 
 pub(crate) mod private {
     pub trait Private {}
+
+    /// Seals [`ResponseExt`](crate::ResponseExt), which is for any `Response<B>`, while
+    /// `Private` is only for the `Response<Body>` sent as a body.
+    pub trait PrivateResponse {}
+}
+
+/// Whether `text` matches `pattern`, where `*` matches any run of characters.
+pub(crate) fn glob_match(pattern: &str, text: &str) -> bool {
+    let (p, t) = (pattern.as_bytes(), text.as_bytes());
+    let (mut pi, mut ti) = (0, 0);
+
+    // Where to go back to when a `*` must match one more character.
+    let mut star: Option<(usize, usize)> = None;
+
+    while ti < t.len() {
+        if p.get(pi) == Some(&b'*') {
+            star = Some((pi, ti));
+            pi += 1;
+        } else if p.get(pi) == Some(&t[ti]) {
+            pi += 1;
+            ti += 1;
+        } else if let Some((sp, st)) = star {
+            star = Some((sp, st + 1));
+            pi = sp + 1;
+            ti = st + 1;
+        } else {
+            return false;
+        }
+    }
+
+    p[pi..].iter().all(|c| *c == b'*')
 }
 
 pub(crate) trait AuthorityExt {
@@ -79,6 +113,79 @@ impl<T> IoResultExt for io::Result<T> {
     }
 }
 
+/// Write all of `bufs`, in as few writes as possible.
+///
+/// Like the unstable `Write::write_all_vectored()`.
+pub(crate) fn write_all_vectored(w: &mut impl Write, bufs: &[&[u8]]) -> io::Result<()> {
+    let mut bufs: Vec<&[u8]> = bufs.iter().copied().filter(|b| !b.is_empty()).collect();
+    let mut start = 0;
+
+    while start < bufs.len() {
+        let slices: Vec<IoSlice> = bufs[start..].iter().map(|b| IoSlice::new(b)).collect();
+
+        let mut n = match w.write_vectored(&slices) {
+            Ok(0) => return Err(ErrorKind::WriteZero.into()),
+            Ok(n) => n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+
+        // Skip what was written.
+        while n > 0 {
+            let len = bufs[start].len();
+            if n >= len {
+                n -= len;
+                start += 1;
+            } else {
+                bufs[start] = &bufs[start][n..];
+                n = 0;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Lowercase hex of the bytes.
+#[cfg(any(feature = "digest-auth", feature = "presign", feature = "_tls"))]
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// A random number from the OS seed of `RandomState`, which spares us a dependency on rand.
+pub(crate) fn random_u64() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+/// Days since 1970-01-01 for a proleptic gregorian date.
+pub(crate) fn days_from_civil(y: i64, m: u32, d: u32) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let m = m as i64;
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + d as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// A number in `0.0..1.0` from the random number `n`.
+pub(crate) fn fraction(n: u64) -> f64 {
+    (n >> 11) as f64 / (1_u64 << 53) as f64
+}
+
+/// Small seeded random number generator (SplitMix64).
+pub(crate) struct Rng(pub u64);
+
+impl Rng {
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
 pub(crate) struct ConsumeBuf {
     buf: Vec<u8>,
     filled: usize,
@@ -101,6 +208,10 @@ impl ConsumeBuf {
         self.buf.resize(size, 0);
     }
 
+    pub fn capacity(&self) -> usize {
+        self.buf.len()
+    }
+
     pub fn add_space(&mut self, size: usize) {
         if size == 0 {
             return;
@@ -154,33 +265,34 @@ pub(crate) struct DebugRequest<'a> {
     pub uri: &'a Uri,
     pub version: Version,
     pub headers: HeaderMap<HeaderValue>,
+    pub redaction: &'a Redaction,
 }
 
 impl<'a> fmt::Debug for DebugRequest<'a> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Request")
             .field("method", &self.method)
-            .field("uri", &DebugUri(self.uri))
+            .field("uri", &DebugUri(self.uri, self.redaction))
             .field("version", &self.version)
-            .field("headers", &DebugHeaders(&self.headers))
+            .field("headers", &DebugHeaders(&self.headers, self.redaction))
             .finish()
     }
 }
 
 /// Wrapper to only log non-sensitive data.
-pub(crate) struct DebugResponse<'a, B>(pub &'a Response<B>);
+pub(crate) struct DebugResponse<'a, B>(pub &'a Response<B>, pub &'a Redaction);
 
 impl<'a, B> fmt::Debug for DebugResponse<'a, B> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Response")
             .field("status", &self.0.status())
             .field("version", &self.0.version())
-            .field("headers", &DebugHeaders(self.0.headers()))
+            .field("headers", &DebugHeaders(self.0.headers(), self.1))
             .finish()
     }
 }
 
-pub(crate) struct DebugHeaders<'a>(pub &'a HeaderMap);
+pub(crate) struct DebugHeaders<'a>(pub &'a HeaderMap, pub &'a Redaction);
 
 const NON_SENSITIVE_HEADERS: &[HeaderName] = &[
     DATE,
@@ -198,22 +310,21 @@ const NON_SENSITIVE_HEADERS: &[HeaderName] = &[
     USER_AGENT,
 ];
 
+impl<'a> DebugHeaders<'a> {
+    fn is_redacted(&self, name: &HeaderName) -> bool {
+        !NON_SENSITIVE_HEADERS.contains(name) || self.1.is_header_redacted(name)
+    }
+}
+
 impl<'a> fmt::Debug for DebugHeaders<'a> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut debug = f.debug_map();
-        debug.entries(
-            self.0
-                .iter()
-                .filter(|(name, _)| NON_SENSITIVE_HEADERS.contains(name)),
-        );
+        debug.entries(self.0.iter().filter(|(name, _)| !self.is_redacted(name)));
 
         let redact_count = self
             .0
             .iter()
-            .filter(|(name, _)| {
-                // println!("{}", name);
-                !NON_SENSITIVE_HEADERS.contains(name)
-            })
+            .filter(|(name, _)| self.is_redacted(name))
             .count();
 
         if redact_count > 0 {
@@ -228,7 +339,7 @@ impl<'a> fmt::Debug for DebugHeaders<'a> {
 }
 
 /// Wrapper to only log non-sensitive data.
-pub(crate) struct DebugUri<'a>(pub &'a Uri);
+pub(crate) struct DebugUri<'a>(pub &'a Uri, pub &'a Redaction);
 
 impl<'a> fmt::Debug for DebugUri<'a> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -241,7 +352,23 @@ impl<'a> fmt::Debug for DebugUri<'a> {
         }
 
         if let Some(q) = self.0.path_and_query() {
-            write!(f, "{}", q)?;
+            write!(f, "{}", q.path())?;
+
+            if let Some(query) = q.query() {
+                write!(f, "?")?;
+
+                for (i, pair) in query.split('&').enumerate() {
+                    if i > 0 {
+                        write!(f, "&")?;
+                    }
+                    match pair.split_once('=') {
+                        Some((k, _)) if self.1.is_query_param_redacted(k) => {
+                            write!(f, "{}=******", k)?
+                        }
+                        _ => write!(f, "{}", pair)?,
+                    }
+                }
+            }
         }
 
         Ok(())
@@ -319,7 +446,6 @@ pub(crate) trait HeaderMapExt {
     fn get_str(&self, k: &str) -> Option<&str>;
     fn is_chunked(&self) -> bool;
     fn content_length(&self) -> Option<u64>;
-    #[cfg(any(feature = "gzip", feature = "brotli"))]
     fn has_accept_encoding(&self) -> bool;
     fn has_user_agent(&self) -> bool;
     fn has_send_body_mode(&self) -> bool {
@@ -346,7 +472,6 @@ impl HeaderMapExt for HeaderMap {
         Some(len)
     }
 
-    #[cfg(any(feature = "gzip", feature = "brotli"))]
     fn has_accept_encoding(&self) -> bool {
         self.contains_key("accept-encoding")
     }
@@ -0,0 +1,693 @@
+//! XML-RPC calls.
+//!
+//! Requires the **xmlrpc** feature.
+//!
+//! [XML-RPC] is the predecessor of SOAP, and still the API of legacy services
+//! such as WordPress and supervisord. Calls are made with [`XmlRpc::call()`], with
+//! the params and the result as a [`Value`].
+//!
+//! ```no_run
+//! use ureq::Agent;
+//! use ureq::xmlrpc::Value;
+//!
+//! let agent = Agent::new_with_defaults();
+//! let rpc = agent.xmlrpc("http://localhost:9001/RPC2");
+//!
+//! let state = rpc.call("supervisor.getState", &[])?;
+//! println!("{:?}", state.get("statename").and_then(Value::as_str));
+//!
+//! rpc.call("supervisor.startProcess", &["web".into(), true.into()])?;
+//! # Ok::<_, ureq::Error>(())
+//! ```
+//!
+//! [XML-RPC]: http://xmlrpc.com/spec.md
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt::Write;
+
+use base64::prelude::BASE64_STANDARD;
+use base64::Engine;
+use http::{header, Request, Uri};
+
+use crate::{Agent, Error, IntoUri};
+
+/// A client for an XML-RPC endpoint.
+///
+/// Created by [`Agent::xmlrpc()`]. See the [module](self) documentation.
+#[derive(Debug, Clone)]
+pub struct XmlRpc {
+    agent: Agent,
+    uri: Result<Uri, String>,
+}
+
+/// A value in a call or response.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// `<int>`, `<i4>` or `<i8>`.
+    Int(i64),
+    /// `<boolean>`.
+    Bool(bool),
+    /// `<string>`, also used for a value without type.
+    String(String),
+    /// `<double>`.
+    Double(f64),
+    /// `<dateTime.iso8601>`, such as `19980717T14:08:55`, which is left unparsed.
+    DateTime(String),
+    /// `<base64>`, decoded.
+    Base64(Vec<u8>),
+    /// `<array>`.
+    Array(Vec<Value>),
+    /// `<struct>`.
+    Struct(BTreeMap<String, Value>),
+    /// `<nil/>`, an extension supported by most implementations.
+    Nil,
+}
+
+impl XmlRpc {
+    pub(crate) fn new(agent: Agent, uri: impl IntoUri) -> Self {
+        XmlRpc {
+            agent,
+            uri: uri.into_uri().map_err(|e| match e {
+                Error::BadUri(v) => v,
+                e => e.to_string(),
+            }),
+        }
+    }
+
+    /// Call `method` with `params`.
+    ///
+    /// A fault response is an [`Error::XmlRpc`] with the fault code and string.
+    pub fn call(&self, method: &str, params: &[Value]) -> Result<Value, Error> {
+        let uri = self.uri.clone().map_err(Error::BadUri)?;
+
+        let request = Request::post(uri)
+            .header(header::CONTENT_TYPE, "text/xml")
+            .body(method_call(method, params))?;
+
+        let mut response = self.agent.run(request)?;
+        let body = response.body_mut().read_to_string()?;
+
+        parse_response(&body)
+    }
+}
+
+impl Value {
+    /// The integer, if this is an `Int`.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Value::Int(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    /// The boolean, if this is a `Bool`.
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    /// The string, if this is a `String`.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// The number, if this is a `Double`.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Double(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    /// The decoded bytes, if this is `Base64`.
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Value::Base64(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// The values, if this is an `Array`.
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// The members, if this is a `Struct`.
+    pub fn as_struct(&self) -> Option<&BTreeMap<String, Value>> {
+        match self {
+            Value::Struct(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// The member `name`, if this is a `Struct` with that member.
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.as_struct()?.get(name)
+    }
+
+    fn write(&self, out: &mut String) {
+        out.push_str("<value>");
+        match self {
+            Value::Int(v) if i32::try_from(*v).is_ok() => {
+                let _ = write!(out, "<int>{}</int>", v);
+            }
+            Value::Int(v) => {
+                let _ = write!(out, "<i8>{}</i8>", v);
+            }
+            Value::Bool(v) => {
+                let _ = write!(out, "<boolean>{}</boolean>", *v as u8);
+            }
+            Value::String(v) => {
+                out.push_str("<string>");
+                escape(out, v);
+                out.push_str("</string>");
+            }
+            Value::Double(v) => {
+                let _ = write!(out, "<double>{}</double>", v);
+            }
+            Value::DateTime(v) => {
+                out.push_str("<dateTime.iso8601>");
+                escape(out, v);
+                out.push_str("</dateTime.iso8601>");
+            }
+            Value::Base64(v) => {
+                let _ = write!(out, "<base64>{}</base64>", BASE64_STANDARD.encode(v));
+            }
+            Value::Array(v) => {
+                out.push_str("<array><data>");
+                for value in v {
+                    value.write(out);
+                }
+                out.push_str("</data></array>");
+            }
+            Value::Struct(v) => {
+                out.push_str("<struct>");
+                for (name, value) in v {
+                    out.push_str("<member><name>");
+                    escape(out, name);
+                    out.push_str("</name>");
+                    value.write(out);
+                    out.push_str("</member>");
+                }
+                out.push_str("</struct>");
+            }
+            Value::Nil => out.push_str("<nil/>"),
+        }
+        out.push_str("</value>");
+    }
+}
+
+impl From<i32> for Value {
+    fn from(v: i32) -> Self {
+        Value::Int(v.into())
+    }
+}
+
+impl From<i64> for Value {
+    fn from(v: i64) -> Self {
+        Value::Int(v)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(v: bool) -> Self {
+        Value::Bool(v)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(v: &str) -> Self {
+        Value::String(v.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(v: String) -> Self {
+        Value::String(v)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(v: f64) -> Self {
+        Value::Double(v)
+    }
+}
+
+impl From<Vec<Value>> for Value {
+    fn from(v: Vec<Value>) -> Self {
+        Value::Array(v)
+    }
+}
+
+impl From<BTreeMap<String, Value>> for Value {
+    fn from(v: BTreeMap<String, Value>) -> Self {
+        Value::Struct(v)
+    }
+}
+
+fn method_call(method: &str, params: &[Value]) -> String {
+    let mut out = String::from("<?xml version=\"1.0\"?>\n<methodCall><methodName>");
+    escape(&mut out, method);
+    out.push_str("</methodName><params>");
+    for param in params {
+        out.push_str("<param>");
+        param.write(&mut out);
+        out.push_str("</param>");
+    }
+    out.push_str("</params></methodCall>");
+    out
+}
+
+fn escape(out: &mut String, s: &str) {
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            c => out.push(c),
+        }
+    }
+}
+
+fn parse_response(xml: &str) -> Result<Value, Error> {
+    let mut parser = Parser {
+        tokens: tokenize(xml)?,
+        pos: 0,
+    };
+
+    parser.open("methodResponse")?;
+
+    if parser.peek_open() == Some("fault") {
+        parser.open("fault")?;
+        let fault = parser.value()?;
+
+        let code = fault
+            .get("faultCode")
+            .and_then(Value::as_i64)
+            .and_then(|c| i32::try_from(c).ok());
+        let string = fault.get("faultString").and_then(Value::as_str);
+
+        return Err(match (code, string) {
+            (Some(code), Some(string)) => Error::XmlRpc(code, string.to_string()),
+            _ => invalid("fault without faultCode and faultString"),
+        });
+    }
+
+    parser.open("params")?;
+    parser.open("param")?;
+    let value = parser.value()?;
+    parser.close("param")?;
+    parser.close("params")?;
+    parser.close("methodResponse")?;
+
+    Ok(value)
+}
+
+fn invalid(reason: &str) -> Error {
+    Error::BadBody(format!("invalid XML-RPC response: {}", reason))
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Open(String),
+    Close(String),
+    Text(String),
+}
+
+/// Split the XML in tags and text. This is just enough XML for XML-RPC, which
+/// doesn't use attributes or namespaces.
+fn tokenize(mut xml: &str) -> Result<Vec<Token>, Error> {
+    let mut tokens = vec![];
+
+    while !xml.is_empty() {
+        let Some(start) = xml.find('<') else {
+            tokens.push(Token::Text(unescape(xml)?));
+            break;
+        };
+
+        if start > 0 {
+            tokens.push(Token::Text(unescape(&xml[..start])?));
+        }
+        xml = &xml[start..];
+
+        // Declaration, comment, CDATA section or DOCTYPE.
+        let skip = |xml: &str, end: &str| {
+            xml.find(end)
+                .map(|i| i + end.len())
+                .ok_or_else(|| invalid("unterminated markup"))
+        };
+
+        if xml.starts_with("<?") {
+            xml = &xml[skip(xml, "?>")?..];
+            continue;
+        }
+        if xml.starts_with("<!--") {
+            xml = &xml[skip(xml, "-->")?..];
+            continue;
+        }
+        if let Some(rest) = xml.strip_prefix("<![CDATA[") {
+            let end = skip(rest, "]]>")?;
+            tokens.push(Token::Text(rest[..end - 3].to_string()));
+            xml = &rest[end..];
+            continue;
+        }
+        if xml.starts_with("<!") {
+            xml = &xml[skip(xml, ">")?..];
+            continue;
+        }
+
+        let end = skip(xml, ">")?;
+        let tag = &xml[1..end - 1];
+        xml = &xml[end..];
+
+        let name = |tag: &str| {
+            tag.split(|c: char| c.is_ascii_whitespace())
+                .next()
+                .unwrap_or_default()
+                .to_string()
+        };
+
+        if let Some(tag) = tag.strip_prefix('/') {
+            tokens.push(Token::Close(name(tag.trim())));
+        } else if let Some(tag) = tag.strip_suffix('/') {
+            tokens.push(Token::Open(name(tag)));
+            tokens.push(Token::Close(name(tag)));
+        } else {
+            tokens.push(Token::Open(name(tag)));
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn unescape(s: &str) -> Result<String, Error> {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+
+    while let Some(i) = rest.find('&') {
+        out.push_str(&rest[..i]);
+        rest = &rest[i..];
+
+        let end = rest
+            .find(';')
+            .ok_or_else(|| invalid("unterminated entity"))?;
+        let entity = &rest[1..end];
+
+        let c = match entity {
+            "amp" => '&',
+            "lt" => '<',
+            "gt" => '>',
+            "quot" => '"',
+            "apos" => '\'',
+            _ => {
+                let n = if let Some(hex) = entity.strip_prefix("#x") {
+                    u32::from_str_radix(hex, 16).ok()
+                } else if let Some(dec) = entity.strip_prefix('#') {
+                    dec.parse().ok()
+                } else {
+                    None
+                };
+                n.and_then(char::from_u32)
+                    .ok_or_else(|| invalid("unknown entity"))?
+            }
+        };
+
+        out.push(c);
+        rest = &rest[end + 1..];
+    }
+
+    out.push_str(rest);
+    Ok(out)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn skip_whitespace(&mut self) {
+        while let Some(Token::Text(t)) = self.tokens.get(self.pos) {
+            if !t.trim().is_empty() {
+                break;
+            }
+            self.pos += 1;
+        }
+    }
+
+    fn peek_open(&mut self) -> Option<&str> {
+        self.skip_whitespace();
+        match self.tokens.get(self.pos) {
+            Some(Token::Open(name)) => Some(name),
+            _ => None,
+        }
+    }
+
+    fn open(&mut self, name: &str) -> Result<(), Error> {
+        self.skip_whitespace();
+        match self.tokens.get(self.pos) {
+            Some(Token::Open(n)) if n == name => {
+                self.pos += 1;
+                Ok(())
+            }
+            _ => Err(invalid(&format!("expected <{}>", name))),
+        }
+    }
+
+    fn close(&mut self, name: &str) -> Result<(), Error> {
+        self.skip_whitespace();
+        match self.tokens.get(self.pos) {
+            Some(Token::Close(n)) if n == name => {
+                self.pos += 1;
+                Ok(())
+            }
+            _ => Err(invalid(&format!("expected </{}>", name))),
+        }
+    }
+
+    /// All text up until the next tag, which can be empty.
+    fn text(&mut self) -> String {
+        let mut text = String::new();
+        while let Some(Token::Text(t)) = self.tokens.get(self.pos) {
+            text.push_str(t);
+            self.pos += 1;
+        }
+        text
+    }
+
+    fn value(&mut self) -> Result<Value, Error> {
+        self.open("value")?;
+
+        // A value without type is a string.
+        let text = self.text();
+        if let Some(Token::Close(n)) = self.tokens.get(self.pos) {
+            if n == "value" {
+                self.pos += 1;
+                return Ok(Value::String(text));
+            }
+        }
+
+        let Some(Token::Open(kind)) = self.tokens.get(self.pos) else {
+            return Err(invalid("expected value type"));
+        };
+        let kind = kind.clone();
+        self.pos += 1;
+
+        let bad = || invalid(&format!("bad <{}>", kind));
+
+        let value = match kind.as_str() {
+            "int" | "i4" | "i8" => Value::Int(self.text().trim().parse().map_err(|_| bad())?),
+            "boolean" => match self.text().trim() {
+                "1" => Value::Bool(true),
+                "0" => Value::Bool(false),
+                _ => return Err(bad()),
+            },
+            "string" => Value::String(self.text()),
+            "double" => Value::Double(self.text().trim().parse().map_err(|_| bad())?),
+            "dateTime.iso8601" => Value::DateTime(self.text().trim().to_string()),
+            "base64" => {
+                let text: String = self.text().split_ascii_whitespace().collect();
+                Value::Base64(BASE64_STANDARD.decode(text).map_err(|_| bad())?)
+            }
+            "array" => {
+                self.open("data")?;
+                let mut values = vec![];
+                while self.peek_open() == Some("value") {
+                    values.push(self.value()?);
+                }
+                self.close("data")?;
+                Value::Array(values)
+            }
+            "struct" => {
+                let mut members = BTreeMap::new();
+                while self.peek_open() == Some("member") {
+                    self.open("member")?;
+                    self.open("name")?;
+                    let name = self.text();
+                    self.close("name")?;
+                    let value = self.value()?;
+                    self.close("member")?;
+                    members.insert(name, value);
+                }
+                Value::Struct(members)
+            }
+            "nil" => Value::Nil,
+            _ => return Err(bad()),
+        };
+
+        self.close(&kind)?;
+        self.close("value")?;
+
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::Config;
+    use crate::transport::MockConnector;
+
+    fn response(body: &str) -> String {
+        format!(
+            "HTTP/1.1 200 OK\r\n\
+            Content-Type: text/xml\r\n\
+            Content-Length: {}\r\n\
+            \r\n\
+            {}",
+            body.len(),
+            body
+        )
+    }
+
+    #[test]
+    fn encode_call() {
+        let mut member = BTreeMap::new();
+        member.insert("a&b".to_string(), Value::Nil);
+
+        let xml = method_call(
+            "demo.echo",
+            &[
+                1.into(),
+                (1i64 << 40).into(),
+                "<x>".into(),
+                vec![true.into(), 1.5.into()].into(),
+                member.into(),
+                Value::Base64(b"hi".to_vec()),
+            ],
+        );
+
+        assert_eq!(
+            xml,
+            "<?xml version=\"1.0\"?>\n<methodCall><methodName>demo.echo</methodName><params>\
+            <param><value><int>1</int></value></param>\
+            <param><value><i8>1099511627776</i8></value></param>\
+            <param><value><string>&lt;x&gt;</string></value></param>\
+            <param><value><array><data>\
+            <value><boolean>1</boolean></value><value><double>1.5</double></value>\
+            </data></array></value></param>\
+            <param><value><struct><member><name>a&amp;b</name><value><nil/></value></member>\
+            </struct></value></param>\
+            <param><value><base64>aGk=</base64></value></param>\
+            </params></methodCall>"
+        );
+    }
+
+    #[test]
+    fn call_and_fault() {
+        let mock = MockConnector::new()
+            .route(
+                "/ok",
+                response(
+                    r#"<?xml version="1.0"?>
+                    <methodResponse>
+                      <params>
+                        <param>
+                          <value><struct>
+                            <member><name>statename</name><value>RUNNING &amp; ok</value></member>
+                            <member><name>pids</name><value><array><data>
+                              <value><i4>12</i4></value>
+                              <value><int>-3</int></value>
+                            </data></array></value></member>
+                            <member><name>blob</name><value><base64>
+                              aGVs
+                              bG8=
+                            </base64></value></member>
+                            <member><name>empty</name><value><string/></value></member>
+                          </struct></value>
+                        </param>
+                      </params>
+                    </methodResponse>"#,
+                ),
+            )
+            .route(
+                "/fault",
+                response(
+                    "<methodResponse><fault><value><struct>\
+                    <member><name>faultCode</name><value><int>10</int></value></member>\
+                    <member><name>faultString</name><value><string>BAD_NAME</string></value></member>\
+                    </struct></value></fault></methodResponse>",
+                ),
+            );
+        let agent = Agent::with_parts(Config::default(), mock.clone(), mock.clone());
+
+        let value = agent
+            .xmlrpc("http://example.test/ok")
+            .call("supervisor.getState", &[])
+            .unwrap();
+
+        assert_eq!(
+            value.get("statename").and_then(Value::as_str),
+            Some("RUNNING & ok")
+        );
+        assert_eq!(
+            value.get("pids").and_then(Value::as_array),
+            Some(&[Value::Int(12), Value::Int(-3)][..])
+        );
+        assert_eq!(
+            value.get("blob").and_then(Value::as_bytes),
+            Some(&b"hello"[..])
+        );
+        assert_eq!(value.get("empty").and_then(Value::as_str), Some(""));
+
+        let request = String::from_utf8(mock.requests().remove(0)).unwrap();
+        assert!(request.contains("content-type: text/xml\r\n"));
+
+        let err = agent
+            .xmlrpc("http://example.test/fault")
+            .call("supervisor.startProcess", &["nope".into()])
+            .unwrap_err();
+        assert!(matches!(err, Error::XmlRpc(10, s) if s == "BAD_NAME"));
+    }
+
+    #[test]
+    fn invalid_response() {
+        let mock = MockConnector::new()
+            .route("/html", response("<html><body>Oops</body></html>"))
+            .route(
+                "/code",
+                response(
+                    "<methodResponse><fault><value><struct>\
+                    <member><name>faultCode</name><value><int>4294967296</int></value></member>\
+                    <member><name>faultString</name><value><string>big</string></value></member>\
+                    </struct></value></fault></methodResponse>",
+                ),
+            );
+        let agent = Agent::with_parts(Config::default(), mock.clone(), mock);
+
+        for path in ["html", "code"] {
+            let uri = format!("http://example.test/{}", path);
+            let err = agent.xmlrpc(uri).call("a", &[]).unwrap_err();
+            assert!(matches!(err, Error::BadBody(_)), "{:?}", err);
+        }
+    }
+}